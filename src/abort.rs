@@ -0,0 +1,224 @@
+//! Decodes the Move aborts found in failure output, like
+//! `Move abort in 0x1::coin: 0x10006`, into the error constants
+//! of the modules that raised them and their doc comments.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use crate::state::collect_files;
+use crate::ui;
+
+//Categories of the std::error module, set in the upper bits of abort codes
+const CATEGORIES: [&str; 14] = [
+    "",
+    "invalid argument",
+    "out of range",
+    "invalid state",
+    "unauthenticated",
+    "permission denied",
+    "not found",
+    "aborted",
+    "already exists",
+    "resource exhausted",
+    "cancelled",
+    "internal",
+    "not implemented",
+    "unavailable",
+];
+
+/// An error constant of a Move module, `const E_NOT_OWNER: u64 = 1;`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorConstant {
+    pub module: String,
+    pub name: String,
+    pub value: u64,
+    pub doc: String,
+}
+
+/// The error constants of the modules a package is compiled from,
+/// its dependencies included.
+#[derive(Clone, Debug, Default)]
+pub struct ErrorMap {
+    constants: Vec<ErrorConstant>,
+}
+
+impl ErrorMap {
+    /// Read the error constants of the packages in these directories, or
+    /// of the current directory if there are none. The sources copied
+    /// into build/ by the compiler bring those of the dependencies.
+    pub fn load(packages: &[PathBuf]) -> Self {
+        let dirs = if packages.is_empty() {
+            vec![PathBuf::from(".")]
+        } else {
+            packages.to_vec()
+        };
+        let mut files = Vec::new();
+        for dir in &dirs {
+            collect_files(&dir.join("sources"), &mut files);
+            collect_files(&dir.join("build"), &mut files);
+        }
+        let constants = files
+            .iter()
+            .filter(|file| file.extension().map_or(false, |x| x == "move"))
+            .filter_map(|file| std::fs::read_to_string(file).ok())
+            .flat_map(|source| parse_source(&source))
+            .collect();
+        ErrorMap { constants }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constants.is_empty()
+    }
+
+    /// One line explaining each abort found in the output
+    pub fn explain(&self, output: &str) -> Vec<String> {
+        let aborts: BTreeSet<_> = find_aborts(output).into_iter().collect();
+        aborts
+            .into_iter()
+            .filter_map(|(location, code)| {
+                let module = location.rsplit("::").next().unwrap_or(&location);
+                let constant = self.lookup(module, code)?;
+                let category = CATEGORIES.get((code >> 16) as usize).copied();
+                let mut line = match category {
+                    Some(category) if !category.is_empty() => format!(
+                        "{} aborted with {} ({}, {:#x})",
+                        location, constant.name, category, code
+                    ),
+                    _ => format!("{} aborted with {} ({:#x})", location, constant.name, code),
+                };
+                if !constant.doc.is_empty() {
+                    line.push_str(": ");
+                    line.push_str(&constant.doc);
+                }
+                Some(line)
+            })
+            .collect()
+    }
+
+    /// Print what the aborts found in the output mean, if there are any
+    pub fn report(&self, output: &str) {
+        let lines = self.explain(output);
+        if lines.is_empty() {
+            return;
+        }
+        ui::failure("Move aborts");
+        for line in lines {
+            ui::print(line);
+        }
+    }
+
+    //The constant of a module an abort code stands for, either as is or
+    //as the reason in the lower bits of a std::error code
+    fn lookup(&self, module: &str, code: u64) -> Option<&ErrorConstant> {
+        let matching = |value: u64| {
+            self.constants
+                .iter()
+                .find(|x| x.module == module && x.value == value)
+        };
+        matching(code).or_else(|| matching(code & 0xffff))
+    }
+}
+
+//Reads the error constants of the modules in a source file, an error
+//constant being a u64 constant named E_SOMETHING or ESOMETHING
+fn parse_source(source: &str) -> Vec<ErrorConstant> {
+    let mut constants = Vec::new();
+    let mut module = String::new();
+    let mut doc = Vec::new();
+    for line in source.lines().map(str::trim) {
+        if let Some(comment) = line.strip_prefix("///") {
+            doc.push(comment.trim().to_string());
+            continue;
+        }
+        if let Some(declaration) = line.strip_prefix("module ") {
+            let name = declaration
+                .split(|c: char| c == '{' || c == ';' || c.is_whitespace())
+                .next()
+                .unwrap_or_default();
+            module = name.rsplit("::").next().unwrap_or(name).to_string();
+        } else if let Some(constant) = line.strip_prefix("const ") {
+            if let Some(constant) = parse_constant(constant) {
+                let (name, value) = constant;
+                constants.push(ErrorConstant {
+                    module: module.clone(),
+                    name,
+                    value,
+                    doc: doc.join(" "),
+                });
+            }
+        }
+        if !line.is_empty() {
+            doc.clear();
+        }
+    }
+    constants
+}
+
+//Name and value of `E_NAME: u64 = 1;`, if it is an error constant
+fn parse_constant(constant: &str) -> Option<(String, u64)> {
+    let (name, rest) = constant.split_once(':')?;
+    let name = name.trim();
+    if !name.starts_with('E')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+    {
+        return None;
+    }
+    let (ty, value) = rest.split_once('=')?;
+    if ty.trim() != "u64" {
+        return None;
+    }
+    let value = value.split(';').next()?.trim();
+    Some((name.to_string(), parse_code(value)?))
+}
+
+//Every abort in the output, as the module's location and the code. The
+//code may already be named by the node, as in `E_NOT_OWNER(0x50001)`
+fn find_aborts(output: &str) -> Vec<(String, u64)> {
+    output
+        .split("Move abort")
+        .skip(1)
+        .filter_map(|rest| {
+            let rest = rest.trim_start().trim_start_matches("in ").trim_start();
+            let mut tokens = rest.split_whitespace();
+            let location = tokens.next()?.trim_end_matches(':');
+            if !location.contains("::") {
+                return None;
+            }
+            let code = tokens.next()?.trim_end_matches(':');
+            let code = match code.split_once('(') {
+                Some((_, code)) => code.split(')').next()?,
+                None => code,
+            };
+            Some((location.to_string(), parse_code(code)?))
+        })
+        .collect()
+}
+
+//A decimal or 0x-hex number
+fn parse_code(code: &str) -> Option<u64> {
+    match code.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => code.parse().ok(),
+    }
+}
+
+#[test]
+fn test_explain() {
+    let source = "module 0xcafe::market {\n    /// Only the owner can list\n    const E_NOT_OWNER: u64 = 1;\n    const MAX_PRICE: u64 = 5;\n}\n";
+    let map = ErrorMap {
+        constants: parse_source(source),
+    };
+    assert_eq!(map.constants.len(), 1);
+    assert_eq!(
+        map.explain("error: Move abort in 0xcafe::market: 0x50001\n"),
+        ["0xcafe::market aborted with E_NOT_OWNER (permission denied, 0x50001): Only the owner can list"]
+    );
+    assert_eq!(
+        map.explain("vm_status: Move abort in 0xcafe::market: E_NOT_OWNER(0x1): Only")
+            .len(),
+        1
+    );
+    assert!(map.explain("Move abort 0x1::coin 0x10006").is_empty());
+}
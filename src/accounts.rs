@@ -0,0 +1,255 @@
+use ed25519_dalek::SigningKey;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::{AptestError, OrFail};
+use crate::faucet::Funder;
+use crate::ui;
+
+/// Octas given to each generated account, 1 APT
+pub const DEFAULT_FUND_AMOUNT: u64 = 100_000_000;
+
+/// Octas given to the fee payer account, 10 APT since it pays for the
+/// gas of every sponsored transaction
+pub const DEFAULT_FEE_PAYER_AMOUNT: u64 = 1_000_000_000;
+
+//Index the fee payer is derived from a seed with, out of the way of the
+//test accounts
+const FEE_PAYER_INDEX: u64 = u64::MAX;
+
+//Accounts created with "aptest account new", by name
+const NAMED_ACCOUNTS_FILE: &str = ".aptest/accounts.json";
+
+/// An account with a single Ed25519 key, generated for the tests
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TestAccount {
+    pub address: String,
+    pub public_key: String,
+    pub private_key: String,
+}
+
+impl TestAccount {
+    /// A new account with a random key
+    pub fn generate() -> Self {
+        let mut private_key = [0u8; 32];
+        OsRng.fill_bytes(&mut private_key);
+        Self::from_private_key(private_key)
+    }
+
+    /// The account `index` of those derived from a seed, the same on
+    /// every machine: its private key is the SHA3-256 hash of the seed
+    /// followed by the index as 8 little endian bytes
+    pub fn from_seed(seed: &[u8], index: u64) -> Self {
+        let mut hasher = Sha3_256::new();
+        hasher.update(seed);
+        hasher.update(index.to_le_bytes());
+        Self::from_private_key(hasher.finalize().into())
+    }
+
+    /// The account controlled by the given Ed25519 private key
+    pub fn from_private_key(private_key: [u8; 32]) -> Self {
+        let public_key = SigningKey::from_bytes(&private_key)
+            .verifying_key()
+            .to_bytes();
+
+        //The authentication key, and so the address, of a single key account
+        //is the hash of the public key followed by the Ed25519 scheme id
+        let mut hasher = Sha3_256::new();
+        hasher.update(public_key);
+        hasher.update([0u8]);
+        let address = hasher.finalize();
+
+        TestAccount {
+            address: format!("0x{}", hex::encode(address)),
+            public_key: format!("0x{}", hex::encode(public_key)),
+            private_key: format!("0x{}", hex::encode(private_key)),
+        }
+    }
+
+    /// The key to sign the account's transactions with
+    pub fn signing_key(&self) -> Result<SigningKey, AptestError> {
+        hex::decode(self.private_key.trim_start_matches("0x"))
+            .ok()
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .map(|key| SigningKey::from_bytes(&key))
+            .ok_or_else(|| {
+                AptestError::Environment(format!(
+                    "The private key of {} is not an Ed25519 key",
+                    self.address
+                ))
+            })
+    }
+}
+
+/// Generate `count` accounts, derived from the seed if there is one and
+/// random otherwise, fund each with `amount` octas through the funder and
+/// write them to `path` as JSON for the tests to pick up.
+pub async fn create_funded(
+    count: usize,
+    amount: u64,
+    seed: Option<&[u8]>,
+    funder: &Funder,
+    path: &Path,
+) -> Result<Vec<TestAccount>, AptestError> {
+    ui::info(format!("Funding {} test accounts on local node...", count));
+    let accounts: Vec<TestAccount> = (0..count)
+        .map(|i| match seed {
+            Some(seed) => TestAccount::from_seed(seed, i as u64),
+            None => TestAccount::generate(),
+        })
+        .collect();
+    for account in &accounts {
+        funder.fund(&account.address, amount).await?;
+        ui::print(format!("Funded {}", account.address));
+    }
+
+    let json = serde_json::to_string_pretty(&accounts).or_fail(
+        AptestError::Environment,
+        "Could not serialize test accounts",
+    )?;
+    std::fs::write(path, json).or_fail(
+        AptestError::Environment,
+        &format!("Could not write {}", path.display()),
+    )?;
+    Ok(accounts)
+}
+
+/// Generate the account paying the gas of sponsored transactions, derived
+/// from the seed if there is one and random otherwise, and fund it with
+/// `amount` octas through the funder.
+pub async fn create_fee_payer(
+    amount: u64,
+    seed: Option<&[u8]>,
+    funder: &Funder,
+) -> Result<TestAccount, AptestError> {
+    ui::info("Funding the fee payer account on local node...");
+    let account = match seed {
+        Some(seed) => TestAccount::from_seed(seed, FEE_PAYER_INDEX),
+        None => TestAccount::generate(),
+    };
+    funder.fund(&account.address, amount).await?;
+    ui::print(format!("Funded {}", account.address));
+    Ok(account)
+}
+
+/// Accounts created with "aptest account new", by name
+pub fn named() -> Result<BTreeMap<String, TestAccount>, AptestError> {
+    match std::fs::read_to_string(NAMED_ACCOUNTS_FILE) {
+        Ok(json) => serde_json::from_str(&json).or_fail(
+            AptestError::Environment,
+            &format!("Could not parse {}", NAMED_ACCOUNTS_FILE),
+        ),
+        Err(_) => Ok(BTreeMap::new()),
+    }
+}
+
+/// Generate an account and remember it under the name.
+pub fn create_named(name: &str) -> Result<TestAccount, AptestError> {
+    let mut accounts = named()?;
+    if accounts.contains_key(name) {
+        return Err(AptestError::Environment(format!(
+            "An account named {} already exists",
+            name
+        )));
+    }
+    let account = TestAccount::generate();
+    accounts.insert(name.to_string(), account.clone());
+
+    let json = serde_json::to_string_pretty(&accounts)
+        .or_fail(AptestError::Environment, "Could not serialize accounts")?;
+    std::fs::create_dir_all(".aptest")
+        .or_fail(AptestError::Environment, "Could not create .aptest")?;
+    std::fs::write(NAMED_ACCOUNTS_FILE, json).or_fail(
+        AptestError::Environment,
+        &format!("Could not write {}", NAMED_ACCOUNTS_FILE),
+    )?;
+    Ok(account)
+}
+
+/// Turn an address, account name or aptos profile name into an address.
+pub fn resolve(account: &str) -> Result<String, AptestError> {
+    if account.starts_with("0x") {
+        return Ok(account.to_string());
+    }
+    if let Some(named) = named()?.remove(account) {
+        return Ok(named.address);
+    }
+    crate::profile::fetch_account(account).map_err(|_| {
+        AptestError::Environment(format!(
+            "{} is neither an address, an account created with \"aptest account new\" nor an aptos profile",
+            account
+        ))
+    })
+}
+
+/// The address and key of an account created with "aptest account new"
+/// or of an aptos profile, to send transactions from.
+pub fn signer(account: &str) -> Result<(String, SigningKey), AptestError> {
+    let (address, private_key) = match named()?.remove(account) {
+        Some(named) => (named.address, named.private_key),
+        None if crate::profile::has_profile(account) => {
+            let config = crate::profile::AptosConfig::load()?;
+            (config.account(account)?, config.private_key(account)?)
+        }
+        None => {
+            return Err(AptestError::Environment(format!(
+                "{} is neither an account created with \"aptest account new\" nor an aptos profile",
+                account
+            )))
+        }
+    };
+    //Newer aptos CLIs prefix keys with their scheme
+    let digits = private_key
+        .trim_start_matches("ed25519-priv-")
+        .trim_start_matches("0x");
+    let key = hex::decode(digits)
+        .ok()
+        .and_then(|key| <[u8; 32]>::try_from(key).ok())
+        .ok_or_else(|| {
+            AptestError::Environment(format!(
+                "The private key of {} is not an Ed25519 key",
+                account
+            ))
+        })?;
+    Ok((address, SigningKey::from_bytes(&key)))
+}
+
+#[test]
+fn test_address_derivation() {
+    let private_key =
+        hex::decode("9bf49a6a0755f953811fce125f2683d50429c3bb49e074147e0089a52eae155f")
+            .unwrap()
+            .try_into()
+            .unwrap();
+    let account = TestAccount::from_private_key(private_key);
+    assert_eq!(
+        account.public_key,
+        "0xb9c6ee1630ef3e711144a648db06bbb2284f7274cfbee53ffcee503cc1a49200"
+    );
+    assert_eq!(
+        account.address,
+        "0x7deeccb1080854f499ec8b4c1b213b82c5e34b925cf6875fec02d4b77adbd2d6"
+    );
+}
+
+#[test]
+fn test_from_seed() {
+    let first = TestAccount::from_seed(&[0xab; 16], 0);
+    assert_eq!(
+        first.address,
+        TestAccount::from_seed(&[0xab; 16], 0).address
+    );
+    assert_ne!(
+        first.address,
+        TestAccount::from_seed(&[0xab; 16], 1).address
+    );
+    assert_ne!(
+        first.address,
+        TestAccount::from_seed(&[0xcd; 16], 0).address
+    );
+}
@@ -0,0 +1,180 @@
+use serde_json::Value;
+
+use crate::error::AptestError;
+use crate::transaction::parse_address;
+
+/// An argument of a Move function, as given in the aptos CLI's
+/// `type:value` form
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MoveValue {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    /// Little endian, as BCS encodes it
+    U256([u8; 32]),
+    Address([u8; 32]),
+    String(String),
+    /// A `vector<u8>`, given as hex
+    Bytes(Vec<u8>),
+    Vector(Vec<MoveValue>),
+}
+
+impl MoveValue {
+    /// The value in the JSON form of the node's REST API, numbers above
+    /// 32 bits being strings
+    pub fn json(&self) -> Value {
+        match self {
+            MoveValue::Bool(x) => Value::from(*x),
+            MoveValue::U8(x) => Value::from(*x),
+            MoveValue::U16(x) => Value::from(*x),
+            MoveValue::U32(x) => Value::from(*x),
+            MoveValue::U64(x) => Value::from(x.to_string()),
+            MoveValue::U128(x) => Value::from(x.to_string()),
+            MoveValue::U256(x) => Value::from(u256_decimal(x)),
+            MoveValue::Address(x) => Value::from(format!("0x{}", hex::encode(x))),
+            MoveValue::String(x) => Value::from(x.as_str()),
+            MoveValue::Bytes(x) => Value::from(format!("0x{}", hex::encode(x))),
+            MoveValue::Vector(x) => Value::Array(x.iter().map(MoveValue::json).collect()),
+        }
+    }
+}
+
+/// Parse an argument in the aptos CLI's `type:value` form, like `u64:10`
+/// or `address:[0x1,0x2]` for a vector. Addresses can also be account
+/// names or aptos profiles.
+pub fn parse(arg: &str) -> Result<MoveValue, AptestError> {
+    let (ty, value) = arg
+        .split_once(':')
+        .ok_or_else(|| invalid(arg, "expected type:value"))?;
+    match value.strip_prefix('[').and_then(|x| x.strip_suffix(']')) {
+        Some(items) => items
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| parse_value(arg, ty, item))
+            .collect::<Result<Vec<_>, _>>()
+            .map(MoveValue::Vector),
+        None => parse_value(arg, ty, value),
+    }
+}
+
+/// The Move type of an argument in `type:value` form, like `u64`
+/// or `vector<address>`
+pub fn type_of(arg: &str) -> Result<String, AptestError> {
+    let (ty, value) = arg
+        .split_once(':')
+        .ok_or_else(|| invalid(arg, "expected type:value"))?;
+    let ty = match ty {
+        "hex" => "vector<u8>",
+        "string" => "0x1::string::String",
+        _ => ty,
+    };
+    if value.starts_with('[') {
+        Ok(format!("vector<{}>", ty))
+    } else {
+        Ok(ty.to_string())
+    }
+}
+
+/// Resolve the address of a `address::module::function` id, which can
+/// also be an account name or an aptos profile
+pub fn function_id(function: &str) -> Result<String, AptestError> {
+    match function.split_once("::") {
+        Some((address, rest)) => Ok(format!("{}::{}", crate::accounts::resolve(address)?, rest)),
+        None => Err(AptestError::Environment(format!(
+            "{} is not a function id, expected address::module::function",
+            function
+        ))),
+    }
+}
+
+//A single value of a type
+fn parse_value(arg: &str, ty: &str, value: &str) -> Result<MoveValue, AptestError> {
+    let number = |_| invalid(arg, &format!("{} is not a {}", value, ty));
+    Ok(match ty {
+        "bool" => MoveValue::Bool(value.parse().map_err(number)?),
+        "u8" => MoveValue::U8(value.parse().map_err(number)?),
+        "u16" => MoveValue::U16(value.parse().map_err(number)?),
+        "u32" => MoveValue::U32(value.parse().map_err(number)?),
+        "u64" => MoveValue::U64(value.parse().map_err(number)?),
+        "u128" => MoveValue::U128(value.parse().map_err(number)?),
+        "u256" => MoveValue::U256(
+            parse_u256(value).ok_or_else(|| invalid(arg, &format!("{} is not a u256", value)))?,
+        ),
+        "address" => MoveValue::Address(parse_address(&crate::accounts::resolve(value)?)?),
+        "string" => MoveValue::String(value.to_string()),
+        "hex" => MoveValue::Bytes(
+            hex::decode(value.trim_start_matches("0x"))
+                .map_err(|_| invalid(arg, &format!("{} is not hex", value)))?,
+        ),
+        _ => return Err(invalid(arg, &format!("unsupported type {}", ty))),
+    })
+}
+
+//A decimal number as the 32 little endian bytes of a u256
+fn parse_u256(value: &str) -> Option<[u8; 32]> {
+    if value.is_empty() {
+        return None;
+    }
+    let mut out = [0u8; 32];
+    for digit in value.bytes() {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        let mut carry = (digit - b'0') as u32;
+        for byte in out.iter_mut() {
+            let x = *byte as u32 * 10 + carry;
+            *byte = x as u8;
+            carry = x >> 8;
+        }
+        if carry != 0 {
+            return None;
+        }
+    }
+    Some(out)
+}
+
+//The decimal form of a little endian u256
+fn u256_decimal(value: &[u8; 32]) -> String {
+    let mut value = *value;
+    let mut digits = Vec::new();
+    loop {
+        let mut remainder = 0u32;
+        for byte in value.iter_mut().rev() {
+            let x = (remainder << 8) | *byte as u32;
+            *byte = (x / 10) as u8;
+            remainder = x % 10;
+        }
+        digits.push(b'0' + remainder as u8);
+        if value.iter().all(|&x| x == 0) {
+            break;
+        }
+    }
+    digits.reverse();
+    String::from_utf8(digits).unwrap_or_default()
+}
+
+fn invalid(arg: &str, reason: &str) -> AptestError {
+    AptestError::Environment(format!("Invalid argument {}: {}", arg, reason))
+}
+
+#[test]
+fn test_parse() {
+    use serde_json::json;
+    assert_eq!(parse("u8:7").unwrap().json(), json!(7));
+    assert_eq!(parse("u64:10").unwrap().json(), json!("10"));
+    assert_eq!(parse("bool:true").unwrap().json(), json!(true));
+    assert_eq!(parse("u64:[1, 2]").unwrap().json(), json!(["1", "2"]));
+    assert_eq!(parse("hex:0xCAFE").unwrap().json(), json!("0xcafe"));
+    let max = "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+    assert_eq!(parse(&format!("u256:{}", max)).unwrap().json(), json!(max));
+    assert!(parse(&format!("u256:{}0", max)).is_err());
+    assert!(parse("u8:256").is_err());
+    assert!(parse("10").is_err());
+    assert!(parse("f64:1.5").is_err());
+    assert_eq!(type_of("address:[0x1]").unwrap(), "vector<address>");
+    assert_eq!(type_of("hex:0x00").unwrap(), "vector<u8>");
+}
@@ -0,0 +1,300 @@
+//! Load testing of a local network: a mix of entry function calls sent
+//! at a target rate from funded accounts, measuring throughput, latency
+//! and the gas used, for performance feedback before going to testnet.
+
+use ed25519_dalek::SigningKey;
+use rand::Rng;
+use serde_json::Value;
+use tokio::task::JoinSet;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::accounts::{TestAccount, DEFAULT_FUND_AMOUNT};
+use crate::error::AptestError;
+use crate::faucet::Funder;
+use crate::gas::GasReport;
+use crate::rest::NodeClient;
+use crate::signal::Interrupt;
+use crate::transaction::{self, EntryFunction};
+use crate::ui;
+
+//How often a submitted transaction is looked up, bounding the precision
+//of the latencies measured
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+//Transactions not executed by then count as failed
+const COMMIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends entry function calls to a node at a steady rate.
+pub struct Bench {
+    node_url: String,
+    funder: Option<Funder>,
+    calls: Vec<(EntryFunction, u32)>,
+    rate: f64,
+    duration: Duration,
+    accounts: usize,
+}
+
+/// What a benchmark measured
+#[derive(Debug, Default)]
+pub struct BenchResult {
+    /// Transactions the node executed
+    pub executed: usize,
+    /// Executed transactions that aborted
+    pub aborted: usize,
+    /// Transactions the node refused or never executed
+    pub rejected: usize,
+    /// From the first submission to the last execution
+    pub elapsed: Duration,
+    /// From submission to execution of each executed transaction, sorted
+    pub latencies: Vec<Duration>,
+    pub gas: GasReport,
+}
+
+//A sender of the benchmark, keeping track of its sequence number so
+//that it can have several transactions in flight
+struct Sender {
+    address: String,
+    key: SigningKey,
+    sequence_number: u64,
+    //Set when a submission failed, leaving a gap the node won't fill
+    stale: Arc<AtomicBool>,
+}
+
+//How one transaction went
+enum Outcome {
+    Executed {
+        latency: Duration,
+        transaction: Value,
+    },
+    Rejected,
+}
+
+impl Bench {
+    pub fn new(node_url: &str) -> Self {
+        Bench {
+            node_url: node_url.to_string(),
+            funder: None,
+            calls: Vec::new(),
+            rate: 10.0,
+            duration: Duration::from_secs(30),
+            accounts: 4,
+        }
+    }
+
+    /// Funds the sending accounts
+    pub fn funder(mut self, funder: Funder) -> Self {
+        self.funder = Some(funder);
+        self
+    }
+
+    /// Add a call to the mix, sent `weight` times as often as a call of
+    /// weight 1
+    pub fn call(mut self, call: EntryFunction, weight: u32) -> Self {
+        self.calls.push((call, weight));
+        self
+    }
+
+    /// Transactions submitted per second
+    pub fn rate(mut self, rate: f64) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// How long transactions are submitted for
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Number of accounts the transactions are sent from
+    pub fn accounts(mut self, accounts: usize) -> Self {
+        self.accounts = accounts;
+        self
+    }
+
+    /// Fund the senders, then submit transactions at the rate for the
+    /// duration and wait for them to be executed.
+    pub async fn run(self, interrupt: &Interrupt) -> Result<BenchResult, AptestError> {
+        let total_weight: u32 = self.calls.iter().map(|(_, weight)| weight).sum();
+        if total_weight == 0 || self.rate <= 0.0 || self.accounts == 0 {
+            return Err(AptestError::Environment(
+                "A benchmark needs calls to send, a rate and accounts to send them from"
+                    .to_string(),
+            ));
+        }
+        let client = NodeClient::new(&self.node_url);
+        let chain_id = client.chain_id().await?;
+        let mut senders = interrupt.race(self.senders()).await?;
+
+        ui::info(format!(
+            "Sending {} transactions per second for {}s...",
+            self.rate,
+            self.duration.as_secs()
+        ));
+        let mut tasks = JoinSet::new();
+        let mut ticks = tokio::time::interval(Duration::from_secs_f64(1.0 / self.rate));
+        let started = Instant::now();
+        let mut next = 0;
+        while started.elapsed() < self.duration {
+            ticks.tick().await;
+            interrupt.check()?;
+            let sender = &mut senders[next % senders.len()];
+            next += 1;
+            if sender.stale.swap(false, Ordering::SeqCst) {
+                sender.sequence_number = client.sequence_number(&sender.address).await?;
+            }
+            let call =
+                &self.calls[pick(&self.calls, rand::thread_rng().gen_range(0..total_weight))].0;
+            let signed = transaction::sign(
+                &sender.address,
+                &sender.key,
+                sender.sequence_number,
+                chain_id,
+                call,
+            )?;
+            sender.sequence_number += 1;
+            tasks.spawn(send(client.clone(), signed, sender.stale.clone()));
+        }
+
+        let mut result = BenchResult::default();
+        let outcomes = async {
+            let mut outcomes = Vec::new();
+            while let Some(outcome) = tasks.join_next().await {
+                outcomes.push(outcome.unwrap_or(Outcome::Rejected));
+            }
+            Ok(outcomes)
+        };
+        for outcome in interrupt.race(outcomes).await? {
+            match outcome {
+                Outcome::Executed {
+                    latency,
+                    transaction,
+                } => {
+                    result.executed += 1;
+                    if transaction["success"].as_bool() != Some(true) {
+                        result.aborted += 1;
+                    }
+                    result.latencies.push(latency);
+                    result.gas.add_transactions(&[transaction]);
+                }
+                Outcome::Rejected => result.rejected += 1,
+            }
+        }
+        result.elapsed = started.elapsed();
+        result.latencies.sort();
+        Ok(result)
+    }
+
+    //New accounts to send from, funded
+    async fn senders(&self) -> Result<Vec<Sender>, AptestError> {
+        let funder = self.funder.as_ref().ok_or_else(|| {
+            AptestError::Environment("Funding the benchmark accounts needs a faucet".to_string())
+        })?;
+        ui::info(format!("Funding {} benchmark accounts...", self.accounts));
+        let mut senders = Vec::new();
+        for _ in 0..self.accounts {
+            let mut private_key = [0u8; 32];
+            rand::thread_rng().fill(&mut private_key);
+            let account = TestAccount::from_private_key(private_key);
+            funder.fund(&account.address, DEFAULT_FUND_AMOUNT).await?;
+            senders.push(Sender {
+                address: account.address,
+                key: SigningKey::from_bytes(&private_key),
+                sequence_number: 0,
+                stale: Arc::new(AtomicBool::new(false)),
+            });
+        }
+        Ok(senders)
+    }
+}
+
+impl BenchResult {
+    /// Executed transactions per second
+    pub fn tps(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.latencies.len() as f64 / secs
+        }
+    }
+
+    /// The latency `percent` of the executed transactions were under
+    pub fn percentile(&self, percent: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (percent / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    /// Print the throughput, latencies and gas of the benchmark
+    pub fn print_summary(&self) {
+        ui::info("Benchmark");
+        ui::print(format!("  {:<12} {:>10}", "executed", self.executed));
+        ui::print(format!("  {:<12} {:>10}", "aborted", self.aborted));
+        ui::print(format!("  {:<12} {:>10}", "rejected", self.rejected));
+        ui::print(format!("  {:<12} {:>10.1}", "tps", self.tps()));
+        for (name, percent) in [("p50", 50.0), ("p90", 90.0), ("p99", 99.0), ("max", 100.0)] {
+            ui::print(format!(
+                "  {:<12} {:>8}ms",
+                format!("latency {}", name),
+                self.percentile(percent).as_millis()
+            ));
+        }
+        ui::print("");
+        self.gas.print_summary();
+    }
+}
+
+//Submits a signed transaction and waits for it to be executed
+async fn send(client: NodeClient, signed: Vec<u8>, stale: Arc<AtomicBool>) -> Outcome {
+    let submitted = Instant::now();
+    let hash = match client.submit(signed).await {
+        Ok(hash) => hash,
+        Err(_) => {
+            stale.store(true, Ordering::SeqCst);
+            return Outcome::Rejected;
+        }
+    };
+    while submitted.elapsed() < COMMIT_TIMEOUT {
+        if let Ok(transaction) = client.transaction(&hash).await {
+            if transaction["type"] != "pending_transaction" {
+                return Outcome::Executed {
+                    latency: submitted.elapsed(),
+                    transaction,
+                };
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    stale.store(true, Ordering::SeqCst);
+    Outcome::Rejected
+}
+
+//The call a number drawn below the total weight falls on
+fn pick<T>(calls: &[(T, u32)], mut draw: u32) -> usize {
+    for (i, (_, weight)) in calls.iter().enumerate() {
+        if draw < *weight {
+            return i;
+        }
+        draw -= weight;
+    }
+    calls.len() - 1
+}
+
+#[test]
+fn test_percentile() {
+    let result = BenchResult {
+        latencies: (1..=100).map(Duration::from_millis).collect(),
+        ..Default::default()
+    };
+    assert_eq!(result.percentile(50.0), Duration::from_millis(50));
+    assert_eq!(result.percentile(99.0), Duration::from_millis(99));
+    assert_eq!(result.percentile(100.0), Duration::from_millis(100));
+    assert_eq!(pick(&[((), 1), ((), 3)], 0), 0);
+    assert_eq!(pick(&[((), 1), ((), 3)], 3), 1);
+}
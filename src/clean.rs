@@ -0,0 +1,117 @@
+//! What previous runs leave behind in `.aptest`, removed by `aptest clean`:
+//! the data of runs that are over, logs past their retention and the cache.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::logs::LOG_DIR;
+use crate::process::alive;
+
+/// Where isolated runs keep their files, in a directory named after
+/// the aptest process
+pub const RUNS_DIR: &str = ".aptest/runs";
+
+//What aptest rebuilds on its own when missing. Named accounts, snapshots
+//and the gas baseline are the user's and are kept
+const CACHE: &[&str] = &[
+    ".aptest/state.json",
+    ".aptest/genesis",
+    ".aptest/chain",
+    ".aptest/coverage",
+    ".aptest/suite-state",
+    ".aptest/indexer-testnet",
+];
+
+/// Remove the directories of isolated runs whose aptest process is gone,
+/// returning those removed
+pub fn stale_runs() -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(RUNS_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| owner(path).map_or(true, |pid| !alive(pid)))
+        .filter(|path| std::fs::remove_dir_all(path).is_ok())
+        .collect()
+}
+
+/// The aptest processes of isolated runs still going
+pub fn running() -> Vec<u32> {
+    std::fs::read_dir(RUNS_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| owner(&entry.path()))
+                .filter(|&pid| alive(pid))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Remove the logs last written to more than `retention` ago, apart from
+/// those in `keep`, returning those removed
+pub fn old_logs(retention: Duration, keep: &[PathBuf]) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(LOG_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let now = SystemTime::now();
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .metadata()
+                .and_then(|x| x.modified())
+                .map_or(false, |modified| expired(modified, now, retention))
+        })
+        .map(|entry| entry.path())
+        .filter(|path| !keep.iter().any(|x| same_file(x, path)))
+        .filter(|path| std::fs::remove_file(path).is_ok())
+        .collect()
+}
+
+/// Remove the cache, returning what was there
+pub fn cache() -> Vec<PathBuf> {
+    CACHE
+        .iter()
+        .map(PathBuf::from)
+        .filter(|path| {
+            if path.is_dir() {
+                std::fs::remove_dir_all(path).is_ok()
+            } else {
+                std::fs::remove_file(path).is_ok()
+            }
+        })
+        .collect()
+}
+
+//The aptest process a run directory is named after
+fn owner(path: &Path) -> Option<u32> {
+    path.file_name()?.to_str()?.parse().ok()
+}
+
+//Whether a file last written at `modified` is past its retention
+fn expired(modified: SystemTime, now: SystemTime, retention: Duration) -> bool {
+    now.duration_since(modified)
+        .map_or(false, |age| age > retention)
+}
+
+//Whether two paths name the same file, however they are written
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[test]
+fn test_expired() {
+    let now = SystemTime::now();
+    let day = Duration::from_secs(24 * 3600);
+    assert!(expired(now - 8 * day, now, 7 * day));
+    assert!(!expired(now - day, now, 7 * day));
+    //Written after the clock was read
+    assert!(!expired(now + day, now, 7 * day));
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::ui;
+
+//Major versions of the aptos CLI aptest knows the commands of
+const SUPPORTED_MAJORS: std::ops::RangeInclusive<u32> = 1..=7;
+
+//The aptos CLI to run, when not the one in PATH
+static PROGRAM: OnceLock<PathBuf> = OnceLock::new();
+
+/// Run this aptos CLI binary instead of the one in PATH, for the rest
+/// of the process. Only the first call has an effect.
+pub fn set_program(path: impl Into<PathBuf>) {
+    let _ = PROGRAM.set(path.into());
+}
+
+/// The aptos CLI binary every aptos command runs
+pub fn program() -> &'static Path {
+    PROGRAM
+        .get()
+        .map_or(Path::new("aptos"), |path| path.as_path())
+}
+
+/// Version of the installed aptos CLI, which some command lines
+/// depend on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CliVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl CliVersion {
+    /// The version of the aptos CLI in PATH, asked once per process.
+    /// `None` if it isn't installed or its version can't be read.
+    pub fn detect() -> Option<Self> {
+        static DETECTED: OnceLock<Option<CliVersion>> = OnceLock::new();
+        *DETECTED.get_or_init(|| {
+            let output = std::process::Command::new(program())
+                .arg("--version")
+                .output()
+                .ok()?;
+            Self::parse(&String::from_utf8_lossy(&output.stdout))
+        })
+    }
+
+    //Reads "aptos 4.2.1" as printed by "aptos --version"
+    fn parse(output: &str) -> Option<Self> {
+        let version = output.split_whitespace().nth(1)?;
+        let mut numbers = version.split('.').map(|x| x.parse().ok());
+        Some(CliVersion {
+            major: numbers.next()??,
+            minor: numbers.next()??,
+            patch: numbers.next().flatten().unwrap_or(0),
+        })
+    }
+
+    /// Whether aptest knows the commands of this version
+    pub fn supported(&self) -> bool {
+        SUPPORTED_MAJORS.contains(&self.major)
+    }
+
+    /// Subcommand of "aptos account" funding an account from a faucet,
+    /// renamed after the 0.x releases
+    pub fn fund_subcommand(&self) -> &'static str {
+        if self.major == 0 {
+            "fund"
+        } else {
+            "fund-with-faucet"
+        }
+    }
+
+    /// Flag of "aptos move publish" and "aptos move run" naming the
+    /// node to send the transaction to, which the 0.x releases
+    /// called the node API URL
+    pub fn url_flag(&self) -> &'static str {
+        if self.major == 0 {
+            "--node-api-url"
+        } else {
+            "--url"
+        }
+    }
+}
+
+impl fmt::Display for CliVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The version to build command lines for: the installed one, or
+/// the newest supported one when it is unknown.
+pub fn version() -> CliVersion {
+    CliVersion::detect().unwrap_or(CliVersion {
+        major: *SUPPORTED_MAJORS.end(),
+        minor: 0,
+        patch: 0,
+    })
+}
+
+/// Warn when the installed aptos CLI is outside the supported range,
+/// since its command lines may then not be the ones aptest uses.
+pub fn check_version() {
+    match CliVersion::detect() {
+        Some(version) if !version.supported() => ui::failure(format!(
+            "Warning: aptos CLI {} is not supported, aptest knows versions {}.x to {}.x. Some commands may fail.",
+            version,
+            SUPPORTED_MAJORS.start(),
+            SUPPORTED_MAJORS.end()
+        )),
+        Some(version) => ui::debug(format!("aptos CLI {}", version)),
+        None => ui::debug("Could not tell the version of the aptos CLI"),
+    }
+}
+
+#[test]
+fn test_parse_version() {
+    let version = CliVersion::parse("aptos 4.2.1\n").unwrap();
+    assert_eq!((version.major, version.minor, version.patch), (4, 2, 1));
+    assert!(version.supported());
+    assert_eq!(version.fund_subcommand(), "fund-with-faucet");
+    assert_eq!(
+        CliVersion::parse("aptos 0.3\n").unwrap().url_flag(),
+        "--node-api-url"
+    );
+    assert!(CliVersion::parse("command not found").is_none());
+}
@@ -0,0 +1,42 @@
+//! The clock of a local network, for modules counting time in epochs like
+//! vesting, lockups or auctions. Block timestamps follow the validator's
+//! own clock and can't be moved, but the root key of a testing chain can
+//! end the current epoch right away instead of waiting for it to run out.
+
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::error::{AptestError, OrFail};
+use crate::faucet::{read_mint_key, ROOT_ADDRESS};
+use crate::rest::NodeClient;
+use crate::transaction::{self, EntryFunction};
+use crate::ui;
+
+//Ends the epoch, for the root account only testing chains have
+const FORCE_END_EPOCH: &str = "0x1::aptos_governance::force_end_epoch_test_only";
+
+/// End the current epoch of the network at `node_url`, signing with the
+/// root key at `mint_key_path`, and return the epoch it is now in.
+pub async fn next_epoch(node_url: &str, mint_key_path: &str) -> Result<u64, AptestError> {
+    let key = read_mint_key(mint_key_path)?;
+    let client = NodeClient::new(node_url);
+    let end = EntryFunction::new(FORCE_END_EPOCH)?;
+    transaction::submit(&client, ROOT_ADDRESS, &key, &end)
+        .await
+        .or_fail(AptestError::Request, "Could not end the epoch")?;
+    client.epoch().await
+}
+
+/// End an epoch of the network at `node_url` every `interval` in the
+/// background, until the returned task is aborted. Epochs failing to end
+/// are only reported, the node's own interval still ending them.
+pub fn end_epochs(node_url: String, mint_key_path: String, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(err) = next_epoch(&node_url, &mint_key_path).await {
+                ui::failure(err.to_string());
+            }
+        }
+    })
+}
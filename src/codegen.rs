@@ -0,0 +1,220 @@
+//! Generates TypeScript bindings for published Move modules from the
+//! ABI the node serves for them: one file per module with a payload
+//! builder for each entry and view function, typed after its parameters.
+
+use serde_json::Value;
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::{AptestError, OrFail};
+use crate::rest::NodeClient;
+
+/// Where the bindings are written, relative to the project
+pub const OUT_DIR: &str = "tests/generated";
+
+//Words TypeScript won't take as a function name
+const RESERVED: [&str; 12] = [
+    "delete", "new", "function", "default", "import", "export", "return", "class", "const", "var",
+    "switch", "in",
+];
+
+/// Write the bindings of the modules, given as `address::name`, to
+/// `out_dir`, along with an index re-exporting them. Returns the files
+/// written.
+pub async fn generate(
+    node_url: &str,
+    modules: &[String],
+    out_dir: &Path,
+) -> Result<Vec<PathBuf>, AptestError> {
+    std::fs::create_dir_all(out_dir).or_fail(
+        AptestError::Environment,
+        &format!("Could not create directory {}", out_dir.display()),
+    )?;
+    let client = NodeClient::new(node_url);
+    let mut files = Vec::new();
+    let mut index = String::from("// Generated by aptest, do not edit.\n");
+    for module in modules {
+        let (address, name) = module.rsplit_once("::").ok_or_else(|| {
+            AptestError::Environment(format!("{} is not of the form address::name", module))
+        })?;
+        let abi = client
+            .get(&format!("/accounts/{}/module/{}", address, name))
+            .await?;
+        let file = out_dir.join(format!("{}.ts", name));
+        write(&file, &bindings(&abi["abi"]))?;
+        let _ = writeln!(index, "export * as {} from \"./{}\";", name, name);
+        files.push(file);
+    }
+    let file = out_dir.join("index.ts");
+    write(&file, &index)?;
+    files.push(file);
+    Ok(files)
+}
+
+//The TypeScript module for the ABI of a Move module
+fn bindings(abi: &Value) -> String {
+    let module = format!(
+        "{}::{}",
+        abi["address"].as_str().unwrap_or_default(),
+        abi["name"].as_str().unwrap_or_default()
+    );
+    let mut out = format!(
+        "// Generated by aptest from the ABI of {}, do not edit.\n\nexport const MODULE = \"{}\";\n",
+        module, module
+    );
+    for function in abi["exposed_functions"].as_array().into_iter().flatten() {
+        let (entry, view) = (
+            function["is_entry"].as_bool() == Some(true),
+            function["is_view"].as_bool() == Some(true),
+        );
+        if !entry && !view {
+            continue;
+        }
+        let name = function["name"].as_str().unwrap_or_default();
+        let strings = |key: &str| -> Vec<String> {
+            function[key]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|x| x.as_str().map(str::to_string))
+                .collect()
+        };
+        //The signers are whoever sends the transaction
+        let params: Vec<String> = strings("params")
+            .into_iter()
+            .filter(|x| x != "&signer" && x != "signer")
+            .collect();
+        let type_params = function["generic_type_params"]
+            .as_array()
+            .map_or(0, |x| x.len());
+
+        let mut args = Vec::new();
+        if type_params > 0 {
+            args.push(format!(
+                "typeArguments: [{}]",
+                vec!["string"; type_params].join(", ")
+            ));
+        }
+        for (i, param) in params.iter().enumerate() {
+            args.push(format!("arg{}: {}", i, input_type(param)));
+        }
+        let values: Vec<String> = (0..params.len()).map(|i| format!("arg{}", i)).collect();
+        let kind = if view { "view" } else { "entry" };
+
+        out.push('\n');
+        if view {
+            let returns: Vec<String> = strings("return").iter().map(|x| output_type(x)).collect();
+            let _ = writeln!(
+                out,
+                "export type {}Result = [{}];\n",
+                pascal_case(name),
+                returns.join(", ")
+            );
+        }
+        let _ = writeln!(out, "/** {}::{}, a {} function */", module, name, kind);
+        let _ = writeln!(
+            out,
+            "export function {}({}) {{",
+            function_name(name),
+            args.join(", ")
+        );
+        let _ = writeln!(out, "  return {{");
+        let _ = writeln!(out, "    function: \"{}::{}\" as const,", module, name);
+        if type_params > 0 {
+            let _ = writeln!(out, "    typeArguments,");
+        } else {
+            let _ = writeln!(out, "    typeArguments: [] as string[],");
+        }
+        let _ = writeln!(out, "    functionArguments: [{}],", values.join(", "));
+        let _ = writeln!(out, "  }};\n}}");
+    }
+    out
+}
+
+//What a parameter of a Move type can be given as
+fn input_type(ty: &str) -> String {
+    match ty {
+        "bool" => "boolean".to_string(),
+        "u8" | "u16" | "u32" => "number".to_string(),
+        "u64" | "u128" | "u256" => "number | bigint | string".to_string(),
+        "address" | "0x1::string::String" => "string".to_string(),
+        "vector<u8>" => "Uint8Array | string".to_string(),
+        _ => match ty.strip_prefix("vector<").and_then(|x| x.strip_suffix('>')) {
+            Some(inner) => format!("Array<{}>", input_type(inner)),
+            //Objects are passed by address
+            None if ty.starts_with("0x1::object::Object<") => "string".to_string(),
+            None => "unknown".to_string(),
+        },
+    }
+}
+
+//What a view function returns for a Move type, in the node's JSON
+fn output_type(ty: &str) -> String {
+    match ty {
+        "bool" => "boolean".to_string(),
+        "u8" | "u16" | "u32" => "number".to_string(),
+        "u64" | "u128" | "u256" | "address" | "0x1::string::String" | "vector<u8>" => {
+            "string".to_string()
+        }
+        _ => match ty.strip_prefix("vector<").and_then(|x| x.strip_suffix('>')) {
+            Some(inner) => format!("Array<{}>", output_type(inner)),
+            None if ty.starts_with("0x1::object::Object<") => "{ inner: string }".to_string(),
+            None => "unknown".to_string(),
+        },
+    }
+}
+
+//A Move function name usable in TypeScript
+fn function_name(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+//get_price as GetPrice
+fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), AptestError> {
+    std::fs::write(path, contents).or_fail(
+        AptestError::Environment,
+        &format!("Could not write {}", path.display()),
+    )
+}
+
+#[test]
+fn test_bindings() {
+    let abi = serde_json::json!({
+        "address": "0xcafe",
+        "name": "market",
+        "exposed_functions": [
+            {"name": "list", "is_entry": true, "is_view": false, "generic_type_params": [],
+             "params": ["&signer", "u64", "vector<address>"], "return": []},
+            {"name": "get_price", "is_entry": false, "is_view": true, "generic_type_params": [{}],
+             "params": ["u64"], "return": ["u64"]},
+            {"name": "helper", "is_entry": false, "is_view": false, "generic_type_params": [],
+             "params": [], "return": []}
+        ]
+    });
+    let out = bindings(&abi);
+    assert!(
+        out.contains("export function list(arg0: number | bigint | string, arg1: Array<string>) {")
+    );
+    assert!(out.contains("export type GetPriceResult = [string];"));
+    assert!(out.contains(
+        "export function get_price(typeArguments: [string], arg0: number | bigint | string) {"
+    ));
+    assert!(!out.contains("helper"));
+}
@@ -0,0 +1,676 @@
+//! Project settings read from `aptest.toml` at the root of the project.
+//!
+//! ```toml
+//! [project]
+//! name = "counter"
+//! template = "ts-mocha"
+//! package_manager = "npm"
+//!
+//! [workspace]
+//! packages = ["core", "periphery"]
+//!
+//! [addresses]
+//! counter = "default"
+//!
+//! [[hooks.post_publish]]
+//! function = "0x42::counter::initialize"
+//! args = ["u64:10"]
+//!
+//! [[hooks.post_test]]
+//! command = "./scripts/notify.sh"
+//!
+//! [[fixtures]]
+//! name = "pool"
+//! function = "0x42::market::create_pool"
+//! args = ["u64:1000"]
+//!
+//! [test]
+//! command = "pytest tests"
+//! suites = ["tests/token/*.py", "tests/market/*.py"]
+//! isolation = "snapshot"
+//! retry_args = ["--onlyFailures"]
+//!
+//! [suites.trading]
+//! files = "tests/trading/*.ts"
+//!
+//! [suites.admin]
+//! command = "npm run test:admin"
+//!
+//! [node]
+//! extra_args = ["--lazy-mode"]
+//! config = "node-override.yaml"
+//! genesis_framework = "framework/head.mrb"
+//!
+//! [aptos]
+//! extra_args = ["--bytecode-version", "6"]
+//!
+//! [compile]
+//! dev = true
+//! skip_fetch_latest_git_deps = true
+//! compiler_version = "v2"
+//! language_version = "2.0"
+//! save_metadata = true
+//!
+//! [publish]
+//! max_gas = 2000000
+//! gas_unit_price = 100
+//! included_artifacts = "none"
+//!
+//! [prove]
+//! enabled = true
+//! extra_args = ["--vc-timeout", "60"]
+//!
+//! [gas]
+//! max_increase = 5.0
+//!
+//! [[bench.calls]]
+//! function = "0x42::counter::increment"
+//! weight = 3
+//!
+//! [chaos.outage]
+//! error_rate = 0.5
+//!
+//! [matrix]
+//! node_bin = ["bin/aptos-node-1.10", "bin/aptos-node-1.11"]
+//! compiler_version = ["v1", "v2"]
+//!
+//! [profile.ci]
+//! start_delay = 120
+//! quiet = true
+//! frozen = true
+//! skip = ["prove"]
+//!
+//! [profile.ci.test]
+//! command = "npm run test:ci"
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::accounts::DEFAULT_FEE_PAYER_AMOUNT;
+use crate::error::{AptestError, OrFail};
+use crate::init::{Example, PackageManager, Template};
+use crate::proxy::Chaos;
+use crate::publish::IncludedArtifacts;
+
+/// Name of the project config file
+pub const CONFIG_FILE: &str = "aptest.toml";
+
+/// Contents of `aptest.toml`, every section being optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub project: Option<Project>,
+    pub workspace: Option<Workspace>,
+    /// Named addresses left as "_" in Move.toml, each set to an address,
+    /// an account created with "aptest account new" or an aptos profile
+    #[serde(default)]
+    pub addresses: BTreeMap<String, String>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// Transactions seeding the state the tests share once published
+    #[serde(default)]
+    pub fixtures: Vec<Seed>,
+    #[serde(default)]
+    pub diff: Diff,
+    /// Account paying for sponsored transactions, created when set
+    pub fee_payer: Option<FeePayer>,
+    #[serde(default)]
+    pub test: Test,
+    /// Named test suites, run like those of [test] and picked with --suite
+    #[serde(default)]
+    pub suites: BTreeMap<String, Suite>,
+    #[serde(default)]
+    pub node: Node,
+    #[serde(default)]
+    pub faucet: Tool,
+    #[serde(default)]
+    pub aptos: Tool,
+    #[serde(default)]
+    pub compile: Compile,
+    #[serde(default)]
+    pub publish: Publish,
+    #[serde(default)]
+    pub prove: Prove,
+    #[serde(default)]
+    pub gas: Gas,
+    #[serde(default)]
+    pub bench: Bench,
+    /// Chaos profiles for --chaos, by name, on top of the built-in ones
+    #[serde(default)]
+    pub chaos: BTreeMap<String, Chaos>,
+    #[serde(default)]
+    pub matrix: Matrix,
+    /// Settings of "aptest run" picked by name with --env
+    #[serde(default)]
+    pub profile: BTreeMap<String, Profile>,
+}
+
+/// Settings of "aptest run" for an environment like CI, each named after
+/// the option it stands for. Options given on the command line or through
+/// APTEST_* variables take precedence
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub start_delay: Option<u64>,
+    pub fund_timeout: Option<u64>,
+    pub publish_timeout: Option<u64>,
+    pub test_timeout: Option<u64>,
+    pub retries: Option<u32>,
+    pub retry_delay: Option<u64>,
+    pub test_retries: Option<u32>,
+    #[serde(default)]
+    pub quiet: bool,
+    #[serde(default)]
+    pub verbose: bool,
+    /// Runs on free ports with files of its own, as --isolate does
+    #[serde(default)]
+    pub isolate: bool,
+    #[serde(default)]
+    pub no_install: bool,
+    #[serde(default)]
+    pub frozen: bool,
+    pub deps_cache: Option<PathBuf>,
+    /// Phases skipped, as with --skip
+    #[serde(default)]
+    pub skip: Vec<String>,
+    #[serde(default)]
+    pub report: Vec<PathBuf>,
+    /// Laid over the [test] section of the project
+    #[serde(default)]
+    pub test: Test,
+}
+
+/// How the local validator is started
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Node {
+    /// Arguments appended to its command line
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    /// Chain id of the node's genesis, 4 (TESTING) unless set. The faucet
+    /// signs for it and the node is checked to run it
+    pub chain_id: Option<u8>,
+    /// Node config file overriding the test defaults
+    pub config: Option<PathBuf>,
+    /// Framework release bundle (.mrb) genesis is built from
+    pub genesis_framework: Option<PathBuf>,
+    /// Seconds after which aptest ends every epoch, however long the
+    /// node's own epochs are
+    pub epoch_interval: Option<u64>,
+}
+
+/// Settings of one of the other programs aptest runs: aptos-faucet,
+/// or the aptos CLI compiling and publishing
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tool {
+    /// Arguments appended to its command line
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+}
+
+/// How the Move packages are built, as --dev, --skip-fetch-latest-git-deps,
+/// --compiler-version, --language-version and --save-metadata set it.
+/// The options take precedence
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Compile {
+    #[serde(default)]
+    pub dev: bool,
+    #[serde(default)]
+    pub skip_fetch_latest_git_deps: bool,
+    pub compiler_version: Option<String>,
+    pub language_version: Option<String>,
+    /// Only applies to "aptos move compile"
+    #[serde(default)]
+    pub save_metadata: bool,
+}
+
+/// Gas settings of "aptos move publish", as --max-gas, --gas-unit-price
+/// and --included-artifacts set them. The options take precedence
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Publish {
+    pub max_gas: Option<u64>,
+    pub gas_unit_price: Option<u64>,
+    pub included_artifacts: Option<IncludedArtifacts>,
+}
+
+/// Running the Move prover after compilation, as --prove does
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Prove {
+    /// Proves on every run, without --prove
+    #[serde(default)]
+    pub enabled: bool,
+    /// Arguments appended to "aptos move prove", like a timeout or a filter
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+}
+
+/// How --gas-check compares the gas used with the baseline
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Gas {
+    /// Percentage a function's gas may grow by, 10 unless set
+    pub max_increase: Option<f64>,
+    /// Only warns about regressions instead of failing the run
+    #[serde(default)]
+    pub warn_only: bool,
+}
+
+/// The transactions "aptest bench" sends
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bench {
+    #[serde(default)]
+    pub calls: Vec<BenchCall>,
+}
+
+/// An entry function call of the benchmark's mix
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BenchCall {
+    /// Entry function, as `address::module::function`
+    pub function: String,
+    /// Arguments in the aptos CLI's `type:value` form, like `u64:10`
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub type_args: Vec<String>,
+    /// How often it is sent relative to the other calls, 1 unless set
+    pub weight: Option<u32>,
+}
+
+/// Settings "aptest matrix" runs the pipeline with every combination of,
+/// each named after the option it stands for
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Matrix {
+    #[serde(default)]
+    pub node_bin: Vec<PathBuf>,
+    #[serde(default)]
+    pub compiler_version: Vec<String>,
+    #[serde(default)]
+    pub language_version: Vec<String>,
+    #[serde(default)]
+    pub included_artifacts: Vec<IncludedArtifacts>,
+}
+
+/// Several Move packages compiled and published together
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workspace {
+    /// Package directories, relative to the project root
+    pub packages: Vec<PathBuf>,
+}
+
+/// How the project was set up, as answered to "aptest init"
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Project {
+    pub name: String,
+    pub template: Template,
+    pub package_manager: PackageManager,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub example: Option<Example>,
+}
+
+/// How the end to end tests are run
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Test {
+    /// Shell command running the tests, "npm run test" unless set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Test file patterns, each run as a suite against a chain of its own
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suites: Vec<String>,
+    /// How each suite gets its own chain, restarting the node unless set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isolation: Option<Isolation>,
+    /// Arguments appended to the test command when --test-retries runs it
+    /// again, like jest's --onlyFailures
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retry_args: Vec<String>,
+}
+
+/// A named test suite, the test command run on some files or a command
+/// of its own. At least one of `files` and `command` is set
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Suite {
+    /// Test file pattern, given to the test command as the patterns of
+    /// [test] suites are
+    pub files: Option<String>,
+    /// Shell command running the suite instead of the test command
+    pub command: Option<String>,
+}
+
+/// How test suites are kept from seeing each other's chain state
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Isolation {
+    /// A fresh node for every suite, funded and published to again
+    Restart,
+    /// Funding and publishing once, then rolling the chain back to
+    /// a snapshot taken right after before every suite
+    Snapshot,
+}
+
+/// Steps run at points of the pipeline
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// Run before compiling, while there is no node yet
+    #[serde(default)]
+    pub pre_compile: Vec<Call>,
+    /// Run after a successful publish
+    #[serde(default)]
+    pub post_publish: Vec<Call>,
+    /// Run right before the tests start
+    #[serde(default)]
+    pub pre_test: Vec<Call>,
+    /// Run once the tests finished, whether they passed or not
+    #[serde(default)]
+    pub post_test: Vec<Call>,
+    /// Run when the run fails at any step
+    #[serde(default)]
+    pub on_failure: Vec<Call>,
+}
+
+/// A shell command, or an entry function call or Move script sent from
+/// a profile with the aptos CLI. Exactly one of `command`, `function`
+/// and `script` is set.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Call {
+    /// Shell command, run with "sh -c" or "cmd /C" on Windows
+    pub command: Option<String>,
+    /// Entry function, as `address::module::function`
+    pub function: Option<String>,
+    /// Move script source, run with "aptos move run-script"
+    pub script: Option<PathBuf>,
+    /// Arguments in the aptos CLI's `type:value` form, like `u64:10`
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub type_args: Vec<String>,
+    /// Profile sending the transaction, "default" unless set
+    pub profile: Option<String>,
+}
+
+/// Settings of the fee payer account of `--fee-payer`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeePayer {
+    /// Octas it is funded with, 10 APT unless set
+    pub balance: Option<u64>,
+}
+
+impl FeePayer {
+    /// Octas the fee payer is funded with
+    pub fn amount(&self) -> u64 {
+        self.balance.unwrap_or(DEFAULT_FEE_PAYER_AMOUNT)
+    }
+}
+
+/// Settings of `--state-diff`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Diff {
+    /// Accounts whose resources are captured, as addresses, account names
+    /// or aptos profiles, instead of the publishing and test accounts
+    #[serde(default)]
+    pub accounts: Vec<String>,
+}
+
+/// An entry function call or Move script seeding on-chain state for the
+/// tests, like a pool or minted tokens. Exactly one of `function` and
+/// `script` is set.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Seed {
+    /// Name the result is logged under, the function or script unless set
+    pub name: Option<String>,
+    /// Entry function, as `address::module::function`
+    pub function: Option<String>,
+    /// Move script source, run with "aptos move run-script"
+    pub script: Option<PathBuf>,
+    /// Arguments in the aptos CLI's `type:value` form, like `u64:10`
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub type_args: Vec<String>,
+    /// Profile sending the transaction, "default" unless set
+    pub profile: Option<String>,
+}
+
+impl Config {
+    /// Read `aptest.toml` from the current directory,
+    /// or the default settings if there is none.
+    pub fn load() -> Result<Self, AptestError> {
+        let contents = match std::fs::read_to_string(CONFIG_FILE) {
+            Ok(contents) => contents,
+            Err(_) => return Ok(Config::default()),
+        };
+        toml::from_str(&contents).or_fail(
+            AptestError::Environment,
+            &format!("Could not parse {}", CONFIG_FILE),
+        )
+    }
+
+    /// The test suites picked by name with --suite, or all of them: the
+    /// patterns of the [test] section, named after themselves, then the
+    /// named suites
+    pub fn pick_suites(&self, names: &[String]) -> Result<Vec<(String, Suite)>, AptestError> {
+        let patterns = self.test.suites.iter().map(|pattern| {
+            let suite = Suite {
+                files: Some(pattern.clone()),
+                command: None,
+            };
+            (pattern.clone(), suite)
+        });
+        let named = self
+            .suites
+            .iter()
+            .map(|(name, suite)| (name.clone(), suite.clone()));
+        let suites: Vec<_> = patterns.chain(named).collect();
+        for (name, suite) in &suites {
+            if suite.files.is_none() && suite.command.is_none() {
+                return Err(AptestError::Environment(format!(
+                    "Suite {} of {} has neither files nor a command",
+                    name, CONFIG_FILE
+                )));
+            }
+        }
+        if names.is_empty() {
+            return Ok(suites);
+        }
+        let mut picked = Vec::new();
+        for name in names {
+            match suites.iter().find(|x| &x.0 == name) {
+                Some(suite) => picked.push(suite.clone()),
+                None if suites.is_empty() => {
+                    return Err(AptestError::Environment(format!(
+                        "No suite {} in {}, which has no test suites",
+                        name, CONFIG_FILE
+                    )))
+                }
+                None => {
+                    let names: Vec<_> = suites.iter().map(|x| x.0.as_str()).collect();
+                    return Err(AptestError::Environment(format!(
+                        "No suite {} in {}, pick one of {}",
+                        name,
+                        CONFIG_FILE,
+                        names.join(", ")
+                    )));
+                }
+            }
+        }
+        Ok(picked)
+    }
+
+    /// Take the profile picked with --env, laying the settings of its
+    /// [test] section over those of the project
+    pub fn take_profile(&mut self, name: &str) -> Result<Profile, AptestError> {
+        let mut profile = match self.profile.remove(name) {
+            Some(profile) => profile,
+            None if self.profile.is_empty() => {
+                return Err(AptestError::Environment(format!(
+                    "No profile {} in {}, which has no [profile] sections",
+                    name, CONFIG_FILE
+                )))
+            }
+            None => {
+                let names: Vec<_> = self.profile.keys().map(String::as_str).collect();
+                return Err(AptestError::Environment(format!(
+                    "No profile {} in {}, pick one of {}",
+                    name,
+                    CONFIG_FILE,
+                    names.join(", ")
+                )));
+            }
+        };
+        let test = std::mem::take(&mut profile.test);
+        if test.command.is_some() {
+            self.test.command = test.command;
+        }
+        if !test.suites.is_empty() {
+            self.test.suites = test.suites;
+        }
+        if test.isolation.is_some() {
+            self.test.isolation = test.isolation;
+        }
+        if !test.retry_args.is_empty() {
+            self.test.retry_args = test.retry_args;
+        }
+        Ok(profile)
+    }
+}
+
+#[test]
+fn test_take_profile() {
+    let mut config: Config = toml::from_str(
+        r#"
+        [test]
+        command = "npm run test"
+        suites = ["tests/*.ts"]
+
+        [profile.ci]
+        start_delay = 120
+        quiet = true
+
+        [profile.ci.test]
+        command = "npm run test:ci"
+        "#,
+    )
+    .unwrap();
+    assert!(config.take_profile("dev").is_err());
+    let profile = config.take_profile("ci").unwrap();
+    assert_eq!(profile.start_delay, Some(120));
+    assert!(profile.quiet);
+    assert_eq!(config.test.command.as_deref(), Some("npm run test:ci"));
+    assert_eq!(config.test.suites, ["tests/*.ts"]);
+}
+
+#[test]
+fn test_pick_suites() {
+    let config: Config = toml::from_str(
+        r#"
+        [test]
+        suites = ["tests/token/*.ts"]
+
+        [suites.trading]
+        files = "tests/trading/*.ts"
+
+        [suites.admin]
+        command = "npm run test:admin"
+        "#,
+    )
+    .unwrap();
+    let names = |suites: Vec<(String, Suite)>| suites.into_iter().map(|x| x.0).collect::<Vec<_>>();
+    assert_eq!(
+        names(config.pick_suites(&[]).unwrap()),
+        ["tests/token/*.ts", "admin", "trading"]
+    );
+    let picked = ["trading".to_string(), "admin".to_string()];
+    assert_eq!(
+        names(config.pick_suites(&picked).unwrap()),
+        ["trading", "admin"]
+    );
+    assert!(config.pick_suites(&["market".to_string()]).is_err());
+    assert!(toml::from_str::<Config>("[suites.empty]")
+        .unwrap()
+        .pick_suites(&[])
+        .is_err());
+}
+
+#[test]
+fn test_compile() {
+    let config: Config = toml::from_str(
+        r#"
+        [compile]
+        skip_fetch_latest_git_deps = true
+        compiler_version = "v2"
+        "#,
+    )
+    .unwrap();
+    assert!(config.compile.skip_fetch_latest_git_deps);
+    assert!(!config.compile.dev);
+    assert_eq!(config.compile.compiler_version.as_deref(), Some("v2"));
+    assert_eq!(config.compile.language_version, None);
+}
+
+#[test]
+fn test_publish() {
+    let config: Config = toml::from_str(
+        r#"
+        [publish]
+        max_gas = 2000000
+        included_artifacts = "none"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.publish.max_gas, Some(2000000));
+    assert_eq!(config.publish.gas_unit_price, None);
+    assert_eq!(
+        config.publish.included_artifacts,
+        Some(IncludedArtifacts::None)
+    );
+    assert!(toml::from_str::<Config>("[publish]\nincluded_artifacts = \"some\"").is_err());
+}
+
+#[test]
+fn test_fixtures() {
+    let config: Config = toml::from_str(
+        r#"
+        [[fixtures]]
+        name = "pool"
+        function = "0x42::market::create_pool"
+        args = ["u64:1000"]
+
+        [[fixtures]]
+        script = "scripts/mint.move"
+        profile = "minter"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.fixtures.len(), 2);
+    assert_eq!(config.fixtures[0].name.as_deref(), Some("pool"));
+    assert_eq!(config.fixtures[0].args, ["u64:1000"]);
+    assert_eq!(config.fixtures[1].profile.as_deref(), Some("minter"));
+    assert!(toml::from_str::<Config>("[[fixtures]]\ncommand = \"./seed.sh\"").is_err());
+}
+
+#[test]
+fn test_fee_payer() {
+    assert_eq!(FeePayer::default().amount(), DEFAULT_FEE_PAYER_AMOUNT);
+    let fee_payer = FeePayer {
+        balance: Some(5000000000),
+    };
+    assert_eq!(fee_payer.amount(), 5000000000);
+}
@@ -0,0 +1,235 @@
+//! Coverage of the Move unit tests, read from the summary of
+//! `aptos move coverage summary --summarize-functions` and written
+//! out as an lcov report for CI tools.
+
+use serde::Serialize;
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::{AptestError, OrFail};
+use crate::state::collect_files;
+use crate::ui;
+
+/// Where the lcov report is written, relative to the project
+pub const LCOV_PATH: &str = ".aptest/coverage/lcov.info";
+
+/// Bytecode instructions of a function run by the unit tests
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FunctionCoverage {
+    pub name: String,
+    pub total: u64,
+    pub covered: u64,
+}
+
+/// Coverage of the functions of a module
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ModuleCoverage {
+    /// As `address::name`
+    pub module: String,
+    /// Source file the module was found in, if any
+    pub source: Option<PathBuf>,
+    pub functions: Vec<FunctionCoverage>,
+}
+
+impl ModuleCoverage {
+    pub fn total(&self) -> u64 {
+        self.functions.iter().map(|x| x.total).sum()
+    }
+
+    pub fn covered(&self) -> u64 {
+        self.functions.iter().map(|x| x.covered).sum()
+    }
+
+    pub fn percent(&self) -> f64 {
+        percent(self.covered(), self.total())
+    }
+}
+
+/// Coverage of the modules of the packages
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Coverage {
+    pub modules: Vec<ModuleCoverage>,
+}
+
+impl Coverage {
+    /// Read the modules out of a coverage summary, looking up their
+    /// sources in the packages
+    pub fn parse(summary: &str, packages: &[Option<&PathBuf>]) -> Self {
+        let mut modules: Vec<ModuleCoverage> = Vec::new();
+        for line in summary.lines().map(str::trim) {
+            if let Some(module) = line.strip_prefix("Module ") {
+                modules.push(ModuleCoverage {
+                    module: short_address(module.trim()),
+                    source: None,
+                    functions: Vec::new(),
+                });
+                continue;
+            }
+            let module = match modules.last_mut() {
+                Some(module) => module,
+                None => continue,
+            };
+            let function = line.trim_start_matches("native").trim_start();
+            if let Some(name) = function.strip_prefix("fun ") {
+                module.functions.push(FunctionCoverage {
+                    name: name.trim().to_string(),
+                    total: 0,
+                    covered: 0,
+                });
+            } else if let Some(function) = module.functions.last_mut() {
+                if let Some(total) = line.strip_prefix("total:") {
+                    function.total = total.trim().parse().unwrap_or(0);
+                } else if let Some(covered) = line.strip_prefix("covered:") {
+                    function.covered = covered.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+        let sources = module_sources(packages);
+        for module in &mut modules {
+            let name = module.module.rsplit("::").next().unwrap_or_default();
+            module.source = sources
+                .iter()
+                .find(|(x, _)| x == name)
+                .map(|(_, path)| path.clone());
+        }
+        Coverage { modules }
+    }
+
+    /// Add the modules of another package
+    pub fn extend(&mut self, other: Coverage) {
+        self.modules.extend(other.modules);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    /// Coverage of all the modules together
+    pub fn percent(&self) -> f64 {
+        let total = self.modules.iter().map(ModuleCoverage::total).sum();
+        let covered = self.modules.iter().map(ModuleCoverage::covered).sum();
+        percent(covered, total)
+    }
+
+    /// Print the coverage of each module
+    pub fn print_summary(&self) {
+        if self.modules.is_empty() {
+            return;
+        }
+        ui::info("Move coverage");
+        for module in &self.modules {
+            ui::print(format!(
+                "  {:<40} {:>6.2}%",
+                module.module,
+                module.percent()
+            ));
+        }
+        ui::print(format!("  {:<40} {:>6.2}%\n", "total", self.percent()));
+    }
+
+    /// The report in lcov's format: a record per module with its
+    /// functions, counting bytecode instructions as lines
+    pub fn lcov(&self) -> String {
+        let mut out = String::new();
+        for module in &self.modules {
+            let _ = writeln!(out, "TN:");
+            let source = match &module.source {
+                Some(source) => source.display().to_string(),
+                None => module.module.clone(),
+            };
+            let _ = writeln!(out, "SF:{}", source);
+            for function in &module.functions {
+                let _ = writeln!(out, "FN:0,{}", function.name);
+            }
+            for function in &module.functions {
+                let hit = u64::from(function.covered > 0);
+                let _ = writeln!(out, "FNDA:{},{}", hit, function.name);
+            }
+            let hit = module.functions.iter().filter(|x| x.covered > 0).count();
+            let _ = writeln!(out, "FNF:{}", module.functions.len());
+            let _ = writeln!(out, "FNH:{}", hit);
+            let _ = writeln!(out, "LF:{}", module.total());
+            let _ = writeln!(out, "LH:{}", module.covered());
+            let _ = writeln!(out, "end_of_record");
+        }
+        out
+    }
+
+    /// Write the lcov report to `path`
+    pub fn write_lcov(&self, path: &Path) -> Result<(), AptestError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).or_fail(
+                AptestError::Environment,
+                &format!("Could not create directory {}", dir.display()),
+            )?;
+        }
+        std::fs::write(path, self.lcov()).or_fail(
+            AptestError::Environment,
+            &format!("Could not write the coverage report to {}", path.display()),
+        )
+    }
+}
+
+//The modules declared in the sources of the packages, with their file
+fn module_sources(packages: &[Option<&PathBuf>]) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    for package in packages {
+        let dir = package.map_or_else(|| PathBuf::from("."), |x| x.to_path_buf());
+        collect_files(&dir.join("sources"), &mut files);
+    }
+    let mut modules = Vec::new();
+    for file in files {
+        let source = match std::fs::read_to_string(&file) {
+            Ok(source) => source,
+            Err(_) => continue,
+        };
+        for line in source.lines().map(str::trim) {
+            if let Some(declaration) = line.strip_prefix("module ") {
+                let id = declaration
+                    .split(|c: char| c == '{' || c == ';' || c.is_whitespace())
+                    .next()
+                    .unwrap_or_default();
+                let name = id.rsplit("::").next().unwrap_or(id);
+                modules.push((name.to_string(), file.clone()));
+            }
+        }
+    }
+    modules
+}
+
+//0x42::counter rather than the 64 digits of the summary
+fn short_address(module: &str) -> String {
+    match module.split_once("::") {
+        Some((address, name)) => {
+            let address = address.trim_start_matches("0x").trim_start_matches('0');
+            format!(
+                "0x{}::{}",
+                if address.is_empty() { "0" } else { address },
+                name
+            )
+        }
+        None => module.to_string(),
+    }
+}
+
+fn percent(covered: u64, total: u64) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        covered as f64 * 100.0 / total as f64
+    }
+}
+
+#[test]
+fn test_parse() {
+    let summary = "+-------------------------+\n| Move Coverage Summary   |\n+-------------------------+\nModule 0000000000000000000000000000000000000000000000000000000000000042::counter\n\t fun increment\n\t\t total: 8\n\t\t covered: 8\n\t\t % coverage: 100.00\n\t fun reset\n\t\t total: 4\n\t\t covered: 0\n\t\t % coverage: 0.00\n>>> % Module coverage: 66.67\n";
+    let coverage = Coverage::parse(summary, &[]);
+    assert_eq!(coverage.modules.len(), 1);
+    let module = &coverage.modules[0];
+    assert_eq!(module.module, "0x42::counter");
+    assert_eq!((module.total(), module.covered()), (12, 8));
+    assert!((coverage.percent() - 66.67).abs() < 0.01);
+    let lcov = coverage.lcov();
+    assert!(lcov.contains("FNDA:1,increment\nFNDA:0,reset\nFNF:2\nFNH:1\nLF:12\nLH:8\n"));
+}
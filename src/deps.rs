@@ -0,0 +1,247 @@
+//! The JS dependencies of the test harness, installed before the tests
+//! when they are missing or older than the manifest or lockfile, which
+//! would otherwise fail the tests in confusing ways. Installs can be
+//! cached by the hash of the lockfile, for CI machines starting afresh.
+
+use sha3::{Digest, Sha3_256};
+use tokio::process::Command;
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::error::{AptestError, OrFail};
+use crate::init::PackageManager;
+use crate::ui;
+
+/// Where installed dependencies are cached unless told otherwise
+pub const CACHE_DIR: &str = ".aptest/deps-cache";
+
+//Touched once aptest installed the dependencies, since package managers
+//don't all update node_modules when nothing changed
+const INSTALLED_MARKER: &str = "node_modules/.aptest-installed";
+
+/// The package manager of the harness in `dir`: the one whose lockfile is
+/// there, or else `recorded` by "aptest init", or else the one detected
+pub fn package_manager(dir: &Path, recorded: Option<PackageManager>) -> PackageManager {
+    [
+        PackageManager::Npm,
+        PackageManager::Pnpm,
+        PackageManager::Yarn,
+        PackageManager::Bun,
+    ]
+    .into_iter()
+    .find(|x| lockfile(dir, *x).is_some())
+    .or(recorded)
+    .unwrap_or_else(PackageManager::detect)
+}
+
+/// Whether the harness in `dir` has a package.json whose dependencies
+/// were never installed, or changed since they were
+pub fn outdated(dir: &Path, package_manager: PackageManager) -> bool {
+    let manifest = dir.join("package.json");
+    if !manifest.is_file() {
+        return false;
+    }
+    let installed = match modified(&dir.join(INSTALLED_MARKER))
+        .or_else(|| modified(&dir.join("node_modules")))
+    {
+        Some(installed) => installed,
+        None => return true,
+    };
+    std::iter::once(manifest)
+        .chain(lockfile(dir, package_manager))
+        .filter_map(|x| modified(&x))
+        .any(|x| x > installed)
+}
+
+/// Install the dependencies of the harness in `dir`. Frozen installs take
+/// the lockfile as is, failing when it is out of date, as CI wants
+pub async fn install(
+    dir: &Path,
+    package_manager: PackageManager,
+    frozen: bool,
+) -> Result<(), AptestError> {
+    ui::info("Installing JS dependencies...");
+    let program = package_manager.program();
+    let mut command = Command::new(&program);
+    command
+        .args(package_manager.install_args(frozen))
+        .current_dir(dir);
+    ui::command(&command);
+    let status = command
+        .stdout(ui::child_stdout())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .or_fail(
+            AptestError::Environment,
+            &format!("Couldn't find {} command. Is it installed ?", program),
+        )?;
+    if !status.success() {
+        return Err(AptestError::Environment(format!(
+            "Installing the JS dependencies with {} failed",
+            package_manager.name()
+        )));
+    }
+    //Losing the marker only means installing again next time
+    let _ = std::fs::write(dir.join(INSTALLED_MARKER), "");
+    Ok(())
+}
+
+/// The key of the installed dependencies in the cache: the hash of the
+/// lockfile, the package manager and the platform, as native modules are
+/// built for it. Dependencies without a lockfile aren't pinned and have none
+pub fn cache_key(dir: &Path, package_manager: PackageManager) -> Option<String> {
+    let contents = std::fs::read(lockfile(dir, package_manager)?).ok()?;
+    let mut hasher = Sha3_256::new();
+    for part in [
+        package_manager.name(),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.update(&contents);
+    Some(hex::encode(hasher.finalize()))
+}
+
+/// Restore the dependencies cached under `key` into the harness in `dir`,
+/// returning whether there were any
+pub fn restore(dir: &Path, cache_dir: &Path, key: &str) -> Result<bool, AptestError> {
+    let cached = cache_dir.join(key);
+    if !cached.is_dir() {
+        return Ok(false);
+    }
+    ui::info("Restoring JS dependencies from the cache...");
+    let node_modules = dir.join("node_modules");
+    if std::fs::symlink_metadata(&node_modules).is_ok() {
+        std::fs::remove_dir_all(&node_modules).or_fail(
+            AptestError::Environment,
+            &format!("Could not remove {}", node_modules.display()),
+        )?;
+    }
+    copy_tree(&cached, &node_modules)?;
+    let _ = std::fs::write(dir.join(INSTALLED_MARKER), "");
+    Ok(true)
+}
+
+/// Cache the dependencies installed in `dir` under `key`, unless they
+/// already are. They are copied aside first, so that runs sharing the
+/// cache never restore a partial copy
+pub fn save(dir: &Path, cache_dir: &Path, key: &str) -> Result<(), AptestError> {
+    let cached = cache_dir.join(key);
+    if cached.is_dir() {
+        return Ok(());
+    }
+    let partial = cache_dir.join(format!("{}.{}", key, std::process::id()));
+    copy_tree(&dir.join("node_modules"), &partial)?;
+    let renamed = std::fs::rename(&partial, &cached);
+    if renamed.is_err() {
+        let _ = std::fs::remove_dir_all(&partial);
+    }
+    //Another run caching the same dependencies first is fine
+    if cached.is_dir() {
+        return Ok(());
+    }
+    renamed.or_fail(
+        AptestError::Environment,
+        &format!("Could not write {}", cached.display()),
+    )
+}
+
+//Copies a directory, keeping symlinks as they are since package managers
+//link binaries and packages with relative ones
+fn copy_tree(from: &Path, to: &Path) -> Result<(), AptestError> {
+    std::fs::create_dir_all(to).or_fail(
+        AptestError::Environment,
+        &format!("Could not create directory {}", to.display()),
+    )?;
+    let entries = std::fs::read_dir(from).or_fail(
+        AptestError::Environment,
+        &format!("Could not read {}", from.display()),
+    )?;
+    for entry in entries {
+        let entry = entry.or_fail(
+            AptestError::Environment,
+            &format!("Could not read {}", from.display()),
+        )?;
+        let (source, target) = (entry.path(), to.join(entry.file_name()));
+        let file_type = entry.file_type().or_fail(
+            AptestError::Environment,
+            &format!("Could not read {}", source.display()),
+        )?;
+        let copied = if file_type.is_symlink() {
+            std::fs::read_link(&source).and_then(|link| symlink(&link, &source, &target))
+        } else if file_type.is_dir() {
+            copy_tree(&source, &target)?;
+            Ok(())
+        } else {
+            std::fs::copy(&source, &target).map(|_| ())
+        };
+        copied.or_fail(
+            AptestError::Environment,
+            &format!("Could not copy {}", source.display()),
+        )?;
+    }
+    Ok(())
+}
+
+//Creates a symlink to `link`, as the one at `source` is
+#[cfg(unix)]
+fn symlink(link: &Path, _source: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(link, target)
+}
+
+//Creates a symlink to `link`, as the one at `source` is. Windows tells
+//links to directories apart, which are followed to see which it is
+#[cfg(windows)]
+fn symlink(link: &Path, source: &Path, target: &Path) -> std::io::Result<()> {
+    if source.is_dir() {
+        std::os::windows::fs::symlink_dir(link, target)
+    } else {
+        std::os::windows::fs::symlink_file(link, target)
+    }
+}
+
+//The lockfile of a package manager in a directory, if there is one
+fn lockfile(dir: &Path, package_manager: PackageManager) -> Option<PathBuf> {
+    package_manager
+        .lockfiles()
+        .iter()
+        .map(|x| dir.join(x))
+        .find(|x| x.is_file())
+}
+
+//When a file or directory was last written to, if it is there
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+#[test]
+fn test_outdated() {
+    let dir = std::env::temp_dir().join(format!("aptest-deps-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    assert!(!outdated(&dir, PackageManager::Npm));
+    std::fs::write(dir.join("package.json"), "{}").unwrap();
+    assert!(outdated(&dir, PackageManager::Npm));
+    std::fs::write(dir.join("yarn.lock"), "").unwrap();
+    assert_eq!(package_manager(&dir, None), PackageManager::Yarn);
+    std::fs::create_dir_all(dir.join("node_modules")).unwrap();
+    std::fs::write(dir.join(INSTALLED_MARKER), "").unwrap();
+    assert!(!outdated(&dir, PackageManager::Yarn));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cache_key() {
+    let dir = std::env::temp_dir().join(format!("aptest-cache-key-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    assert_eq!(cache_key(&dir, PackageManager::Npm), None);
+    std::fs::write(dir.join("package-lock.json"), "{}").unwrap();
+    let key = cache_key(&dir, PackageManager::Npm).unwrap();
+    assert_eq!(key.len(), 64);
+    std::fs::write(dir.join("package-lock.json"), "{ }").unwrap();
+    assert_ne!(cache_key(&dir, PackageManager::Npm).unwrap(), key);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
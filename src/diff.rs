@@ -0,0 +1,228 @@
+//! Resources of accounts captured before and after the tests, and how they
+//! changed, for `aptest run --state-diff` and `aptest diff`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::error::{AptestError, OrFail};
+use crate::rest::NodeClient;
+use crate::ui;
+
+/// Resources of accounts at one point, by address then resource type
+pub type Capture = BTreeMap<String, BTreeMap<String, Value>>;
+
+/// What happened to a resource between two captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Created,
+    Deleted,
+    Modified,
+}
+
+/// A field of a modified resource that changed, its path joined with dots
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    pub path: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// A resource created, deleted or modified between two captures
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub account: String,
+    pub resource: String,
+    pub kind: Kind,
+    /// Fields that changed, every one of them for created or deleted
+    /// resources
+    pub fields: Vec<Field>,
+}
+
+/// The resources of `accounts` on the node behind `client`. Accounts the
+/// node doesn't know yet have none.
+pub async fn capture(client: &NodeClient, accounts: &[String]) -> Capture {
+    let mut capture = Capture::new();
+    for account in accounts {
+        let resources = client.resources(account).await.unwrap_or_default();
+        let resources = resources
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|x| Some((x["type"].as_str()?.to_string(), x["data"].clone())))
+            .collect();
+        capture.insert(account.clone(), resources);
+    }
+    capture
+}
+
+/// The resources that changed from `before` to `after`, by account and
+/// resource type, an account missing from a capture having none
+pub fn changes(before: &Capture, after: &Capture) -> Vec<Change> {
+    let empty = BTreeMap::new();
+    let mut changes = Vec::new();
+    let accounts: BTreeSet<_> = before.keys().chain(after.keys()).collect();
+    for account in accounts {
+        let before = before.get(account).unwrap_or(&empty);
+        let after = after.get(account).unwrap_or(&empty);
+        let types: BTreeSet<_> = before.keys().chain(after.keys()).collect();
+        for resource in types {
+            let (old, new) = (before.get(resource), after.get(resource));
+            let kind = match (old, new) {
+                (None, Some(_)) => Kind::Created,
+                (Some(_), None) => Kind::Deleted,
+                (Some(old), Some(new)) if old != new => Kind::Modified,
+                _ => continue,
+            };
+            let mut fields = Vec::new();
+            diff_values(
+                "",
+                old.unwrap_or(&Value::Null),
+                new.unwrap_or(&Value::Null),
+                &mut fields,
+            );
+            changes.push(Change {
+                account: account.clone(),
+                resource: resource.clone(),
+                kind,
+                fields,
+            });
+        }
+    }
+    changes
+}
+
+/// Print the changes by account, `+` for created resources, `-` for
+/// deleted ones and `~` for modified ones followed by their fields
+pub fn print(changes: &[Change]) {
+    if changes.is_empty() {
+        ui::info("No resource of the tracked accounts changed");
+        return;
+    }
+    ui::info("State changes");
+    let mut account = None;
+    for change in changes {
+        if account != Some(&change.account) {
+            ui::print(&change.account);
+            account = Some(&change.account);
+        }
+        let sign = match change.kind {
+            Kind::Created => "+",
+            Kind::Deleted => "-",
+            Kind::Modified => "~",
+        };
+        ui::print(format!("  {} {}", sign, change.resource));
+        if change.kind == Kind::Modified {
+            for field in &change.fields {
+                ui::print(format!(
+                    "      {}: {} -> {}",
+                    field.path, field.before, field.after
+                ));
+            }
+        }
+    }
+}
+
+/// Write captures or changes to `path` as JSON
+pub fn write(value: &impl Serialize, path: &Path) -> Result<(), AptestError> {
+    let json = serde_json::to_string_pretty(value)
+        .or_fail(AptestError::Environment, "Could not serialize the state")?;
+    if let Some(dir) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).or_fail(
+            AptestError::Environment,
+            &format!("Could not create {}", dir.display()),
+        )?;
+    }
+    std::fs::write(path, json).or_fail(
+        AptestError::Environment,
+        &format!("Could not write {}", path.display()),
+    )
+}
+
+/// Read a capture written by an earlier run
+pub fn read(path: &Path) -> Result<Capture, AptestError> {
+    let json = std::fs::read_to_string(path).or_fail(
+        AptestError::Environment,
+        &format!("Could not read {}", path.display()),
+    )?;
+    serde_json::from_str(&json).or_fail(
+        AptestError::Environment,
+        &format!("Could not parse {}", path.display()),
+    )
+}
+
+//Adds the leaves of two values that differ to `fields`, objects being
+//compared field by field and anything else as a whole
+fn diff_values(path: &str, before: &Value, after: &Value, fields: &mut Vec<Field>) {
+    if before == after {
+        return;
+    }
+    let join = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+    match (before, after) {
+        (Value::Object(old), Value::Object(new)) => {
+            for key in old.keys().filter(|x| !new.contains_key(*x)) {
+                diff_values(&join(key), &old[key], &Value::Null, fields);
+            }
+            for (key, value) in new {
+                let old = old.get(key).unwrap_or(&Value::Null);
+                diff_values(&join(key), old, value, fields);
+            }
+        }
+        (Value::Object(old), Value::Null) => {
+            for (key, value) in old {
+                diff_values(&join(key), value, &Value::Null, fields);
+            }
+        }
+        (Value::Null, Value::Object(new)) => {
+            for (key, value) in new {
+                diff_values(&join(key), &Value::Null, value, fields);
+            }
+        }
+        _ => fields.push(Field {
+            path: path.to_string(),
+            before: before.clone(),
+            after: after.clone(),
+        }),
+    }
+}
+
+#[test]
+fn test_changes() {
+    use serde_json::json;
+    let capture = |resources: Value| -> Capture {
+        serde_json::from_value(json!({ "0xcafe": resources })).unwrap()
+    };
+    let coin = "0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>";
+    let before = capture(json!({
+        coin: {"coin": {"value": "100"}, "frozen": false},
+        "0xcafe::market::Listing": {"price": "10"},
+    }));
+    let after = capture(json!({
+        coin: {"coin": {"value": "90"}, "frozen": false},
+        "0xcafe::market::Pool": {"reserve": "5"},
+    }));
+    assert!(changes(&after, &after).is_empty());
+    let found = changes(&before, &after);
+    assert_eq!(found.len(), 3);
+    assert_eq!(found[0].resource, coin);
+    assert_eq!(found[0].kind, Kind::Modified);
+    assert_eq!(
+        found[0].fields,
+        [Field {
+            path: "coin.value".to_string(),
+            before: json!("100"),
+            after: json!("90"),
+        }]
+    );
+    assert_eq!(found[1].kind, Kind::Deleted);
+    assert_eq!(found[2].kind, Kind::Created);
+    assert_eq!(found[2].fields[0].path, "reserve");
+}
@@ -0,0 +1,78 @@
+use std::fmt::Display;
+use thiserror::Error;
+
+/// Everything that can make an aptest invocation fail.
+/// Each class of failure exits with its own code so that
+/// scripts wrapping aptest can tell them apart.
+#[derive(Debug, Error)]
+pub enum AptestError {
+    ///Missing binaries, config files, unwritable directories...
+    #[error("{0}")]
+    Environment(String),
+
+    ///"aptos move compile" failed
+    #[error("{0}")]
+    Compile(String),
+
+    ///The validator or faucet could not be brought up
+    #[error("{0}")]
+    NodeStart(String),
+
+    ///Funding, "aptos move publish" or a fixture failed
+    #[error("{0}")]
+    Publish(String),
+
+    ///The end to end test suite failed
+    #[error("{0}")]
+    Tests(String),
+
+    ///A request to the node or faucet failed
+    #[error("{0}")]
+    Request(String),
+
+    ///A hook configured in aptest.toml failed
+    #[error("{0}")]
+    Hook(String),
+
+    ///A child process ran past its timeout and was killed
+    #[error("{0}")]
+    Timeout(String),
+
+    ///The node or faucet exited while the run relied on it
+    #[error("{0}")]
+    NodeExited(String),
+
+    ///A termination signal was received mid-run
+    #[error("Interrupted, shutting down...")]
+    Interrupted,
+}
+
+impl AptestError {
+    /// The process exit code for this class of failure.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AptestError::Environment(_) => 2,
+            AptestError::Compile(_) => 3,
+            AptestError::NodeStart(_) => 4,
+            AptestError::Publish(_) => 5,
+            AptestError::Tests(_) => 6,
+            AptestError::Request(_) => 7,
+            AptestError::Hook(_) => 8,
+            AptestError::Timeout(_) => 9,
+            AptestError::NodeExited(_) => 10,
+            AptestError::Interrupted => 130,
+        }
+    }
+}
+
+/// Classifies a lower level error as one of the aptest failure kinds,
+/// prefixing it with a human readable explanation.
+pub trait OrFail<T> {
+    fn or_fail(self, kind: fn(String) -> AptestError, msg: &str) -> Result<T, AptestError>;
+}
+
+impl<T, E: Display> OrFail<T> for Result<T, E> {
+    fn or_fail(self, kind: fn(String) -> AptestError, msg: &str) -> Result<T, AptestError> {
+        self.map_err(|e| kind(format!("{}\n\n{}", msg, e)))
+    }
+}
@@ -0,0 +1,164 @@
+//! The events emitted while the tests ran, normalized so that runs of the
+//! same tests can be compared: account addresses and hashes are replaced
+//! by placeholders in order of appearance, timestamps and gas fees left
+//! out.
+
+use serde_json::{Map, Value};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::{AptestError, OrFail};
+use crate::history::TransactionRecord;
+
+/// Where the events are written unless told otherwise
+pub const EVENTS_FILE: &str = "events.json";
+
+//Events whose data changes from run to run whatever the tests do
+const IGNORED_EVENTS: [&str; 1] = ["0x1::transaction_fee::FeeStatement"];
+
+//Fewer hex digits are framework or named addresses, the same every run
+const MIN_ADDRESS_DIGITS: usize = 32;
+
+//Differences listed before the rest are only counted
+const MAX_DIFFERENCES: usize = 10;
+
+/// The events of the transactions, in order, as `{ type, data }`
+pub fn normalize(transactions: &[TransactionRecord]) -> Vec<Value> {
+    let mut addresses = BTreeMap::new();
+    transactions
+        .iter()
+        .flat_map(|x| &x.events)
+        .filter(|event| {
+            !IGNORED_EVENTS
+                .iter()
+                .any(|ignored| event["type"] == *ignored)
+        })
+        .map(|event| normalize_value(event, &mut addresses))
+        .collect()
+}
+
+/// The differences between the events of a snapshot and those emitted,
+/// one line each, empty if they match
+pub fn diff(expected: &[Value], actual: &[Value]) -> Vec<String> {
+    let mut differences = Vec::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(expected), Some(actual)) if expected != actual => differences.push(format!(
+                "event {}: expected {}, got {}",
+                i, expected, actual
+            )),
+            (Some(expected), None) => {
+                differences.push(format!("event {}: expected {}, missing", i, expected))
+            }
+            (None, Some(actual)) => differences.push(format!("event {}: unexpected {}", i, actual)),
+            _ => (),
+        }
+    }
+    if differences.len() > MAX_DIFFERENCES {
+        let more = differences.len() - MAX_DIFFERENCES;
+        differences.truncate(MAX_DIFFERENCES);
+        differences.push(format!("and {} more", more));
+    }
+    differences
+}
+
+/// Read events written by [`write`]
+pub fn read(path: &Path) -> Result<Vec<Value>, AptestError> {
+    let json = std::fs::read_to_string(path).or_fail(
+        AptestError::Environment,
+        &format!("Could not read {}", path.display()),
+    )?;
+    serde_json::from_str(&json).or_fail(
+        AptestError::Environment,
+        &format!("Could not parse {}", path.display()),
+    )
+}
+
+/// Write the events to `path` as JSON
+pub fn write(events: &[Value], path: &Path) -> Result<(), AptestError> {
+    let json = serde_json::to_string_pretty(events)
+        .or_fail(AptestError::Environment, "Could not serialize events")?;
+    std::fs::write(path, json).or_fail(
+        AptestError::Environment,
+        &format!("Could not write {}", path.display()),
+    )
+}
+
+//Replaces the addresses in a value and drops the timestamps
+fn normalize_value(value: &Value, addresses: &mut BTreeMap<String, usize>) -> Value {
+    match value {
+        Value::String(x) => Value::String(replace_addresses(x, addresses)),
+        Value::Array(x) => Value::Array(
+            x.iter()
+                .map(|item| normalize_value(item, addresses))
+                .collect(),
+        ),
+        Value::Object(x) => Value::Object(
+            x.iter()
+                .filter(|(key, _)| !key.contains("timestamp"))
+                .map(|(key, item)| (key.clone(), normalize_value(item, addresses)))
+                .collect::<Map<_, _>>(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+//Replaces every long 0x-hex number of a string, in a type like
+//`0xcafe...::market::Listed` or on its own, by `<address N>`
+fn replace_addresses(text: &str, addresses: &mut BTreeMap<String, usize>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("0x") {
+        out.push_str(&rest[..start]);
+        let digits = rest[start + 2..]
+            .find(|c: char| !c.is_ascii_hexdigit())
+            .unwrap_or(rest.len() - start - 2);
+        let hex = &rest[start..start + 2 + digits];
+        if digits >= MIN_ADDRESS_DIGITS {
+            let key = hex[2..].trim_start_matches('0').to_ascii_lowercase();
+            let next = addresses.len() + 1;
+            let n = *addresses.entry(key).or_insert(next);
+            out.push_str(&format!("<address {}>", n));
+        } else {
+            out.push_str(hex);
+        }
+        rest = &rest[start + 2 + digits..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[test]
+fn test_normalize() {
+    use serde_json::json;
+    let account = format!("0x{}", "ab".repeat(32));
+    let record = |events: Value| TransactionRecord {
+        version: 0,
+        hash: String::new(),
+        sender: String::new(),
+        function: None,
+        success: true,
+        vm_status: String::new(),
+        gas_used: 0,
+        events: events.as_array().unwrap().clone(),
+    };
+    let transactions = [record(json!([
+        {"type": format!("{}::market::Listed", account), "data": {"seller": account, "price": "10", "timestamp": "1700"}},
+        {"type": "0x1::transaction_fee::FeeStatement", "data": {"total": "9"}},
+        {"type": "0x1::coin::Deposit", "data": {"account": "0x1", "amount": "5"}}
+    ]))];
+    let events = normalize(&transactions);
+    assert_eq!(
+        events,
+        [
+            json!({"type": "<address 1>::market::Listed", "data": {"seller": "<address 1>", "price": "10"}}),
+            json!({"type": "0x1::coin::Deposit", "data": {"account": "0x1", "amount": "5"}})
+        ]
+    );
+    assert!(diff(&events, &events).is_empty());
+    assert_eq!(
+        diff(&events[..1], &events),
+        [format!("event 1: unexpected {}", events[1])]
+    );
+}
@@ -0,0 +1,257 @@
+use ed25519_dalek::SigningKey;
+
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::error::{AptestError, OrFail};
+use crate::logs::write_line;
+use crate::process::{drain, spawn_grouped, GroupChild, Log};
+use crate::rest::NodeClient;
+use crate::transaction::{self, EntryFunction};
+use crate::ui;
+
+/// Default faucet funding accounts on the local validator
+pub const FAUCET_URL: &str = if cfg!(windows) {
+    "http://127.0.0.1:8000"
+} else {
+    "http://0.0.0.0:8000"
+};
+
+//The faucet binary in PATH
+const FAUCET_BIN: &str = "aptos-faucet";
+
+//Account holding the mint capability on local networks
+pub(crate) const ROOT_ADDRESS: &str = "0xa550c18";
+
+/// Configures an `aptos-faucet` minting from the local network's root key.
+/// Handed to [`crate::LocalNode::faucet`], which starts it alongside
+/// the validator.
+pub struct Faucet {
+    port: u16,
+    binary: PathBuf,
+    extra_args: Vec<String>,
+}
+
+impl Default for Faucet {
+    fn default() -> Self {
+        Faucet {
+            port: 8000,
+            binary: PathBuf::from(FAUCET_BIN),
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+impl Faucet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Port the faucet listens on
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Run this binary instead of the aptos-faucet in PATH
+    pub fn binary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.binary = path.into();
+        self
+    }
+
+    /// Arguments appended to the faucet's command line
+    pub fn extra_args(mut self, args: Vec<String>) -> Self {
+        self.extra_args = args;
+        self
+    }
+
+    //The port it listens on, which has to be free before it starts
+    pub(crate) fn listen_port(&self) -> u16 {
+        self.port
+    }
+
+    /// Where the faucet can be reached once started
+    pub fn url(&self) -> String {
+        let host = if cfg!(windows) {
+            "127.0.0.1"
+        } else {
+            "0.0.0.0"
+        };
+        format!("http://{}:{}", host, self.port)
+    }
+
+    //Starts the faucet against the validator's mint key, draining its output
+    //into the network's log and the output file if any, and calling
+    //`on_exit` once it is closed. None if aptos-faucet isn't installed
+    pub(crate) fn spawn(
+        &self,
+        mint_key_path: &str,
+        node_url: &str,
+        chain_id: u8,
+        log: Log,
+        mut output: Option<File>,
+        on_exit: impl FnOnce() + Send + 'static,
+    ) -> Result<Option<GroupChild>, AptestError> {
+        ui::info("Starting faucet...");
+        let port = self.port.to_string();
+        let chain_id = chain_id.to_string();
+        let spawned = spawn_grouped(
+            Command::new(&self.binary)
+                .args([
+                    "--chain-id",
+                    chain_id.as_str(),
+                    "--mint-key-file-path",
+                    mint_key_path,
+                    "--address",
+                    "0.0.0.0",
+                    "--port",
+                    port.as_str(),
+                    "--server-url",
+                    node_url,
+                ])
+                .args(&self.extra_args)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        );
+        let mut faucet_child = match spawned {
+            Ok(faucet_child) => faucet_child,
+            //Only the default faucet is optional, one asked for has to be there
+            Err(e) if e.kind() == ErrorKind::NotFound && self.binary == Path::new(FAUCET_BIN) => {
+                return Ok(None)
+            }
+            Err(e) => {
+                return Err(AptestError::Environment(format!(
+                    "Could not start {}\n\n{}",
+                    self.binary.display(),
+                    e
+                )))
+            }
+        };
+
+        //Both outputs go to the log, rather than to aptest's own
+        let (stdout, stderr) = (
+            faucet_child.child.stdout.take(),
+            faucet_child.child.stderr.take(),
+        );
+        let mut stdout_output = output.as_ref().and_then(|x| x.try_clone().ok());
+        let stdout_log = log.clone();
+        tokio::spawn(async move {
+            let drain_stdout = async {
+                if let Some(stdout) = stdout {
+                    drain(stdout, stdout_log, |line| {
+                        write_line(&mut stdout_output, line)
+                    })
+                    .await;
+                }
+            };
+            let drain_stderr = async {
+                if let Some(stderr) = stderr {
+                    drain(stderr, log, |line| write_line(&mut output, line)).await;
+                }
+            };
+            tokio::join!(drain_stdout, drain_stderr);
+            on_exit();
+        });
+        Ok(Some(faucet_child))
+    }
+}
+
+/// How accounts get funded on a network: through a faucet, or by minting
+/// directly with the root key when aptos-faucet isn't installed.
+#[derive(Clone, Debug)]
+pub enum Funder {
+    Faucet(String),
+    Mint {
+        node_url: String,
+        mint_key_path: String,
+    },
+}
+
+impl Funder {
+    /// Create and fund an account with `amount` octas
+    pub async fn fund(&self, address: &str, amount: u64) -> Result<(), AptestError> {
+        match self {
+            Funder::Faucet(faucet_url) => fund(faucet_url, address, amount).await,
+            Funder::Mint {
+                node_url,
+                mint_key_path,
+            } => mint(node_url, mint_key_path, address, amount).await,
+        }
+    }
+}
+
+/// Create and fund an account with `amount` octas through a faucet.
+pub async fn fund(faucet_url: &str, address: &str, amount: u64) -> Result<(), AptestError> {
+    let response = reqwest::Client::new()
+        .post(format!("{}/mint", faucet_url))
+        .query(&[
+            ("amount", amount.to_string()),
+            ("address", address.to_string()),
+        ])
+        .send()
+        .await
+        .or_fail(
+            AptestError::Publish,
+            &format!("Could not reach the faucet at {}", faucet_url),
+        )?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(AptestError::Publish(format!(
+            "The faucet refused to fund {}: {}\n\n{}",
+            address, status, body
+        )));
+    }
+    Ok(())
+}
+
+/// Create and fund an account with `amount` octas by submitting mint
+/// transactions signed with the root key, as the faucet would.
+pub async fn mint(
+    node_url: &str,
+    mint_key_path: &str,
+    address: &str,
+    amount: u64,
+) -> Result<(), AptestError> {
+    let key = read_mint_key(mint_key_path)?;
+    let client = NodeClient::new(node_url);
+
+    if !client.account_exists(address).await {
+        let create =
+            EntryFunction::new("0x1::aptos_account::create_account")?.address_arg(address)?;
+        transaction::submit(&client, ROOT_ADDRESS, &key, &create)
+            .await
+            .or_fail(
+                AptestError::Publish,
+                &format!("Could not create {}", address),
+            )?;
+    }
+    let mint = EntryFunction::new("0x1::aptos_coin::mint")?
+        .address_arg(address)?
+        .u64_arg(amount);
+    transaction::submit(&client, ROOT_ADDRESS, &key, &mint)
+        .await
+        .or_fail(
+            AptestError::Publish,
+            &format!("Could not mint to {}", address),
+        )?;
+    Ok(())
+}
+
+//The node writes the root key BCS encoded, as a length prefixed byte sequence
+pub(crate) fn read_mint_key(path: &str) -> Result<SigningKey, AptestError> {
+    let bytes = std::fs::read(path).or_fail(
+        AptestError::Environment,
+        &format!("Could not read the mint key at {}", path),
+    )?;
+    let key = match bytes.len() {
+        33 => &bytes[1..],
+        _ => &bytes[..],
+    };
+    let key = <[u8; 32]>::try_from(key)
+        .map_err(|_| AptestError::Environment(format!("{} is not an Ed25519 private key", path)))?;
+    Ok(SigningKey::from_bytes(&key))
+}
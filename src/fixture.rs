@@ -0,0 +1,108 @@
+//! Rust-native end to end tests, using aptest as a dev-dependency:
+//!
+//! ```no_run
+//! //Marked #[test] in a tests/ file of the Move project's Rust crate
+//! fn counter_increments() -> Result<(), aptest::AptestError> {
+//!     let localnet = aptest::fixture::localnet()?;
+//!     let account = localnet.account().unwrap();
+//!     println!("{} published to {}", account, localnet.node_url());
+//!     Ok(())
+//! }
+//! ```
+
+use std::sync::{Mutex, MutexGuard};
+use tokio::runtime::Runtime;
+
+use crate::error::{AptestError, OrFail};
+use crate::node::{LocalNet, LocalNode};
+use crate::profile::AptosConfig;
+use crate::publish::Publisher;
+use crate::signal::Interrupt;
+
+//Every localnet binds the same ports, so tests using
+//the fixture run one after the other
+static LOCALNET_LOCK: Mutex<()> = Mutex::new(());
+
+/// A running local network with the package in the current directory
+/// published to it. Everything is torn down when it goes out of scope.
+///
+/// The fixture drives the network from its own runtime, so it is meant for
+/// plain `#[test]` functions. Async tests should use [`LocalNode`] directly.
+pub struct Fixture {
+    net: LocalNet,
+    account: Option<String>,
+    private_key: Option<String>,
+    //Keeps draining the node output until the network is torn down
+    _runtime: Runtime,
+    //Declared last so it is released after the network is torn down
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Fixture {
+    /// Start the configured node, then compile and publish the package
+    /// with the publisher unless it is `None`.
+    pub fn new(node: LocalNode, publisher: Option<Publisher>) -> Result<Self, AptestError> {
+        //A test panicking while holding the lock still tore its network down
+        let lock = LOCALNET_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let runtime = Runtime::new().or_fail(
+            AptestError::Environment,
+            "Could not start the fixture's tokio runtime",
+        )?;
+        let interrupt = Interrupt::never();
+        let net = runtime.block_on(node.start(&interrupt))?;
+
+        let (mut account, mut private_key) = (None, None);
+        if let Some(publisher) = publisher {
+            let publisher = publisher.node_url(net.node_url()).funder(net.funder());
+            runtime.block_on(async {
+                publisher.compile().await?;
+                publisher.publish().await
+            })?;
+            let name = publisher.first_profile().unwrap_or("default");
+            let config = AptosConfig::load()?;
+            account = Some(config.account(name)?);
+            private_key = Some(config.private_key(name)?);
+        }
+
+        Ok(Fixture {
+            net,
+            account,
+            private_key,
+            _runtime: runtime,
+            _lock: lock,
+        })
+    }
+
+    /// REST API of the validator
+    pub fn node_url(&self) -> &str {
+        self.net.node_url()
+    }
+
+    /// Faucet URL, if a faucet was started
+    pub fn faucet_url(&self) -> Option<&str> {
+        self.net.faucet_url()
+    }
+
+    /// Path to the root key able to mint coins on this network
+    pub fn mint_key_path(&self) -> &str {
+        //The fixture always starts its own node
+        self.net.mint_key_path().unwrap_or_default()
+    }
+
+    /// Address the package was published from
+    pub fn account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+
+    /// Private key of the account the package was published from
+    pub fn private_key(&self) -> Option<&str> {
+        self.private_key.as_deref()
+    }
+}
+
+/// Start a local network with a faucet and publish the package
+/// in the current directory to it, with the default settings.
+pub fn localnet() -> Result<Fixture, AptestError> {
+    Fixture::new(LocalNode::new(), Some(Publisher::new()))
+}
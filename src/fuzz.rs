@@ -0,0 +1,426 @@
+//! Fuzzing of the entry functions of a published module: random arguments
+//! generated after the module's ABI are sent from throwaway accounts, and
+//! aborts that aren't one of the module's error constants, or an invariant
+//! view function turning false, reported with what reproduces them.
+
+use ed25519_dalek::SigningKey;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+
+use crate::abort::ErrorMap;
+use crate::accounts::{TestAccount, DEFAULT_FUND_AMOUNT};
+use crate::error::AptestError;
+use crate::faucet::Funder;
+use crate::rest::NodeClient;
+use crate::signal::Interrupt;
+use crate::transaction::{self, EntryFunction};
+use crate::ui;
+
+const U256_MAX: &str =
+    "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+
+//Longest vector, string or byte string generated
+const MAX_LEN: usize = 8;
+
+/// Sends random calls to the entry functions of a module.
+pub struct Fuzzer {
+    node_url: String,
+    funder: Option<Funder>,
+    module: String,
+    functions: Vec<String>,
+    runs: usize,
+    seed: u64,
+    accounts: usize,
+    invariant: Option<String>,
+    errors: ErrorMap,
+}
+
+/// A call that aborted unexpectedly or broke the invariant
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub function: String,
+    /// Arguments in the aptos CLI's `type:value` form
+    pub args: Vec<String>,
+    pub sender: String,
+    pub reason: String,
+}
+
+/// How the calls to a function went
+#[derive(Clone, Debug, Default)]
+pub struct FunctionRuns {
+    pub runs: usize,
+    pub executed: usize,
+    /// Aborts with one of the module's error constants
+    pub expected_aborts: usize,
+}
+
+/// What fuzzing found
+#[derive(Debug, Default)]
+pub struct FuzzResult {
+    pub seed: u64,
+    pub functions: BTreeMap<String, FunctionRuns>,
+    /// Functions left out, with why
+    pub skipped: Vec<(String, String)>,
+    pub findings: Vec<Finding>,
+}
+
+//An entry function of the ABI the fuzzer can call
+struct Target {
+    name: String,
+    params: Vec<String>,
+}
+
+impl Fuzzer {
+    /// Fuzz the module at `module`, given as `address::name`
+    pub fn new(node_url: &str, module: &str) -> Self {
+        Fuzzer {
+            node_url: node_url.to_string(),
+            funder: None,
+            module: module.to_string(),
+            functions: Vec::new(),
+            runs: 100,
+            seed: rand::thread_rng().gen(),
+            accounts: 2,
+            invariant: None,
+            errors: ErrorMap::default(),
+        }
+    }
+
+    /// Funds the throwaway accounts
+    pub fn funder(mut self, funder: Funder) -> Self {
+        self.funder = Some(funder);
+        self
+    }
+
+    /// Only call these functions, all the entry functions of the module
+    /// if there are none
+    pub fn functions(mut self, functions: Vec<String>) -> Self {
+        self.functions = functions;
+        self
+    }
+
+    /// Number of calls sent
+    pub fn runs(mut self, runs: usize) -> Self {
+        self.runs = runs;
+        self
+    }
+
+    /// Seed of the random calls, and of the accounts, so that a run can
+    /// be reproduced
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Number of throwaway accounts the calls are sent from
+    pub fn accounts(mut self, accounts: usize) -> Self {
+        self.accounts = accounts;
+        self
+    }
+
+    /// View function, as `address::module::function`, taking no arguments
+    /// and returning whether the module's state is sound. It is called
+    /// after every executed call
+    pub fn invariant(mut self, function: Option<String>) -> Self {
+        self.invariant = function;
+        self
+    }
+
+    /// Error constants aborts are expected with
+    pub fn errors(mut self, errors: ErrorMap) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    pub async fn run(self, interrupt: &Interrupt) -> Result<FuzzResult, AptestError> {
+        let client = NodeClient::new(&self.node_url);
+        let mut result = FuzzResult {
+            seed: self.seed,
+            ..Default::default()
+        };
+        let targets = self.targets(&client, &mut result).await?;
+        if targets.is_empty() {
+            return Err(AptestError::Environment(format!(
+                "{} has no entry function to fuzz",
+                self.module
+            )));
+        }
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let senders = interrupt.race(self.senders(&mut rng)).await?;
+        let mut addresses: Vec<String> = senders.iter().map(|(x, _)| x.clone()).collect();
+        addresses.push("0x1".to_string());
+
+        ui::info(format!(
+            "Fuzzing {} with seed {}...",
+            self.module, self.seed
+        ));
+        for _ in 0..self.runs {
+            interrupt.check()?;
+            let target = &targets[rng.gen_range(0..targets.len())];
+            let (sender, key) = &senders[rng.gen_range(0..senders.len())];
+            let args: Vec<String> = target
+                .params
+                .iter()
+                .filter_map(|param| arbitrary(param, &mut rng, &addresses))
+                .collect();
+            let function = format!("{}::{}", self.module, target.name);
+            let mut call = EntryFunction::new(&function)?;
+            for arg in &args {
+                call = call.arg(&crate::args::parse(arg)?);
+            }
+            let runs = result.functions.entry(function.clone()).or_default();
+            runs.runs += 1;
+            let finding = |reason: String| Finding {
+                function: function.clone(),
+                args: args.clone(),
+                sender: sender.clone(),
+                reason,
+            };
+            match interrupt
+                .race(transaction::submit(&client, sender, key, &call))
+                .await
+            {
+                Ok(_) => {
+                    runs.executed += 1;
+                    if let Some(reason) = self.check_invariant(&client).await? {
+                        result.findings.push(finding(reason));
+                    }
+                }
+                Err(AptestError::Interrupted) => return Err(AptestError::Interrupted),
+                Err(err) => {
+                    let message = err.to_string();
+                    if self.errors.explain(&message).is_empty() {
+                        result.findings.push(finding(message));
+                    } else {
+                        runs.expected_aborts += 1;
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    //The entry functions of the module that can be called with generated
+    //arguments, the others being recorded as skipped
+    async fn targets(
+        &self,
+        client: &NodeClient,
+        result: &mut FuzzResult,
+    ) -> Result<Vec<Target>, AptestError> {
+        let (address, name) = self.module.rsplit_once("::").ok_or_else(|| {
+            AptestError::Environment(format!("{} is not of the form address::name", self.module))
+        })?;
+        let module = client
+            .get(&format!("/accounts/{}/module/{}", address, name))
+            .await?;
+        let mut targets = Vec::new();
+        for function in module["abi"]["exposed_functions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            let name = function["name"].as_str().unwrap_or_default().to_string();
+            if function["is_entry"] != Value::Bool(true)
+                || (!self.functions.is_empty() && !self.functions.contains(&name))
+            {
+                continue;
+            }
+            let mut params: Vec<String> = function["params"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|x| x.as_str().map(str::to_string))
+                .collect();
+            //The sender signs for the first signer
+            if params
+                .first()
+                .map_or(false, |x| x == "&signer" || x == "signer")
+            {
+                params.remove(0);
+            }
+            let skip = if function["generic_type_params"]
+                .as_array()
+                .map_or(false, |x| !x.is_empty())
+            {
+                Some("generic".to_string())
+            } else {
+                params
+                    .iter()
+                    .find(|param| !supported(param))
+                    .map(|param| format!("unsupported parameter {}", param))
+            };
+            match skip {
+                Some(reason) => result.skipped.push((name, reason)),
+                None => targets.push(Target { name, params }),
+            }
+        }
+        Ok(targets)
+    }
+
+    //Throwaway accounts derived from the seed, funded
+    async fn senders(&self, rng: &mut StdRng) -> Result<Vec<(String, SigningKey)>, AptestError> {
+        let funder = self.funder.as_ref().ok_or_else(|| {
+            AptestError::Environment("Funding the fuzzing accounts needs a faucet".to_string())
+        })?;
+        let mut senders = Vec::new();
+        for _ in 0..self.accounts.max(1) {
+            let mut private_key = [0u8; 32];
+            rng.fill(&mut private_key);
+            let account = TestAccount::from_private_key(private_key);
+            funder.fund(&account.address, DEFAULT_FUND_AMOUNT).await?;
+            senders.push((account.address, SigningKey::from_bytes(&private_key)));
+        }
+        Ok(senders)
+    }
+
+    //Why the invariant doesn't hold anymore, if it doesn't
+    async fn check_invariant(&self, client: &NodeClient) -> Result<Option<String>, AptestError> {
+        let invariant = match &self.invariant {
+            Some(invariant) => invariant,
+            None => return Ok(None),
+        };
+        match client.view(invariant, &[], Vec::new()).await {
+            Ok(values) if values[0] == Value::Bool(true) => Ok(None),
+            Ok(values) => Ok(Some(format!("{} returned {}", invariant, values))),
+            Err(err) => Ok(Some(format!("{} failed: {}", invariant, err))),
+        }
+    }
+}
+
+impl FuzzResult {
+    /// Print how the calls to each function went, then the findings with
+    /// the command reproducing each of them
+    pub fn print_summary(&self) {
+        ui::info(format!("Fuzzing summary, seed {}", self.seed));
+        ui::print(format!(
+            "  {:<48} {:>6} {:>9} {:>8}",
+            "function", "runs", "executed", "aborted"
+        ));
+        for (function, runs) in &self.functions {
+            ui::print(format!(
+                "  {:<48} {:>6} {:>9} {:>8}",
+                function, runs.runs, runs.executed, runs.expected_aborts
+            ));
+        }
+        for (function, reason) in &self.skipped {
+            ui::print(format!("  {:<48} skipped, {}", function, reason));
+        }
+        ui::print("");
+        if self.findings.is_empty() {
+            return;
+        }
+        ui::failure(format!("{} findings", self.findings.len()));
+        for finding in &self.findings {
+            ui::print(format!("{}\n  {}", finding.function, finding.reason));
+            let mut command = format!("  aptest call {}", finding.function);
+            if !finding.args.is_empty() {
+                command += &format!(" --args {}", finding.args.join(" "));
+            }
+            ui::print(format!("{}  (sent by {})", command, finding.sender));
+        }
+    }
+}
+
+//Whether values of a Move type can be generated
+fn supported(ty: &str) -> bool {
+    match ty.strip_prefix("vector<").and_then(|x| x.strip_suffix('>')) {
+        Some(inner) => !inner.starts_with("vector<") && supported(inner),
+        None => scalar(ty).is_some(),
+    }
+}
+
+//The type:value name of a Move type that isn't a vector
+fn scalar(ty: &str) -> Option<&'static str> {
+    Some(match ty {
+        "bool" => "bool",
+        "u8" => "u8",
+        "u16" => "u16",
+        "u32" => "u32",
+        "u64" => "u64",
+        "u128" => "u128",
+        "u256" => "u256",
+        "address" => "address",
+        "0x1::string::String" => "string",
+        _ => return None,
+    })
+}
+
+//A random argument of a Move type in type:value form, biased towards
+//the edges of its range
+fn arbitrary(ty: &str, rng: &mut StdRng, addresses: &[String]) -> Option<String> {
+    if ty == "vector<u8>" {
+        let bytes: Vec<u8> = (0..rng.gen_range(0..=MAX_LEN)).map(|_| rng.gen()).collect();
+        return Some(format!("hex:0x{}", hex::encode(bytes)));
+    }
+    if let Some(inner) = ty.strip_prefix("vector<").and_then(|x| x.strip_suffix('>')) {
+        let name = scalar(inner)?;
+        let items: Vec<String> = (0..rng.gen_range(0..=MAX_LEN / 2))
+            .map(|_| value(name, rng, addresses))
+            .collect();
+        return Some(format!("{}:[{}]", name, items.join(",")));
+    }
+    let name = scalar(ty)?;
+    Some(format!("{}:{}", name, value(name, rng, addresses)))
+}
+
+//A random value of a type:value type
+fn value(name: &str, rng: &mut StdRng, addresses: &[String]) -> String {
+    //One value in four is an edge
+    let edge = rng.gen_range(0..4) == 0;
+    macro_rules! number {
+        ($ty:ty) => {
+            if edge {
+                [0, 1, <$ty>::MAX][rng.gen_range(0..3)].to_string()
+            } else {
+                rng.gen::<$ty>().to_string()
+            }
+        };
+    }
+    match name {
+        "bool" => rng.gen::<bool>().to_string(),
+        "u8" => number!(u8),
+        "u16" => number!(u16),
+        "u32" => number!(u32),
+        "u64" => number!(u64),
+        "u128" => number!(u128),
+        "u256" if edge => ["0", "1", U256_MAX][rng.gen_range(0..3)].to_string(),
+        "u256" => rng.gen::<u128>().to_string(),
+        "address" if rng.gen_range(0..4) == 0 => {
+            format!("0x{}", hex::encode(rng.gen::<[u8; 32]>()))
+        }
+        "address" => addresses[rng.gen_range(0..addresses.len())].clone(),
+        _ => (0..rng.gen_range(0..=MAX_LEN))
+            .map(|_| rng.sample(rand::distributions::Alphanumeric) as char)
+            .collect(),
+    }
+}
+
+#[test]
+fn test_arbitrary() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let addresses = ["0x1".to_string()];
+    for ty in [
+        "bool",
+        "u8",
+        "u64",
+        "u256",
+        "address",
+        "0x1::string::String",
+        "vector<u8>",
+        "vector<u128>",
+        "vector<address>",
+    ] {
+        for _ in 0..50 {
+            let arg = arbitrary(ty, &mut rng, &addresses).unwrap();
+            assert!(crate::args::parse(&arg).is_ok(), "{}", arg);
+            assert_eq!(crate::args::type_of(&arg).unwrap(), ty);
+        }
+    }
+    assert!(!supported("vector<vector<u8>>"));
+    assert!(!supported(
+        "0x1::object::Object<0x1::fungible_asset::Metadata>"
+    ));
+}
@@ -0,0 +1,220 @@
+//! Gas used by a run: the publish transaction of each package, and the
+//! entry functions called by the tests aggregated per function, to catch
+//! gas regressions while developing.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::{AptestError, OrFail};
+use crate::publish::Deployment;
+use crate::ui;
+
+/// Where the gas report a run is checked against is kept
+pub const BASELINE_PATH: &str = ".aptest/gas-baseline.json";
+
+/// Percentage gas may grow by before it counts as a regression
+pub const DEFAULT_MAX_INCREASE: f64 = 10.0;
+
+/// Gas used by the calls to an entry function
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionGas {
+    pub calls: u64,
+    pub total: u64,
+    pub min: u64,
+    pub max: u64,
+}
+
+impl FunctionGas {
+    /// Gas used by a call on average
+    pub fn mean(&self) -> u64 {
+        self.total.checked_div(self.calls).unwrap_or(0)
+    }
+
+    fn add(&mut self, gas_used: u64) {
+        if self.calls == 0 || gas_used < self.min {
+            self.min = gas_used;
+        }
+        self.max = self.max.max(gas_used);
+        self.total += gas_used;
+        self.calls += 1;
+    }
+}
+
+/// Gas units used by the transactions of a run
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GasReport {
+    /// Gas used publishing each package, by package name
+    pub publish: BTreeMap<String, u64>,
+    /// Gas used by each entry function, as `address::module::function`
+    pub functions: BTreeMap<String, FunctionGas>,
+}
+
+impl GasReport {
+    /// Record the gas used publishing the packages, those left unchanged
+    /// on chain having used none
+    pub fn published(&mut self, deployments: &[Deployment]) {
+        for deployment in deployments.iter().filter(|x| !x.skipped) {
+            self.publish
+                .insert(deployment.package.clone(), deployment.gas_used);
+        }
+    }
+
+    /// Add the gas used by the entry function calls among these user
+    /// transactions, as the node's REST API returns them
+    pub fn add_transactions(&mut self, transactions: &[Value]) {
+        for transaction in transactions {
+            let payload = &transaction["payload"];
+            if payload["type"] != "entry_function_payload" {
+                continue;
+            }
+            let (function, gas_used) = match (
+                payload["function"].as_str(),
+                transaction["gas_used"]
+                    .as_str()
+                    .and_then(|x| x.parse().ok()),
+            ) {
+                (Some(function), Some(gas_used)) => (function, gas_used),
+                _ => continue,
+            };
+            self.functions
+                .entry(function.to_string())
+                .or_default()
+                .add(gas_used);
+        }
+    }
+
+    /// Read the report saved at `path`, if there is one
+    pub fn load(path: &Path) -> Result<Option<Self>, AptestError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let json = std::fs::read_to_string(path).or_fail(
+            AptestError::Environment,
+            &format!("Could not read {}", path.display()),
+        )?;
+        serde_json::from_str(&json).map(Some).or_fail(
+            AptestError::Environment,
+            &format!("Could not parse {}", path.display()),
+        )
+    }
+
+    /// Save the report to `path` as JSON
+    pub fn save(&self, path: &Path) -> Result<(), AptestError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).or_fail(
+                AptestError::Environment,
+                &format!("Could not create directory {}", dir.display()),
+            )?;
+        }
+        let json = serde_json::to_string_pretty(self).or_fail(
+            AptestError::Environment,
+            "Could not serialize the gas report",
+        )?;
+        std::fs::write(path, json).or_fail(
+            AptestError::Environment,
+            &format!("Could not write {}", path.display()),
+        )
+    }
+
+    /// One line for each package whose publish, or function whose mean
+    /// call, used over `max_increase` percent more gas than in the
+    /// baseline. What the baseline doesn't have is not compared.
+    pub fn regressions(&self, baseline: &GasReport, max_increase: f64) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut compare = |what: String, before: u64, after: u64| {
+            if before == 0 {
+                return;
+            }
+            let increase = (after as f64 - before as f64) * 100.0 / before as f64;
+            if increase > max_increase {
+                lines.push(format!(
+                    "{}: {} -> {} (+{:.1}%)",
+                    what, before, after, increase
+                ));
+            }
+        };
+        for (package, gas_used) in &self.publish {
+            if let Some(before) = baseline.publish.get(package) {
+                compare(format!("publish {}", package), *before, *gas_used);
+            }
+        }
+        for (function, gas) in &self.functions {
+            if let Some(before) = baseline.functions.get(function) {
+                compare(function.clone(), before.mean(), gas.mean());
+            }
+        }
+        lines
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.publish.is_empty() && self.functions.is_empty()
+    }
+
+    /// Print a table of the gas used by each package and function
+    pub fn print_summary(&self) {
+        if self.is_empty() {
+            return;
+        }
+        ui::info("Gas report");
+        for (package, gas_used) in &self.publish {
+            ui::print(format!(
+                "  {:<48} {:>10}",
+                format!("publish {}", package),
+                gas_used
+            ));
+        }
+        if !self.functions.is_empty() {
+            ui::print(format!(
+                "  {:<48} {:>10} {:>10} {:>10} {:>6}",
+                "function", "mean", "min", "max", "calls"
+            ));
+        }
+        for (function, gas) in &self.functions {
+            ui::print(format!(
+                "  {:<48} {:>10} {:>10} {:>10} {:>6}",
+                function,
+                gas.mean(),
+                gas.min,
+                gas.max,
+                gas.calls
+            ));
+        }
+        ui::print("");
+    }
+}
+
+#[test]
+fn test_add_transactions() {
+    let transactions = serde_json::json!([
+        {"gas_used": "12", "payload": {"type": "entry_function_payload", "function": "0x42::counter::increment"}},
+        {"gas_used": "8", "payload": {"type": "entry_function_payload", "function": "0x42::counter::increment"}},
+        {"gas_used": "500", "payload": {"type": "script_payload"}}
+    ]);
+    let mut report = GasReport::default();
+    report.add_transactions(transactions.as_array().unwrap());
+    assert_eq!(
+        report.functions["0x42::counter::increment"],
+        FunctionGas {
+            calls: 2,
+            total: 20,
+            min: 8,
+            max: 12
+        }
+    );
+    assert_eq!(report.functions["0x42::counter::increment"].mean(), 10);
+
+    let mut baseline = report.clone();
+    baseline
+        .functions
+        .get_mut("0x42::counter::increment")
+        .unwrap()
+        .total = 18;
+    assert!(report.regressions(&baseline, 20.0).is_empty());
+    assert_eq!(
+        report.regressions(&baseline, 10.0),
+        ["0x42::counter::increment: 9 -> 10 (+11.1%)"]
+    );
+}
@@ -0,0 +1,93 @@
+//! The transactions committed while the tests ran, kept for post-mortem
+//! debugging of end to end failures.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use std::path::Path;
+
+use crate::error::{AptestError, OrFail};
+
+/// Where the transactions are written unless told otherwise
+pub const TRANSACTIONS_FILE: &str = "transactions.json";
+
+/// A user transaction as written to the dump
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TransactionRecord {
+    pub version: u64,
+    pub hash: String,
+    pub sender: String,
+    /// Entry function called, none for scripts and multisig payloads
+    pub function: Option<String>,
+    pub success: bool,
+    pub vm_status: String,
+    pub gas_used: u64,
+    /// Events emitted, as `{ type, data }`
+    pub events: Vec<Value>,
+}
+
+impl TransactionRecord {
+    /// Read a user transaction as the node's REST API returns it
+    pub fn from_json(transaction: &Value) -> Self {
+        let number = |value: &Value| value.as_str().and_then(|x| x.parse().ok()).unwrap_or(0);
+        let text = |value: &Value| value.as_str().unwrap_or_default().to_string();
+        TransactionRecord {
+            version: number(&transaction["version"]),
+            hash: text(&transaction["hash"]),
+            sender: text(&transaction["sender"]),
+            function: transaction["payload"]["function"]
+                .as_str()
+                .map(str::to_string),
+            success: transaction["success"].as_bool() == Some(true),
+            vm_status: text(&transaction["vm_status"]),
+            gas_used: number(&transaction["gas_used"]),
+            events: transaction["events"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|event| {
+                    serde_json::json!({
+                        "type": event["type"],
+                        "data": event["data"],
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Write the transactions to `path` as JSON
+pub fn write(transactions: &[TransactionRecord], path: &Path) -> Result<(), AptestError> {
+    let json = serde_json::to_string_pretty(transactions)
+        .or_fail(AptestError::Environment, "Could not serialize transactions")?;
+    std::fs::write(path, json).or_fail(
+        AptestError::Environment,
+        &format!("Could not write {}", path.display()),
+    )
+}
+
+#[test]
+fn test_from_json() {
+    let transaction = serde_json::json!({
+        "version": "42",
+        "hash": "0xabc",
+        "sender": "0xcafe",
+        "success": false,
+        "vm_status": "Move abort in 0xcafe::counter: E_LIMIT(0x1)",
+        "gas_used": "9",
+        "payload": {"type": "entry_function_payload", "function": "0xcafe::counter::increment"},
+        "events": [{"guid": {}, "sequence_number": "0", "type": "0x1::transaction_fee::FeeStatement", "data": {"total": "9"}}]
+    });
+    let record = TransactionRecord::from_json(&transaction);
+    assert_eq!(record.version, 42);
+    assert_eq!(
+        record.function.as_deref(),
+        Some("0xcafe::counter::increment")
+    );
+    assert!(!record.success);
+    assert_eq!(record.gas_used, 9);
+    assert_eq!(
+        record.events,
+        [serde_json::json!({"type": "0x1::transaction_fee::FeeStatement", "data": {"total": "9"}})]
+    );
+}
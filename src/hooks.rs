@@ -0,0 +1,100 @@
+use tokio::process::Command;
+
+use crate::cli;
+use crate::config::Call;
+use crate::error::{AptestError, OrFail};
+use crate::process;
+use crate::ui;
+
+/// Run the steps of a hook one after the other, failing on the first
+/// that doesn't go through. Every step gets the environment variables
+/// in `envs` plus APTEST_HOOK naming the hook. Steps sending transactions
+/// need the `node_url` to send them to.
+pub async fn run(
+    hook: &str,
+    calls: &[Call],
+    envs: &[(String, String)],
+    node_url: Option<&str>,
+) -> Result<(), AptestError> {
+    if calls.is_empty() {
+        return Ok(());
+    }
+    ui::info(format!("Running {} hooks...", hook));
+    for call in calls {
+        run_call(hook, call, envs, node_url).await?;
+    }
+    Ok(())
+}
+
+//Runs one step with the shell, "aptos move run" or "aptos move run-script"
+async fn run_call(
+    hook: &str,
+    call: &Call,
+    envs: &[(String, String)],
+    node_url: Option<&str>,
+) -> Result<(), AptestError> {
+    let (mut command, name) = match (&call.command, &call.function, &call.script) {
+        (Some(shell), None, None) => {
+            let [program, flag] = process::shell();
+            let mut command = Command::new(program);
+            command.args([flag, shell.as_str()]);
+            (command, shell.clone())
+        }
+        (None, Some(function), None) => {
+            let mut command = Command::new(cli::program());
+            command.args(["move", "run", "--function-id", function.as_str()]);
+            (command, function.clone())
+        }
+        (None, None, Some(script)) => {
+            let mut command = Command::new(cli::program());
+            command
+                .args(["move", "run-script", "--script-path"])
+                .arg(script);
+            (command, script.display().to_string())
+        }
+        _ => {
+            return Err(AptestError::Hook(format!(
+                "Every {} hook needs exactly one of command, function or script",
+                hook
+            )))
+        }
+    };
+
+    //Transactions need a node to go to
+    if call.command.is_none() {
+        let node_url = node_url.ok_or_else(|| {
+            AptestError::Hook(format!(
+                "{} hooks run before the node is up, so they can only run commands",
+                hook
+            ))
+        })?;
+        if !call.args.is_empty() {
+            command.arg("--args").args(&call.args);
+        }
+        if !call.type_args.is_empty() {
+            command.arg("--type-args").args(&call.type_args);
+        }
+        let profile = call.profile.as_deref().unwrap_or("default");
+        let url_flag = cli::version().url_flag();
+        command.args(["--profile", profile, url_flag, node_url, "--assume-yes"]);
+    }
+    ui::print(&name);
+
+    command
+        .envs(envs.iter().map(|(k, v)| (k, v)))
+        .env("APTEST_HOOK", hook);
+    ui::command(&command);
+    let status = command
+        .stdout(ui::child_stdout())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .or_fail(AptestError::Environment, &format!("Could not run {}", name))?;
+    if !status.success() {
+        return Err(AptestError::Hook(format!(
+            "The {} hook {} failed",
+            hook, name
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,834 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::cli;
+use crate::config::{Project, Test, CONFIG_FILE};
+use crate::error::{AptestError, OrFail};
+use crate::process;
+use crate::ui;
+
+/// Test harness scaffolded next to the Move package
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Template {
+    /// TypeScript tests run by mocha
+    TsMocha,
+    /// TypeScript tests run by jest
+    TsJest,
+    /// Python tests run by pytest with the aptos-sdk package
+    Python,
+    /// A cargo test crate using the Rust SDK
+    Rust,
+}
+
+impl Template {
+    /// The directory the tests of the harness are in, and their extension
+    pub fn tests(self) -> (&'static str, &'static str) {
+        match self {
+            Template::TsMocha | Template::TsJest => ("tests", "ts"),
+            Template::Python => ("tests", "py"),
+            Template::Rust => ("e2e", "rs"),
+        }
+    }
+}
+
+/// Example Move module scaffolded into the package, with its
+/// unit tests and a TypeScript end to end test
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Example {
+    /// A counter each account can increment
+    Counter,
+    /// A coin anyone can claim
+    Coin,
+    /// An NFT collection anyone can mint from
+    Nft,
+}
+
+/// Installs the JS dependencies of the TypeScript templates
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PackageManager {
+    Npm,
+    Yarn,
+    Pnpm,
+    Bun,
+}
+
+impl PackageManager {
+    /// The package manager aptest was started through, as npx, pnpm dlx
+    /// or the like tell in npm_config_user_agent, or else the first one
+    /// installed of npm, pnpm, yarn and bun, or else npm
+    pub fn detect() -> Self {
+        let all = [
+            PackageManager::Npm,
+            PackageManager::Pnpm,
+            PackageManager::Yarn,
+            PackageManager::Bun,
+        ];
+        let user_agent = std::env::var("npm_config_user_agent").unwrap_or_default();
+        all.into_iter()
+            .find(|x| user_agent.starts_with(&format!("{}/", x.name())))
+            .or_else(|| all.into_iter().find(|x| installed(&x.program())))
+            .unwrap_or(PackageManager::Npm)
+    }
+
+    /// Name of the command, as npm_config_user_agent has it
+    pub fn name(&self) -> &'static str {
+        match self {
+            PackageManager::Npm => "npm",
+            PackageManager::Yarn => "yarn",
+            PackageManager::Pnpm => "pnpm",
+            PackageManager::Bun => "bun",
+        }
+    }
+
+    /// The command to run, which is a `.cmd` shim on Windows
+    /// except for bun
+    pub fn program(&self) -> String {
+        match self {
+            PackageManager::Bun => self.name().to_string(),
+            _ => process::script(self.name()),
+        }
+    }
+
+    /// The lockfiles it writes, bun having changed formats
+    pub fn lockfiles(&self) -> &'static [&'static str] {
+        match self {
+            PackageManager::Npm => &["package-lock.json"],
+            PackageManager::Yarn => &["yarn.lock"],
+            PackageManager::Pnpm => &["pnpm-lock.yaml"],
+            PackageManager::Bun => &["bun.lock", "bun.lockb"],
+        }
+    }
+
+    /// Arguments installing the dependencies. Frozen installs take the
+    /// lockfile as is, failing when it is out of date
+    pub fn install_args(&self, frozen: bool) -> &'static [&'static str] {
+        match (self, frozen) {
+            (_, false) => &["install"],
+            (PackageManager::Npm, true) => &["ci"],
+            (_, true) => &["install", "--frozen-lockfile"],
+        }
+    }
+}
+
+//Whether a command is found in PATH
+fn installed(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// Sets up a new project: the Move package, the test harness of a
+/// template, and `aptest.toml` recording how it was set up.
+pub struct Init {
+    project: Project,
+    template_url: Option<String>,
+    offline: bool,
+    install: bool,
+    force: bool,
+    skip_move_init: bool,
+}
+
+impl Init {
+    pub fn new(name: impl Into<String>) -> Self {
+        Init {
+            project: Project {
+                name: name.into(),
+                template: Template::TsMocha,
+                package_manager: PackageManager::Npm,
+                example: None,
+            },
+            template_url: None,
+            offline: false,
+            install: true,
+            force: false,
+            skip_move_init: false,
+        }
+    }
+
+    /// Test harness to scaffold, mocha unless set
+    pub fn template(mut self, template: Template) -> Self {
+        self.project.template = template;
+        self
+    }
+
+    /// Git repository to clone the project from instead of scaffolding
+    /// it, with [`NAME_PLACEHOLDER`] replaced by the project name in
+    /// its files and file names
+    pub fn template_url(mut self, url: Option<String>) -> Self {
+        self.template_url = url;
+        self
+    }
+
+    /// Example module to scaffold, needing a TypeScript template
+    pub fn example(mut self, example: Option<Example>) -> Self {
+        self.project.example = example;
+        self
+    }
+
+    /// Package manager installing the JS dependencies, npm unless set
+    pub fn package_manager(mut self, package_manager: PackageManager) -> Self {
+        self.project.package_manager = package_manager;
+        self
+    }
+
+    /// Use baked-in versions instead of asking the npm
+    /// registry for the latest SDK
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Install the dependencies of the harness, done unless set to false
+    pub fn install(mut self, install: bool) -> Self {
+        self.install = install;
+        self
+    }
+
+    /// Set up an existing project, keeping the files it already has
+    /// and only adding the missing ones. The Move package is only
+    /// created if there is no Move.toml.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Leave the Move package out, for adding aptest to an existing
+    /// one or keeping it elsewhere
+    pub fn skip_move_init(mut self, skip: bool) -> Self {
+        self.skip_move_init = skip;
+        self
+    }
+
+    /// Ask for each setting on the terminal, the current ones being
+    /// the defaults and the name of the directory the default name
+    pub fn ask(mut self) -> Result<Self, AptestError> {
+        if self.project.name.is_empty() {
+            self.project.name = std::env::current_dir()
+                .ok()
+                .and_then(|dir| Some(dir.file_name()?.to_string_lossy().into_owned()))
+                .unwrap_or_default();
+        }
+        let project = &mut self.project;
+        project.name = ui::ask("Project name", &project.name)?;
+        if self.template_url.is_some() {
+            self.install = ui::confirm("Install dependencies now?", self.install)?;
+            return Ok(self);
+        }
+        project.template = ui::choose("Test template", project.template)?;
+
+        project.example = None;
+        if matches!(project.template, Template::TsMocha | Template::TsJest) {
+            project.package_manager = ui::choose("Package manager", project.package_manager)?;
+            if ui::confirm("Scaffold an example module?", false)? {
+                project.example = Some(ui::choose("Example", Example::Counter)?);
+            }
+        }
+        if project.template != Template::Rust {
+            self.install = ui::confirm("Install dependencies now?", self.install)?;
+        }
+        Ok(self)
+    }
+
+    /// Init all the files and directories for the project. Files that
+    /// already exist are an error, unless forced to keep them.
+    pub async fn run(&self) -> Result<(), AptestError> {
+        //check for Move.toml
+        let has_package = Path::new("./Move.toml").exists();
+        if has_package && !self.force && !self.skip_move_init {
+            return Err(AptestError::Environment(
+                "Move.toml file already exists here! Use --skip-move-init to add aptest to this package"
+                    .to_string(),
+            ));
+        }
+        if let Some(url) = &self.template_url {
+            return self.remote_init(url).await;
+        }
+        let Project {
+            name,
+            template,
+            example,
+            ..
+        } = &self.project;
+        if example.is_some() && !matches!(template, Template::TsMocha | Template::TsJest) {
+            return Err(AptestError::Environment(
+                "Examples come with TypeScript tests, use the ts-mocha or ts-jest template"
+                    .to_string(),
+            ));
+        }
+
+        self.write("./.gitignore", &gitignore(*template))?;
+        //The Move package and the test harness don't depend
+        //on each other, so they are set up concurrently
+        let move_init = async {
+            if self.skip_move_init || has_package {
+                Ok(())
+            } else {
+                move_init(name).await
+            }
+        };
+        tokio::try_join!(move_init, self.harness_init())?;
+        if let Some(example) = example {
+            self.example_init(*example)?;
+        }
+        self.write_config()
+    }
+
+    //Sets up the tests of the chosen template
+    async fn harness_init(&self) -> Result<(), AptestError> {
+        let name = &self.project.name;
+        match self.project.template {
+            Template::TsMocha => self.js_init().await,
+            Template::TsJest => {
+                self.write("./jest.config.js", JEST_CONFIG)?;
+                self.js_init().await
+            }
+            Template::Python => self.python_init().await,
+            Template::Rust => self.rust_init(name),
+        }
+    }
+
+    //Write package.json, tsconfig.json and an example test,
+    //then install the JS dependencies
+    async fn js_init(&self) -> Result<(), AptestError> {
+        let Project {
+            name,
+            template,
+            package_manager,
+            ..
+        } = &self.project;
+        let package_json = package_json(name, *template, &sdk_version(self.offline).await)?;
+        self.write("./package.json", &package_json)?;
+        self.write("./tsconfig.json", TSCONFIG)?;
+        create_dir("./tests")?;
+        self.write("./tests/example.spec.ts", EXAMPLE_TEST)?;
+        if !self.install {
+            return Ok(());
+        }
+        install(&package_manager.program(), &["install"]).await
+    }
+
+    //Clone a template repository into the project, naming it,
+    //then install the dependencies it declares
+    async fn remote_init(&self, url: &str) -> Result<(), AptestError> {
+        let clone_dir =
+            std::env::temp_dir().join(format!("aptest-template-{}", std::process::id()));
+        ui::info(format!("Cloning template {}...", url));
+        let status = Command::new("git")
+            .args(["clone", "--depth", "1", "--quiet", url])
+            .arg(&clone_dir)
+            .status()
+            .await
+            .or_fail(
+                AptestError::Environment,
+                "Couldn't find git command. Is it installed ?",
+            )?;
+        if !status.success() {
+            return Err(AptestError::Environment(format!(
+                "Could not clone template {}",
+                url
+            )));
+        }
+        let copied = self.copy_template(&clone_dir, Path::new("."));
+        //The clone is only needed for copying
+        let _ = std::fs::remove_dir_all(&clone_dir);
+        copied?;
+
+        if !self.install {
+            return Ok(());
+        }
+        if Path::new("./package.json").exists() {
+            install(&self.project.package_manager.program(), &["install"]).await?;
+        }
+        if Path::new("./requirements.txt").exists() {
+            install("python3", &PIP_INSTALL).await?;
+        }
+        Ok(())
+    }
+
+    //Record how the project was set up in aptest.toml, with the test
+    //command of harnesses not run with npm and the example's address
+    fn write_config(&self) -> Result<(), AptestError> {
+        let mut addresses = BTreeMap::new();
+        if self.project.example.is_some() {
+            addresses.insert(EXAMPLE_ADDRESS, "default");
+        }
+        let command = match self.project.template {
+            Template::Python => Some("python3 -m pytest tests"),
+            Template::Rust => Some("cargo test --manifest-path e2e/Cargo.toml"),
+            Template::TsMocha | Template::TsJest => None,
+        };
+        let config = GeneratedConfig {
+            project: &self.project,
+            addresses,
+            test: command.map(|x| Test {
+                command: Some(x.to_string()),
+                ..Test::default()
+            }),
+        };
+        let contents = toml::to_string(&config).or_fail(
+            AptestError::Environment,
+            &format!("Could not serialize {}", CONFIG_FILE),
+        )?;
+        self.write(CONFIG_FILE, &contents)
+    }
+}
+
+//The sections of aptest.toml written by init
+#[derive(Serialize)]
+struct GeneratedConfig<'a> {
+    project: &'a Project,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    addresses: BTreeMap<&'a str, &'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    test: Option<Test>,
+}
+
+//Build output, aptest's files and those of the harness
+fn gitignore(template: Template) -> String {
+    let harness = match template {
+        Template::TsMocha | Template::TsJest => "node_modules/\n",
+        Template::Python => "__pycache__/\n.pytest_cache/\n",
+        Template::Rust => "e2e/target/\n",
+    };
+    format!(
+        "build/\n.aptest/\naccounts.json\ndeployment.json\nfixtures.json\nvalidator.log\n{}",
+        harness
+    )
+}
+
+//run aptos move init --name args.init.name
+async fn move_init(name: &str) -> Result<(), AptestError> {
+    let mut init_child = Command::new(cli::program())
+        .args(["move", "init", "--name", name])
+        .spawn()
+        .or_fail(
+            AptestError::Environment,
+            "Couldn't find aptos command. Is it installed ?",
+        )?;
+
+    init_child.wait().await.or_fail(
+        AptestError::Environment,
+        "Could not wait for aptos move init to finish",
+    )?;
+    Ok(())
+}
+
+//-----------------------------TypeScript---------------------------------------
+
+const JEST_CONFIG: &str = "module.exports = {
+  preset: \"ts-jest\",
+  testEnvironment: \"node\",
+  testMatch: [\"<rootDir>/tests/**/*.ts\"],
+  testTimeout: 30000,
+};
+";
+
+const TSCONFIG: &str = "{
+  \"compilerOptions\": {
+    \"target\": \"es2020\",
+    \"module\": \"commonjs\",
+    \"strict\": true,
+    \"esModuleInterop\": true,
+    \"skipLibCheck\": true
+  },
+  \"include\": [\"tests/**/*.ts\"]
+}
+";
+
+//Runs under both mocha and jest, hence node's assert
+const EXAMPLE_TEST: &str = "import assert from \"assert\";
+import { Account, Aptos, AptosConfig, Network } from \"@aptos-labs/ts-sdk\";
+
+//Set by aptest for the node it started
+const aptos = new Aptos(
+  new AptosConfig({
+    network: Network.CUSTOM,
+    fullnode: `${process.env.APTEST_NODE_URL}/v1`,
+    faucet: process.env.APTEST_FAUCET_URL,
+  })
+);
+
+describe(\"local node\", () => {
+  it(\"funds a new account\", async () => {
+    const account = Account.generate();
+
+    await aptos.fundAccount({
+      accountAddress: account.accountAddress,
+      amount: 100_000_000,
+    });
+
+    const balance = await aptos.getAccountAPTAmount({
+      accountAddress: account.accountAddress,
+    });
+    assert.strictEqual(balance, 100_000_000);
+  });
+});
+";
+
+//The TypeScript SDK the tests are written with
+const SDK_PACKAGE: &str = "@aptos-labs/ts-sdk";
+
+//Versions used with --offline, or when the registry can't be reached
+const SDK_VERSION: &str = "^1.33.1";
+const TYPESCRIPT_VERSION: &str = "^5.3.3";
+const TYPES_NODE_VERSION: &str = "^20.11.0";
+
+const NPM_REGISTRY: &str = "https://registry.npmjs.org";
+
+//package.json running the tests of the template
+fn package_json(name: &str, template: Template, sdk_version: &str) -> Result<String, AptestError> {
+    let (test, mut dependencies) = match template {
+        Template::TsJest => (
+            "jest",
+            json!({
+                "@types/jest": "^29.5.12",
+                "jest": "^29.7.0",
+                "ts-jest": "^29.1.2",
+            }),
+        ),
+        _ => (
+            "mocha --timeout 30000 -r ts-node/register 'tests/**/*.ts'",
+            json!({
+                "@types/mocha": "^10.0.6",
+                "mocha": "^10.2.0",
+                "ts-node": "^10.9.2",
+            }),
+        ),
+    };
+    dependencies[SDK_PACKAGE] = json!(sdk_version);
+    dependencies["@types/node"] = json!(TYPES_NODE_VERSION);
+    dependencies["typescript"] = json!(TYPESCRIPT_VERSION);
+
+    let package_json = json!({
+        "name": format!("test_{}", name),
+        "version": "1.0.0",
+        "private": true,
+        "scripts": { "test": test },
+        "dependencies": dependencies,
+    });
+    serde_json::to_string_pretty(&package_json)
+        .or_fail(AptestError::Environment, "Could not serialize package.json")
+}
+
+//Latest version of the SDK on the npm registry, or the
+//baked-in one when offline or the registry can't be reached
+async fn sdk_version(offline: bool) -> String {
+    if offline {
+        return SDK_VERSION.to_string();
+    }
+    match latest_version(SDK_PACKAGE).await {
+        Some(version) => format!("^{}", version),
+        None => {
+            ui::debug(format!(
+                "Could not reach the npm registry, using {} {}",
+                SDK_PACKAGE, SDK_VERSION
+            ));
+            SDK_VERSION.to_string()
+        }
+    }
+}
+
+async fn latest_version(package: &str) -> Option<String> {
+    let url = format!("{}/{}/latest", NPM_REGISTRY, package);
+    let response = reqwest::Client::new()
+        .get(url)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .ok()?;
+    let latest: Value = response.error_for_status().ok()?.json().await.ok()?;
+    latest["version"].as_str().map(|x| x.to_string())
+}
+
+//-----------------------------Python---------------------------------------
+
+const REQUIREMENTS: &str = "aptos-sdk>=0.5.1
+pytest>=7.2.0
+";
+
+const PYTHON_TEST: &str = "import os
+
+from aptos_sdk.client import RestClient
+
+
+def test_node_is_up():
+    client = RestClient(os.environ[\"APTEST_NODE_URL\"] + \"/v1\")
+    assert client.chain_id > 0
+";
+
+const PIP_INSTALL: [&str; 5] = ["-m", "pip", "install", "-r", "requirements.txt"];
+
+impl Init {
+    //Write requirements.txt and a first test,
+    //then install the Python dependencies
+    async fn python_init(&self) -> Result<(), AptestError> {
+        self.write("./requirements.txt", REQUIREMENTS)?;
+        create_dir("./tests")?;
+        self.write("./tests/test_node.py", PYTHON_TEST)?;
+        if !self.install {
+            return Ok(());
+        }
+        install("python3", &PIP_INSTALL).await
+    }
+}
+
+//-----------------------------Rust---------------------------------------
+
+const RUST_TEST: &str = "use aptos_sdk::rest_client::Client;
+
+#[tokio::test]
+async fn node_is_up() {
+    let url = std::env::var(\"APTEST_NODE_URL\").expect(\"tests are run by aptest\");
+    let client = Client::new(url.parse().unwrap());
+    client.get_ledger_information().await.unwrap();
+}
+";
+
+impl Init {
+    //Write the e2e crate, whose dependencies
+    //are fetched by cargo on the first run
+    fn rust_init(&self, name: &str) -> Result<(), AptestError> {
+        let cargo_toml = format!(
+            "[package]
+name = \"test_{}\"
+version = \"0.1.0\"
+edition = \"2021\"
+publish = false
+
+[dev-dependencies]
+aptos-sdk = {{ git = \"https://github.com/aptos-labs/aptos-core\", branch = \"devnet\" }}
+tokio = {{ version = \"1\", features = [\"full\"] }}
+",
+            name
+        );
+        create_dir("./e2e/src")?;
+        create_dir("./e2e/tests")?;
+        self.write("./e2e/Cargo.toml", &cargo_toml)?;
+        self.write("./e2e/src/lib.rs", "")?;
+        self.write("./e2e/tests/node.rs", RUST_TEST)
+    }
+}
+
+//-----------------------------Examples---------------------------------------
+
+//Named address of the example modules, bound to the default profile
+const EXAMPLE_ADDRESS: &str = "example";
+
+//Where the example modules are published in unit tests
+const EXAMPLE_DEV_ADDRESS: &str = "0xcafe";
+
+//File name, Move module and end to end test of an example
+fn example_sources(example: Example) -> (&'static str, &'static str, &'static str) {
+    match example {
+        Example::Counter => (
+            "counter",
+            include_str!("../templates/examples/counter.move"),
+            include_str!("../templates/examples/counter.spec.ts"),
+        ),
+        Example::Coin => (
+            "example_coin",
+            include_str!("../templates/examples/coin.move"),
+            include_str!("../templates/examples/coin.spec.ts"),
+        ),
+        Example::Nft => (
+            "example_nft",
+            include_str!("../templates/examples/nft.move"),
+            include_str!("../templates/examples/nft.spec.ts"),
+        ),
+    }
+}
+
+impl Init {
+    //Write the example module and its test, and declare its named
+    //address in Move.toml
+    fn example_init(&self, example: Example) -> Result<(), AptestError> {
+        let (file, module, test) = example_sources(example);
+        create_dir("./sources")?;
+        self.write(format!("./sources/{}.move", file), module)?;
+        self.write(format!("./tests/{}.spec.ts", file), test)?;
+
+        let manifest = std::fs::read_to_string("./Move.toml")
+            .or_fail(AptestError::Environment, "Could not read Move.toml")?;
+        let mut manifest = add_address(&manifest, "addresses", "_");
+        manifest = add_address(&manifest, "dev-addresses", EXAMPLE_DEV_ADDRESS);
+        if example == Example::Nft && !manifest.contains("[dependencies.AptosTokenObjects]") {
+            manifest = add_token_objects(&manifest)?;
+        }
+        write_file("./Move.toml", &manifest)
+    }
+}
+
+//Adds the example's named address to a section of Move.toml,
+//adding the section if it is missing. Does nothing if it's there
+fn add_address(manifest: &str, section: &str, value: &str) -> String {
+    let header = format!("[{}]\n", section);
+    let line = format!("{} = \"{}\"\n", EXAMPLE_ADDRESS, value);
+    if manifest.contains(&format!("{}{}", header, line)) {
+        return manifest.to_string();
+    }
+    match manifest.find(&header) {
+        Some(at) => {
+            let (before, after) = manifest.split_at(at + header.len());
+            format!("{}{}{}", before, line, after)
+        }
+        None => format!("{}\n{}{}", manifest, header, line),
+    }
+}
+
+//Adds AptosTokenObjects to Move.toml, from the same
+//revision as the AptosFramework dependency
+fn add_token_objects(manifest: &str) -> Result<String, AptestError> {
+    let parsed: toml::Value =
+        toml::from_str(manifest).or_fail(AptestError::Environment, "Could not parse Move.toml")?;
+    let framework = parsed
+        .get("dependencies")
+        .and_then(|x| x.get("AptosFramework"));
+    let (git, rev) = framework
+        .and_then(|x| Some((x.get("git")?.as_str()?, x.get("rev")?.as_str()?)))
+        .ok_or_else(|| {
+            AptestError::Environment(
+                "Move.toml has no git dependency on AptosFramework".to_string(),
+            )
+        })?;
+    Ok(format!(
+        "{}\n\n[dependencies.AptosTokenObjects]\ngit = \"{}\"\nrev = \"{}\"\nsubdir = \"aptos-move/framework/aptos-token-objects\"\n",
+        manifest.trim_end(),
+        git,
+        rev
+    ))
+}
+
+//-----------------------------Files---------------------------------------
+
+/// Replaced by the project name in the files and file
+/// names of templates cloned from a git repository
+pub const NAME_PLACEHOLDER: &str = "{{project_name}}";
+
+impl Init {
+    //Copies a cloned template into the project, but for its
+    //git metadata, naming the project along the way
+    fn copy_template(&self, from: &Path, to: &Path) -> Result<(), AptestError> {
+        let name = &self.project.name;
+        let entries = std::fs::read_dir(from).or_fail(
+            AptestError::Environment,
+            &format!("Could not read {}", from.display()),
+        )?;
+        for entry in entries {
+            let entry = entry.or_fail(AptestError::Environment, "Could not read the template")?;
+            let file_name = entry
+                .file_name()
+                .to_string_lossy()
+                .replace(NAME_PLACEHOLDER, name);
+            if file_name == ".git" {
+                continue;
+            }
+            let (source, target) = (entry.path(), to.join(file_name));
+            if source.is_dir() {
+                create_dir(&target)?;
+                self.copy_template(&source, &target)?;
+                continue;
+            }
+            if self.keep(&target)? {
+                continue;
+            }
+            //Binary files are copied as they are
+            let copied = match std::fs::read_to_string(&source) {
+                Ok(text) => std::fs::write(&target, text.replace(NAME_PLACEHOLDER, name)),
+                Err(_) => std::fs::copy(&source, &target).map(|_| ()),
+            };
+            copied.or_fail(
+                AptestError::Environment,
+                &format!("Could not copy {}", source.display()),
+            )?;
+        }
+        Ok(())
+    }
+
+    //Writes a file of the project, unless it exists already
+    fn write(&self, path: impl AsRef<Path>, contents: &str) -> Result<(), AptestError> {
+        if self.keep(path.as_ref())? {
+            Ok(())
+        } else {
+            write_file(path, contents)
+        }
+    }
+
+    //Whether to leave an existing file as it is, which is an
+    //error unless forced, rather than overwriting it
+    fn keep(&self, path: &Path) -> Result<bool, AptestError> {
+        if !path.exists() {
+            return Ok(false);
+        }
+        if !self.force {
+            return Err(AptestError::Environment(format!(
+                "{} already exists! Use --force to keep it and only add what is missing",
+                path.display()
+            )));
+        }
+        ui::print(format!("Keeping existing {}", path.display()));
+        Ok(true)
+    }
+}
+
+//Runs a package manager's install command
+async fn install(program: &str, args: &[&str]) -> Result<(), AptestError> {
+    ui::info("Installing dependencies...");
+    let mut install_child = Command::new(program).args(args).spawn().or_fail(
+        AptestError::Environment,
+        &format!("Couldn't find {} command. Is it installed ?", program),
+    )?;
+    install_child.wait().await.or_fail(
+        AptestError::Environment,
+        &format!("Could not wait for {} install to finish", program),
+    )?;
+    Ok(())
+}
+
+fn write_file(path: impl AsRef<Path>, contents: &str) -> Result<(), AptestError> {
+    let path = path.as_ref();
+    std::fs::write(path, contents).or_fail(
+        AptestError::Environment,
+        &format!("Failed to create file {}", path.display()),
+    )
+}
+
+fn create_dir(path: impl AsRef<Path>) -> Result<(), AptestError> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path).or_fail(
+        AptestError::Environment,
+        &format!("Could not create directory {}", path.display()),
+    )
+}
+
+#[test]
+fn test_package_json() {
+    let package_json = package_json("counter", Template::TsJest, "^1.0.0").unwrap();
+    let package_json: Value = serde_json::from_str(&package_json).unwrap();
+    assert_eq!(package_json["name"], "test_counter");
+    assert_eq!(package_json["scripts"]["test"], "jest");
+    assert_eq!(package_json["dependencies"]["@aptos-labs/ts-sdk"], "^1.0.0");
+    assert_eq!(package_json["dependencies"]["ts-jest"], "^29.1.2");
+}
+
+#[test]
+fn test_add_address() {
+    let manifest = "[package]\nname = \"counter\"\n\n[addresses]\n\n[dev-addresses]\n";
+    let manifest = add_address(manifest, "addresses", "_");
+    let manifest = add_address(&manifest, "dev-addresses", "0xcafe");
+    assert_eq!(
+        manifest,
+        "[package]\nname = \"counter\"\n\n[addresses]\nexample = \"_\"\n\n[dev-addresses]\nexample = \"0xcafe\"\n"
+    );
+    assert_eq!(
+        add_address("[package]\n", "addresses", "_"),
+        "[package]\n\n[addresses]\nexample = \"_\"\n"
+    );
+}
@@ -0,0 +1,79 @@
+//! A small framework to assist in testing aptos programs.
+//!
+//! The `aptest` binary is a thin wrapper around this library, which can
+//! also be used directly to drive a local network from other Rust tools:
+//!
+//! ```no_run
+//! use aptest::signal::Interrupt;
+//! use aptest::{Faucet, LocalNode, Publisher, TestRunner};
+//!
+//! # async fn run() -> Result<(), aptest::AptestError> {
+//! let interrupt = Interrupt::listen()?;
+//! let publisher = Publisher::new();
+//! publisher.compile().await?;
+//!
+//! let net = LocalNode::new()
+//!     .faucet(Some(Faucet::new().port(8000)))
+//!     .start(&interrupt)
+//!     .await?;
+//!
+//! publisher.node_url(net.node_url()).publish().await?;
+//! TestRunner::new()
+//!     .env("APTEST_NODE_URL", net.node_url())
+//!     .run(&interrupt)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod abort;
+pub mod accounts;
+pub mod args;
+pub mod bench;
+pub mod clean;
+pub mod cli;
+pub mod clock;
+pub mod codegen;
+pub mod config;
+pub mod coverage;
+pub mod deps;
+pub mod diff;
+pub mod error;
+pub mod events;
+pub mod faucet;
+pub mod fixture;
+pub mod fuzz;
+pub mod gas;
+pub mod history;
+pub mod hooks;
+pub mod init;
+pub mod lock;
+pub mod logs;
+pub mod matrix;
+pub mod multisig;
+pub mod network;
+pub mod node;
+pub mod process;
+pub mod profile;
+pub mod proxy;
+pub mod publish;
+pub mod recording;
+pub mod report;
+pub mod rest;
+pub mod retry;
+pub mod runner;
+pub mod seed;
+pub mod shard;
+pub mod signal;
+pub mod snapshot;
+pub mod state;
+pub mod transaction;
+pub mod ui;
+pub mod watch;
+pub mod workspace;
+
+pub use error::AptestError;
+pub use faucet::Faucet;
+pub use node::{LocalNet, LocalNode};
+pub use publish::Publisher;
+pub use runner::TestRunner;
@@ -0,0 +1,112 @@
+//! Keeps two runs from sharing a project, where they would fight over the
+//! node's ports, `.aptos/config.yaml` and the logs. Isolated runs have
+//! their own of each and don't take the lock.
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{AptestError, OrFail};
+use crate::process::alive;
+
+/// Holds the pid of the run that has the project
+pub const LOCK_FILE: &str = ".aptest/lock";
+
+/// The project taken by this process, given back when dropped
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    /// Take the project, failing with the pid of the run that has it if
+    /// it is still going. A lock left behind by a run that is gone is
+    /// taken over, and `force` takes it from a live one too
+    pub fn acquire(force: bool) -> Result<Self, AptestError> {
+        let path = PathBuf::from(LOCK_FILE);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).or_fail(
+                AptestError::Environment,
+                &format!("Could not create directory {}", dir.display()),
+            )?;
+        }
+        //Should another run take it over at the same time, only one of
+        //the two creates it again
+        for _ in 0..2 {
+            match create(&path) {
+                Ok(()) => return Ok(Lock { path }),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(AptestError::Environment(format!(
+                        "Could not write {}\n\n{}",
+                        path.display(),
+                        e
+                    )))
+                }
+            }
+            match holder(&path) {
+                Some(pid) if !force && pid != std::process::id() && alive(pid) => {
+                    return Err(AptestError::Environment(format!(
+                        "Another aptest run (pid {}) is going in this project. Wait for it to \
+                         finish, run with --isolate to run alongside it, or with --force-lock \
+                         if it isn't aptest",
+                        pid
+                    )))
+                }
+                //Just created by a run yet to write its pid
+                None if !force => {
+                    return Err(AptestError::Environment(format!(
+                        "Another aptest run is taking {}, run with --force-lock if none is",
+                        path.display()
+                    )))
+                }
+                _ => {
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+        Err(AptestError::Environment(format!(
+            "Another aptest run took {} at the same time",
+            path.display()
+        )))
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        //Unless another run took it over with --force-lock
+        if holder(&self.path) == Some(std::process::id()) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// The run holding the project, if it is still going
+pub fn running() -> Option<u32> {
+    holder(Path::new(LOCK_FILE)).filter(|&pid| alive(pid))
+}
+
+/// Remove the lock of a run that is gone, returning whether there was one
+pub fn remove_stale() -> bool {
+    running().is_none() && std::fs::remove_file(LOCK_FILE).is_ok()
+}
+
+//Creates the lock with this process' pid, failing if it exists
+fn create(path: &Path) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    write!(file, "{}", std::process::id())
+}
+
+//The pid of the run holding a lock, if it can be read
+fn holder(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[test]
+fn test_holder() {
+    let path = std::env::temp_dir().join(format!("aptest-lock-{}", std::process::id()));
+    assert_eq!(holder(&path), None);
+    create(&path).unwrap();
+    assert_eq!(holder(&path), Some(std::process::id()));
+    assert_eq!(create(&path).unwrap_err().kind(), ErrorKind::AlreadyExists);
+    std::fs::remove_file(&path).unwrap();
+}
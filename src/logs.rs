@@ -0,0 +1,110 @@
+//! The output of the validator and faucet, written to files as they run
+//! so that it can be read from another terminal with `aptest logs`.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::{AptestError, OrFail};
+use crate::signal::Interrupt;
+
+/// Where the logs of the network are written
+pub const LOG_DIR: &str = ".aptest/logs";
+
+/// Output of the validator, or of the whole stack with the indexer
+pub const NODE_LOG: &str = "node.log";
+
+/// Output of the faucet
+pub const FAUCET_LOG: &str = "faucet.log";
+
+//Bytes read from the end of a log for its last lines, so that a large
+//log isn't read whole
+const TAIL_BYTES: u64 = 1 << 20;
+
+//How often a followed log is checked for new output
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Create the file a log is written to, replacing the one of the
+/// previous network
+pub fn create(path: &Path) -> Result<File, AptestError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).or_fail(
+            AptestError::Environment,
+            &format!("Could not create directory {}", dir.display()),
+        )?;
+    }
+    File::create(path).or_fail(
+        AptestError::Environment,
+        &format!("Could not create {}", path.display()),
+    )
+}
+
+/// Copy a line of output to a log file, if there is one. Losing output
+/// is better than stopping the network, so errors are ignored.
+pub(crate) fn write_line(output: &mut Option<File>, line: &str) {
+    if let Some(file) = output {
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+/// The last `lines` lines of a log, and the length it had when read
+pub fn tail(path: &Path, lines: usize) -> Result<(String, u64), AptestError> {
+    let mut file = File::open(path).or_fail(
+        AptestError::Environment,
+        &format!("Could not open {}", path.display()),
+    )?;
+    let len = file
+        .seek(SeekFrom::End(0))
+        .or_fail(AptestError::Environment, "Could not read the log")?;
+    let start = len.saturating_sub(TAIL_BYTES);
+    let mut bytes = Vec::new();
+    file.seek(SeekFrom::Start(start))
+        .and_then(|_| file.read_to_end(&mut bytes))
+        .or_fail(AptestError::Environment, "Could not read the log")?;
+    Ok((last_lines(&String::from_utf8_lossy(&bytes), lines), len))
+}
+
+/// Print what is written to a log from `offset` on, until interrupted.
+/// A log starting over, for a new network, is printed from its start.
+pub async fn follow(
+    path: &Path,
+    mut offset: u64,
+    interrupt: &Interrupt,
+) -> Result<(), AptestError> {
+    loop {
+        interrupt.sleep(FOLLOW_INTERVAL).await?;
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        let len = file
+            .seek(SeekFrom::End(0))
+            .or_fail(AptestError::Environment, "Could not read the log")?;
+        if len < offset {
+            offset = 0;
+        }
+        if len == offset {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_to_end(&mut bytes))
+            .or_fail(AptestError::Environment, "Could not read the log")?;
+        offset += bytes.len() as u64;
+        print!("{}", String::from_utf8_lossy(&bytes));
+    }
+}
+
+//The last lines of a text
+fn last_lines(text: &str, lines: usize) -> String {
+    let all: Vec<_> = text.lines().collect();
+    all[all.len().saturating_sub(lines)..].join("\n")
+}
+
+#[test]
+fn test_last_lines() {
+    assert_eq!(last_lines("a\nb\nc\n", 2), "b\nc");
+    assert_eq!(last_lines("a\nb\n", 5), "a\nb");
+    assert_eq!(last_lines("", 3), "");
+}
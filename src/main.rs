@@ -1,443 +1,3581 @@
-use clap::{Parser, Subcommand};
+use aptest::abort::ErrorMap;
+use aptest::accounts::{TestAccount, DEFAULT_FUND_AMOUNT};
+use aptest::bench::Bench;
+use aptest::config::{Config, FeePayer, Isolation, Profile, Suite, CONFIG_FILE};
+use aptest::error::OrFail;
+use aptest::events::EVENTS_FILE;
+use aptest::faucet::{Funder, FAUCET_URL};
+use aptest::fuzz::Fuzzer;
+use aptest::gas::{GasReport, BASELINE_PATH, DEFAULT_MAX_INCREASE};
+use aptest::history::TRANSACTIONS_FILE;
+use aptest::init::{Example, Init, PackageManager, Template};
+use aptest::lock::{Lock, LOCK_FILE};
+use aptest::logs::{FAUCET_LOG, LOG_DIR, NODE_LOG};
+use aptest::multisig::{Multisig, Threshold};
+use aptest::network::Remote;
+use aptest::node::{GENESIS_CACHE_DIR, NODE_URL};
+use aptest::profile::AptosConfig;
+use aptest::proxy::{Chaos, Proxy, CHAOS_PROFILES, PROXY_LOG_FILE};
+use aptest::publish::{IncludedArtifacts, UpgradePolicy};
+use aptest::recording::{Entry, Recording};
+use aptest::report::{print_matrix_summary, write_matrix, MatrixRun, Report};
+use aptest::rest::NodeClient;
+use aptest::retry::Retry;
+use aptest::shard::Shard;
+use aptest::signal::Interrupt;
+use aptest::state::{LastRun, Localnet, State};
+use aptest::transaction::EntryFunction;
+use aptest::ui::{self, OutputFormat, Verbosity};
+use aptest::watch::Watcher;
+use aptest::{AptestError, Faucet, LocalNet, LocalNode, Publisher, TestRunner};
+use clap::{
+    ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum, ValueSource,
+};
+use clap_complete::Shell;
 use colored::*;
+use serde_json::json;
 
-use std::fs::File;
-use std::io::{Read, Write};
-use std::process::{Child, Command, Output, Stdio};
-use std::sync::mpsc::channel;
-use std::thread::sleep;
-use std::time::Duration;
-use yaml_rust::YamlLoader;
-
-macro_rules! pretty_expect {
-    ($e:expr, $msg:expr) => {
-        match $e {
-            Ok(v) => v,
-            Err(e) => {
-                println!("\n{}\n", $msg);
-                println!("{}\n", e);
-                std::process::exit(1);
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+///A small framework to assist in testing aptos programs
+#[derive(Parser)]
+#[clap(version, about, long_about = None)]
+struct Sub {
+    #[clap(subcommand)]
+    cmd: Subcmds,
+    ///Disables colored output, as does setting NO_COLOR
+    #[clap(long, global = true)]
+    no_color: bool,
+    ///Runs as if aptest was started in this directory
+    #[clap(long, global = true, value_name = "DIR")]
+    dir: Option<PathBuf>,
+}
+#[derive(Parser)]
+struct InitArgs {
+    #[clap(required_unless_present = "interactive")]
+    name: Option<String>,
+
+    ///Test harness to scaffold next to the Move package
+    #[clap(long, value_enum, default_value = "ts-mocha")]
+    template: Template,
+
+    ///Git repository to clone the project from instead, with {{project_name}}
+    ///replaced by the name of the project
+    #[clap(long, value_name = "URL", conflicts_with_all = &["template", "example"])]
+    template_url: Option<String>,
+
+    ///Uses baked-in dependency versions instead of asking the npm
+    ///registry for the latest SDK
+    #[clap(long)]
+    offline: bool,
+
+    ///Example module to scaffold, with Move unit tests and an end to end test
+    #[clap(long, value_enum)]
+    example: Option<Example>,
+
+    ///Package manager installing the JS dependencies and running the tests,
+    ///by default the one aptest was started through or the first installed
+    #[clap(long, value_enum, value_name = "PM")]
+    pm: Option<PackageManager>,
+
+    ///Sets up an existing project, keeping its files and only adding the
+    ///missing ones. The Move package is only created if there is no Move.toml
+    #[clap(long)]
+    force: bool,
+
+    ///Leaves the Move package out, for adding aptest to an existing one
+    #[clap(long)]
+    skip_move_init: bool,
+
+    ///Doesn't install the dependencies of the test harness
+    #[clap(long)]
+    no_install: bool,
+
+    ///Asks for each setting instead, the options given being the defaults
+    #[clap(long, short)]
+    interactive: bool,
+}
+
+#[derive(Clone, Parser)]
+struct Args {
+    ///Removes call to "aptos move compile", as --skip compile does
+    #[clap(short = 'c', long, env = "APTEST_NO_COMPILE")]
+    no_compile: bool,
+
+    ///Removes call to "aptos move publish", as --skip publish does
+    #[clap(short = 'p', long, env = "APTEST_NO_PUBLISH")]
+    no_publish: bool,
+
+    ///Runs only these phases of the pipeline. The node is only started
+    ///when publishing or running the tests
+    #[clap(
+        long,
+        env = "APTEST_ONLY",
+        value_enum,
+        value_name = "PHASES",
+        value_delimiter = ',',
+        conflicts_with = "skip"
+    )]
+    only: Vec<Phase>,
+
+    ///Skips these phases of the pipeline
+    #[clap(
+        long,
+        env = "APTEST_SKIP",
+        value_enum,
+        value_name = "PHASES",
+        value_delimiter = ','
+    )]
+    skip: Vec<Phase>,
+
+    ///Runs only these test suites, named in aptest.toml or given by the
+    ///file pattern of the [test] section
+    #[clap(
+        long,
+        env = "APTEST_SUITES",
+        value_name = "SUITES",
+        value_delimiter = ',',
+        conflicts_with_all = &["interactive", "watch", "replay"]
+    )]
+    suite: Vec<String>,
+
+    ///Runs only this shard of the end to end tests, like 2/5: every 5th of
+    ///the test suites, or of the test files under tests/ if there are none
+    #[clap(
+        long,
+        env = "APTEST_SHARD",
+        value_name = "INDEX/TOTAL",
+        value_parser,
+        conflicts_with_all = &["interactive", "watch"]
+    )]
+    shard: Option<Shard>,
+
+    ///Test files of the shard, found when the run starts
+    #[clap(skip)]
+    shard_files: Vec<PathBuf>,
+
+    ///Specifies the maximum number of seconds to wait on the validator
+    ///and faucet spinning up and answering requests
+    #[clap(short = 'd', long, env = "APTEST_START_DELAY", default_value = "60")]
+    start_delay: u64,
+
+    ///Seconds allowed to funding each profile before failing
+    #[clap(long, env = "APTEST_FUND_TIMEOUT", value_name = "SECS")]
+    fund_timeout: Option<u64>,
+
+    ///Seconds allowed to each "aptos move publish" before failing
+    #[clap(long, env = "APTEST_PUBLISH_TIMEOUT", value_name = "SECS")]
+    publish_timeout: Option<u64>,
+
+    ///Seconds allowed to the end to end tests before killing them
+    #[clap(long, env = "APTEST_TEST_TIMEOUT", value_name = "SECS")]
+    test_timeout: Option<u64>,
+
+    ///Runs the end to end tests again when they fail or time out, up to
+    ///this many times, the run then passing as flaky
+    #[clap(
+        long,
+        env = "APTEST_TEST_RETRIES",
+        value_name = "N",
+        default_value = "0"
+    )]
+    test_retries: u32,
+
+    ///Run just the validator node, without a faucet, as --skip faucet does
+    #[clap(long, short = 'f', env = "APTEST_NO_FAUCET")]
+    no_faucet: bool,
+
+    ///Generates a new genesis for the node instead of starting fresh chains
+    ///from the one cached in .aptest/genesis
+    #[clap(long, env = "APTEST_NO_GENESIS_CACHE")]
+    no_genesis_cache: bool,
+
+    ///Runs this aptos-node binary instead of the one in PATH
+    #[clap(long, env = "APTEST_NODE_BIN", value_name = "PATH")]
+    node_bin: Option<PathBuf>,
+
+    ///Runs this aptos-faucet binary instead of the one in PATH
+    #[clap(long, env = "APTEST_FAUCET_BIN", value_name = "PATH")]
+    faucet_bin: Option<PathBuf>,
+
+    ///Runs this aptos CLI binary instead of the one in PATH
+    #[clap(long, env = "APTEST_APTOS_BIN", value_name = "PATH")]
+    aptos_bin: Option<PathBuf>,
+
+    ///Starts validator and waits for Ctrl+C so that end to end tests can be run manually
+    #[clap(long, short, env = "APTEST_INTERACTIVE")]
+    interactive: bool,
+
+    ///Starts the node again when it exits during an --interactive session,
+    ///up to this many times, funding and publishing to it again
+    #[clap(
+        long,
+        env = "APTEST_RESTART_NODE",
+        value_name = "N",
+        requires = "interactive"
+    )]
+    restart_node: Option<u32>,
+
+    ///Generates TypeScript bindings for the published modules into
+    ///tests/generated before the tests run
+    #[clap(long, env = "APTEST_CODEGEN")]
+    codegen: bool,
+
+    ///Runs the Move unit tests with coverage before starting the node,
+    ///printing the coverage of each module and writing it as lcov to
+    ///.aptest/coverage/lcov.info
+    #[clap(long, env = "APTEST_COVERAGE")]
+    coverage: bool,
+
+    ///Prints the gas used by the publish transactions and by each entry
+    ///function the tests called once the run is over
+    #[clap(long, env = "APTEST_GAS_REPORT")]
+    gas_report: bool,
+
+    ///Fails the run when the gas used by a function grows past the
+    ///baseline in .aptest/gas-baseline.json, implies --gas-report
+    #[clap(long, env = "APTEST_GAS_CHECK")]
+    gas_check: bool,
+
+    ///Runs "aptos move prove" after compiling, failing proofs halting
+    ///the run before the node starts
+    #[clap(long, env = "APTEST_PROVE")]
+    prove: bool,
+
+    ///Logs the output of the validator to a file
+    #[clap(long = "log", short, env = "APTEST_LOG")]
+    log_node: bool,
+
+    ///Keeps the node alive and re-runs compile, publish and the tests
+    ///whenever sources/**/*.move or tests/**/*.ts change
+    #[clap(long, short, env = "APTEST_WATCH", conflicts_with = "interactive")]
+    watch: bool,
+
+    ///Doesn't install the JS dependencies of the tests when they are
+    ///missing or older than package.json or the lockfile
+    #[clap(long, env = "APTEST_NO_INSTALL")]
+    no_install: bool,
+
+    ///Installs the JS dependencies from the lockfile as is, failing when
+    ///it is out of date, as CI wants
+    #[clap(long, env = "APTEST_FROZEN", conflicts_with = "no_install")]
+    frozen: bool,
+
+    ///Caches the installed JS dependencies in this directory by the hash of
+    ///the lockfile, restoring them instead of installing again
+    #[clap(
+        long,
+        env = "APTEST_DEPS_CACHE",
+        value_name = "DIR",
+        min_values = 0,
+        default_missing_value = aptest::deps::CACHE_DIR,
+        conflicts_with = "no_install"
+    )]
+    deps_cache: Option<PathBuf>,
+
+    ///Leaves the node running once the tests are over, passed or not, with
+    ///its URLs and account keys printed, until Ctrl+C
+    #[clap(long, env = "APTEST_KEEP_ALIVE", conflicts_with_all = &["interactive", "watch", "replay"])]
+    keep_alive: bool,
+
+    ///Skips the compile, prove and coverage phases the last run got through,
+    ///unless the packages changed since, continuing from the one that failed
+    #[clap(long, env = "APTEST_RESUME", conflicts_with_all = &["isolate", "watch", "replay"])]
+    resume: bool,
+
+    ///Keeps the node's data in this directory so that chain state
+    ///(published modules, funded accounts) survives restarts
+    #[clap(long, env = "APTEST_PERSIST_STATE", value_name = "DIR")]
+    persist_state: Option<PathBuf>,
+
+    ///Wipes the persisted chain state before starting the node
+    #[clap(long, env = "APTEST_RESET", requires = "persist_state")]
+    reset: bool,
+
+    ///Runs on free ports with profiles and files of its own, so that
+    ///several runs can share a machine and a project
+    #[clap(long, env = "APTEST_ISOLATE", conflicts_with_all = &["persist_state", "attach", "no_auto_profile"])]
+    isolate: bool,
+
+    ///Takes the project from the run holding .aptest/lock even though its
+    ///process is still alive, for when it isn't aptest
+    #[clap(long, env = "APTEST_FORCE_LOCK", conflicts_with = "isolate")]
+    force_lock: bool,
+
+    ///Also runs the indexer and its GraphQL API through the aptos CLI,
+    ///which needs Docker, exposing it to the tests as APTEST_INDEXER_URL
+    #[clap(long, env = "APTEST_WITH_INDEXER", conflicts_with_all = &["attach", "isolate"])]
+    with_indexer: bool,
+
+    ///Uses the node already running at this URL instead of starting one.
+    ///A node answering on the default port is attached to automatically
+    #[clap(
+        long,
+        env = "APTEST_ATTACH",
+        value_name = "URL",
+        min_values = 0,
+        default_missing_value = NODE_URL
+    )]
+    attach: Option<String>,
+
+    ///Publishes to and tests against a public network instead of a local
+    ///one: devnet, testnet or the URL of a node. Only devnet has a faucet
+    ///to fund with
+    #[clap(
+        long,
+        env = "APTEST_NETWORK",
+        value_name = "NETWORK",
+        conflicts_with_all = &["attach", "persist_state", "isolate", "with_indexer", "replay", "restart_node"]
+    )]
+    network: Option<String>,
+
+    ///Generates and funds this many extra test accounts, written to
+    ///accounts.json and exposed to the tests through env variables
+    #[clap(long, env = "APTEST_ACCOUNTS", value_name = "N", default_value = "0")]
+    accounts: usize,
+
+    ///Derives the extra test accounts from this hex seed instead of
+    ///generating them at random, so that their addresses are known ahead
+    #[clap(long, env = "APTEST_ACCOUNT_SEED", value_name = "HEX")]
+    account_seed: Option<String>,
+
+    ///Generates and funds an account paying the gas of sponsored
+    ///transactions, exposed to the tests through env variables
+    #[clap(long, env = "APTEST_FEE_PAYER")]
+    fee_payer: bool,
+
+    ///Creates and funds a multisig account needing K of N generated owners
+    ///to approve, like 2-of-3, exposed to the tests through env variables
+    #[clap(long, env = "APTEST_MULTISIG", value_name = "K-OF-N", value_parser)]
+    multisig: Option<Threshold>,
+
+    ///Octas to fund each publishing profile with, instead of the aptos CLI default
+    #[clap(long, env = "APTEST_FUND_AMOUNT", value_name = "OCTAS")]
+    fund_amount: Option<u64>,
+
+    ///Aptos profiles to fund before publishing, besides the publishing ones
+    #[clap(
+        long,
+        env = "APTEST_FUND_PROFILES",
+        value_name = "PROFILES",
+        value_delimiter = ','
+    )]
+    fund_profiles: Vec<String>,
+
+    ///Aptos profiles to publish the package from, one after the other
+    #[clap(
+        long,
+        env = "APTEST_PROFILE",
+        value_name = "PROFILES",
+        value_delimiter = ',',
+        default_value = "default"
+    )]
+    profile: Vec<String>,
+
+    ///Attempts at funding and publishing before giving up, for failures
+    ///caused by the node or faucet not being ready yet
+    #[clap(long, env = "APTEST_RETRIES", value_name = "N", default_value = "3")]
+    retries: u32,
+
+    ///Milliseconds to wait before the first retry, doubled after each one
+    #[clap(
+        long,
+        env = "APTEST_RETRY_DELAY",
+        value_name = "MS",
+        default_value = "500"
+    )]
+    retry_delay: u64,
+
+    ///Fails on profiles missing from .aptos/config.yaml instead of
+    ///creating them with a new key for the local network
+    #[clap(long, env = "APTEST_NO_AUTO_PROFILE")]
+    no_auto_profile: bool,
+
+    ///Directory of the Move package, when it isn't the current directory.
+    ///Overrides the workspace in aptest.toml
+    #[clap(long, env = "APTEST_PACKAGE_DIR", value_name = "DIR")]
+    package_dir: Option<PathBuf>,
+
+    ///Publishes even when the package is unchanged since it was last
+    ///published to the same chain
+    #[clap(long, env = "APTEST_FORCE_PUBLISH")]
+    force_publish: bool,
+
+    ///Hides the output of aptos, the node and the tests, only showing
+    ///aptest's own messages and failures
+    #[clap(long, short, env = "APTEST_QUIET", conflicts_with = "verbose")]
+    quiet: bool,
+
+    ///Also shows the commands run, their environment and readiness polling
+    #[clap(long, short, env = "APTEST_VERBOSE")]
+    verbose: bool,
+
+    ///Writes a report of the run to this file, as JUnit XML if it ends
+    ///in .xml and as JSON otherwise. Can be given several times
+    #[clap(long, env = "APTEST_REPORT", value_name = "FILE")]
+    report: Vec<PathBuf>,
+
+    ///Writes the transactions committed while the tests ran, with their
+    ///status, gas and events, to this file once the run is over
+    #[clap(
+        long,
+        env = "APTEST_DUMP_TRANSACTIONS",
+        value_name = "FILE",
+        min_values = 0,
+        default_missing_value = TRANSACTIONS_FILE
+    )]
+    dump_transactions: Option<PathBuf>,
+
+    ///Writes the events emitted while the tests ran, normalized to
+    ///compare between runs, to this file once the run is over
+    #[clap(
+        long,
+        env = "APTEST_EVENTS",
+        value_name = "FILE",
+        min_values = 0,
+        default_missing_value = EVENTS_FILE
+    )]
+    events: Option<PathBuf>,
+
+    ///Fails the run when the events emitted while the tests ran differ
+    ///from those in this file, which is written if it doesn't exist
+    #[clap(long, env = "APTEST_COMPARE_EVENTS", value_name = "FILE")]
+    compare_events: Option<PathBuf>,
+
+    ///Writes how the resources of the tracked accounts changed while the
+    ///tests ran to this file, and prints it
+    #[clap(
+        long,
+        env = "APTEST_STATE_DIFF",
+        value_name = "FILE",
+        min_values = 0,
+        default_missing_value = STATE_DIFF_FILE
+    )]
+    state_diff: Option<PathBuf>,
+
+    ///Ends an epoch of the local network every SECS seconds, for modules
+    ///counting time in epochs
+    #[clap(
+        long,
+        env = "APTEST_EPOCH_INTERVAL",
+        value_name = "SECS",
+        conflicts_with_all = &["attach", "network"]
+    )]
+    epoch_interval: Option<u64>,
+
+    ///Routes the tests' requests to the node and faucet through a proxy
+    ///adding latency, dropped connections and 503s: slow, flaky, hostile
+    ///or a profile of the chaos section of aptest.toml
+    #[clap(long, env = "APTEST_CHAOS", value_name = "PROFILE")]
+    chaos: Option<String>,
+
+    ///Routes the tests' requests to the node through a proxy logging each
+    ///of them and its response to this file, as JSON lines
+    #[clap(
+        long,
+        env = "APTEST_PROXY_LOG",
+        value_name = "FILE",
+        min_values = 0,
+        default_missing_value = PROXY_LOG_FILE
+    )]
+    proxy_log: Option<PathBuf>,
+
+    ///Records the tests' requests to the node and faucet, the answers they
+    ///got and the tests' environment to this file, for --replay
+    #[clap(long, env = "APTEST_RECORD", value_name = "FILE", conflicts_with_all = &["chaos", "watch", "interactive"])]
+    record: Option<PathBuf>,
+
+    ///Runs the tests against the answers of a recording made with --record
+    ///instead of a node, skipping compilation, the node and publishing
+    #[clap(
+        long,
+        env = "APTEST_REPLAY",
+        value_name = "FILE",
+        conflicts_with_all = &["record", "chaos", "watch", "interactive", "attach", "persist_state", "isolate", "with_indexer"]
+    )]
+    replay: Option<PathBuf>,
+
+    ///Upgrade policy to publish with instead of the one in Move.toml.
+    ///Without it, packages already on chain are republished as a compatible upgrade
+    #[clap(long, env = "APTEST_UPGRADE_POLICY", value_enum, value_name = "POLICY")]
+    upgrade_policy: Option<UpgradePolicy>,
+
+    ///Builds the packages in dev mode, with their dev-addresses and
+    ///dev-dependencies
+    #[clap(long, env = "APTEST_DEV")]
+    dev: bool,
+
+    ///Builds with the git dependencies already fetched instead of updating
+    ///them, for machines that are offline
+    #[clap(long, env = "APTEST_SKIP_FETCH_LATEST_GIT_DEPS")]
+    skip_fetch_latest_git_deps: bool,
+
+    ///Version of the Move compiler to build with, instead of the aptos
+    ///CLI default
+    #[clap(long, env = "APTEST_COMPILER_VERSION", value_name = "VERSION")]
+    compiler_version: Option<String>,
+
+    ///Version of the Move language to build with, instead of the aptos
+    ///CLI default
+    #[clap(long, env = "APTEST_LANGUAGE_VERSION", value_name = "VERSION")]
+    language_version: Option<String>,
+
+    ///Saves the package metadata next to the bytecode when compiling
+    #[clap(long, env = "APTEST_SAVE_METADATA")]
+    save_metadata: bool,
+
+    ///Maximum gas units each publish may use, instead of the aptos CLI's
+    ///estimate. Overrides max_gas in the [publish] section of aptest.toml
+    #[clap(long, env = "APTEST_MAX_GAS", value_name = "UNITS")]
+    max_gas: Option<u64>,
+
+    ///Octas paid per gas unit of each publish, instead of the aptos CLI's
+    ///estimate. Overrides gas_unit_price in the [publish] section of aptest.toml
+    #[clap(long, env = "APTEST_GAS_UNIT_PRICE", value_name = "OCTAS")]
+    gas_unit_price: Option<u64>,
+
+    ///Artifacts published along with the bytecode, fewer making packages
+    ///smaller. Overrides included_artifacts in the [publish] section of aptest.toml
+    #[clap(
+        long,
+        env = "APTEST_INCLUDED_ARTIFACTS",
+        value_enum,
+        value_name = "KIND"
+    )]
+    included_artifacts: Option<IncludedArtifacts>,
+
+    ///Prints one JSON event per line on stdout for each milestone of the run,
+    ///moving everything else to stderr
+    #[clap(
+        long,
+        env = "APTEST_OUTPUT",
+        value_enum,
+        value_name = "FORMAT",
+        default_value = "text"
+    )]
+    output: OutputFormat,
+
+    ///Applies the settings of this profile of aptest.toml to the options
+    ///not given on the command line
+    #[clap(long, env = "APTEST_ENV", value_name = "PROFILE")]
+    env: Option<String>,
+
+    //The options given on the command line or through the environment,
+    //which a profile leaves alone
+    #[clap(skip)]
+    given: Vec<String>,
+}
+
+//Steps of the pipeline picked with --only and --skip. Proving and
+//coverage still have to be asked for to run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum Phase {
+    Compile,
+    Prove,
+    Coverage,
+    Faucet,
+    Publish,
+    E2e,
+}
+
+//Where the generated test accounts are written
+const ACCOUNTS_FILE: &str = "accounts.json";
+
+//Where the details of the published packages are written
+const DEPLOYMENT_FILE: &str = "deployment.json";
+
+//Outcome of the fixtures, for the tests
+const FIXTURES_FILE: &str = "fixtures.json";
+
+//Node data directory of isolated test suites, unless state is persisted
+const SUITE_STATE_DIR: &str = ".aptest/suite-state";
+
+//Snapshot test suites are rolled back to
+const SUITE_SNAPSHOT: &str = "suites";
+
+//Where --state-diff writes the state changes by default
+const STATE_DIFF_FILE: &str = "state-diff.json";
+
+//Where --state-diff keeps the resources captured around the tests
+const STATE_DIR: &str = ".aptest/state";
+
+//Where the test files are found for --shard
+const TESTS_DIR: &str = "tests";
+
+//The explorer, showing the local network on the default port
+const EXPLORER_URL: &str = "https://explorer.aptoslabs.com/?network=local";
+
+//Time given to a crashed node's ports to free up before it is started again
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+//Time the node and faucet have to answer "aptest status"
+const STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Subcommand)]
+enum Subcmds {
+    ///Initialize a new project
+    Init(InitArgs),
+
+    ///Runs the framework in the current directory
+    Run(Args),
+
+    ///Runs the framework once for every combination of the settings listed
+    ///in the [matrix] section of aptest.toml, then sums up how each went
+    Matrix(Args),
+
+    ///Kills leftover processes of crashed runs and removes their data, old logs and the cache
+    Clean(CleanArgs),
+
+    ///Creates, funds and inspects accounts on the local network
+    #[clap(subcommand)]
+    Account(AccountCmd),
+
+    ///Saves and restores the chain state persisted with --persist-state
+    #[clap(subcommand)]
+    Snapshot(SnapshotCmd),
+
+    ///Lists the test suites of aptest.toml
+    #[clap(subcommand)]
+    Suites(SuitesCmd),
+
+    ///Calls a view function on the local network and prints what it returned
+    View(ViewArgs),
+
+    ///Sends an entry function transaction to the local network
+    Call(CallArgs),
+
+    ///Prints the resources, modules or table items of the local network
+    #[clap(subcommand)]
+    Query(QueryCmd),
+
+    ///Prints how the resources of accounts changed between two captures
+    ///of --state-diff
+    Diff(DiffArgs),
+
+    ///Shows the epoch of the local network and ends epochs early
+    #[clap(subcommand)]
+    Clock(ClockCmd),
+
+    ///Generates TypeScript bindings for published modules
+    Codegen(CodegenArgs),
+
+    ///Sends entry function calls to the local network at a steady rate and
+    ///reports the throughput, latencies and gas
+    Bench(BenchArgs),
+
+    ///Sends random calls to the entry functions of a published module and
+    ///reports the unexpected aborts
+    Fuzz(FuzzArgs),
+
+    ///Prints the output of the local network aptest is running
+    Logs(LogsArgs),
+
+    ///Tells whether aptest is running a local network, how it is doing and
+    ///what was published
+    Status,
+
+    ///Prints the completion script for a shell
+    Completions {
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Parser)]
+struct CleanArgs {
+    ///Days the logs of the network are kept after they were last written to
+    #[clap(long, value_name = "DAYS", default_value = "7")]
+    keep_logs: u64,
+}
+
+#[derive(Parser)]
+struct LogsArgs {
+    ///Keeps printing the output as it is written, until Ctrl+C
+    #[clap(long, short)]
+    follow: bool,
+
+    ///Prints the output of the faucet instead of the validator's
+    #[clap(long)]
+    faucet: bool,
+
+    ///Number of lines printed from the end of the output
+    #[clap(long, short = 'n', value_name = "N", default_value = "50")]
+    lines: usize,
+}
+
+#[derive(Parser)]
+struct ViewArgs {
+    ///The function, as address::module::function. The address can also be
+    ///an account name or an aptos profile
+    function: String,
+
+    ///Arguments in the aptos CLI's type:value form, like u64:10
+    #[clap(long, multiple_values = true)]
+    args: Vec<String>,
+
+    ///Type arguments, like 0x1::aptos_coin::AptosCoin
+    #[clap(long, multiple_values = true)]
+    type_args: Vec<String>,
+
+    #[clap(long, value_name = "URL", default_value = NODE_URL)]
+    node_url: String,
+}
+
+#[derive(Parser)]
+struct DiffArgs {
+    ///The earlier capture, like .aptest/state/before.json
+    before: PathBuf,
+
+    ///The later capture
+    after: PathBuf,
+
+    ///Prints the changes as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Parser)]
+struct CallArgs {
+    ///The function, as address::module::function. The address can also be
+    ///an account name or an aptos profile
+    function: String,
+
+    ///Arguments in the aptos CLI's type:value form, like u64:10
+    #[clap(long, multiple_values = true)]
+    args: Vec<String>,
+
+    ///Type arguments, like 0x1::aptos_coin::AptosCoin
+    #[clap(long, multiple_values = true)]
+    type_args: Vec<String>,
+
+    ///Account sending the transaction, an account name or an aptos profile
+    #[clap(long, value_name = "ACCOUNT", default_value = "default")]
+    from: String,
+
+    #[clap(long, value_name = "URL", default_value = NODE_URL)]
+    node_url: String,
+}
+
+#[derive(Parser)]
+struct CodegenArgs {
+    ///Accounts whose modules to generate bindings for, addresses, account
+    ///names or aptos profiles. By default the modules in deployment.json
+    accounts: Vec<String>,
+
+    ///Directory the bindings are written to
+    #[clap(long, value_name = "DIR", default_value = aptest::codegen::OUT_DIR)]
+    out_dir: PathBuf,
+
+    #[clap(long, value_name = "URL", default_value = NODE_URL)]
+    node_url: String,
+}
+
+#[derive(Parser)]
+struct BenchArgs {
+    ///Function to call instead of the calls of aptest.toml, as
+    ///address::module::function
+    function: Option<String>,
+
+    ///Arguments of the function in the aptos CLI's type:value form
+    #[clap(long, multiple_values = true)]
+    args: Vec<String>,
+
+    ///Type arguments of the function
+    #[clap(long, multiple_values = true)]
+    type_args: Vec<String>,
+
+    ///Transactions submitted per second
+    #[clap(long, default_value = "10")]
+    rate: f64,
+
+    ///Seconds transactions are submitted for
+    #[clap(long, value_name = "SECS", default_value = "30")]
+    duration: u64,
+
+    ///Accounts generated and funded to send the transactions from
+    #[clap(long, value_name = "N", default_value = "4")]
+    accounts: usize,
+
+    #[clap(long, value_name = "URL", default_value = NODE_URL)]
+    node_url: String,
+
+    #[clap(long, value_name = "URL", default_value = FAUCET_URL)]
+    faucet_url: String,
+}
+
+#[derive(Parser)]
+struct FuzzArgs {
+    ///The module, as address::name. The address can also be an account
+    ///name or an aptos profile
+    module: String,
+
+    ///Entry functions to call, all those of the module by default
+    #[clap(long, multiple_values = true)]
+    functions: Vec<String>,
+
+    ///Number of calls sent
+    #[clap(long, default_value = "100")]
+    runs: usize,
+
+    ///Seed of the random calls, printed with the results to reproduce them
+    #[clap(long)]
+    seed: Option<u64>,
+
+    ///Throwaway accounts generated and funded to send the calls from
+    #[clap(long, value_name = "N", default_value = "2")]
+    accounts: usize,
+
+    ///View function without arguments returning whether the module's
+    ///state is sound, checked after every call
+    #[clap(long, value_name = "FUNCTION")]
+    invariant: Option<String>,
+
+    #[clap(long, value_name = "URL", default_value = NODE_URL)]
+    node_url: String,
+
+    #[clap(long, value_name = "URL", default_value = FAUCET_URL)]
+    faucet_url: String,
+}
+
+#[derive(Subcommand)]
+enum QueryCmd {
+    ///Prints every resource under an account, or the one of a type
+    Resources {
+        ///An address, an account name or an aptos profile
+        account: String,
+
+        ///Resource type, like 0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>
+        #[clap(long = "type", value_name = "TYPE")]
+        resource_type: Option<String>,
+
+        #[clap(flatten)]
+        options: QueryOptions,
+    },
+
+    ///Prints the modules published under an account and their functions
+    Modules {
+        ///An address, an account name or an aptos profile
+        account: String,
+
+        #[clap(flatten)]
+        options: QueryOptions,
+    },
+
+    ///Prints an item of a table
+    Table {
+        ///Handle of the table, as found in the resource holding it
+        handle: String,
+
+        ///Key in the aptos CLI's type:value form, like address:alice
+        #[clap(long)]
+        key: String,
+
+        ///Type of the values of the table
+        #[clap(long, value_name = "TYPE")]
+        value_type: String,
+
+        #[clap(flatten)]
+        options: QueryOptions,
+    },
+}
+
+#[derive(Parser)]
+struct QueryOptions {
+    ///Prints the node's answer as JSON, on one line
+    #[clap(long)]
+    json: bool,
+
+    #[clap(long, value_name = "URL", default_value = NODE_URL)]
+    node_url: String,
+}
+
+#[derive(Subcommand)]
+enum AccountCmd {
+    ///Generates a new account and saves it under a name in .aptest/accounts.json
+    New { name: String },
+
+    ///Funds an account through the faucet
+    Fund {
+        ///An address, an account name or an aptos profile
+        account: String,
+
+        ///Octas to mint
+        #[clap(long, default_value = "100000000")]
+        amount: u64,
+
+        #[clap(long, value_name = "URL", default_value = FAUCET_URL)]
+        faucet_url: String,
+    },
+
+    ///Lists the named accounts and the aptos profiles
+    List,
+
+    ///Prints the APT balance of an account
+    Balance {
+        ///An address, an account name or an aptos profile
+        account: String,
+
+        #[clap(long, value_name = "URL", default_value = NODE_URL)]
+        node_url: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotCmd {
+    ///Copies the node's data directory into .aptest/snapshots under a name
+    Save {
+        name: String,
+
+        ///Data directory of the node, as given to --persist-state
+        #[clap(long, value_name = "DIR")]
+        state_dir: PathBuf,
+    },
+
+    ///Replaces the node's data directory with a saved snapshot
+    Restore {
+        name: String,
+
+        ///Data directory of the node, as given to --persist-state
+        #[clap(long, value_name = "DIR")]
+        state_dir: PathBuf,
+    },
+
+    ///Lists the saved snapshots
+    List,
+}
+
+#[derive(Subcommand)]
+enum ClockCmd {
+    ///Prints the epoch the chain is in and the timestamp of its latest block
+    Show {
+        #[clap(long, value_name = "URL", default_value = NODE_URL)]
+        node_url: String,
+    },
+
+    ///Ends the current epoch right away, signing with the root key of the
+    ///network
+    NextEpoch {
+        ///Number of epochs to end
+        #[clap(long, value_name = "N", default_value = "1")]
+        epochs: u32,
+
+        #[clap(long, value_name = "URL", default_value = NODE_URL)]
+        node_url: String,
+
+        ///Root key of the network, that of the one aptest is running unless
+        ///given
+        #[clap(long, value_name = "FILE")]
+        mint_key: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SuitesCmd {
+    ///Lists the test suites with their files or command, by the names
+    ///--suite picks them with
+    List,
+}
+
+#[tokio::main]
+async fn main() {
+    let matches = Sub::command().get_matches();
+    let sub = Sub::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    ui::init_style(sub.no_color);
+    //Everything aptest reads and writes is relative to the project, which
+    //is where it runs from then on
+    if let Some(dir) = &sub.dir {
+        let entered = std::env::set_current_dir(dir).or_fail(
+            AptestError::Environment,
+            &format!("Could not enter directory {}", dir.display()),
+        );
+        if let Err(err) = entered {
+            report(&err);
+            std::process::exit(err.exit_code());
+        }
+    }
+
+    let result = match sub.cmd {
+        Subcmds::Init(args) => init(args).await,
+        Subcmds::Run(mut args) => {
+            if let Some(matches) = matches.subcommand_matches("run") {
+                args.given = given(matches);
+            }
+            run(args, &mut Report::new()).await
+        }
+        Subcmds::Matrix(mut args) => {
+            if let Some(matches) = matches.subcommand_matches("matrix") {
+                args.given = given(matches);
+            }
+            matrix(args).await
+        }
+        Subcmds::Clean(args) => clean(args),
+        Subcmds::Account(cmd) => account(cmd).await,
+        Subcmds::Snapshot(cmd) => snapshot(cmd).await,
+        Subcmds::Suites(cmd) => suites_cmd(cmd),
+        Subcmds::View(args) => view(args).await,
+        Subcmds::Call(args) => call(args).await,
+        Subcmds::Query(cmd) => query(cmd).await,
+        Subcmds::Diff(args) => diff(args),
+        Subcmds::Clock(cmd) => clock(cmd).await,
+        Subcmds::Codegen(args) => codegen(args).await,
+        Subcmds::Bench(args) => bench(args).await,
+        Subcmds::Fuzz(args) => fuzz(args).await,
+        Subcmds::Logs(args) => logs(args).await,
+        Subcmds::Status => status().await,
+        Subcmds::Completions { shell } => {
+            completions(shell);
+            Ok(())
+        }
+    };
+
+    if let Err(err) = result {
+        report(&err);
+        std::process::exit(err.exit_code());
+    }
+}
+
+fn report(err: &AptestError) {
+    ui::failure(format!("Error: {}", err));
+}
+
+//Sets up a new project as configured on the command line, or as answered
+//to the questions asked with --interactive
+async fn init(args: InitArgs) -> Result<(), AptestError> {
+    let mut init = Init::new(args.name.unwrap_or_default())
+        .template(args.template)
+        .template_url(args.template_url)
+        .package_manager(args.pm.unwrap_or_else(PackageManager::detect))
+        .example(args.example)
+        .offline(args.offline)
+        .install(!args.no_install)
+        .force(args.force)
+        .skip_move_init(args.skip_move_init);
+    if args.interactive {
+        init = init.ask()?;
+    }
+    init.run().await
+}
+
+//Runs the pipeline once or in watch mode, running
+//the on_failure hooks when it fails
+async fn run(mut args: Args, report: &mut Report) -> Result<(), AptestError> {
+    if args.quiet {
+        ui::set_verbosity(Verbosity::Quiet);
+    } else if args.verbose {
+        ui::set_verbosity(Verbosity::Verbose);
+    }
+    ui::set_output(args.output);
+    let interrupt = Interrupt::listen()?;
+    if let Some(path) = &args.aptos_bin {
+        aptest::cli::set_program(path);
+    }
+    aptest::cli::check_version();
+    let mut config = Config::load()?;
+    if let Some(name) = &args.env {
+        let profile = config.take_profile(name)?;
+        apply_profile(&mut args, profile)?;
+    }
+    //Isolated runs have ports and files of their own
+    let _lock = if args.isolate {
+        None
+    } else {
+        Some(Lock::acquire(args.force_lock)?)
+    };
+    if args.isolate {
+        isolate(&mut args, &mut config)?;
+    }
+    args.gas_report |= args.gas_check;
+    take_shard(&mut args, &config)?;
+    start_progress(&mut args, &config)?;
+    //The proxies of every suite or watch cycle append to the same log
+    for path in [&args.proxy_log, &args.record].into_iter().flatten() {
+        let _ = std::fs::remove_file(path);
+    }
+    if args.watch {
+        let result = watch(&args, &config, &interrupt).await;
+        forget_localnet();
+        cleanup_isolated(&args);
+        cleanup_done(&result);
+        return result;
+    }
+    let mut result = pipeline(&args, &config, &interrupt, report).await;
+    //Baselines are of what the tests did, and aren't replaced without them
+    let tested = runs(&args, Phase::E2e);
+    if result.is_ok() && tested {
+        result = gas_baseline(&args, &config, report).await;
+    }
+    if result.is_ok() && tested {
+        result = compare_events(&args, report).await;
+    }
+    if let Err(err) = &result {
+        on_failure(&config, err).await;
+    }
+    report.finish();
+    report.print_summary(&result);
+    forget_localnet();
+    finish_progress(&args, &result);
+    write_reports(&args, report, &result)?;
+    cleanup_isolated(&args);
+    cleanup_done(&result);
+    result
+}
+
+//Runs the pipeline once for every combination of the [matrix] section of
+//aptest.toml, going on after failures, then prints and writes the reports
+//of all of them together
+async fn matrix(mut args: Args) -> Result<(), AptestError> {
+    if args.watch || args.interactive {
+        return Err(AptestError::Environment(
+            "The matrix runs the pipeline through for each combination, it can't --watch \
+             or stay --interactive"
+                .to_string(),
+        ));
+    }
+    let mut config = Config::load()?;
+    //The profile is applied here once, its reports being those of the
+    //whole matrix, rather than by every run
+    if let Some(name) = args.env.clone() {
+        let profile = config.take_profile(&name)?;
+        apply_profile(&mut args, profile)?;
+        args.env = None;
+    }
+    let combinations = config.matrix.combinations();
+    if combinations.is_empty() {
+        return Err(AptestError::Environment(format!(
+            "Nothing to combine, list settings in the [matrix] section of {}",
+            CONFIG_FILE
+        )));
+    }
+    let mut matrix_runs = Vec::new();
+    for (i, combination) in combinations.iter().enumerate() {
+        ui::info(format!(
+            "Matrix run {}/{}: {}",
+            i + 1,
+            combinations.len(),
+            combination.describe()
+        ));
+        let mut cell = args.clone();
+        //Written once for all of the runs instead
+        cell.report.clear();
+        cell.node_bin = combination.node_bin.clone().or(cell.node_bin);
+        cell.compiler_version = combination
+            .compiler_version
+            .clone()
+            .or(cell.compiler_version);
+        cell.language_version = combination
+            .language_version
+            .clone()
+            .or(cell.language_version);
+        cell.included_artifacts = combination.included_artifacts.or(cell.included_artifacts);
+        let mut report = Report::new();
+        let result = run(cell, &mut report).await;
+        let interrupted = matches!(result, Err(AptestError::Interrupted));
+        matrix_runs.push(MatrixRun {
+            combination: combination.describe(),
+            report,
+            result,
+        });
+        if interrupted {
+            break;
+        }
+    }
+
+    print_matrix_summary(&matrix_runs);
+    for path in &args.report {
+        write_matrix(&matrix_runs, path)?;
+    }
+    if let Some(Err(AptestError::Interrupted)) = matrix_runs.last().map(|x| &x.result) {
+        return Err(AptestError::Interrupted);
+    }
+    let failed = matrix_runs.iter().filter(|x| x.result.is_err()).count();
+    if failed > 0 {
+        return Err(AptestError::Tests(format!(
+            "{} of {} matrix runs failed",
+            failed,
+            matrix_runs.len()
+        )));
+    }
+    ui::success("Done");
+    Ok(())
+}
+
+//Narrows the tests down to those of the shard given with --shard: the
+//test suites picked if there are some, the test files found under tests/
+//otherwise. A shard left with none skips the tests
+fn take_shard(args: &mut Args, config: &Config) -> Result<(), AptestError> {
+    let shard = match args.shard {
+        Some(shard) => shard,
+        None => return Ok(()),
+    };
+    let suites = config.pick_suites(&args.suite)?;
+    let (what, count) = if suites.is_empty() {
+        args.shard_files = shard.pick(aptest::shard::discover(Path::new(TESTS_DIR))?);
+        ("test files", args.shard_files.len())
+    } else {
+        args.suite = shard.pick(suites.into_iter().map(|x| x.0).collect());
+        ("test suites", args.suite.len())
+    };
+    if count == 0 {
+        ui::info(format!(
+            "Shard {} has no {}, skipping e2e tests",
+            shard, what
+        ));
+        args.skip.push(Phase::E2e);
+    } else {
+        ui::info(format!("Shard {} runs {} {}", shard, count, what));
+    }
+    Ok(())
+}
+
+//The options of a subcommand given on the command line or through the
+//environment rather than left to their default
+fn given(matches: &ArgMatches) -> Vec<String> {
+    Args::command()
+        .get_arguments()
+        .map(|arg| arg.get_id())
+        .filter(|id| {
+            matches!(
+                matches.value_source(*id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        })
+        .map(String::from)
+        .collect()
+}
+
+//Applies the settings of the profile picked with --env to the options
+//left unset, unless one they conflict with is given
+fn apply_profile(args: &mut Args, profile: Profile) -> Result<(), AptestError> {
+    let given = args.given.clone();
+    let unset = |ids: &[&str]| ids.iter().all(|id| !given.iter().any(|x| x == id));
+    if let Some(x) = profile.start_delay.filter(|_| unset(&["start_delay"])) {
+        args.start_delay = x;
+    }
+    if let Some(x) = profile.retries.filter(|_| unset(&["retries"])) {
+        args.retries = x;
+    }
+    if let Some(x) = profile.retry_delay.filter(|_| unset(&["retry_delay"])) {
+        args.retry_delay = x;
+    }
+    if let Some(x) = profile.test_retries.filter(|_| unset(&["test_retries"])) {
+        args.test_retries = x;
+    }
+    args.fund_timeout = args.fund_timeout.or(profile.fund_timeout);
+    args.publish_timeout = args.publish_timeout.or(profile.publish_timeout);
+    args.test_timeout = args.test_timeout.or(profile.test_timeout);
+    if unset(&["quiet", "verbose"]) {
+        args.quiet = profile.quiet;
+        args.verbose = profile.verbose && !profile.quiet;
+    }
+    if profile.isolate
+        && unset(&[
+            "persist_state",
+            "attach",
+            "network",
+            "no_auto_profile",
+            "with_indexer",
+            "resume",
+            "replay",
+            "force_lock",
+        ])
+    {
+        args.isolate = true;
+    }
+    if unset(&["frozen", "deps_cache"]) {
+        args.no_install |= profile.no_install;
+    }
+    if unset(&["no_install"]) {
+        args.frozen |= profile.frozen;
+        args.deps_cache = args.deps_cache.take().or(profile.deps_cache);
+    }
+    if unset(&["skip", "only"]) {
+        for name in &profile.skip {
+            let phase = Phase::from_str(name, true).map_err(|_| {
+                AptestError::Environment(format!(
+                    "Unknown phase {} skipped by profile {}",
+                    name,
+                    args.env.as_deref().unwrap_or_default()
+                ))
+            })?;
+            args.skip.push(phase);
+        }
+    }
+    if unset(&["report"]) {
+        args.report = profile.report;
+    }
+    Ok(())
+}
+
+//Whether a phase of the pipeline runs, as picked with --only and --skip
+//or the flags skipping a single one
+fn runs(args: &Args, phase: Phase) -> bool {
+    let skipped = match phase {
+        Phase::Compile => args.no_compile,
+        Phase::Faucet => args.no_faucet,
+        Phase::Publish => args.no_publish,
+        _ => false,
+    };
+    let picked = args.only.is_empty() || args.only.contains(&phase);
+    picked && !skipped && !args.skip.contains(&phase)
+}
+
+//Whether the run keeps track of how far it gets, for --resume. Isolated
+//runs don't, running alongside others, nor do watch and replay runs
+fn tracks_progress(args: &Args) -> bool {
+    !args.isolate && !args.watch && args.replay.is_none()
+}
+
+//Starts keeping track of how far the run gets. With --resume, the phases
+//the last run got through on the same packages are skipped, and kept as
+//done should this run fail too
+fn start_progress(args: &mut Args, config: &Config) -> Result<(), AptestError> {
+    if !tracks_progress(args) {
+        return Ok(());
+    }
+    //A run that can't find its packages fails on its own further on
+    let digests: Vec<_> = packages(args, config)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|dir| aptest::state::package_digest(dir).ok())
+        .collect();
+    let mut state = State::load();
+    let mut done = Vec::new();
+    if args.resume {
+        match state.last_run() {
+            None => ui::info("There is no run to resume, running everything"),
+            Some(last) if last.passed => ui::info("The last run passed, running everything again"),
+            Some(last) if last.digests != digests => {
+                ui::info("The packages changed since the last run, running everything again")
+            }
+            Some(last) => {
+                for name in &last.done {
+                    if let Ok(phase) = Phase::from_str(name, false) {
+                        args.skip.push(phase);
+                        done.push(name.clone());
+                    }
+                }
+                if done.is_empty() {
+                    ui::info("Resuming the last run from the start");
+                } else {
+                    ui::info(format!(
+                        "Resuming the last run, skipping {}",
+                        done.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+    state.set_last_run(Some(LastRun {
+        digests,
+        done,
+        passed: false,
+    }));
+    state.save()
+}
+
+//Records a phase the run got through, for a resumed run to skip
+fn phase_done(args: &Args, phase: Phase) -> Result<(), AptestError> {
+    if !tracks_progress(args) {
+        return Ok(());
+    }
+    let mut state = State::load();
+    let mut last_run = state.last_run().cloned().unwrap_or_default();
+    let name = phase_name(phase);
+    if !last_run.done.contains(&name) {
+        last_run.done.push(name);
+    }
+    state.set_last_run(Some(last_run));
+    state.save()
+}
+
+//Records whether the run passed, which leaves nothing to resume
+fn finish_progress(args: &Args, result: &Result<(), AptestError>) {
+    if !tracks_progress(args) {
+        return;
+    }
+    let mut state = State::load();
+    if let Some(last_run) = state.last_run() {
+        let last_run = LastRun {
+            passed: result.is_ok(),
+            ..last_run.clone()
+        };
+        state.set_last_run(Some(last_run));
+        let _ = state.save();
+    }
+}
+
+//The name of a phase, as given to --only and --skip
+fn phase_name(phase: Phase) -> String {
+    phase
+        .to_possible_value()
+        .map(|x| x.get_name().to_string())
+        .unwrap_or_default()
+}
+
+//Where the node and faucet write their output as they run, a directory
+//of their own for isolated runs
+fn log_dir(args: &Args) -> PathBuf {
+    if args.isolate {
+        run_dir().join("logs")
+    } else {
+        PathBuf::from(LOG_DIR)
+    }
+}
+
+//Scratch directory of an isolated run, named after the process
+fn run_dir() -> PathBuf {
+    Path::new(aptest::clean::RUNS_DIR).join(std::process::id().to_string())
+}
+
+//Where a file written for the tests goes, in the run's own
+//directory for isolated runs
+fn run_file(args: &Args, name: &str) -> PathBuf {
+    if args.isolate {
+        run_dir().join(name)
+    } else {
+        PathBuf::from(name)
+    }
+}
+
+//Gives an isolated run profiles of its own, named after the process, so
+//that concurrent runs don't fund and publish from the same accounts.
+//Named addresses pointing at one of the profiles follow it
+fn isolate(args: &mut Args, config: &mut Config) -> Result<(), AptestError> {
+    let scoped = |profile: &String| format!("{}-{}", profile, std::process::id());
+    let profiles: Vec<_> = args.profile.iter().chain(&args.fund_profiles).collect();
+    for account in config.addresses.values_mut() {
+        if profiles.contains(&&*account) {
+            *account = scoped(account);
+        }
+    }
+    args.profile = args.profile.iter().map(scoped).collect();
+    args.fund_profiles = args.fund_profiles.iter().map(scoped).collect();
+    std::fs::create_dir_all(run_dir()).or_fail(
+        AptestError::Environment,
+        "Could not create the directory of the run",
+    )
+}
+
+//Removes the profiles and files of an isolated run once it is over.
+//Failures are only reported, leaving the run's own result
+fn cleanup_isolated(args: &Args) {
+    if !args.isolate {
+        return;
+    }
+    let profiles: Vec<_> = args
+        .profile
+        .iter()
+        .chain(&args.fund_profiles)
+        .cloned()
+        .collect();
+    if let Err(err) = aptest::profile::remove_profiles(&profiles) {
+        report(&err);
+    }
+    let _ = std::fs::remove_dir_all(run_dir());
+    let _ = aptest::snapshot::delete(&suite_snapshot(args));
+}
+
+//Last event of a run, once the node and faucet are gone
+fn cleanup_done(result: &Result<(), AptestError>) {
+    ui::event(
+        "cleanup_done",
+        json!({
+            "success": result.is_ok(),
+            "exit_code": result.as_ref().err().map_or(0, |e| e.exit_code()),
+        }),
+    );
+}
+
+//Announces the packages about to be compiled
+fn compile_started(packages: &[PathBuf]) {
+    ui::event("compile_started", json!({ "packages": packages }));
+}
+
+//Announces the node, and faucet if any, answering requests
+fn node_ready(net: &LocalNet) {
+    ui::event(
+        "node_ready",
+        json!({
+            "node_url": net.node_url(),
+            "faucet_url": net.faucet_url(),
+            "indexer_url": net.indexer_url(),
+        }),
+    );
+}
+
+//Leaves the network up after the tests with --keep-alive, whether they
+//passed or not, with what it takes to inspect it, until Ctrl+C
+async fn keep_alive(
+    args: &Args,
+    net: &LocalNet,
+    runner: &TestRunner,
+    result: &Result<(), AptestError>,
+    interrupt: &Interrupt,
+) {
+    if !args.keep_alive || matches!(result, Err(AptestError::Interrupted)) {
+        return;
+    }
+    match result {
+        Ok(()) => ui::success("End to end tests passed, the Local Node is kept alive."),
+        Err(_) => ui::failure("End to end tests failed, the Local Node is kept alive."),
+    }
+    print_connection(args, net, runner).await;
+    ui::info("Ctrl+C to exit tool and close node...");
+    interrupt.wait().await;
+}
+
+//Prints the URLs of the network, its chain id, the key minting on it and
+//the accounts funded on it with their private keys
+async fn print_connection(args: &Args, net: &LocalNet, runner: &TestRunner) {
+    let line = |name: &str, value: &str| ui::print(format!("  {:<16} {}", name, value));
+    line("node", net.node_url());
+    if let Some(faucet_url) = net.faucet_url() {
+        line("faucet", faucet_url);
+    }
+    if let Some(indexer_url) = net.indexer_url() {
+        line("indexer", indexer_url);
+    }
+    if let Ok(chain_id) = NodeClient::new(net.node_url()).chain_id().await {
+        line("chain id", &chain_id.to_string());
+    }
+    if let Some(path) = net.mint_key_path() {
+        line("mint key", path);
+    }
+    line("explorer", EXPLORER_URL);
+    if let Ok(aptos) = AptosConfig::load() {
+        for name in &args.profile {
+            if let (Ok(account), Ok(key)) = (aptos.account(name), aptos.private_key(name)) {
+                line(&format!("profile {}", name), &account);
+                line("  private key", &key);
+            }
+        }
+    }
+    let env = |key: String| {
+        runner
+            .envs()
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| value.clone())
+    };
+    if let Some(address) = env("APTEST_FEE_PAYER_ADDRESS".to_string()) {
+        line("fee payer", &address);
+        let key = env("APTEST_FEE_PAYER_PRIVATE_KEY".to_string());
+        line("  private key", &key.unwrap_or_default());
+    }
+    if let Some(address) = env("APTEST_MULTISIG_ADDRESS".to_string()) {
+        let required = env("APTEST_MULTISIG_REQUIRED".to_string()).unwrap_or_default();
+        line("multisig", &format!("{} ({} approvals)", address, required));
+        for i in 0.. {
+            let owner = env(format!("APTEST_MULTISIG_OWNER_{}_ADDRESS", i));
+            let key = env(format!("APTEST_MULTISIG_OWNER_{}_PRIVATE_KEY", i));
+            match (owner, key) {
+                (Some(owner), Some(key)) => {
+                    line(&format!("  owner {}", i), &owner);
+                    line("    private key", &key);
+                }
+                _ => break,
+            }
+        }
+    }
+    for i in 0.. {
+        let address = env(format!("APTEST_ACCOUNT_{}_ADDRESS", i));
+        let key = env(format!("APTEST_ACCOUNT_{}_PRIVATE_KEY", i));
+        match (address, key) {
+            (Some(address), Some(key)) => {
+                line(&format!("account {}", i), &address);
+                line("  private key", &key);
+            }
+            _ => break,
+        }
+    }
+    ui::print("");
+}
+
+//Runs the whole pipeline. The local network is torn down when it
+//goes out of scope, so every early return cleans up after itself
+async fn pipeline(
+    args: &Args,
+    config: &Config,
+    interrupt: &Interrupt,
+    report: &mut Report,
+) -> Result<(), AptestError> {
+    if let Some(path) = &args.replay {
+        return replay(args, config, path, interrupt, report).await;
+    }
+    let packages = packages(args, config)?;
+    //Named addresses may refer to profiles yet to be created, whose URLs
+    //are set for good once the node is up
+    match remote(args)? {
+        Some(remote) => create_profiles(
+            args,
+            &remote.node_url,
+            remote
+                .faucet_url
+                .as_deref()
+                .filter(|_| runs(args, Phase::Faucet)),
+        )?,
+        None => create_profiles(
+            args,
+            args.attach.as_deref().unwrap_or(NODE_URL),
+            runs(args, Phase::Faucet).then_some(FAUCET_URL),
+        )?,
+    }
+    let addresses = named_addresses(args, config, &packages)?;
+    let publisher = Publisher::new()
+        .packages(packages.clone())
+        .named_addresses(addresses.clone())
+        .build_args(build_args(args, config))
+        .save_metadata(args.save_metadata || config.compile.save_metadata)
+        .extra_args(config.aptos.extra_args.clone());
+
+    let tests = runs(args, Phase::E2e);
+    let needs_node = runs(args, Phase::Publish) || tests || args.interactive || args.keep_alive;
+    let picked = config.pick_suites(&args.suite)?;
+    let in_suites = !picked.is_empty() && tests && !args.interactive;
+
+    //Compilation, while the node starts up when one is needed. Test suites
+    //start nodes of their own
+    let mut net = None;
+    if runs(args, Phase::Compile) {
+        //Cleanup not needed because nodes haven't been started yet
+        let hooks = &config.hooks.pre_compile;
+        interrupt
+            .race(aptest::hooks::run("pre_compile", hooks, &[], None))
+            .await?;
+        compile_started(&packages);
+        let compile = interrupt.race(publisher.compile());
+        if needs_node && !in_suites {
+            let node = start_node(args, config, interrupt);
+            let (_, started) = report
+                .concurrently(("compile", compile), ("node", node))
+                .await?;
+            net = Some(started);
+        } else {
+            report.phase("compile", compile).await?;
+        }
+        interrupt.check()?;
+        phase_done(args, Phase::Compile)?;
+    }
+    prove(args, config, &publisher, interrupt, report).await?;
+    coverage(args, &publisher, interrupt, report).await?;
+
+    //Nothing left needs a node
+    if !needs_node {
+        ui::success("Done");
+        return Ok(());
+    }
+
+    if in_suites {
+        return suites(
+            args, config, &picked, &packages, &addresses, interrupt, report,
+        )
+        .await;
+    }
+
+    //Local Node start, unless it came up while compiling
+    let net = match net {
+        Some(net) => net,
+        None => {
+            report
+                .phase("node", start_node(args, config, interrupt))
+                .await?
+        }
+    };
+    node_ready(&net);
+    if args.interactive {
+        return interactive(args, config, net, &packages, &addresses, interrupt, report).await;
+    }
+    //From here on the run fails as soon as the node or faucet exits
+    let interrupt = &net.supervise(interrupt);
+    let runner = prepare(args, config, &net, &packages, &addresses, interrupt, report).await?;
+
+    //Start End to End tests and wait for them to finish
+    let result = tests("tests", args, config, &net, &runner, interrupt, report).await;
+    keep_alive(args, &net, &runner, &result, interrupt).await;
+    result?;
+
+    drop(net);
+    ui::success("Done");
+    Ok(())
+}
+
+//Keeps the network up for end to end tests run by hand until Ctrl+C.
+//With --restart-node, a node or faucet that exits is started again, up
+//to that many times, and funded and published to again
+async fn interactive(
+    args: &Args,
+    config: &Config,
+    mut net: LocalNet,
+    packages: &[PathBuf],
+    addresses: &BTreeMap<String, String>,
+    interrupt: &Interrupt,
+    report: &mut Report,
+) -> Result<(), AptestError> {
+    let mut restarts = 0;
+    loop {
+        let supervised = net.supervise(interrupt);
+        let runner = prepare(args, config, &net, packages, addresses, &supervised, report).await?;
+        ui::success("Local Node is running.");
+        print_connection(args, &net, &runner).await;
+        ui::info(
+            "End to End tests can be run separately now, or Ctrl+C\nto exit tool and close node...",
+        );
+        supervised.wait().await;
+        let err = match supervised.exited() {
+            Ok(()) => break,
+            Err(err) => err,
+        };
+        if restarts >= args.restart_node.unwrap_or_default() {
+            return Err(err);
+        }
+        restarts += 1;
+        ui::failure(format!("Error: {}", err));
+        ui::info(format!(
+            "Restarting the local node ({}/{})...",
+            restarts,
+            args.restart_node.unwrap_or_default()
+        ));
+        //What is left of the network goes first, freeing its ports
+        drop(net);
+        interrupt.sleep(RESTART_DELAY).await?;
+        net = report
+            .phase("node", start_node(args, config, interrupt))
+            .await?;
+        node_ready(&net);
+    }
+
+    drop(net);
+    ui::success("Done");
+    Ok(())
+}
+
+//Proves the packages when asked to, on the command line or in aptest.toml
+async fn prove(
+    args: &Args,
+    config: &Config,
+    publisher: &Publisher,
+    interrupt: &Interrupt,
+    report: &mut Report,
+) -> Result<(), AptestError> {
+    if !runs(args, Phase::Prove) || (!args.prove && !config.prove.enabled) {
+        return Ok(());
+    }
+    let prove = publisher.prove(&config.prove.extra_args);
+    report.phase("prove", interrupt.race(prove)).await?;
+    interrupt.check()?;
+    phase_done(args, Phase::Prove)
+}
+
+//Runs the Move unit tests with coverage when asked to, keeping the
+//coverage for the end of the run
+async fn coverage(
+    args: &Args,
+    publisher: &Publisher,
+    interrupt: &Interrupt,
+    report: &mut Report,
+) -> Result<(), AptestError> {
+    if !runs(args, Phase::Coverage) || !args.coverage {
+        return Ok(());
+    }
+    let coverage = report
+        .phase("coverage", interrupt.race(publisher.coverage()))
+        .await?;
+    report.set_coverage(coverage);
+    interrupt.check()?;
+    phase_done(args, Phase::Coverage)
+}
+
+//Readies a started network for the tests: creates the missing profiles
+//and the extra accounts, then funds and publishes unless told not to.
+//Returns the test runner pointed at the network
+async fn prepare(
+    args: &Args,
+    config: &Config,
+    net: &LocalNet,
+    packages: &[PathBuf],
+    addresses: &BTreeMap<String, String>,
+    interrupt: &Interrupt,
+    report: &mut Report,
+) -> Result<TestRunner, AptestError> {
+    ensure_profiles(args, net)?;
+    let accounts = report
+        .phase("accounts", create_accounts(args, config, net))
+        .await?;
+    let mut runner = runner_for(args, config, net, &accounts, report)?;
+
+    if runs(args, Phase::Publish) {
+        let publisher = publisher_for(args, config, packages, addresses, net);
+        report
+            .phase("funding", interrupt.race(publisher.fund()))
+            .await?;
+        let publish = publish(args, config, net, &publisher, runner, interrupt);
+        runner = report.phase("publish", publish).await?;
+        publish_gas(args, report)?;
+        interrupt.check()?;
+    }
+    Ok(runner)
+}
+
+//Runs each test suite of aptest.toml against a chain of its own, either
+//restarting the node for every suite or rolling it back to a snapshot
+//taken once everything was published. Every suite runs even if one
+//fails, the first failure failing the run
+async fn suites(
+    args: &Args,
+    config: &Config,
+    picked: &[(String, Suite)],
+    packages: &[PathBuf],
+    addresses: &BTreeMap<String, String>,
+    interrupt: &Interrupt,
+    report: &mut Report,
+) -> Result<(), AptestError> {
+    if args.attach.is_some() || args.network.is_some() {
+        return Err(AptestError::Environment(
+            "Test suites each need a chain of their own, so they can't run on an attached node \
+             or a public network"
+                .to_string(),
+        ));
+    }
+    if args.record.is_some() {
+        return Err(AptestError::Environment(
+            "Recording runs one test command against one chain, test suites can't be recorded"
+                .to_string(),
+        ));
+    }
+    let mut failed = Vec::new();
+    let mut record = |name: &str, result: Result<(), AptestError>| match result {
+        Err(AptestError::Tests(_)) => {
+            failed.push(name.to_string());
+            Ok(())
+        }
+        result => result,
+    };
+
+    match config.test.isolation.unwrap_or(Isolation::Restart) {
+        Isolation::Restart => {
+            for (i, (name, suite)) in picked.iter().enumerate() {
+                ui::info(format!("Running suite {} on a fresh chain...", name));
+                let node = local_node(args, config)?.reset_state(true).start(interrupt);
+                let net = report.phase("node", node).await?;
+                node_ready(&net);
+                let interrupt = &net.supervise(interrupt);
+                let runner =
+                    prepare(args, config, &net, packages, addresses, interrupt, report).await?;
+                let runner = suite_runner(runner, config, name, suite);
+                let phase = format!("tests {}", name);
+                let result = tests(&phase, args, config, &net, &runner, interrupt, report).await;
+                if i + 1 == picked.len() {
+                    keep_alive(args, &net, &runner, &result, interrupt).await;
+                }
+                record(name, result)?;
+            }
+        }
+        Isolation::Snapshot => {
+            let state_dir = match (&args.persist_state, args.isolate) {
+                (Some(dir), _) => dir.clone(),
+                (None, true) => run_dir().join("suite-state"),
+                (None, false) => PathBuf::from(SUITE_STATE_DIR),
+            };
+            let snapshot = suite_snapshot(args);
+            let reset = args.persist_state.is_none() || args.reset;
+            let node = local_node(args, config)?
+                .persist_state(&state_dir)
+                .reset_state(reset)
+                .start(interrupt);
+            let net = report.phase("node", node).await?;
+            node_ready(&net);
+            let supervised = net.supervise(interrupt);
+            let runner =
+                prepare(args, config, &net, packages, addresses, &supervised, report).await?;
+            //The node's data is only consistent once it is stopped
+            drop(net);
+            aptest::snapshot::save(&state_dir, &snapshot)?;
+
+            for (i, (name, suite)) in picked.iter().enumerate() {
+                ui::info(format!("Running suite {} on a rolled back chain...", name));
+                aptest::snapshot::restore(&snapshot, &state_dir)?;
+                let node = local_node(args, config)?
+                    .persist_state(&state_dir)
+                    .start(interrupt);
+                let net = report.phase("node", node).await?;
+                node_ready(&net);
+                let interrupt = &net.supervise(interrupt);
+                let runner = suite_runner(runner.clone(), config, name, suite);
+                let phase = format!("tests {}", name);
+                let result = tests(&phase, args, config, &net, &runner, interrupt, report).await;
+                if i + 1 == picked.len() {
+                    keep_alive(args, &net, &runner, &result, interrupt).await;
+                }
+                record(name, result)?;
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(AptestError::Tests(format!(
+            "{} of {} test suites failed: {}",
+            failed.len(),
+            picked.len(),
+            failed.join(", ")
+        )));
+    }
+    ui::success("Done");
+    Ok(())
+}
+
+//Snapshot test suites are rolled back to, one per run for isolated runs
+fn suite_snapshot(args: &Args) -> String {
+    if args.isolate {
+        format!("{}-{}", SUITE_SNAPSHOT, std::process::id())
+    } else {
+        SUITE_SNAPSHOT.to_string()
+    }
+}
+
+//The test runner of a suite, running its command if it has one. Its files
+//are exposed to test commands from aptest.toml as APTEST_SUITE and passed
+//on to "npm run test" otherwise
+fn suite_runner(runner: TestRunner, config: &Config, name: &str, suite: &Suite) -> TestRunner {
+    let mut runner = runner.env("APTEST_SUITE_NAME", name);
+    if let Some(command) = &suite.command {
+        let [shell, flag] = aptest::process::shell();
+        runner = runner.command(shell, &[flag, command]);
+    }
+    let files = match &suite.files {
+        Some(files) => files,
+        None => return runner,
+    };
+    let runner = runner.env("APTEST_SUITE", files);
+    if config.test.command.is_some() || suite.command.is_some() {
+        runner
+    } else {
+        runner.args(&["--", files])
+    }
+}
+
+//Keeps the node alive and re-runs compile, publish (as an upgrade)
+//and the tests every time the Move sources or the tests change
+async fn watch(args: &Args, config: &Config, interrupt: &Interrupt) -> Result<(), AptestError> {
+    let net = start_node(args, config, interrupt).await?;
+    node_ready(&net);
+    let interrupt = &net.supervise(interrupt);
+    ensure_profiles(args, &net)?;
+    let accounts = create_accounts(args, config, &net).await?;
+    let packages = packages(args, config)?;
+    let addresses = named_addresses(args, config, &packages)?;
+    let publisher = publisher_for(args, config, &packages, &addresses, &net);
+    let watcher = if packages.is_empty() {
+        Watcher::new().watch("sources", "move")
+    } else {
+        packages.iter().fold(Watcher::new(), |watcher, dir| {
+            watcher.watch(dir.join("sources"), "move")
+        })
+    };
+    let mut watcher = watcher.watch_tests(config.project.as_ref().map(|x| x.template));
+
+    loop {
+        //Each cycle gets its own report, overwriting the previous one
+        let mut run_report = Report::new();
+        let mut result = iteration(
+            args,
+            config,
+            &net,
+            &accounts,
+            &publisher,
+            interrupt,
+            &mut run_report,
+        )
+        .await;
+        if result.is_ok() {
+            result = gas_baseline(args, config, &mut run_report).await;
+        }
+        if result.is_ok() {
+            result = compare_events(args, &mut run_report).await;
+        }
+        run_report.print_summary(&result);
+        if let Err(err) = write_reports(args, &run_report, &result) {
+            report(&err);
+        }
+        //Failures are reported but only end the loop on Ctrl+C, or once
+        //the node is gone
+        match result {
+            Ok(()) => ui::success("All good."),
+            Err(AptestError::Interrupted) => return Err(AptestError::Interrupted),
+            Err(err @ AptestError::NodeExited(_)) => return Err(err),
+            Err(err) => {
+                report(&err);
+                on_failure(config, &err).await;
+            }
+        }
+
+        ui::info("Watching for changes, Ctrl+C to exit tool and close node...");
+        let changed = interrupt.race(watcher.changed()).await?;
+        for path in &changed {
+            ui::print(format!("Changed: {}", path.display()));
+        }
+    }
+}
+
+//One compile, publish and test cycle of watch mode
+async fn iteration(
+    args: &Args,
+    config: &Config,
+    net: &LocalNet,
+    accounts: &TestAccounts,
+    publisher: &Publisher,
+    interrupt: &Interrupt,
+    report: &mut Report,
+) -> Result<(), AptestError> {
+    if runs(args, Phase::Compile) {
+        let hooks = &config.hooks.pre_compile;
+        interrupt
+            .race(aptest::hooks::run("pre_compile", hooks, &[], None))
+            .await?;
+        compile_started(&packages(args, config)?);
+        report
+            .phase("compile", interrupt.race(publisher.compile()))
+            .await?;
+    }
+    prove(args, config, publisher, interrupt, report).await?;
+    coverage(args, publisher, interrupt, report).await?;
+    let mut runner = runner_for(args, config, net, accounts, report)?;
+    if runs(args, Phase::Publish) {
+        report
+            .phase("funding", interrupt.race(publisher.fund()))
+            .await?;
+        let publish = publish(args, config, net, publisher, runner, interrupt);
+        runner = report.phase("publish", publish).await?;
+        publish_gas(args, report)?;
+    }
+    tests("tests", args, config, net, &runner, interrupt, report).await
+}
+
+//The test runner for the network, with its accounts, going through
+//proxies with --chaos, --proxy-log or --record
+fn runner_for(
+    args: &Args,
+    config: &Config,
+    net: &LocalNet,
+    accounts: &TestAccounts,
+    report: &Report,
+) -> Result<TestRunner, AptestError> {
+    let accounts_file = run_file(args, ACCOUNTS_FILE);
+    let mut runner = test_runner(args, config, report)
+        .network(net)
+        .accounts(&accounts.accounts, &accounts_file.to_string_lossy());
+    if let Some(fee_payer) = &accounts.fee_payer {
+        runner = runner.fee_payer(fee_payer);
+    }
+    if let Some(multisig) = &accounts.multisig {
+        runner = runner.multisig(multisig);
+    }
+    //Tests of time dependent modules end epochs through aptest itself
+    if let (Some(mint_key), Ok(exe)) = (net.mint_key_path(), std::env::current_exe()) {
+        let next_epoch = format!(
+            "\"{}\" clock next-epoch --node-url {} --mint-key \"{}\"",
+            exe.display(),
+            net.node_url(),
+            mint_key
+        );
+        runner = runner.env("APTEST_NEXT_EPOCH", next_epoch);
+    }
+    let chaos = match &args.chaos {
+        Some(name) => Some(chaos_profile(config, name)?),
+        None => None,
+    };
+    if chaos.is_some() || args.proxy_log.is_some() || args.record.is_some() {
+        let mut proxy = Proxy::new(net.node_url());
+        if let Some(chaos) = &chaos {
+            proxy = proxy.chaos(chaos.clone());
+        }
+        if let Some(path) = &args.proxy_log {
+            proxy = proxy.log(path);
+        }
+        if let Some(path) = &args.record {
+            proxy = proxy.record(path, "node");
+        }
+        runner = runner.proxy("APTEST_NODE_URL", proxy.start()?);
+    }
+    if let Some(faucet_url) = net.faucet_url() {
+        if let Some(chaos) = chaos {
+            let proxy = Proxy::new(faucet_url).chaos(chaos).start()?;
+            runner = runner.proxy("APTEST_FAUCET_URL", proxy);
+        } else if let Some(path) = &args.record {
+            let proxy = Proxy::new(faucet_url).record(path, "faucet").start()?;
+            runner = runner.proxy("APTEST_FAUCET_URL", proxy);
+        }
+    }
+    Ok(runner)
+}
+
+//The test runner whatever it runs against, running the test command of
+//aptest.toml if any, or the tests with the package manager the project was
+//set up with, and capturing the tests' output into the report when one was
+//asked for
+fn test_runner(args: &Args, config: &Config, report: &Report) -> TestRunner {
+    let packages = packages(args, config).unwrap_or_default();
+    let mut runner = TestRunner::new()
+        .errors(ErrorMap::load(&packages))
+        .timeout(args.test_timeout.map(Duration::from_secs));
+    if let Some(command) = &config.test.command {
+        let [shell, flag] = aptest::process::shell();
+        runner = runner.command(shell, &[flag, command]);
+    } else if let Some(project) = &config.project {
+        runner = runner.command(project.package_manager.program(), &["run", "test"]);
+    }
+    //The files of the shard are passed on to "npm run test", test commands
+    //from aptest.toml having to select them themselves
+    if let Some(shard) = &args.shard {
+        runner = runner.env("APTEST_SHARD", shard.to_string());
+    }
+    if !args.shard_files.is_empty() {
+        let files: Vec<_> = args
+            .shard_files
+            .iter()
+            .map(|x| x.to_string_lossy())
+            .collect();
+        runner = runner.env("APTEST_SHARD_FILES", files.join(" "));
+        if config.test.command.is_none() {
+            let files: Vec<_> = files.iter().map(|x| x.as_ref()).collect();
+            runner = runner.args(&["--"]).args(&files);
+        }
+    }
+    if args.report.is_empty() {
+        runner
+    } else {
+        runner.capture(report.test_output())
+    }
+}
+
+//Runs the tests against the answers recorded with --record instead of a
+//node. The files the recorded environment points to are written back,
+//and the tests get that environment with the URLs of replaying proxies
+async fn replay(
+    args: &Args,
+    config: &Config,
+    path: &Path,
+    interrupt: &Interrupt,
+    report: &mut Report,
+) -> Result<(), AptestError> {
+    let recording = Recording::load(path)?;
+    for (file, contents) in &recording.files {
+        let file = Path::new(file);
+        if let Some(dir) = file.parent().filter(|x| !x.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).or_fail(
+                AptestError::Environment,
+                &format!("Could not create directory {}", dir.display()),
+            )?;
+        }
+        std::fs::write(file, contents).or_fail(
+            AptestError::Environment,
+            &format!("Could not write {}", file.display()),
+        )?;
+    }
+    let mut runner = test_runner(args, config, report);
+    for (key, value) in &recording.env {
+        runner = runner.env(key, value);
+    }
+    let node = Proxy::replay(recording.exchanges("node")).start()?;
+    let node_url = node.url().to_string();
+    runner = runner.proxy("APTEST_NODE_URL", node);
+    let faucet = recording.exchanges("faucet");
+    if !faucet.is_empty() {
+        runner = runner.proxy("APTEST_FAUCET_URL", Proxy::replay(faucet).start()?);
+    }
+    ui::info(format!(
+        "Replaying {} answers from {}...",
+        recording.exchanges.len(),
+        path.display()
+    ));
+    install_dependencies(args, config, interrupt, report).await?;
+    report
+        .phase("tests", test(config, &node_url, &runner, interrupt))
+        .await?;
+    ui::success("Done");
+    Ok(())
+}
+
+//Adds the environment of the tests to the recording, but for the URLs
+//since a replay serves its own, along with the files it points to
+fn record_environment(path: &Path, runner: &TestRunner) -> Result<(), AptestError> {
+    let env: BTreeMap<String, String> = runner
+        .envs()
+        .iter()
+        .filter(|(key, _)| !key.ends_with("_URL"))
+        .cloned()
+        .collect();
+    for key in [
+        "APTEST_ACCOUNTS_FILE",
+        "APTEST_DEPLOYMENT_FILE",
+        "APTEST_FIXTURES_FILE",
+    ] {
+        if let Some(file) = env.get(key) {
+            let contents = std::fs::read_to_string(file).or_fail(
+                AptestError::Environment,
+                &format!("Could not read {}", file),
+            )?;
+            let entry = Entry::File {
+                path: file.clone(),
+                contents,
+            };
+            aptest::recording::append(path, &entry)?;
+        }
+    }
+    aptest::recording::append(path, &Entry::Env(env))
+}
+
+//The chaos profile of aptest.toml with this name, or else the built-in one
+fn chaos_profile(config: &Config, name: &str) -> Result<Chaos, AptestError> {
+    config
+        .chaos
+        .get(name)
+        .cloned()
+        .or_else(|| Chaos::profile(name))
+        .ok_or_else(|| {
+            AptestError::Environment(format!(
+                "Unknown chaos profile {}, expected one of {} or of the chaos section of aptest.toml",
+                name,
+                CHAOS_PROFILES.join(", ")
+            ))
+        })
+}
+
+//Writes the reports asked for on the command line, the lcov report when
+//the unit tests ran with coverage and the transactions or events when
+//asked for
+fn write_reports(
+    args: &Args,
+    report: &Report,
+    result: &Result<(), AptestError>,
+) -> Result<(), AptestError> {
+    for path in &args.report {
+        report.write(path, result)?;
+    }
+    if let Some(coverage) = report.coverage() {
+        coverage.write_lcov(Path::new(aptest::coverage::LCOV_PATH))?;
+    }
+    if let Some(path) = &args.dump_transactions {
+        aptest::history::write(report.transactions(), path)?;
+    }
+    if let Some(path) = &args.events {
+        aptest::events::write(&aptest::events::normalize(report.transactions()), path)?;
+    }
+    if let Some(path) = &args.state_diff {
+        aptest::diff::write(&report.state_changes(), path)?;
+    }
+    Ok(())
+}
+
+//Deploys, hands the deployments to the tests and runs the post_publish
+//hooks and fixtures if anything was actually published
+async fn publish(
+    args: &Args,
+    config: &Config,
+    net: &LocalNet,
+    publisher: &Publisher,
+    runner: TestRunner,
+    interrupt: &Interrupt,
+) -> Result<TestRunner, AptestError> {
+    let deployments = interrupt.race(publisher.deploy()).await?;
+    let deployment_file = run_file(args, DEPLOYMENT_FILE);
+    aptest::publish::write_deployments(&deployments, &deployment_file)?;
+    ui::success("Deployment successful.");
+    ui::event("publish_succeeded", json!({ "deployments": deployments }));
+
+    if args.codegen {
+        let modules: Vec<_> = deployments.iter().flat_map(|x| x.modules.clone()).collect();
+        let out_dir = Path::new(aptest::codegen::OUT_DIR);
+        aptest::codegen::generate(net.node_url(), &modules, out_dir).await?;
+        ui::success(format!("Generated bindings in {}.", out_dir.display()));
+    }
+    let mut runner = runner.deployments(&deployments, &deployment_file.to_string_lossy());
+    let fixtures_file = run_file(args, FIXTURES_FILE);
+    if deployments.iter().any(|x| !x.skipped) {
+        let hooks = &config.hooks.post_publish;
+        let post_publish =
+            aptest::hooks::run("post_publish", hooks, runner.envs(), Some(net.node_url()));
+        interrupt.race(post_publish).await?;
+        let seeded = interrupt
+            .race(aptest::seed::seed(&config.fixtures, net.node_url()))
+            .await?;
+        if !seeded.is_empty() {
+            aptest::seed::write(&seeded, &fixtures_file)?;
+            ui::event("fixtures_seeded", json!({ "fixtures": seeded }));
+        }
+    }
+    //A chain left as it was keeps the state seeded by an earlier run
+    if !config.fixtures.is_empty() && fixtures_file.exists() {
+        runner = runner.env("APTEST_FIXTURES_FILE", fixtures_file.to_string_lossy());
+    }
+    Ok(runner)
+}
+
+//Installs the JS dependencies of the tests when they are missing or out
+//of date, unless told not to
+async fn install_dependencies(
+    args: &Args,
+    config: &Config,
+    interrupt: &Interrupt,
+    report: &mut Report,
+) -> Result<(), AptestError> {
+    let dir = Path::new(".");
+    let recorded = config.project.as_ref().map(|x| x.package_manager);
+    let package_manager = aptest::deps::package_manager(dir, recorded);
+    if args.no_install || !aptest::deps::outdated(dir, package_manager) {
+        return Ok(());
+    }
+    let key = aptest::deps::cache_key(dir, package_manager);
+    let cache = args.deps_cache.as_deref().zip(key.as_deref());
+    let install = async {
+        if let Some((cache_dir, key)) = cache {
+            if aptest::deps::restore(dir, cache_dir, key)? {
+                return Ok(());
             }
         }
+        aptest::deps::install(dir, package_manager, args.frozen).await?;
+        if let Some((cache_dir, key)) = cache {
+            //Installing again next time is no reason to fail
+            if let Err(err) = aptest::deps::save(dir, cache_dir, key) {
+                ui::failure(format!("Could not cache the JS dependencies: {}", err));
+            }
+        }
+        Ok::<_, AptestError>(())
     };
+    report.phase("install", interrupt.race(install)).await?;
+    interrupt.check()
 }
-macro_rules! cleanup_expect {
-    ($e:expr, $msg:expr, $children:expr, $args:expr) => {
-        match $e {
-            Ok(v) => v,
-            Err(e) => {
-                println!("\n{}\n", $msg);
-                println!("{}\n", e);
-                cleanup($children, $args);
-                std::process::exit(1);
+
+//Runs the tests as a phase of the report. With --gas-report, or when the
+//transactions or their events are asked for, the transactions committed
+//while they ran are read back from the node, and their gas or themselves
+//added to the report
+async fn tests(
+    name: &str,
+    args: &Args,
+    config: &Config,
+    net: &LocalNet,
+    runner: &TestRunner,
+    interrupt: &Interrupt,
+    report: &mut Report,
+) -> Result<(), AptestError> {
+    if !runs(args, Phase::E2e) {
+        return Ok(());
+    }
+    install_dependencies(args, config, interrupt, report).await?;
+    let client = NodeClient::new(net.node_url());
+    let start = if args.gas_report || keeps_transactions(args) {
+        Some(client.ledger_version().await?)
+    } else {
+        None
+    };
+    if let Some(path) = &args.record {
+        record_environment(path, runner)?;
+    }
+    let tracked = if args.state_diff.is_some() {
+        tracked_accounts(config, runner)?
+    } else {
+        Vec::new()
+    };
+    let before = aptest::diff::capture(&client, &tracked).await;
+    //Failing tests are run again, only the transactions of the last
+    //attempt counting
+    let retries = Cell::new(0);
+    let start = Cell::new(start);
+    let retry_args: Vec<_> = config.test.retry_args.iter().map(String::as_str).collect();
+    let retry_runner = runner.clone().args(&retry_args);
+    let attempts = async {
+        loop {
+            let runner = if retries.get() == 0 {
+                runner
+            } else {
+                &retry_runner
+            };
+            let result = test(config, net.node_url(), runner, interrupt).await;
+            let failed = matches!(result, Err(AptestError::Tests(_) | AptestError::Timeout(_)));
+            if !failed || retries.get() == args.test_retries {
+                return result;
+            }
+            retries.set(retries.get() + 1);
+            ui::info(format!(
+                "Running e2e tests again ({}/{})...",
+                retries.get(),
+                args.test_retries
+            ));
+            if start.get().is_some() {
+                start.set(Some(client.ledger_version().await?));
             }
         }
     };
+    let result = report.phase(name, attempts).await;
+    report.set_retries(name, retries.get());
+    if result.is_ok() && retries.get() > 0 {
+        ui::info(format!(
+            "e2e tests passed on attempt {}, they are flaky",
+            retries.get() + 1
+        ));
+    }
+    let start = start.get();
+    //There is no node to read the transactions from once it exited
+    let interrupted = matches!(
+        result,
+        Err(AptestError::Interrupted | AptestError::NodeExited(_))
+    );
+    if let (Some(start), false) = (start, interrupted) {
+        match client.user_transactions_since(start).await {
+            Ok(transactions) => {
+                if args.gas_report {
+                    report.gas_mut().add_transactions(&transactions);
+                }
+                if keeps_transactions(args) {
+                    report.add_transactions(&transactions);
+                }
+            }
+            //The tests failing matters more
+            Err(err) => return result.and(Err(err)),
+        }
+    }
+    if args.state_diff.is_some() && !interrupted {
+        let after = aptest::diff::capture(&client, &tracked).await;
+        let dir = if args.isolate {
+            run_dir().join("state")
+        } else {
+            PathBuf::from(STATE_DIR)
+        };
+        aptest::diff::write(&before, &dir.join("before.json"))?;
+        aptest::diff::write(&after, &dir.join("after.json"))?;
+        let changes = aptest::diff::changes(&before, &after);
+        aptest::diff::print(&changes);
+        report.add_state_changes(changes);
+    }
+    result
 }
-macro_rules! make_file {
-    ($path:expr, $content:expr) => {
-        let mut file = pretty_expect!(File::create($path), "Failed to create file");
-        pretty_expect!(
-            file.write_all($content.as_bytes()),
-            "Failed to write to file"
-        );
+
+//The accounts whose resources --state-diff captures: those of the diff
+//section of aptest.toml, or else the publishing accounts and the test
+//accounts, fee payer and multisig account handed to the tests
+fn tracked_accounts(config: &Config, runner: &TestRunner) -> Result<Vec<String>, AptestError> {
+    if !config.diff.accounts.is_empty() {
+        return config
+            .diff
+            .accounts
+            .iter()
+            .map(|x| aptest::accounts::resolve(x))
+            .collect();
+    }
+    let mut accounts: Vec<_> = runner
+        .envs()
+        .iter()
+        .filter(|(key, _)| {
+            (key.starts_with("APTEST_DEPLOYMENT_") && key.ends_with("_SENDER"))
+                || (key.starts_with("APTEST_ACCOUNT_") && key.ends_with("_ADDRESS"))
+                || key == "APTEST_FEE_PAYER_ADDRESS"
+                || key == "APTEST_MULTISIG_ADDRESS"
+        })
+        .map(|(_, address)| address.clone())
+        .collect();
+    accounts.sort();
+    accounts.dedup();
+    Ok(accounts)
+}
+
+//Adds the gas used by the publish transactions to the report when
+//asked to, from the deployments the run just wrote
+fn publish_gas(args: &Args, report: &mut Report) -> Result<(), AptestError> {
+    if args.gas_report {
+        let deployments = aptest::publish::read_deployments(&run_file(args, DEPLOYMENT_FILE))?;
+        report.gas_mut().published(&deployments);
+    }
+    Ok(())
+}
+
+//Whether the transactions of the tests are written out, or their events
+fn keeps_transactions(args: &Args) -> bool {
+    args.dump_transactions.is_some() || args.events.is_some() || args.compare_events.is_some()
+}
+
+//Compares the events of a passing run with the snapshot given with
+//--compare-events, saving them as the snapshot if there is none yet
+async fn compare_events(args: &Args, report: &mut Report) -> Result<(), AptestError> {
+    let path = match &args.compare_events {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let events = aptest::events::normalize(report.transactions());
+    if !path.exists() {
+        aptest::events::write(&events, path)?;
+        ui::info(format!(
+            "Saved the events emitted as the snapshot in {}",
+            path.display()
+        ));
+        return Ok(());
+    }
+    let check = async {
+        let differences = aptest::events::diff(&aptest::events::read(path)?, &events);
+        if differences.is_empty() {
+            return Ok(());
+        }
+        Err(AptestError::Tests(format!(
+            "The events emitted differ from {}:\n{}",
+            path.display(),
+            differences.join("\n")
+        )))
     };
+    report.phase("events", check).await
 }
-macro_rules! make_dir {
-    ($path:expr) => {
-        pretty_expect!(
-            std::fs::create_dir_all($path),
-            format!("Could not create directory {}", $path)
-        );
+
+//Checks the gas used by a passing run against the baseline with
+//--gas-check, failing or warning about regressions, or saves it as the
+//baseline with --gas-report. The baseline a check compares with is only
+//ever replaced by --gas-report, so that regressions can't creep in
+async fn gas_baseline(
+    args: &Args,
+    config: &Config,
+    report: &mut Report,
+) -> Result<(), AptestError> {
+    let gas = match report.gas() {
+        Some(gas) => gas.clone(),
+        None => return Ok(()),
+    };
+    let path = Path::new(BASELINE_PATH);
+    let baseline = match GasReport::load(path)? {
+        Some(baseline) if args.gas_check => baseline,
+        _ => {
+            gas.save(path)?;
+            ui::info(format!(
+                "Saved the gas used as the baseline in {}",
+                path.display()
+            ));
+            return Ok(());
+        }
+    };
+    let max_increase = config.gas.max_increase.unwrap_or(DEFAULT_MAX_INCREASE);
+    let regressions = gas.regressions(&baseline, max_increase);
+    if regressions.is_empty() {
+        return Ok(());
+    }
+    if config.gas.warn_only {
+        ui::failure(format!("Gas usage grew over {}%", max_increase));
+        for line in regressions {
+            ui::print(line);
+        }
+        return Ok(());
+    }
+    let check = async {
+        Err(AptestError::Tests(format!(
+            "Gas usage grew over {}% compared to {}:\n{}",
+            max_increase,
+            path.display(),
+            regressions.join("\n")
+        )))
     };
+    report.phase("gas", check).await
 }
 
-///A small framework to assist in testing aptos programs
-#[derive(Parser)]
-#[clap(version, about, long_about = None)]
-struct Sub {
-    #[clap(subcommand)]
-    cmd: Subcmds,
+//Runs the tests between the pre_test and post_test hooks,
+//the latter running whether the tests passed or not
+async fn test(
+    config: &Config,
+    node_url: &str,
+    runner: &TestRunner,
+    interrupt: &Interrupt,
+) -> Result<(), AptestError> {
+    let (envs, node_url) = (runner.envs(), Some(node_url));
+    let hooks = &config.hooks.pre_test;
+    interrupt
+        .race(aptest::hooks::run("pre_test", hooks, envs, node_url))
+        .await?;
+
+    let result = runner.run(interrupt).await;
+    if let Err(AptestError::Interrupted) = result {
+        return result;
+    }
+    ui::event(
+        "tests_finished",
+        json!({
+            "success": result.is_ok(),
+            "exit_code": result.as_ref().err().map_or(0, |e| e.exit_code()),
+        }),
+    );
+
+    let hooks = &config.hooks.post_test;
+    interrupt
+        .race(aptest::hooks::run("post_test", hooks, envs, node_url))
+        .await?;
+    result
 }
-#[derive(Parser)]
-struct Args {
-    ///Removes call to "aptos move compile"
-    #[clap(short = 'c', long)]
-    no_compile: bool,
 
-    ///Removes call to "aptos move publish"
-    #[clap(short = 'p', long)]
-    no_publish: bool,
+//Runs the on_failure hooks with the error exposed as APTEST_ERROR and
+//APTEST_EXIT_CODE. Their own failures are only reported, so the
+//original error still decides the exit code
+async fn on_failure(config: &Config, err: &AptestError) {
+    if let AptestError::Interrupted = err {
+        return;
+    }
+    let envs = [
+        ("APTEST_ERROR".to_string(), err.to_string()),
+        ("APTEST_EXIT_CODE".to_string(), err.exit_code().to_string()),
+    ];
+    let hooks = &config.hooks.on_failure;
+    if let Err(hook_err) = aptest::hooks::run("on_failure", hooks, &envs, None).await {
+        report(&hook_err);
+    }
+}
 
-    ///Specifies the number of seconds to wait on the validator
-    ///spinning up before trying to interact with it
-    #[clap(short = 'd', long, default_value = "14")]
-    start_delay: u64,
+//Creates the publishing and funded profiles missing from the aptos config
+//file, pointed at the local network, unless told not to, and checks that
+//they all point at it
+fn ensure_profiles(args: &Args, net: &LocalNet) -> Result<(), AptestError> {
+    create_profiles(args, net.node_url(), net.faucet_url())?;
+    for profile in args.profile.iter().chain(&args.fund_profiles) {
+        //Isolated nodes get new ports every time they start
+        if args.isolate {
+            aptest::profile::set_urls(profile, net.node_url(), net.faucet_url())?;
+        }
+        //Profiles of another network would fund and publish there
+        AptosConfig::load()?.check_rest_url(profile, net.node_url())?;
+    }
+    Ok(())
+}
 
-    ///Run just the validator node, without a faucet
-    #[clap(long, short = 'f')]
-    no_faucet: bool,
+//Creates the publishing and funded profiles missing from the aptos config
+//file, pointed at these URLs, unless told not to
+fn create_profiles(
+    args: &Args,
+    node_url: &str,
+    faucet_url: Option<&str>,
+) -> Result<(), AptestError> {
+    if args.no_auto_profile {
+        return Ok(());
+    }
+    for profile in args.profile.iter().chain(&args.fund_profiles) {
+        if aptest::profile::has_profile(profile) {
+            continue;
+        }
+        let account = aptest::profile::create_local_profile(profile, node_url, faucet_url)?;
+        ui::info(format!(
+            "Created aptos profile {} for {}",
+            profile, account.address
+        ));
+    }
+    Ok(())
+}
 
-    ///Starts validator and waits for Ctrl+C so that end to end tests can be run manually
-    #[clap(long, short)]
-    interactive: bool,
+//Accounts generated for the tests
+#[derive(Default)]
+struct TestAccounts {
+    accounts: Vec<TestAccount>,
+    fee_payer: Option<TestAccount>,
+    multisig: Option<Multisig>,
+}
 
-    ///Logs the output of the validator to a file
-    #[clap(long = "log", short)]
-    log_node: bool,
+//Generates and funds the extra test accounts requested on the command line,
+//the fee payer account if asked for there or in aptest.toml and the
+//multisig account
+async fn create_accounts(
+    args: &Args,
+    config: &Config,
+    net: &LocalNet,
+) -> Result<TestAccounts, AptestError> {
+    let fee_payer = args.fee_payer || config.fee_payer.is_some();
+    if args.accounts == 0 && !fee_payer && args.multisig.is_none() {
+        return Ok(TestAccounts::default());
+    }
+    let funder = net.funder().ok_or_else(|| {
+        AptestError::Environment("Funding test accounts needs a faucet".to_string())
+    })?;
+    let seed = match &args.account_seed {
+        Some(seed) => Some(hex::decode(seed.trim_start_matches("0x")).map_err(|_| {
+            AptestError::Environment(format!("The account seed {} is not hex", seed))
+        })?),
+        None => None,
+    };
+    let accounts = if args.accounts == 0 {
+        Vec::new()
+    } else {
+        aptest::accounts::create_funded(
+            args.accounts,
+            DEFAULT_FUND_AMOUNT,
+            seed.as_deref(),
+            &funder,
+            &run_file(args, ACCOUNTS_FILE),
+        )
+        .await?
+    };
+    let fee_payer = if fee_payer {
+        let amount = config
+            .fee_payer
+            .as_ref()
+            .map_or_else(|| FeePayer::default().amount(), FeePayer::amount);
+        Some(aptest::accounts::create_fee_payer(amount, seed.as_deref(), &funder).await?)
+    } else {
+        None
+    };
+    let multisig = match args.multisig {
+        Some(threshold) => Some(
+            aptest::multisig::create(
+                threshold,
+                DEFAULT_FUND_AMOUNT,
+                seed.as_deref(),
+                &funder,
+                net.node_url(),
+            )
+            .await?,
+        ),
+        None => None,
+    };
+    Ok(TestAccounts {
+        accounts,
+        fee_payer,
+        multisig,
+    })
 }
 
-#[derive(Subcommand)]
-enum Subcmds {
-    ///Initialize a new project
-    Init { name: String },
+//Starts the validator, and faucet unless disabled, as configured on the command line.
+//Attaches to an already running node or a public network instead when asked to,
+//or when a node is answering on the default port (which a new node couldn't bind anyway)
+async fn start_node(
+    args: &Args,
+    config: &Config,
+    interrupt: &Interrupt,
+) -> Result<LocalNet, AptestError> {
+    if let Some(remote) = remote(args)? {
+        let faucet_url = remote
+            .faucet_url
+            .as_deref()
+            .filter(|_| runs(args, Phase::Faucet));
+        return LocalNet::attach(&remote.node_url, faucet_url).await;
+    }
+    let faucet_url = runs(args, Phase::Faucet).then_some(FAUCET_URL);
+    if let Some(url) = &args.attach {
+        return LocalNet::attach(url, faucet_url).await;
+    }
+    if !own_node(args, config) && aptest::node::default_node_running().await {
+        return LocalNet::attach(aptest::node::NODE_URL, faucet_url).await;
+    }
+    let mut net = local_node(args, config)?.start(interrupt).await?;
+    if let Some(secs) = args.epoch_interval.or(config.node.epoch_interval) {
+        net.epoch_interval(Duration::from_secs(secs))?;
+    }
+    if !args.isolate {
+        remember_localnet(args, &net)?;
+    }
+    Ok(net)
+}
 
-    ///Runs the framework in the current directory
-    Run(Args),
+//Whether the run starts a node of its own rather than attaching to one
+//already answering on the default port. Epochs are only ended early for
+//a node aptest starts itself
+fn own_node(args: &Args, config: &Config) -> bool {
+    args.persist_state.is_some()
+        || args.isolate
+        || args.with_indexer
+        || args.epoch_interval.or(config.node.epoch_interval).is_some()
 }
 
-fn main() {
-    let sub = Sub::parse();
+//Records the network in the state, for "aptest logs" to find
+fn remember_localnet(args: &Args, net: &LocalNet) -> Result<(), AptestError> {
+    let dir = Path::new(LOG_DIR);
+    let mut state = State::load();
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs());
+    state.set_localnet(Some(Localnet {
+        pid: std::process::id(),
+        started,
+        node_url: net.node_url().to_string(),
+        faucet_url: net.faucet_url().map(str::to_string),
+        node_log: dir.join(NODE_LOG),
+        //The faucet of the indexer stack logs along with the node
+        faucet_log: (net.faucet_url().is_some() && !args.with_indexer)
+            .then(|| dir.join(FAUCET_LOG)),
+        mint_key: net.mint_key_path().map(str::to_string),
+    }));
+    state.save()
+}
 
-    //If the sub command is init, call the init function,
-    //else return runargs 
-    let args = match sub.cmd {
-        Subcmds::Init { name } => init(name),
-        Subcmds::Run(runargs) => runargs,
-    };
+//Removes the network this process ran from the state once it's gone
+fn forget_localnet() {
+    let mut state = State::load();
+    if state.localnet().map(|x| x.pid) == Some(std::process::id()) {
+        state.set_localnet(None);
+        let _ = state.save();
+    }
+}
+
+//The validator, and faucet unless disabled, as configured on the command line
+//and in aptest.toml. Isolated runs let the validator pick its ports and give
+//the faucet a free one
+fn local_node(args: &Args, config: &Config) -> Result<LocalNode, AptestError> {
+    let mut node = LocalNode::new()
+        .start_timeout(Duration::from_secs(args.start_delay))
+        .random_ports(args.isolate)
+        .with_indexer(args.with_indexer)
+        .log_dir(log_dir(args))
+        .extra_args(config.node.extra_args.clone());
+    if !args.no_genesis_cache {
+        node = node.genesis_cache(GENESIS_CACHE_DIR);
+    }
+    if let Some(path) = &args.node_bin {
+        node = node.binary(path);
+    }
+    if let Some(chain_id) = config.node.chain_id {
+        node = node.chain_id(chain_id);
+    }
+    if let Some(path) = &config.node.config {
+        node = node.config_override(path);
+    }
+    if let Some(path) = &config.node.genesis_framework {
+        node = node.genesis_framework(path);
+    }
+    if !runs(args, Phase::Faucet) {
+        node = node.faucet(None);
+    } else {
+        let mut faucet = Faucet::new().extra_args(config.faucet.extra_args.clone());
+        if let Some(path) = &args.faucet_bin {
+            faucet = faucet.binary(path);
+        }
+        if args.isolate {
+            faucet = faucet.port(aptest::node::free_port()?);
+        }
+        node = node.faucet(Some(faucet));
+    }
+    if args.log_node {
+        node = node.log_file("validator.log");
+    }
+    if let Some(dir) = &args.persist_state {
+        node = node.persist_state(dir).reset_state(args.reset);
+    }
+    Ok(node)
+}
 
-    let (tx, rx) = channel();
+//The package given on the command line, or those of the workspace in
+//aptest.toml in dependency order, or none for the current directory
+fn packages(args: &Args, config: &Config) -> Result<Vec<PathBuf>, AptestError> {
+    if let Some(dir) = &args.package_dir {
+        return Ok(vec![dir.clone()]);
+    }
+    match &config.workspace {
+        Some(workspace) => workspace.ordered_packages(),
+        None => Ok(Vec::new()),
+    }
+}
 
-    ctrlc::set_handler(move || {
-        tx.send(())
-            .expect("Could not send signal to setup Ctrl-C handler")
-    })
-    .expect("Could not set Ctrl-C handler");
-
-    //Compilation
-    if !args.no_compile {
-        println!("\n{}\n", "Compiling Move code...".bright_blue().bold());
-        let exit_code = Command::new("aptos")
-            .args(["move", "compile"])
-            .status()
-            .expect("Couldn't find aptos command. Is it installed ?");
-        if !exit_code.success() {
-            println!(
-                "\n{}\n",
-                "Compilation failed, exiting early...".bright_red().bold()
-            );
-            //Cleanup not needed because nodes haven't been started yet
-            std::process::exit(1);
+//The public network run against with --network, if any
+fn remote(args: &Args) -> Result<Option<Remote>, AptestError> {
+    args.network.as_deref().map(Remote::parse).transpose()
+}
+
+//The named addresses of aptest.toml, resolved to addresses. Dev builds
+//publish the [dev-addresses] of the packages that aptest.toml leaves
+//out from the first publishing profile, whose account the modules
+//have to be at
+fn named_addresses(
+    args: &Args,
+    config: &Config,
+    packages: &[PathBuf],
+) -> Result<BTreeMap<String, String>, AptestError> {
+    let mut addresses = config
+        .addresses
+        .iter()
+        .map(|(name, account)| Ok((name.clone(), aptest::accounts::resolve(account)?)))
+        .collect::<Result<BTreeMap<_, _>, AptestError>>()?;
+    if !args.dev && !config.compile.dev {
+        return Ok(addresses);
+    }
+    let current = [PathBuf::from(".")];
+    let dirs = if packages.is_empty() {
+        &current[..]
+    } else {
+        packages
+    };
+    let profile = args.profile.first().map_or("default", String::as_str);
+    for dir in dirs {
+        for name in aptest::workspace::dev_addresses(dir)? {
+            if !addresses.contains_key(&name) {
+                addresses.insert(name, aptest::accounts::resolve(profile)?);
+            }
         }
     }
+    Ok(addresses)
+}
+
+//Publishes to the given network, funding the configured profiles
+//if it has a faucet or a mint key to fall back on
+fn publisher_for(
+    args: &Args,
+    config: &Config,
+    packages: &[PathBuf],
+    addresses: &BTreeMap<String, String>,
+    net: &LocalNet,
+) -> Publisher {
+    Publisher::new()
+        .packages(packages.to_vec())
+        .named_addresses(addresses.clone())
+        .build_args(build_args(args, config))
+        .node_url(net.node_url())
+        .funder(net.funder())
+        .fund_amount(args.fund_amount)
+        .fund_profiles(args.fund_profiles.clone())
+        .profiles(args.profile.clone())
+        .force(args.force_publish)
+        .upgrade_policy(args.upgrade_policy)
+        .max_gas(args.max_gas.or(config.publish.max_gas))
+        .gas_unit_price(args.gas_unit_price.or(config.publish.gas_unit_price))
+        .included_artifacts(
+            args.included_artifacts
+                .or(config.publish.included_artifacts),
+        )
+        .extra_args(config.aptos.extra_args.clone())
+        .fund_timeout(args.fund_timeout.map(Duration::from_secs))
+        .publish_timeout(args.publish_timeout.map(Duration::from_secs))
+        .retry(
+            Retry::new()
+                .attempts(args.retries)
+                .delay(Duration::from_millis(args.retry_delay)),
+        )
+}
 
-    //Local Node start
-    let children = start_node(&args);
+//Options of the package build, those on the command line taking
+//precedence over the [compile] section of aptest.toml
+fn build_args(args: &Args, config: &Config) -> Vec<String> {
+    let mut build_args = Vec::new();
+    if args.dev || config.compile.dev {
+        build_args.push("--dev".to_string());
+    }
+    if args.skip_fetch_latest_git_deps || config.compile.skip_fetch_latest_git_deps {
+        build_args.push("--skip-fetch-latest-git-deps".to_string());
+    }
+    let compiler = args.compiler_version.as_ref();
+    if let Some(version) = compiler.or(config.compile.compiler_version.as_ref()) {
+        build_args.extend(["--compiler-version".to_string(), version.clone()]);
+    }
+    let language = args.language_version.as_ref();
+    if let Some(version) = language.or(config.compile.language_version.as_ref()) {
+        build_args.extend(["--language-version".to_string(), version.clone()]);
+    }
+    build_args
+}
 
-    if !args.no_publish {
-        match publish() {
-            Ok(_) => {
-                println!("\n{}\n", "Deployment successful.".bright_green().bold());
+//Account management against an already running network
+async fn account(cmd: AccountCmd) -> Result<(), AptestError> {
+    match cmd {
+        AccountCmd::New { name } => {
+            let account = aptest::accounts::create_named(&name)?;
+            println!("{}: {}", name.bright_green().bold(), account.address);
+        }
+        AccountCmd::Fund {
+            account,
+            amount,
+            faucet_url,
+        } => {
+            let address = aptest::accounts::resolve(&account)?;
+            aptest::faucet::fund(&faucet_url, &address, amount).await?;
+            ui::success(format!("Funded {} with {} octas.", address, amount));
+        }
+        AccountCmd::List => {
+            ui::info("Accounts");
+            for (name, account) in aptest::accounts::named()? {
+                println!("{:<20} {}", name, account.address);
             }
-            Err(err) => {
-                println!(
-                    "\n{}{}\n",
-                    "Error: ".bright_red().bold(),
-                    err.bright_red().bold()
-                );
-                cleanup(children, &args);
-                std::process::exit(1);
+            ui::info("Aptos profiles");
+            for (name, address) in aptest::profile::list_profiles()? {
+                println!("{:<20} {}", name, address);
             }
         }
+        AccountCmd::Balance { account, node_url } => {
+            let address = aptest::accounts::resolve(&account)?;
+            let balance = NodeClient::new(&node_url).balance(&address).await?;
+            println!("{} octas", balance);
+        }
     }
+    Ok(())
+}
 
-    if args.interactive {
-        println!("\n{}\n", "Local Node is running.".bright_green().bold());
-        println!(
-            "{}\n",
-            "End to End tests can be run separately now, or Ctrl+C\nto exit tool and close node..."
-                .bright_blue()
-                .bold()
-        );
-        rx.recv().expect("Could not receive from channel.");
-    } else {
-        //Start End to End tests and wait for them to finish
-        let mut e2e_child = cleanup_expect!(
-            e2e_tests(),
-            "Error running e2e tests".bright_red().bold(),
-            children,
-            &args
-        );
-        e2e_child.wait().expect("Could not wait on npm child");
-    }
-
-    cleanup(children, &args);
-    println!("\n{}", "Done".bright_green().bold());
-}
-
-//Cleans up running nodes and logs them if requested
-fn cleanup(children: (Child, Option<Child>, String), args: &Args) {
-    let mut node_child = children.0;
-    let maybe_faucet_child = children.1;
-    let scanned_output = children.2;
-    //Close node and faucet
-    println!("\n{}\n", "Closing local node...".bright_blue().bold());
-    node_child
-        .kill()
-        .expect("Could not kill validator process.");
-    let node_output = node_child
-        .wait_with_output()
-        .expect("Could not wait on validator.");
-    let node_output = String::from_utf8_lossy(&node_output.stdout[..]).to_string();
-
-    let foutput: Option<Output>;
-    let mut faucet_output = String::new();
-    if let Some(mut faucet_child) = maybe_faucet_child {
-        faucet_child.kill().expect("Could not kill faucet process.");
-        foutput = Some(
-            faucet_child
-                .wait_with_output()
-                .expect("Could not wait on faucet."),
-        );
-        faucet_output = String::from_utf8_lossy(&foutput.unwrap().stderr[..]).to_string();
-    }
+//Calls a view function and prints the values it returned, a single one
+//on its own
+async fn view(args: ViewArgs) -> Result<(), AptestError> {
+    let function = aptest::args::function_id(&args.function)?;
+    let arguments = args
+        .args
+        .iter()
+        .map(|arg| aptest::args::parse(arg).map(|value| value.json()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let result = NodeClient::new(&args.node_url)
+        .view(&function, &args.type_args, arguments)
+        .await?;
+    let result = match result.as_array() {
+        Some(values) if values.len() == 1 => &values[0],
+        _ => &result,
+    };
+    let pretty = serde_json::to_string_pretty(result).or_fail(
+        AptestError::Request,
+        "Could not print the result of the view function",
+    )?;
+    println!("{}", pretty);
+    Ok(())
+}
 
-    //Write out node's log if requested
-    if args.log_node {
-        let mut log_file = File::create("validator.log").expect("Could not create log file.");
-        let mut log_string = scanned_output;
-        log_string.push_str(node_output.as_str());
-        log_string.push_str(faucet_output.as_str());
-        log_file
-            .write_all(log_string.as_bytes())
-            .expect("Could not write to log file.");
+//Prints the clock of the network, or ends epochs with its root key
+async fn clock(cmd: ClockCmd) -> Result<(), AptestError> {
+    match cmd {
+        ClockCmd::Show { node_url } => {
+            let client = NodeClient::new(&node_url);
+            let timestamp = client.ledger_timestamp().await?;
+            println!("epoch      {}", client.epoch().await?);
+            println!(
+                "timestamp  {}.{:06}",
+                timestamp / 1_000_000,
+                timestamp % 1_000_000
+            );
+        }
+        ClockCmd::NextEpoch {
+            epochs,
+            node_url,
+            mint_key,
+        } => {
+            let mint_key = mint_key
+                .or_else(|| State::load().localnet().and_then(|x| x.mint_key.clone()))
+                .ok_or_else(|| {
+                    AptestError::Environment(
+                        "aptest isn't running a network, give its root key with --mint-key"
+                            .to_string(),
+                    )
+                })?;
+            for _ in 0..epochs {
+                let epoch = aptest::clock::next_epoch(&node_url, &mint_key).await?;
+                ui::success(format!("Now in epoch {}.", epoch));
+            }
+        }
     }
+    Ok(())
 }
 
-///Start the local node and return a tuple of the child process and
-/// optional faucet child process
-fn start_node(args: &Args) -> (Child, Option<Child>, String) {
-    println!(
-        "\n{}\n",
-        "Starting local validator node...".bright_blue().bold()
-    );
+//Prints the state changes between two captures written by --state-diff
+fn diff(args: DiffArgs) -> Result<(), AptestError> {
+    let before = aptest::diff::read(&args.before)?;
+    let after = aptest::diff::read(&args.after)?;
+    let changes = aptest::diff::changes(&before, &after);
+    if args.json {
+        let json = serde_json::to_string_pretty(&changes).or_fail(
+            AptestError::Environment,
+            "Could not print the state changes",
+        )?;
+        println!("{}", json);
+    } else {
+        aptest::diff::print(&changes);
+    }
+    Ok(())
+}
 
-    let node_attempt = Command::new("aptos-node")
-        .args(["--test"])
-        .stdout(Stdio::piped())
-        .spawn();
+//Signs and sends an entry function transaction, printing its gas and
+//events once executed. An abort fails with the node's decoded reason
+async fn call(args: CallArgs) -> Result<(), AptestError> {
+    let function = aptest::args::function_id(&args.function)?;
+    let entry = entry_function(&function, &args.type_args, &args.args)?;
+    let (sender, key) = aptest::accounts::signer(&args.from)?;
+    let client = NodeClient::new(&args.node_url);
+    let hash = match aptest::transaction::submit(&client, &sender, &key, &entry).await {
+        Ok(hash) => hash,
+        Err(err) => {
+            ErrorMap::load(&[]).report(&err.to_string());
+            return Err(err);
+        }
+    };
+    let transaction = client.transaction(&hash).await?;
 
-    let mut node_child = pretty_expect!(
-        node_attempt,
-        "Could not find the aptos-node command. Is it installed ?..."
-            .bright_red()
-            .bold()
+    ui::success(format!("{} executed.", function));
+    println!("hash      {}", hash);
+    println!("sender    {}", sender);
+    println!(
+        "gas used  {}",
+        transaction["gas_used"].as_str().unwrap_or_default()
     );
-
-    //This is hardcoded because since the validator runs constantly
-    //it doesn't print EOF in the stdout stream, so we have to grab
-    //a predetermined amount of bytes. 450 bytes should be enough
-    //to find the mint key file, but there is likely a more robust
-    //way to do this.
-    let mut buffer: [u8; 450] = [0; 450];
-    node_child
-        .stdout
-        .as_mut()
-        .expect("Could not get stdout reference from node child process")
-        .read_exact(&mut buffer)
-        .expect("Could not read from node child process stdout");
-
-    let node_output = String::from_utf8_lossy(&buffer[..]).to_string();
-
-    let mint_key_path = find_mint_path(node_output.clone());
-
-    if !args.no_faucet {
-        sleep(Duration::from_secs(args.start_delay / 2));
-        let faucet_attempt = Command::new("aptos-faucet")
-            .args([
-                "--chain-id",
-                "TESTING",
-                "--mint-key-file-path",
-                mint_key_path.as_str(),
-                "--address",
-                "0.0.0.0",
-                "--port",
-                "8000",
-                "--server-url",
-                "http://localhost:8080",
-            ])
-            .stderr(Stdio::piped())
-            .spawn();
-
-        let faucet_child = cleanup_expect!(
-            faucet_attempt,
-            "Could not find the aptos-faucet command. Is it installed ?..."
-                .bright_red()
-                .bold(),
-            (node_child, None, node_output),
-            args
+    for event in transaction["events"].as_array().into_iter().flatten() {
+        let data = serde_json::to_string_pretty(&event["data"]).unwrap_or_default();
+        println!(
+            "event     {}\n{}",
+            event["type"].as_str().unwrap_or_default(),
+            data
         );
+    }
+    Ok(())
+}
 
-        sleep(Duration::from_secs(args.start_delay / 2));
-        return (node_child, Some(faucet_child), node_output);
+//Fetches state from the node's REST API, printed as it came with --json
+//and laid out for reading otherwise
+async fn query(cmd: QueryCmd) -> Result<(), AptestError> {
+    let (result, options) = match &cmd {
+        QueryCmd::Resources {
+            account,
+            resource_type,
+            options,
+        } => {
+            let address = aptest::accounts::resolve(account)?;
+            let client = NodeClient::new(&options.node_url);
+            let result = match resource_type {
+                Some(resource_type) => client.resource(&address, resource_type).await?,
+                None => client.resources(&address).await?,
+            };
+            (result, options)
+        }
+        QueryCmd::Modules { account, options } => {
+            let address = aptest::accounts::resolve(account)?;
+            let result = NodeClient::new(&options.node_url).modules(&address).await?;
+            (result, options)
+        }
+        QueryCmd::Table {
+            handle,
+            key,
+            value_type,
+            options,
+        } => {
+            let key_type = aptest::args::type_of(key)?;
+            let key = aptest::args::parse(key)?.json();
+            let result = NodeClient::new(&options.node_url)
+                .table_item(handle, &key_type, value_type, key)
+                .await?;
+            (result, options)
+        }
+    };
+    if options.json {
+        println!("{}", result);
+        return Ok(());
     }
-    sleep(Duration::from_secs(args.start_delay));
 
-    (node_child, None, node_output)
+    let pretty =
+        |value: &serde_json::Value| serde_json::to_string_pretty(value).unwrap_or_default();
+    match cmd {
+        QueryCmd::Resources { .. } => {
+            //A single resource comes alone, every resource as a list
+            for resource in result.as_array().cloned().unwrap_or_else(|| vec![result]) {
+                ui::info(resource["type"].as_str().unwrap_or_default());
+                println!("{}", pretty(&resource["data"]));
+            }
+        }
+        QueryCmd::Modules { .. } => {
+            for module in result.as_array().into_iter().flatten() {
+                let abi = &module["abi"];
+                ui::info(format!(
+                    "{}::{}",
+                    abi["address"].as_str().unwrap_or_default(),
+                    abi["name"].as_str().unwrap_or_default()
+                ));
+                for function in abi["exposed_functions"].as_array().into_iter().flatten() {
+                    let kind = if function["is_view"].as_bool() == Some(true) {
+                        "view"
+                    } else if function["is_entry"].as_bool() == Some(true) {
+                        "entry"
+                    } else {
+                        "public"
+                    };
+                    println!(
+                        "{:<8}{}",
+                        kind,
+                        function["name"].as_str().unwrap_or_default()
+                    );
+                }
+            }
+        }
+        QueryCmd::Table { .. } => println!("{}", pretty(&result)),
+    }
+    Ok(())
 }
 
-/// Publish the contract to the validator node,
-/// will halt and error if the publishing fails
-fn publish() -> Result<(), String> {
-    //-----------------------------Funding--------------------------------------
-    println!(
-        "\n{}\n",
-        "Funding new account on local node...".bright_blue().bold()
-    );
+//A call to a function given as on the command line, its address and
+//those of its arguments possibly being account names or profiles
+fn entry_function(
+    function: &str,
+    type_args: &[String],
+    args: &[String],
+) -> Result<EntryFunction, AptestError> {
+    let mut entry = EntryFunction::new(&aptest::args::function_id(function)?)?;
+    for tag in type_args {
+        entry = entry.type_arg(tag)?;
+    }
+    for arg in args {
+        entry = entry.arg(&aptest::args::parse(arg)?);
+    }
+    Ok(entry)
+}
 
-    let account = fetch_account();
-    let account = account.as_str();
+//Benchmarks the local network with the function given, or the mix of
+//calls of aptest.toml
+async fn bench(args: BenchArgs) -> Result<(), AptestError> {
+    let interrupt = Interrupt::listen()?;
+    let config = Config::load()?;
+    let mut bench = Bench::new(&args.node_url)
+        .funder(Funder::Faucet(args.faucet_url.clone()))
+        .rate(args.rate)
+        .duration(Duration::from_secs(args.duration))
+        .accounts(args.accounts);
+    match &args.function {
+        Some(function) => {
+            bench = bench.call(entry_function(function, &args.type_args, &args.args)?, 1);
+        }
+        None if config.bench.calls.is_empty() => {
+            return Err(AptestError::Environment(
+                "Nothing to benchmark, give a function or [[bench.calls]] in aptest.toml"
+                    .to_string(),
+            ));
+        }
+        None => {
+            for call in &config.bench.calls {
+                let entry = entry_function(&call.function, &call.type_args, &call.args)?;
+                bench = bench.call(entry, call.weight.unwrap_or(1));
+            }
+        }
+    }
+    bench.run(&interrupt).await?.print_summary();
+    Ok(())
+}
 
-    Command::new("aptos")
-        .args([
-            "account",
-            "fund",
-            "--faucet-url",
-            "http://0.0.0.0:8000",
-            "--account",
-            account,
-        ])
-        .status()
-        .expect("Couldn't find aptos command. Is it installed ?");
-
-    //-----------------------------Deploying-------------------------------------
-    println!("\n{}\n", "Deploying move code...".bright_blue().bold());
-    let publish_code = Command::new("aptos")
-        .args(["move", "publish", "--url", "http://0.0.0.0:8080"])
-        .status()
-        .expect("Couldn't find aptos command. Is it installed ?");
-
-    //------------------------Error Handling of Publish--------------------------
-    if !publish_code.success() {
-        Err("Aptos reports publish failed".to_string())
-    } else {
+//Fuzzes the entry functions of a module, failing when calls aborted
+//with something else than the module's error constants
+async fn fuzz(args: FuzzArgs) -> Result<(), AptestError> {
+    let interrupt = Interrupt::listen()?;
+    let module = aptest::args::function_id(&args.module)?;
+    let invariant = match &args.invariant {
+        Some(function) => Some(aptest::args::function_id(function)?),
+        None => None,
+    };
+    let mut fuzzer = Fuzzer::new(&args.node_url, &module)
+        .funder(Funder::Faucet(args.faucet_url.clone()))
+        .functions(args.functions.clone())
+        .runs(args.runs)
+        .accounts(args.accounts)
+        .invariant(invariant)
+        .errors(ErrorMap::load(&[]));
+    if let Some(seed) = args.seed {
+        fuzzer = fuzzer.seed(seed);
+    }
+    let result = fuzzer.run(&interrupt).await?;
+    result.print_summary();
+    if result.findings.is_empty() {
+        ui::success("No unexpected aborts.");
         Ok(())
+    } else {
+        Err(AptestError::Tests(format!(
+            "Fuzzing found {} unexpected aborts or invariant violations, rerun with --seed {}",
+            result.findings.len(),
+            result.seed
+        )))
     }
 }
 
-//Runs the tests with "npm run test"
-fn e2e_tests() -> Result<Child, std::io::Error> {
-    println!("\n{}\n", "Running e2e tests...".bright_blue().bold());
-    Command::new("npm").args(["run", "test"]).spawn()
+//Prints the end of the output of the network in the state, then what
+//it writes next with --follow while it is still running
+async fn logs(args: LogsArgs) -> Result<(), AptestError> {
+    let state = State::load();
+    let localnet = state.localnet().ok_or_else(|| {
+        AptestError::Environment("No local network started by aptest is running".to_string())
+    })?;
+    let path = match (&localnet.faucet_log, args.faucet) {
+        (_, false) => &localnet.node_log,
+        (Some(path), true) => path,
+        (None, true) => {
+            return Err(AptestError::Environment(
+                "The local network has no faucet log of its own".to_string(),
+            ))
+        }
+    };
+    let (lines, len) = aptest::logs::tail(path, args.lines)?;
+    if !lines.is_empty() {
+        ui::print(lines);
+    }
+    if !args.follow {
+        return Ok(());
+    }
+    if NodeClient::new(&localnet.node_url).get("").await.is_err() {
+        return Err(AptestError::Environment(format!(
+            "The local network at {} is no longer running",
+            localnet.node_url
+        )));
+    }
+    let interrupt = Interrupt::listen()?;
+    match aptest::logs::follow(path, len, &interrupt).await {
+        Err(AptestError::Interrupted) => Ok(()),
+        result => result,
+    }
 }
 
-//------------------------------------------------------------------------------
-//                             Helper Functions
-//------------------------------------------------------------------------------
+//Prints the network aptest runs with its ports and uptime, the node's
+//ledger, the faucet's health and the packages published
+async fn status() -> Result<(), AptestError> {
+    let state = State::load();
+    let line = |name: &str, value: String| ui::print(format!("  {:<16} {}", name, value));
+    let port = |url: &str| url.rsplit(':').next().unwrap_or_default().to_string();
+    let mut chain = None;
+    match state.localnet() {
+        Some(localnet) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |x| x.as_secs());
+            let uptime = now.saturating_sub(localnet.started);
+            ui::info("Local network");
+            line("aptest pid", localnet.pid.to_string());
+            line(
+                "uptime",
+                format!(
+                    "{}h {:02}m {:02}s",
+                    uptime / 3600,
+                    uptime / 60 % 60,
+                    uptime % 60
+                ),
+            );
+            line(
+                "node",
+                format!("{} (port {})", localnet.node_url, port(&localnet.node_url)),
+            );
+            let client = NodeClient::with_timeout(&localnet.node_url, STATUS_TIMEOUT);
+            match client.get("").await {
+                Ok(info) => {
+                    line("ledger version", info["ledger_version"].to_string());
+                    line("chain id", info["chain_id"].to_string());
+                    let identity = aptest::state::node_identity(&localnet.node_url);
+                    chain = tokio::time::timeout(STATUS_TIMEOUT, identity)
+                        .await
+                        .ok()
+                        .and_then(Result::ok);
+                }
+                Err(_) => line("ledger version", "node not answering".to_string()),
+            }
+            if let Some(faucet_url) = &localnet.faucet_url {
+                let http = reqwest::Client::builder()
+                    .timeout(STATUS_TIMEOUT)
+                    .build()
+                    .unwrap_or_default();
+                let health = match http.get(format!("{}/health", faucet_url)).send().await {
+                    Ok(answer) if answer.status().is_success() => "healthy".to_string(),
+                    Ok(answer) => format!("unhealthy ({})", answer.status()),
+                    Err(_) => "not answering".to_string(),
+                };
+                line(
+                    "faucet",
+                    format!("{} (port {}), {}", faucet_url, port(faucet_url), health),
+                );
+            }
+        }
+        None if aptest::node::default_node_running().await => ui::info(format!(
+            "No local network started by aptest is running, but a node answers at {}",
+            NODE_URL
+        )),
+        None => ui::info("No local network started by aptest is running"),
+    }
 
-/// Fetch the account from the aptos config file
-/// for funding it on the local node.
-fn fetch_account() -> String {
-    let config_file = std::fs::read_to_string(".aptos/config.yaml")
-        .expect("Couldn't find .aptos/config.yaml. Did you run aptos init?");
-    let config_yaml =
-        YamlLoader::load_from_str(&config_file).expect("Could not parse aptos config file");
-    let config_yaml = &config_yaml[0];
-    let account = &config_yaml["profiles"]["default"]["account"]
-        .as_str()
-        .expect("Could not find a default account in config file");
-    account.to_string()
+    let mut published = state.published_packages().peekable();
+    if published.peek().is_some() {
+        ui::print("");
+        ui::info("Published packages");
+    }
+    for (package, published) in published {
+        let digest = &published.digest[..published.digest.len().min(12)];
+        let chain = if chain.as_deref() == Some(published.node.as_str()) {
+            "on the running chain"
+        } else {
+            "on another chain"
+        };
+        line(package, format!("digest {}, {}", digest, chain));
+    }
+    Ok(())
 }
 
-/// Finds the path to the mint key file in the node's output.
-fn find_mint_path(line: String) -> String {
-    let mut path =
-    line.split(':')
-        .skip_while(|x| !x.contains("Aptos root key path"))
-        .nth(1)
-        .expect("Could not find Aptos root key path in line. Perhaps give the node more time to spin up?")
-        .split('\n')
-        .next()
-        .unwrap()
-        .trim()
-        .to_string();
-    path.retain(|x| x != '\"');
-    path
+//Generates bindings for the modules of the given accounts, or for
+//those the last run published
+async fn codegen(args: CodegenArgs) -> Result<(), AptestError> {
+    let modules = if args.accounts.is_empty() {
+        aptest::publish::read_deployments(Path::new(DEPLOYMENT_FILE))?
+            .into_iter()
+            .flat_map(|x| x.modules)
+            .collect()
+    } else {
+        let client = NodeClient::new(&args.node_url);
+        let mut modules = Vec::new();
+        for account in &args.accounts {
+            let address = aptest::accounts::resolve(account)?;
+            for module in client
+                .modules(&address)
+                .await?
+                .as_array()
+                .into_iter()
+                .flatten()
+            {
+                let name = module["abi"]["name"].as_str().unwrap_or_default();
+                modules.push(format!("{}::{}", address, name));
+            }
+        }
+        modules
+    };
+    let files = aptest::codegen::generate(&args.node_url, &modules, &args.out_dir).await?;
+    for file in files {
+        ui::print(file.display());
+    }
+    ui::success(format!("Generated bindings in {}.", args.out_dir.display()));
+    Ok(())
 }
 
-#[test]
-fn test_mint_path() {
-    let mint_path = find_mint_path(
-        "Aptos root key path: \"/home/user/.aptos/mint.key\"\nWaypoint: stuff".to_string(),
-    );
-    dbg!(&mint_path);
-    assert_eq!(mint_path, "/home/user/.aptos/mint.key");
+//Saves or restores chain state, which only makes sense with the node stopped
+async fn snapshot(cmd: SnapshotCmd) -> Result<(), AptestError> {
+    let stopped = || async {
+        if aptest::node::default_node_running().await {
+            return Err(AptestError::Environment(format!(
+                "A node is running at {}, stop it before touching its chain state",
+                NODE_URL
+            )));
+        }
+        Ok(())
+    };
+    match cmd {
+        SnapshotCmd::Save { name, state_dir } => {
+            stopped().await?;
+            aptest::snapshot::save(&state_dir, &name)?;
+            ui::success(format!(
+                "Saved {} as snapshot {}.",
+                state_dir.display(),
+                name
+            ));
+        }
+        SnapshotCmd::Restore { name, state_dir } => {
+            stopped().await?;
+            aptest::snapshot::restore(&name, &state_dir)?;
+            ui::success(format!(
+                "Restored snapshot {} into {}.",
+                name,
+                state_dir.display()
+            ));
+        }
+        SnapshotCmd::List => {
+            for name in aptest::snapshot::list() {
+                println!("{}", name);
+            }
+        }
+    }
+    Ok(())
 }
 
-//Init all the files and directories for a new project if they don't exist.
-//Should never return to main.
-fn init(name: String) -> ! {
-    //check for Move.toml
-    if std::fs::read_to_string("./Move.toml").is_ok() {
-        println!(
-            "\n{}\n",
-            "Move.toml file already exists here!".bright_blue().bold()
-        );
-        std::process::exit(1);
+//Prints the test suites of aptest.toml
+fn suites_cmd(cmd: SuitesCmd) -> Result<(), AptestError> {
+    match cmd {
+        SuitesCmd::List => {
+            let suites = Config::load()?.pick_suites(&[])?;
+            let width = suites.iter().map(|x| x.0.len()).max().unwrap_or(0);
+            for (name, suite) in &suites {
+                let files = suite.files.as_deref().unwrap_or_default();
+                let command = suite.command.as_deref().unwrap_or_default();
+                let detail = if files.is_empty() || command.is_empty() {
+                    format!("{}{}", files, command)
+                } else {
+                    format!("{} ({})", command, files)
+                };
+                println!(
+                    "{}  {}",
+                    format!("{:<width$}", name).bright_green().bold(),
+                    detail
+                );
+            }
+        }
     }
+    Ok(())
+}
 
-    //run aptos move init --name args.init.name
-    let init_attempt = Command::new("aptos")
-        .args(["move", "init", "--name", name.as_str()])
-        .spawn();
+//Kills leftover process groups recorded by previous runs, and removes
+//the data of runs that are over, old logs and, unless a run is still
+//going, the cache
+fn clean(args: CleanArgs) -> Result<(), AptestError> {
+    ui::info("Cleaning up after previous runs...");
+    let killed = aptest::process::kill_recorded();
+    for pgid in &killed {
+        println!("Killed process group {}", pgid);
+    }
+    let mut cleaned = !killed.is_empty();
 
-    let mut init_child = pretty_expect!(
-        init_attempt,
-        "Couldn't find aptos command. Is it installed ?"
-            .bold()
-            .bright_blue()
-    );
+    //A network whose aptest process is gone went down with it
+    let mut state = State::load();
+    let localnet = state
+        .localnet()
+        .filter(|x| aptest::process::alive(x.pid))
+        .cloned();
+    if let (Some(pid), None) = (state.localnet().map(|x| x.pid), &localnet) {
+        println!("Forgot the network of aptest process {}", pid);
+        state.set_localnet(None);
+        state.save()?;
+        cleaned = true;
+    }
 
-    pretty_expect!(
-        init_child.wait(),
-        "Could not wait for aptos move init to finish"
-    );
+    let mut removed = |path: &Path| {
+        println!("Removed {}", path.display());
+        cleaned = true;
+    };
 
-    let package_json = format!(
-"{{
-    \"name\": \"test_{}\",
-    \"version\": \"1.0.0\",
-    \"scripts\": {{
-      \"test\": \"env TS_NODE_COMPILER_OPTIONS='{{\\\"module\\\": \\\"commonjs\\\" }}' mocha -r ts-node/register 'tests/**/*.ts'\"
-    }},
-    \"dependencies\": {{
-      \"@types/chai\": \"^4.3.1\",
-      \"@types/mocha\": \"^9.1.1\",
-      \"aptos\": \"^1.2.0\",
-      \"chai\": \"^4.3.6\",
-      \"mocha\": \"^10.0.0\",
-      \"ts-mocha\": \"^10.0.0\",
-      \"typescript\": \"^4.7.4\",
-    }}
-}}",
-    name.as_str()
-    );
+    for path in aptest::clean::stale_runs() {
+        removed(&path);
+    }
+    if aptest::lock::remove_stale() {
+        removed(Path::new(LOCK_FILE));
+    }
+    //Suite snapshots of isolated runs are named after their process
+    for name in aptest::snapshot::list() {
+        let pid = name
+            .strip_prefix(&format!("{}-", SUITE_SNAPSHOT))
+            .and_then(|x| x.parse().ok());
+        if pid.map_or(false, |pid| !aptest::process::alive(pid))
+            && aptest::snapshot::delete(&name).is_ok()
+        {
+            removed(&aptest::snapshot::path(&name));
+        }
+    }
 
-    make_file!("./package.json", package_json);
-    make_dir!("./tests");
+    let keep: Vec<_> = localnet
+        .iter()
+        .flat_map(|x| std::iter::once(x.node_log.clone()).chain(x.faucet_log.clone()))
+        .collect();
+    let retention = Duration::from_secs(args.keep_logs * 24 * 3600);
+    for path in aptest::clean::old_logs(retention, &keep) {
+        removed(&path);
+    }
 
-    let install_attempt = Command::new("npm").args(["install"]).spawn();
+    //Runs still going rely on the cache
+    let running = localnet
+        .map(|x| x.pid)
+        .into_iter()
+        .chain(aptest::lock::running())
+        .chain(aptest::clean::running())
+        .chain(aptest::process::recorded_owners())
+        .next();
+    match running {
+        Some(pid) => ui::info(format!(
+            "Keeping the cache, aptest process {} is still running",
+            pid
+        )),
+        None => {
+            for path in aptest::clean::cache() {
+                removed(&path);
+            }
+        }
+    }
 
-    println!("\n{}\n", "Installing dependencies...".bright_blue().bold());
-    let mut install_child = pretty_expect!(
-        install_attempt,
-        "Couldn't find npm command. Is it installed ?"
-            .bold()
-            .bright_blue()
-    );
-    pretty_expect!(
-        install_child.wait(),
-        "Could not wait for npm install to finish"
-    );
-    std::process::exit(0);
+    if !cleaned {
+        ui::success("Nothing to clean up.");
+    } else {
+        ui::success("Done");
+    }
+    Ok(())
+}
+
+//Writes the completion script for a shell to stdout
+fn completions(shell: Shell) {
+    let mut command = Sub::command();
+    clap_complete::generate(shell, &mut command, "aptest", &mut std::io::stdout());
+}
+
+#[test]
+fn test_own_node() {
+    let args = |flags: &[&str]| Args::try_parse_from(["run"].iter().chain(flags)).unwrap();
+    let config = Config::default();
+    assert!(!own_node(&args(&[]), &config));
+    assert!(own_node(&args(&["--epoch-interval", "5"]), &config));
+    let config: Config = toml::from_str("[node]\nepoch_interval = 5").unwrap();
+    assert!(own_node(&args(&[]), &config));
 }
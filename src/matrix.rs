@@ -0,0 +1,106 @@
+//! The runs of `aptest matrix`, one for every combination of the settings
+//! listed in the `[matrix]` section of `aptest.toml`, to check a package
+//! against several node versions or compiler settings at once.
+
+use std::path::PathBuf;
+
+use crate::config::Matrix;
+use crate::publish::IncludedArtifacts;
+
+/// The settings of one run of the matrix, left `None` where the matrix
+/// doesn't list any
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Combination {
+    pub node_bin: Option<PathBuf>,
+    pub compiler_version: Option<String>,
+    pub language_version: Option<String>,
+    pub included_artifacts: Option<IncludedArtifacts>,
+}
+
+impl Matrix {
+    /// Every combination of the settings listed, the first ones varying
+    /// the slowest. Empty if none are
+    pub fn combinations(&self) -> Vec<Combination> {
+        let mut combinations = vec![Combination::default()];
+        combinations = vary(combinations, &self.node_bin, |c, x| {
+            c.node_bin = Some(x.clone())
+        });
+        combinations = vary(combinations, &self.compiler_version, |c, x| {
+            c.compiler_version = Some(x.clone())
+        });
+        combinations = vary(combinations, &self.language_version, |c, x| {
+            c.language_version = Some(x.clone())
+        });
+        combinations = vary(combinations, &self.included_artifacts, |c, x| {
+            c.included_artifacts = Some(*x)
+        });
+        combinations.retain(|x| *x != Combination::default());
+        combinations
+    }
+}
+
+impl Combination {
+    /// The settings it has, like `compiler_version=v2 included_artifacts=none`
+    pub fn describe(&self) -> String {
+        let mut settings = Vec::new();
+        if let Some(path) = &self.node_bin {
+            settings.push(format!("node_bin={}", path.display()));
+        }
+        if let Some(version) = &self.compiler_version {
+            settings.push(format!("compiler_version={}", version));
+        }
+        if let Some(version) = &self.language_version {
+            settings.push(format!("language_version={}", version));
+        }
+        if let Some(artifacts) = self.included_artifacts {
+            settings.push(format!("included_artifacts={}", artifacts.as_str()));
+        }
+        settings.join(" ")
+    }
+}
+
+//Every combination with each of the values, or the combinations as they
+//are if there are none
+fn vary<T>(
+    combinations: Vec<Combination>,
+    values: &[T],
+    set: impl Fn(&mut Combination, &T),
+) -> Vec<Combination> {
+    if values.is_empty() {
+        return combinations;
+    }
+    let mut varied = Vec::new();
+    for combination in combinations {
+        for value in values {
+            let mut combination = combination.clone();
+            set(&mut combination, value);
+            varied.push(combination);
+        }
+    }
+    varied
+}
+
+#[test]
+fn test_combinations() {
+    let matrix = Matrix {
+        node_bin: vec!["node-1".into(), "node-2".into()],
+        compiler_version: vec!["v1".to_string(), "v2".to_string()],
+        included_artifacts: vec![IncludedArtifacts::None],
+        ..Matrix::default()
+    };
+    let described: Vec<_> = matrix
+        .combinations()
+        .iter()
+        .map(Combination::describe)
+        .collect();
+    assert_eq!(
+        described,
+        [
+            "node_bin=node-1 compiler_version=v1 included_artifacts=none",
+            "node_bin=node-1 compiler_version=v2 included_artifacts=none",
+            "node_bin=node-2 compiler_version=v1 included_artifacts=none",
+            "node_bin=node-2 compiler_version=v2 included_artifacts=none",
+        ]
+    );
+    assert!(Matrix::default().combinations().is_empty());
+}
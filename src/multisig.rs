@@ -0,0 +1,142 @@
+//! Multisig accounts of `0x1::multisig_account` owned by generated test
+//! accounts, for modules governed by several signers.
+
+use serde::Serialize;
+use serde_json::json;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::accounts::{TestAccount, DEFAULT_FUND_AMOUNT};
+use crate::args::MoveValue;
+use crate::error::{AptestError, OrFail};
+use crate::faucet::Funder;
+use crate::rest::NodeClient;
+use crate::transaction::{self, parse_address, EntryFunction};
+use crate::ui;
+
+//Index the owners are derived from a seed from, out of the way of the
+//test accounts
+const OWNER_INDEX: u64 = 1 << 32;
+
+/// How many of how many owners have to approve a transaction, like 2-of-3
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Threshold {
+    pub required: usize,
+    pub owners: usize,
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{} isn't a threshold like 2-of-3", s);
+        let (required, owners) = s.split_once("-of-").ok_or_else(invalid)?;
+        let required = required.trim().parse().map_err(|_| invalid())?;
+        let owners = owners.trim().parse().map_err(|_| invalid())?;
+        if required == 0 || required > owners {
+            return Err(format!(
+                "A multisig of {} owners needs between 1 and {} approvals",
+                owners, owners
+            ));
+        }
+        Ok(Threshold { required, owners })
+    }
+}
+
+impl Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-of-{}", self.required, self.owners)
+    }
+}
+
+/// A multisig account, with the keys of its owners
+#[derive(Debug, Serialize)]
+pub struct Multisig {
+    pub address: String,
+    /// Approvals a transaction needs
+    pub required: usize,
+    /// The first one created the account
+    pub owners: Vec<TestAccount>,
+}
+
+/// Generate the owners, derived from the seed if there is one and random
+/// otherwise, fund them with 1 APT each for gas and have the first one
+/// create a multisig account with the others, which is funded with
+/// `amount` octas.
+pub async fn create(
+    threshold: Threshold,
+    amount: u64,
+    seed: Option<&[u8]>,
+    funder: &Funder,
+    node_url: &str,
+) -> Result<Multisig, AptestError> {
+    ui::info(format!(
+        "Creating a {} multisig account on local node...",
+        threshold
+    ));
+    let owners: Vec<TestAccount> = (0..threshold.owners)
+        .map(|i| match seed {
+            Some(seed) => TestAccount::from_seed(seed, OWNER_INDEX + i as u64),
+            None => TestAccount::generate(),
+        })
+        .collect();
+    for owner in &owners {
+        funder.fund(&owner.address, DEFAULT_FUND_AMOUNT).await?;
+    }
+
+    //The address depends on the creator's sequence number, so it is read
+    //right before creating the account
+    let client = NodeClient::new(node_url);
+    let creator = &owners[0];
+    let next = client
+        .view(
+            "0x1::multisig_account::get_next_multisig_account_address",
+            &[],
+            vec![json!(creator.address)],
+        )
+        .await?;
+    let address = next[0]
+        .as_str()
+        .ok_or_else(|| AptestError::Request(format!("Unexpected multisig address {}", next)))?
+        .to_string();
+
+    let additional = owners[1..]
+        .iter()
+        .map(|x| parse_address(&x.address).map(MoveValue::Address))
+        .collect::<Result<Vec<_>, _>>()?;
+    let create = EntryFunction::new("0x1::multisig_account::create_with_owners")?
+        .arg(&MoveValue::Vector(additional))
+        .u64_arg(threshold.required as u64)
+        .arg(&MoveValue::Vector(Vec::new()))
+        .arg(&MoveValue::Vector(Vec::new()));
+    transaction::submit(&client, &creator.address, &creator.signing_key()?, &create)
+        .await
+        .or_fail(
+            AptestError::Publish,
+            "Could not create the multisig account",
+        )?;
+    funder.fund(&address, amount).await?;
+    ui::print(format!("Funded multisig {}", address));
+
+    Ok(Multisig {
+        address,
+        required: threshold.required,
+        owners,
+    })
+}
+
+#[test]
+fn test_threshold() {
+    let threshold: Threshold = "2-of-3".parse().unwrap();
+    assert_eq!(
+        threshold,
+        Threshold {
+            required: 2,
+            owners: 3
+        }
+    );
+    assert_eq!(threshold.to_string(), "2-of-3");
+    assert!("0-of-3".parse::<Threshold>().is_err());
+    assert!("4-of-3".parse::<Threshold>().is_err());
+    assert!("2/3".parse::<Threshold>().is_err());
+}
@@ -0,0 +1,69 @@
+//! Public networks `aptest run --network` publishes to and tests against
+//! instead of a local one, for smoke tests before a release.
+
+use crate::error::AptestError;
+
+//Public networks by name, with their REST API and faucet. The testnet
+//faucet can only be used through its web page
+const NETWORKS: [(&str, &str, Option<&str>); 2] = [
+    (
+        "devnet",
+        "https://fullnode.devnet.aptoslabs.com",
+        Some("https://faucet.devnet.aptoslabs.com"),
+    ),
+    ("testnet", "https://fullnode.testnet.aptoslabs.com", None),
+];
+
+/// A network run against through its public endpoints
+#[derive(Debug, PartialEq, Eq)]
+pub struct Remote {
+    pub node_url: String,
+    /// Faucet funding goes through, publishing profiles having to be
+    /// funded beforehand without one
+    pub faucet_url: Option<String>,
+}
+
+impl Remote {
+    /// The network named `devnet` or `testnet`, or the node whose REST API
+    /// is at this URL, which gets no faucet
+    pub fn parse(network: &str) -> Result<Self, AptestError> {
+        if let Some((_, node_url, faucet_url)) = NETWORKS.iter().find(|x| x.0 == network) {
+            return Ok(Remote {
+                node_url: node_url.to_string(),
+                faucet_url: faucet_url.map(str::to_string),
+            });
+        }
+        if network.starts_with("http://") || network.starts_with("https://") {
+            return Ok(Remote {
+                node_url: network.trim_end_matches('/').to_string(),
+                faucet_url: None,
+            });
+        }
+        if network == "mainnet" {
+            return Err(AptestError::Environment(
+                "aptest doesn't publish to mainnet, where tests would spend real APT".to_string(),
+            ));
+        }
+        Err(AptestError::Environment(format!(
+            "Unknown network {}, expected devnet, testnet or the URL of a node",
+            network
+        )))
+    }
+}
+
+#[test]
+fn test_parse() {
+    let devnet = Remote::parse("devnet").unwrap();
+    assert_eq!(devnet.node_url, "https://fullnode.devnet.aptoslabs.com");
+    assert!(devnet.faucet_url.is_some());
+    assert_eq!(Remote::parse("testnet").unwrap().faucet_url, None);
+    assert_eq!(
+        Remote::parse("https://node.example.com/").unwrap(),
+        Remote {
+            node_url: "https://node.example.com".to_string(),
+            faucet_url: None,
+        }
+    );
+    assert!(Remote::parse("mainnet").is_err());
+    assert!(Remote::parse("localnet").is_err());
+}
@@ -0,0 +1,1061 @@
+use sha3::{Digest, Sha3_256};
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::{oneshot, watch, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout_at, Instant};
+
+use crate::error::{AptestError, OrFail};
+use crate::faucet::{Faucet, Funder};
+use crate::logs::{write_line, FAUCET_LOG, NODE_LOG};
+use crate::process::{drain, last_lines, recent_output, spawn_grouped, GroupChild, Log};
+use crate::rest::NodeClient;
+use crate::signal::Interrupt;
+use crate::snapshot::{copy_dir, remove};
+use crate::state::{node_identity, State};
+use crate::ui;
+
+/// REST API of the local validator. Windows can't connect to 0.0.0.0
+pub const NODE_URL: &str = if cfg!(windows) {
+    "http://127.0.0.1:8080"
+} else {
+    "http://0.0.0.0:8080"
+};
+
+//Port of the REST API of the local validator
+const NODE_PORT: u16 = 8080;
+
+//Services of "aptos node run-local-testnet", which only listens on localhost
+const CLI_NODE_URL: &str = "http://127.0.0.1:8080";
+const CLI_FAUCET_URL: &str = "http://127.0.0.1:8081";
+const CLI_READY_URL: &str = "http://127.0.0.1:8070";
+const CLI_INDEXER_URL: &str = "http://127.0.0.1:8090/v1/graphql";
+const CLI_PORTS: &[(u16, &str)] = &[
+    (8080, "node API"),
+    (8070, "readiness endpoint"),
+    (8090, "indexer API"),
+];
+const CLI_FAUCET_PORT: u16 = 8081;
+
+//Data directory of networks with an indexer, unless persisted elsewhere
+const INDEXER_TEST_DIR: &str = ".aptest/indexer-testnet";
+
+/// Where generated genesis are cached unless told otherwise
+pub const GENESIS_CACHE_DIR: &str = ".aptest/genesis";
+
+//Data directory of chains started from a cached genesis. The node config
+//generated with a genesis points at the directory it was generated in,
+//so it is always generated and restored there
+const CACHED_CHAIN_DIR: &str = ".aptest/chain";
+
+//Lines of the validator's stderr shown when it dies
+const NODE_ERROR_LINES: usize = 50;
+
+//Time given to the aptos CLI to stop its containers
+const INDEXER_STOP_GRACE: Duration = Duration::from_secs(10);
+
+//Time a node or faucet checked on has to answer, a hung one never does
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+//Lines printed by the validator and the aptos CLI as the network comes up
+const MILESTONES: &[(&str, Milestone)] = &[
+    ("Completed generating configuration", Milestone::Genesis),
+    ("Aptos is running", Milestone::Api),
+    ("Node API is ready", Milestone::Api),
+    ("Faucet is ready", Milestone::Faucet),
+    ("Indexer API is ready", Milestone::Indexer),
+];
+
+/// Chain id of the genesis "aptos-node --test" generates, named TESTING
+pub const TESTING_CHAIN_ID: u8 = 4;
+
+/// Configures and starts a local validator node with `aptos-node --test`.
+pub struct LocalNode {
+    start_timeout: Duration,
+    log_file: Option<PathBuf>,
+    log_dir: Option<PathBuf>,
+    faucet: Option<Faucet>,
+    state_dir: Option<PathBuf>,
+    reset_state: bool,
+    random_ports: bool,
+    binary: PathBuf,
+    extra_args: Vec<String>,
+    chain_id: u8,
+    config_override: Option<PathBuf>,
+    genesis_framework: Option<PathBuf>,
+    genesis_cache: Option<PathBuf>,
+    with_indexer: bool,
+}
+
+impl Default for LocalNode {
+    fn default() -> Self {
+        LocalNode {
+            start_timeout: Duration::from_secs(60),
+            log_file: None,
+            log_dir: None,
+            faucet: Some(Faucet::new()),
+            state_dir: None,
+            reset_state: false,
+            random_ports: false,
+            binary: PathBuf::from("aptos-node"),
+            extra_args: Vec::new(),
+            chain_id: TESTING_CHAIN_ID,
+            config_override: None,
+            genesis_framework: None,
+            genesis_cache: None,
+            with_indexer: false,
+        }
+    }
+}
+
+impl LocalNode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long to wait on the validator (and faucet) spinning up
+    /// and answering requests before giving up.
+    pub fn start_timeout(mut self, start_timeout: Duration) -> Self {
+        self.start_timeout = start_timeout;
+        self
+    }
+
+    /// The faucet to start alongside the validator, or `None`
+    /// to run just the validator node.
+    pub fn faucet(mut self, faucet: Option<Faucet>) -> Self {
+        self.faucet = faucet;
+        self
+    }
+
+    /// Write the output of the validator (and faucet) to this file
+    /// when the network is torn down.
+    pub fn log_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log_file = Some(path.into());
+        self
+    }
+
+    /// Write the output of the validator and of the faucet to node.log
+    /// and faucet.log in this directory as they run, for `aptest logs`
+    pub fn log_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.log_dir = Some(dir.into());
+        self
+    }
+
+    /// Keep the node's data directory here so chain state (published
+    /// modules, funded accounts) survives restarts, instead of starting
+    /// from a fresh genesis in a temporary directory every time.
+    pub fn persist_state(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.state_dir = Some(dir.into());
+        self
+    }
+
+    /// Wipe the persisted state before starting, for a fresh genesis
+    pub fn reset_state(mut self, reset: bool) -> Self {
+        self.reset_state = reset;
+        self
+    }
+
+    /// Let the validator pick free ports instead of the default ones, so
+    /// that several networks can run side by side. Its REST API is then
+    /// found in its output.
+    pub fn random_ports(mut self, random_ports: bool) -> Self {
+        self.random_ports = random_ports;
+        self
+    }
+
+    /// Run this binary instead of the aptos-node in PATH, such as
+    /// a locally built or forked node
+    pub fn binary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.binary = path.into();
+        self
+    }
+
+    /// Arguments appended to the validator's command line
+    pub fn extra_args(mut self, args: Vec<String>) -> Self {
+        self.extra_args = args;
+        self
+    }
+
+    /// Chain id the node's genesis has, which the faucet signs for.
+    /// The node is checked to run it once up, since only a custom node
+    /// binary or data directory can change it.
+    pub fn chain_id(mut self, chain_id: u8) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    /// Node config file overriding the test defaults, like consensus
+    /// or mempool settings
+    pub fn config_override(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_override = Some(path.into());
+        self
+    }
+
+    /// Framework release bundle (.mrb) to build genesis from instead of
+    /// the one built into the node, to test against modified on-chain
+    /// parameters like the gas schedule or epoch duration
+    pub fn genesis_framework(mut self, path: impl Into<PathBuf>) -> Self {
+        self.genesis_framework = Some(path.into());
+        self
+    }
+
+    /// Start fresh chains from a copy of one generated once and kept in
+    /// this directory, instead of generating a genesis every time, which
+    /// takes most of the node's startup. Nodes with persisted state or
+    /// random ports generate their own.
+    pub fn genesis_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.genesis_cache = Some(dir.into());
+        self
+    }
+
+    /// Also run the indexer: the node's transaction stream, the indexer
+    /// processors and a GraphQL API over their database. The network is
+    /// then started with "aptos node run-local-testnet", which needs Docker,
+    /// on its own ports, and the node's binary, extra arguments and
+    /// genesis settings are not used.
+    pub fn with_indexer(mut self, with_indexer: bool) -> Self {
+        self.with_indexer = with_indexer;
+        self
+    }
+
+    /// Start the validator and faucet, wait until both answer requests,
+    /// and return the handle to the local network.
+    pub async fn start(mut self, interrupt: &Interrupt) -> Result<LocalNet, AptestError> {
+        if self.with_indexer {
+            return self.start_with_indexer(interrupt).await;
+        }
+        let cache = match (&self.genesis_cache, &self.state_dir, self.random_ports) {
+            (Some(cache), None, false) => cache.clone(),
+            _ => return self.start_validator(interrupt).await,
+        };
+        self.state_dir = Some(self.restore_genesis(&cache, interrupt).await?);
+        self.reset_state = false;
+        let net = self.start_validator(interrupt).await?;
+
+        //Chains started from the same genesis share their identity, so
+        //what was published to the previous ones isn't on this one
+        let node = node_identity(&net.node_url).await?;
+        let mut state = State::load();
+        state.forget_chain(&node);
+        state.save()?;
+        Ok(net)
+    }
+
+    //Readies the data directory of a fresh chain from the cached genesis,
+    //generating it first if it isn't cached yet, and returns it
+    async fn restore_genesis(
+        &self,
+        cache: &Path,
+        interrupt: &Interrupt,
+    ) -> Result<PathBuf, AptestError> {
+        let chain_dir = std::env::current_dir()
+            .or_fail(
+                AptestError::Environment,
+                "Could not read the current directory",
+            )?
+            .join(CACHED_CHAIN_DIR);
+        let key = self.genesis_key(&chain_dir).await?;
+        let cached = cache.join(&key);
+        remove(&chain_dir)?;
+        if cached.is_dir() {
+            copy_dir(&cached, &chain_dir)?;
+            return Ok(chain_dir);
+        }
+
+        ui::info("Generating a genesis to cache...");
+        let generator = LocalNode {
+            start_timeout: self.start_timeout,
+            log_file: None,
+            log_dir: None,
+            faucet: None,
+            state_dir: Some(chain_dir.clone()),
+            reset_state: false,
+            random_ports: false,
+            binary: self.binary.clone(),
+            extra_args: self.extra_args.clone(),
+            chain_id: self.chain_id,
+            config_override: self.config_override.clone(),
+            genesis_framework: self.genesis_framework.clone(),
+            genesis_cache: None,
+            with_indexer: false,
+        };
+        //The node's data is only consistent once it is stopped
+        drop(generator.start_validator(interrupt).await?);
+        let partial = cache.join(format!("{}.{}", key, std::process::id()));
+        copy_dir(&chain_dir, &partial)?;
+        if std::fs::rename(&partial, &cached).is_err() {
+            //Another run may have cached the same genesis first
+            remove(&partial)?;
+        }
+        Ok(chain_dir)
+    }
+
+    //What the generated genesis and node config depend on: the node's
+    //version, its settings and where its data lives
+    async fn genesis_key(&self, chain_dir: &Path) -> Result<String, AptestError> {
+        let version = Command::new(&self.binary)
+            .arg("--version")
+            .output()
+            .await
+            .or_fail(
+                AptestError::Environment,
+                &format!(
+                    "Could not find the {} command. Is it installed ?...",
+                    self.binary.display()
+                ),
+            )?;
+        let mut hasher = Sha3_256::new();
+        hasher.update(&version.stdout);
+        hasher.update([self.chain_id]);
+        for arg in &self.extra_args {
+            hasher.update(arg.as_bytes());
+            hasher.update([0]);
+        }
+        hasher.update(chain_dir.to_string_lossy().as_bytes());
+        for path in [&self.config_override, &self.genesis_framework]
+            .into_iter()
+            .flatten()
+        {
+            let contents = std::fs::read(path).or_fail(
+                AptestError::Environment,
+                &format!("Could not read {}", path.display()),
+            )?;
+            hasher.update([0]);
+            hasher.update(&contents);
+        }
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    //Starts the validator, and the faucet if there is one
+    async fn start_validator(self, interrupt: &Interrupt) -> Result<LocalNet, AptestError> {
+        ui::info("Starting local validator node...");
+        let deadline = Instant::now() + self.start_timeout;
+
+        let mut ports = Vec::new();
+        if !self.random_ports {
+            ports.push((NODE_PORT, "node API"));
+        }
+        if let Some(faucet) = &self.faucet {
+            ports.push((faucet.listen_port(), "faucet"));
+        }
+        check_ports(&ports)?;
+
+        let mut command = Command::new(&self.binary);
+        command
+            .args(["--test"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if self.random_ports {
+            command.arg("--random-ports");
+        }
+        if let Some(state_dir) = &self.state_dir {
+            if self.reset_state && state_dir.exists() {
+                ui::info("Resetting chain state...");
+                std::fs::remove_dir_all(state_dir).or_fail(
+                    AptestError::Environment,
+                    &format!("Could not remove {}", state_dir.display()),
+                )?;
+            }
+            //An existing directory is reused by the node, otherwise it
+            //generates a new genesis in it
+            command.arg("--test-dir").arg(state_dir);
+        }
+        if let Some(path) = &self.config_override {
+            command.arg("--test-config-override").arg(path);
+        }
+        if let Some(path) = &self.genesis_framework {
+            command.arg("--genesis-framework").arg(path);
+        }
+        command.args(&self.extra_args);
+
+        //Opened before spawning, as nothing would clean up the node
+        //should it fail
+        let mut output = self.log_output(NODE_LOG)?;
+        let mut node_child = spawn_grouped(&mut command).or_fail(
+            AptestError::Environment,
+            &format!(
+                "Could not find the {} command. Is it installed ?...",
+                self.binary.display()
+            ),
+        )?;
+
+        //The validator runs constantly, so its output is drained in the
+        //background for the log while watching for the mint key path,
+        //and for the REST API when it picks its own ports
+        let log: Log = Arc::new(Mutex::new(Vec::new()));
+        let spinner = ui::spinner("Starting the validator").follow(&log);
+        let startup = Startup::default();
+        let (path_tx, path_rx) = oneshot::channel();
+        //Panics and configuration errors go to stderr, kept on their own
+        //to show should the validator die
+        let errors = Log::default();
+        let mut drains = Vec::new();
+        if let Some(stderr) = node_child.child.stderr.take() {
+            let mut error_output = output.as_ref().and_then(|x| x.try_clone().ok());
+            let log = log.clone();
+            drains.push(tokio::spawn(drain(stderr, errors.clone(), move |line| {
+                write_line(&mut error_output, line);
+                if let Ok(mut log) = log.lock() {
+                    log.extend_from_slice(line.as_bytes());
+                }
+            })));
+        }
+        if let Some(stdout) = node_child.child.stdout.take() {
+            let mut path_tx = Some(path_tx);
+            let mut mint_key_path = None;
+            let mut node_url = (!self.random_ports).then(|| NODE_URL.to_string());
+            let startup = startup.clone();
+            drains.push(tokio::spawn(drain(stdout, log.clone(), move |line| {
+                write_line(&mut output, line);
+                startup.line(line);
+                if let Ok(path) = find_mint_path(line.to_string()) {
+                    mint_key_path = Some(path);
+                }
+                if let Some(url) = find_rest_url(line) {
+                    node_url.get_or_insert(url);
+                }
+                if let (Some(path), Some(url)) = (&mint_key_path, &node_url) {
+                    if let Some(tx) = path_tx.take() {
+                        let _ = tx.send((path.clone(), url.clone()));
+                    }
+                }
+            })));
+        }
+        let (exits, exit) = watch::channel(None);
+        let exits = Arc::new(exits);
+        on_exit(drains, exits.clone(), {
+            let errors = errors.clone();
+            move || {
+                format!(
+                    "The validator exited\n\nLast errors of the validator:\n{}",
+                    last_lines(&errors, NODE_ERROR_LINES)
+                )
+            }
+        });
+
+        //From here on dropping the network cleans up the node
+        let mut net = LocalNet {
+            node: Some(node_child),
+            node_url: NODE_URL.to_string(),
+            faucet: None,
+            faucet_url: None,
+            log: log.clone(),
+            log_file: self.log_file,
+            mint_key_path: None,
+            mint_fallback: false,
+            indexer_url: None,
+            exit,
+            clock: None,
+        };
+
+        let (mint_key_path, node_url): (String, String) = interrupt
+            .race(async {
+                timeout_at(deadline, path_rx)
+                    .await
+                    .map_err(|_| {
+                        AptestError::Timeout(format!(
+                            "Could not find Aptos root key path in the node output. Perhaps give the node more time to spin up?\n\nLast output of the node:\n{}",
+                            recent_output(&net.log)
+                        ))
+                    })?
+                    .map_err(|_| {
+                        AptestError::NodeStart(format!(
+                            "The validator exited before printing its root key path and REST API\n\nLast errors of the validator:\n{}",
+                            last_lines(&errors, NODE_ERROR_LINES)
+                        ))
+                    })
+            })
+            .await?;
+        net.node_url = node_url;
+
+        //The faucet only needs the mint key, so it comes up
+        //while the validator's API is still starting
+        if let Some(faucet) = &self.faucet {
+            let output = self.log_output(FAUCET_LOG)?;
+            let faucet_exited = {
+                let (exits, log) = (exits.clone(), log.clone());
+                move || {
+                    exited(
+                        &exits,
+                        format!(
+                            "The faucet exited\n\nLast output of the network:\n{}",
+                            recent_output(&log)
+                        ),
+                    )
+                }
+            };
+            match faucet.spawn(
+                &mint_key_path,
+                &net.node_url,
+                self.chain_id,
+                log,
+                output,
+                faucet_exited,
+            )? {
+                Some(faucet_child) => {
+                    net.faucet = Some(faucet_child);
+                    net.faucet_url = Some(faucet.url());
+                }
+                None => {
+                    ui::info("aptos-faucet is not installed, minting with the root key instead...");
+                    net.mint_fallback = true;
+                }
+            }
+        }
+        net.mint_key_path = Some(mint_key_path);
+
+        let (node, log, faucet_url) = (&mut net.node, &net.log, &net.faucet_url);
+        let node_ready = wait_ready(
+            format!("{}/v1", net.node_url),
+            deadline,
+            log,
+            &startup,
+            Some(Milestone::Api),
+        );
+        let faucet_ready = async {
+            match faucet_url {
+                Some(url) => {
+                    let url = format!("{}/health", url);
+                    wait_ready(url, deadline, log, &startup, Some(Milestone::Faucet)).await
+                }
+                None => Ok(()),
+            }
+        };
+        interrupt
+            .race(async {
+                tokio::select! {
+                    ready = async { tokio::try_join!(node_ready, faucet_ready).map(|_| ()) } => ready,
+                    err = exit_of(node, &errors) => Err(err),
+                }
+            })
+            .await?;
+        drop(spinner);
+
+        check_chain_id(&net.node_url, self.chain_id).await?;
+        Ok(net)
+    }
+
+    //Starts the node, faucet and indexer stack with the aptos CLI, and
+    //waits on its readiness endpoint which answers once all are healthy
+    async fn start_with_indexer(self, interrupt: &Interrupt) -> Result<LocalNet, AptestError> {
+        ui::info("Starting local network with indexer...");
+        let deadline = Instant::now() + self.start_timeout;
+        let test_dir = self
+            .state_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(INDEXER_TEST_DIR));
+
+        let mut ports = CLI_PORTS.to_vec();
+        if self.faucet.is_some() {
+            ports.push((CLI_FAUCET_PORT, "faucet"));
+        }
+        check_ports(&ports)?;
+
+        let mut command = Command::new(crate::cli::program());
+        command
+            .args([
+                "node",
+                "run-local-testnet",
+                "--with-indexer-api",
+                "--assume-yes",
+            ])
+            .arg("--test-dir")
+            .arg(&test_dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        //An existing directory is reused unless persisted state is kept
+        if self.state_dir.is_none() || self.reset_state {
+            command.arg("--force-restart");
+        }
+        if self.faucet.is_none() {
+            command.arg("--no-faucet");
+        }
+        let mut output = self.log_output(NODE_LOG)?;
+        let mut child = spawn_grouped(&mut command).or_fail(
+            AptestError::Environment,
+            "Couldn't find aptos command. Is it installed ?",
+        )?;
+
+        let log: Log = Arc::new(Mutex::new(Vec::new()));
+        let spinner = ui::spinner("Starting the network").follow(&log);
+        let startup = Startup::default();
+        let mut error_output = output.as_ref().and_then(|x| x.try_clone().ok());
+        let mut drains = Vec::new();
+        if let Some(stdout) = child.child.stdout.take() {
+            let startup = startup.clone();
+            drains.push(tokio::spawn(drain(stdout, log.clone(), move |line| {
+                write_line(&mut output, line);
+                startup.line(line);
+            })));
+        }
+        if let Some(stderr) = child.child.stderr.take() {
+            let startup = startup.clone();
+            drains.push(tokio::spawn(drain(stderr, log.clone(), move |line| {
+                write_line(&mut error_output, line);
+                startup.line(line);
+            })));
+        }
+        let (exits, exit) = watch::channel(None);
+        on_exit(drains, Arc::new(exits), {
+            let log = log.clone();
+            move || {
+                format!(
+                    "The local network exited\n\nLast output of the network:\n{}",
+                    recent_output(&log)
+                )
+            }
+        });
+
+        //From here on dropping the network cleans up the whole stack
+        let net = LocalNet {
+            node: Some(child),
+            node_url: CLI_NODE_URL.to_string(),
+            faucet: None,
+            faucet_url: self.faucet.as_ref().map(|_| CLI_FAUCET_URL.to_string()),
+            log,
+            log_file: self.log_file,
+            mint_key_path: Some(test_dir.join("mint.key").to_string_lossy().into_owned()),
+            mint_fallback: false,
+            indexer_url: Some(CLI_INDEXER_URL.to_string()),
+            exit,
+            clock: None,
+        };
+        interrupt
+            .race(wait_ready(
+                CLI_READY_URL.to_string(),
+                deadline,
+                &net.log,
+                &startup,
+                None,
+            ))
+            .await?;
+        drop(spinner);
+        check_chain_id(&net.node_url, self.chain_id).await?;
+        Ok(net)
+    }
+
+    //The file a log of the log directory is written to, if there is one
+    fn log_output(&self, name: &str) -> Result<Option<File>, AptestError> {
+        match &self.log_dir {
+            Some(dir) => crate::logs::create(&dir.join(name)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+//Fails, telling what to do about it, when something already listens on
+//one of the ports the network is about to take, which would otherwise
+//bring it down with an obscure error
+fn check_ports(ports: &[(u16, &str)]) -> Result<(), AptestError> {
+    for (port, service) in ports {
+        let taken = matches!(
+            std::net::TcpListener::bind(("0.0.0.0", *port)),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse
+        );
+        if !taken {
+            continue;
+        }
+        let holder = crate::process::port_holder(*port)
+            .map(|(pid, name)| format!(" by {} (pid {})", name, pid))
+            .unwrap_or_default();
+        return Err(AptestError::NodeStart(format!(
+            "Port {} of the {} is already taken{}.\n\nUse the node already running with --attach, \
+             run on free ports with --isolate, or stop what earlier runs left behind with \
+             \"aptest clean\"",
+            port, service, holder
+        )));
+    }
+    Ok(())
+}
+
+//Where the processes of a network record why they went away
+type Exits = Arc<watch::Sender<Option<String>>>;
+
+//Records why a process of the network went away, unless one did before
+fn exited(exits: &Exits, reason: String) {
+    exits.send_if_modified(|exit| {
+        if exit.is_some() {
+            return false;
+        }
+        *exit = Some(reason);
+        true
+    });
+}
+
+//Records why a process went away once its output is closed, which it
+//only is when the process exits
+fn on_exit(
+    drains: Vec<JoinHandle<()>>,
+    exits: Exits,
+    reason: impl FnOnce() -> String + Send + 'static,
+) {
+    tokio::spawn(async move {
+        for drain in drains {
+            let _ = drain.await;
+        }
+        exited(&exits, reason());
+    });
+}
+
+//Waits on the validator exiting before it is ready, for the failure
+//with the last errors it wrote
+async fn exit_of(node: &mut Option<GroupChild>, errors: &Log) -> AptestError {
+    let status = match node {
+        Some(node) => node.child.wait().await,
+        None => std::future::pending().await,
+    };
+    let status = status.map_or_else(|e| e.to_string(), |x| x.to_string());
+    AptestError::NodeStart(format!(
+        "The validator exited ({}) before it was ready\n\nLast errors of the validator:\n{}",
+        status,
+        last_lines(errors, NODE_ERROR_LINES)
+    ))
+}
+
+//Fails if the node doesn't run the chain id its faucet signs for
+async fn check_chain_id(node_url: &str, expected: u8) -> Result<(), AptestError> {
+    let chain_id = NodeClient::new(node_url).chain_id().await?;
+    if chain_id == expected {
+        Ok(())
+    } else {
+        Err(AptestError::NodeStart(format!(
+            "The node runs chain id {} but chain id {} was expected",
+            chain_id, expected
+        )))
+    }
+}
+
+/// Handles to the processes making up the local network.
+/// Dropping it kills the validator and faucet and logs their output if
+/// requested, so every exit path (panics included) tears them down.
+/// Networks that were attached to rather than started are left running.
+pub struct LocalNet {
+    node: Option<GroupChild>,
+    node_url: String,
+    faucet: Option<GroupChild>,
+    faucet_url: Option<String>,
+    log: Log,
+    log_file: Option<PathBuf>,
+    mint_key_path: Option<String>,
+    //Funds by minting directly since the faucet couldn't be started
+    mint_fallback: bool,
+    //GraphQL API of the indexer, when the aptos CLI runs the network
+    indexer_url: Option<String>,
+    //Why a process of the network went away, once one did
+    exit: watch::Receiver<Option<String>>,
+    //Ends epochs early, with an epoch interval
+    clock: Option<JoinHandle<()>>,
+}
+
+impl LocalNet {
+    /// `interrupt`, also cutting the run short once the validator or
+    /// faucet exits, which fails it with their last output
+    pub fn supervise(&self, interrupt: &Interrupt) -> Interrupt {
+        interrupt.or_exit(self.exit.clone())
+    }
+
+    /// Use a node that is already running instead of starting one,
+    /// along with its faucet if there is one.
+    pub async fn attach(node_url: &str, faucet_url: Option<&str>) -> Result<Self, AptestError> {
+        let node_url = node_url.trim_end_matches('/').to_string();
+        if !is_up(&format!("{}/v1", node_url)).await {
+            return Err(AptestError::NodeStart(format!(
+                "No node is answering at {}",
+                node_url
+            )));
+        }
+        ui::success(format!("Attached to the node running at {}", node_url));
+        //Nodes that aren't ours aren't watched
+        let (_, exit) = watch::channel(None);
+
+        let mut attached_faucet = None;
+        if let Some(faucet_url) = faucet_url {
+            let faucet_url = faucet_url.trim_end_matches('/');
+            if is_up(&format!("{}/health", faucet_url)).await {
+                attached_faucet = Some(faucet_url.to_string());
+            }
+        }
+
+        Ok(LocalNet {
+            node: None,
+            node_url,
+            faucet: None,
+            faucet_url: attached_faucet,
+            log: Log::default(),
+            log_file: None,
+            mint_key_path: None,
+            mint_fallback: false,
+            indexer_url: None,
+            exit,
+            clock: None,
+        })
+    }
+
+    /// REST API of the validator
+    pub fn node_url(&self) -> &str {
+        &self.node_url
+    }
+
+    /// GraphQL API of the indexer, if it was started
+    pub fn indexer_url(&self) -> Option<&str> {
+        self.indexer_url.as_deref()
+    }
+
+    /// Faucet URL, if there is a faucet
+    pub fn faucet_url(&self) -> Option<&str> {
+        self.faucet_url.as_deref()
+    }
+
+    /// How accounts get funded on this network, if they can be
+    pub fn funder(&self) -> Option<Funder> {
+        match (&self.faucet_url, &self.mint_key_path) {
+            (Some(faucet_url), _) => Some(Funder::Faucet(faucet_url.clone())),
+            (None, Some(mint_key_path)) if self.mint_fallback => Some(Funder::Mint {
+                node_url: self.node_url.clone(),
+                mint_key_path: mint_key_path.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Path to the root key able to mint coins on this network,
+    /// only known for networks aptest started itself
+    pub fn mint_key_path(&self) -> Option<&str> {
+        self.mint_key_path.as_deref()
+    }
+
+    /// End an epoch every `interval` with the root key, for as long as the
+    /// network is up, so that modules counting time in epochs don't have
+    /// to wait for the node's own interval. Needs a network aptest started.
+    pub fn epoch_interval(&mut self, interval: Duration) -> Result<(), AptestError> {
+        if interval.is_zero() {
+            return Err(AptestError::Environment(
+                "The epoch interval has to be at least a second".to_string(),
+            ));
+        }
+        let mint_key_path = self.mint_key_path.clone().ok_or_else(|| {
+            AptestError::Environment(
+                "Epochs can only be ended early on a network aptest started".to_string(),
+            )
+        })?;
+        let clock = crate::clock::end_epochs(self.node_url.clone(), mint_key_path, interval);
+        self.clock = Some(clock);
+        Ok(())
+    }
+}
+
+impl Drop for LocalNet {
+    fn drop(&mut self) {
+        if let Some(clock) = &self.clock {
+            clock.abort();
+        }
+        //Attached networks are not ours to close
+        let node = match self.node.as_mut() {
+            Some(node) => node,
+            None => return,
+        };
+
+        //Close node and faucet
+        ui::info("Closing local node...");
+        if self.indexer_url.is_some() {
+            node.stop(INDEXER_STOP_GRACE);
+        } else {
+            node.kill();
+        }
+        if let Some(faucet) = self.faucet.as_mut() {
+            faucet.kill();
+        }
+
+        //Write out node's log if requested.
+        //Panicking inside drop would abort, so errors are only reported
+        if let Some(log_file) = &self.log_file {
+            let output = self.log.lock().map(|x| x.clone()).unwrap_or_default();
+            if let Err(e) = std::fs::write(log_file, output) {
+                ui::failure("Could not write to log file.");
+                ui::print(format!("{}\n", e));
+            }
+        }
+    }
+}
+
+//A step of a network's startup
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Milestone {
+    Genesis,
+    Api,
+    Faucet,
+    Indexer,
+}
+
+impl Milestone {
+    //The milestone a line of output announces, if any
+    fn find(line: &str) -> Option<Self> {
+        MILESTONES
+            .iter()
+            .find(|(text, _)| line.contains(text))
+            .map(|(_, milestone)| *milestone)
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Milestone::Genesis => "Genesis built",
+            Milestone::Api => "Node API started",
+            Milestone::Faucet => "Faucet listening",
+            Milestone::Indexer => "Indexer API started",
+        }
+    }
+}
+
+//The milestones a network has reached, ticked off as they happen and
+//waking up whoever polls the network
+#[derive(Clone, Default)]
+struct Startup {
+    reached: Arc<Mutex<Vec<Milestone>>>,
+    notify: Arc<Notify>,
+}
+
+impl Startup {
+    fn line(&self, line: &str) {
+        if let Some(milestone) = Milestone::find(line) {
+            self.reach(milestone);
+        }
+    }
+
+    fn reach(&self, milestone: Milestone) {
+        let mut reached = self.reached.lock().unwrap_or_else(|e| e.into_inner());
+        if reached.contains(&milestone) {
+            return;
+        }
+        reached.push(milestone);
+        ui::check(milestone.describe());
+        self.notify.notify_waiters();
+    }
+}
+
+/// Whether a node is already answering at the default URL,
+/// which `aptest run` then attaches to instead of starting a new one.
+pub async fn default_node_running() -> bool {
+    is_up(&format!("{}/v1", NODE_URL)).await
+}
+
+//Whether an HTTP endpoint answers successfully right now
+async fn is_up(url: &str) -> bool {
+    let client = reqwest::Client::builder()
+        .timeout(PROBE_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    match client.get(url).send().await {
+        Ok(response) => response.status().is_success(),
+        Err(_) => false,
+    }
+}
+
+/// Poll an HTTP endpoint until it answers successfully or the deadline passes,
+/// showing the end of the network's log if it doesn't. A milestone seen in
+/// the output has it polled again right away rather than after the pause,
+/// and the endpoint answering ticks off `milestone` if the output didn't.
+async fn wait_ready(
+    url: String,
+    deadline: Instant,
+    log: &Log,
+    startup: &Startup,
+    milestone: Option<Milestone>,
+) -> Result<(), AptestError> {
+    let client = reqwest::Client::new();
+    let poll = async {
+        loop {
+            match client.get(&url).send().await {
+                Ok(response) if response.status().is_success() => break,
+                Ok(response) => ui::debug(format!("{} answered {}", url, response.status())),
+                Err(e) => ui::debug(format!("{} is not up yet: {}", url, e)),
+            }
+            let _ =
+                tokio::time::timeout(Duration::from_millis(250), startup.notify.notified()).await;
+        }
+        if let Some(milestone) = milestone {
+            startup.reach(milestone);
+        }
+    };
+    timeout_at(deadline, poll).await.map_err(|_| {
+        AptestError::Timeout(format!(
+            "Timed out waiting for {} to answer. Perhaps give the node more time to spin up?\n\nLast output of the network:\n{}",
+            url,
+            recent_output(log)
+        ))
+    })
+}
+
+/// A port nothing listens on right now, for a service started next to
+/// another network.
+pub fn free_port() -> Result<u16, AptestError> {
+    std::net::TcpListener::bind("0.0.0.0:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .or_fail(AptestError::Environment, "Could not find a free port")
+}
+
+//Finds the REST API the node listens on in its output
+fn find_rest_url(line: &str) -> Option<String> {
+    let (_, url) = line.split_once("REST API endpoint:")?;
+    Some(url.trim().trim_end_matches('/').to_string())
+}
+
+/// Finds the path to the mint key file in the node's output.
+fn find_mint_path(line: String) -> Result<String, AptestError> {
+    let mut path =
+    line.split(':')
+        .skip_while(|x| !x.contains("Aptos root key path"))
+        .nth(1)
+        .ok_or_else(|| AptestError::NodeStart("Could not find Aptos root key path in line. Perhaps give the node more time to spin up?".to_string()))?
+        .split('\n')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    path.retain(|x| x != '\"');
+    Ok(path)
+}
+
+#[test]
+fn test_mint_path() {
+    let mint_path = find_mint_path(
+        "Aptos root key path: \"/home/user/.aptos/mint.key\"\nWaypoint: stuff".to_string(),
+    )
+    .unwrap();
+    dbg!(&mint_path);
+    assert_eq!(mint_path, "/home/user/.aptos/mint.key");
+}
+
+#[test]
+fn test_milestones() {
+    assert_eq!(
+        Milestone::find("Completed generating configuration:"),
+        Some(Milestone::Genesis)
+    );
+    assert_eq!(
+        Milestone::find("Aptos is running, press ctrl-c to exit"),
+        Some(Milestone::Api)
+    );
+    assert_eq!(
+        Milestone::find("Faucet is ready. Endpoint: http://127.0.0.1:8081"),
+        Some(Milestone::Faucet)
+    );
+    assert_eq!(Milestone::find("\tWaypoint: 0:abc"), None);
+}
+
+#[test]
+fn test_rest_url() {
+    assert_eq!(
+        find_rest_url("\tREST API endpoint: http://0.0.0.0:41873\n").as_deref(),
+        Some("http://0.0.0.0:41873")
+    );
+    assert_eq!(
+        find_rest_url("\tMetrics endpoint: http://0.0.0.0:9101"),
+        None
+    );
+}
@@ -0,0 +1,547 @@
+#[cfg(unix)]
+use nix::errno::Errno;
+#[cfg(unix)]
+use nix::sys::signal::{kill, killpg, Signal};
+#[cfg(unix)]
+use nix::unistd::Pid;
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+
+use crate::signal::Interrupt;
+
+//Lines of output shown when a child times out
+const RECENT_LINES: usize = 20;
+
+//File recording the process groups spawned by aptest, each with the
+//aptest process owning it and when its leader started, so that leftovers
+//from crashed runs can be hunted down
+const PID_FILE: &str = ".aptest/pids";
+
+/// Output collected from child processes, written out on request
+pub type Log = Arc<Mutex<Vec<u8>>>;
+
+/// A child process leading its own process group, so that it
+/// and everything it spawns can be terminated together.
+pub struct GroupChild {
+    pub child: Child,
+    pgid: u32,
+}
+
+/// Spawn a command as the leader of its own process group.
+pub fn spawn_grouped(command: &mut Command) -> Result<GroupChild, std::io::Error> {
+    crate::ui::command(command);
+    new_group(command);
+    let child = command.spawn()?;
+    //The id is only gone once the child has been waited on
+    let pgid = child.id().unwrap_or_default();
+    record_group(pgid, started(pgid).as_deref());
+    Ok(GroupChild { child, pgid })
+}
+
+impl GroupChild {
+    /// Kill every process in the group.
+    pub fn kill(&mut self) {
+        //The group may already be gone, which is fine
+        kill_group(self.pgid);
+        forget_group(self.pgid);
+    }
+
+    /// Ask the group leader to shut down as on Ctrl+C, so that it can
+    /// clean up what lives outside the group (like Docker containers),
+    /// then kill the group once it exited or the grace period is over.
+    pub fn stop(&mut self, grace: Duration) {
+        if interrupt_leader(self.pgid) {
+            let deadline = Instant::now() + grace;
+            while Instant::now() < deadline && matches!(self.child.try_wait(), Ok(None)) {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        }
+        self.kill();
+    }
+
+    /// Wait on the group leader, killing the whole group if a
+    /// termination signal is received first.
+    pub async fn wait_or_interrupt(
+        &mut self,
+        interrupt: &Interrupt,
+    ) -> Result<Option<ExitStatus>, std::io::Error> {
+        tokio::select! {
+            status = self.child.wait() => {
+                forget_group(self.pgid);
+                Ok(Some(status?))
+            }
+            _ = interrupt.wait() => {
+                self.kill();
+                let _ = self.child.wait().await;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Name of a command installed as a script, which Windows runs through
+/// a `.cmd` shim, like npm.
+pub fn script(name: &str) -> String {
+    if cfg!(windows) {
+        format!("{}.cmd", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Program and flag running a command line through the shell,
+/// `sh -c` or `cmd /C` on Windows.
+pub fn shell() -> [&'static str; 2] {
+    if cfg!(windows) {
+        ["cmd", "/C"]
+    } else {
+        ["sh", "-c"]
+    }
+}
+
+//Makes the command lead a new process group
+#[cfg(unix)]
+fn new_group(command: &mut Command) {
+    command.process_group(0);
+}
+
+//Makes the command lead a new process group
+#[cfg(windows)]
+fn new_group(command: &mut Command) {
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x200;
+    command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+}
+
+//Sends SIGINT to the leader of a group, returning whether it was there
+#[cfg(unix)]
+fn interrupt_leader(pid: u32) -> bool {
+    kill(Pid::from_raw(pid as i32), Signal::SIGINT).is_ok()
+}
+
+//Windows can't deliver Ctrl+C to a single child, which is killed instead
+#[cfg(windows)]
+fn interrupt_leader(_pid: u32) -> bool {
+    false
+}
+
+//Kills every process of a group, returning whether there was one
+#[cfg(unix)]
+fn kill_group(pgid: u32) -> bool {
+    killpg(Pid::from_raw(pgid as i32), Signal::SIGKILL).is_ok()
+}
+
+//Kills a process and its whole tree, Windows having no process
+//groups to signal, returning whether there was one
+#[cfg(windows)]
+fn kill_group(pid: u32) -> bool {
+    std::process::Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid.to_string()])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map_or(false, |status| status.success())
+}
+
+/// Copy everything a child writes into the log as it comes, so its pipe
+/// never fills up, handing each line to `on_line` to watch for milestones.
+pub async fn drain(reader: impl AsyncRead + Unpin, log: Log, mut on_line: impl FnMut(&str)) {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    while let Ok(read) = reader.read_until(b'\n', &mut line).await {
+        if read == 0 {
+            break;
+        }
+        on_line(&String::from_utf8_lossy(&line[..]));
+        if let Ok(mut log) = log.lock() {
+            log.extend_from_slice(&line[..]);
+        }
+        line.clear();
+    }
+}
+
+/// Run a tool to completion behind a spinner for `step`, which shows the
+/// last line it writes, then all it wrote should it fail. Without
+/// spinners its output goes through as usual.
+pub async fn status_with_spinner(command: &mut Command, step: &str) -> std::io::Result<ExitStatus> {
+    command.kill_on_drop(true);
+    if !crate::ui::spinners() {
+        return command.stdout(crate::ui::child_stdout()).status().await;
+    }
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let log = Log::default();
+    let spinner = crate::ui::spinner(step).follow(&log);
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let drain_stdout = async {
+        if let Some(stdout) = stdout {
+            drain(stdout, log.clone(), |_| {}).await;
+        }
+    };
+    let drain_stderr = async {
+        if let Some(stderr) = stderr {
+            drain(stderr, log.clone(), |_| {}).await;
+        }
+    };
+    let status = tokio::join!(child.wait(), drain_stdout, drain_stderr).0;
+    drop(spinner);
+    if !matches!(&status, Ok(status) if status.success()) {
+        let output = log.lock().map(|x| x.clone()).unwrap_or_default();
+        eprint!("{}", String::from_utf8_lossy(&output));
+    }
+    status
+}
+
+/// The last lines written to a log, to show what a child that had to
+/// be killed was doing.
+pub fn recent_output(log: &Log) -> String {
+    last_lines(log, RECENT_LINES)
+}
+
+/// The last `count` lines written to a log
+pub fn last_lines(log: &Log, count: usize) -> String {
+    let bytes = log.lock().map(|x| x.clone()).unwrap_or_default();
+    let output = String::from_utf8_lossy(&bytes);
+    let lines: Vec<_> = output.lines().collect();
+    let recent = &lines[lines.len().saturating_sub(count)..];
+    if recent.is_empty() {
+        "(no output)".to_string()
+    } else {
+        recent.join("\n")
+    }
+}
+
+/// Kill the process groups recorded by previous runs that never got
+/// to clean up after themselves, returning the ids of those killed.
+/// Groups of runs still going are left alone.
+pub fn kill_recorded() -> Vec<i32> {
+    let recorded = std::fs::read_to_string(PID_FILE).unwrap_or_default();
+    let mut killed = Vec::new();
+    let mut remaining = String::new();
+    for line in recorded.lines() {
+        let (pgid, owner, recorded_start) = match parse_group(line) {
+            Some(group) => group,
+            None => continue,
+        };
+        if owner.map_or(false, alive) {
+            remaining.push_str(&format!("{}\n", line.trim()));
+            continue;
+        }
+        let leader = Leader {
+            alive: alive(pgid),
+            started: started(pgid),
+        };
+        match identify(recorded_start.as_deref(), &leader, || group_alive(pgid)) {
+            Leftover::Ours => {
+                if kill_group(pgid) {
+                    killed.push(pgid as i32);
+                }
+            }
+            Leftover::Gone => {}
+            Leftover::Unknown => remaining.push_str(&format!("{}\n", line.trim())),
+        }
+    }
+    if remaining.is_empty() {
+        let _ = std::fs::remove_file(PID_FILE);
+    } else {
+        let _ = std::fs::write(PID_FILE, remaining);
+    }
+    killed
+}
+
+//What became of the leader of a recorded group
+struct Leader {
+    alive: bool,
+    started: Option<String>,
+}
+
+//A recorded group whose aptest process is gone, as far as can be told
+#[derive(Debug, PartialEq, Eq)]
+enum Leftover {
+    //Still running, to be killed
+    Ours,
+    //Terminated, or its id taken by another process since
+    Gone,
+    //Can't be told apart from another process, to be kept
+    Unknown,
+}
+
+//Tells whether a recorded group is still the one spawned, by the start
+//time of its leader, which unlike its name survives it exec-ing into
+//another program, as npm does into node. A group whose leader exited
+//is still ours while it has members, as its id can't be reused until
+//they are all gone.
+fn identify(
+    recorded_start: Option<&str>,
+    leader: &Leader,
+    group_alive: impl FnOnce() -> bool,
+) -> Leftover {
+    if !leader.alive {
+        return if group_alive() {
+            Leftover::Ours
+        } else {
+            Leftover::Gone
+        };
+    }
+    match (recorded_start, &leader.started) {
+        (Some(recorded), Some(started)) if recorded == started => Leftover::Ours,
+        (Some(_), Some(_)) => Leftover::Gone,
+        _ => Leftover::Unknown,
+    }
+}
+
+/// The aptest processes still running that spawned recorded groups
+pub fn recorded_owners() -> Vec<u32> {
+    let recorded = std::fs::read_to_string(PID_FILE).unwrap_or_default();
+    let mut owners: Vec<_> = recorded
+        .lines()
+        .filter_map(|x| parse_group(x)?.1)
+        .filter(|&pid| alive(pid))
+        .collect();
+    owners.dedup();
+    owners
+}
+
+/// Whether a process is still running
+#[cfg(unix)]
+pub fn alive(pid: u32) -> bool {
+    //A process of another user can't be signaled but is there all the same
+    kill(Pid::from_raw(pid as i32), None) != Err(Errno::ESRCH)
+}
+
+/// Whether a process is still running
+#[cfg(windows)]
+pub fn alive(pid: u32) -> bool {
+    std::process::Command::new("tasklist")
+        .args(["/NH", "/FI", &format!("PID eq {}", pid)])
+        .output()
+        .map_or(false, |output| {
+            String::from_utf8_lossy(&output.stdout)
+                .split_whitespace()
+                .any(|x| x == pid.to_string())
+        })
+}
+
+//When a process started, as ps tells it
+#[cfg(unix)]
+fn started(pid: u32) -> Option<String> {
+    let output = std::process::Command::new("ps")
+        .args(["-o", "lstart=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let started = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!started.is_empty()).then_some(started)
+}
+
+//When a process started, in ticks, as PowerShell tells it
+#[cfg(windows)]
+fn started(pid: u32) -> Option<String> {
+    let output = std::process::Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-Command",
+            &format!("(Get-Process -Id {}).StartTime.Ticks", pid),
+        ])
+        .output()
+        .ok()?;
+    let started = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    started.parse::<u64>().ok().map(|_| started)
+}
+
+//Whether any process of a group is left
+#[cfg(unix)]
+fn group_alive(pgid: u32) -> bool {
+    killpg(Pid::from_raw(pgid as i32), None) != Err(Errno::ESRCH)
+}
+
+//Without process groups the tree is only found through its root
+#[cfg(windows)]
+fn group_alive(_pgid: u32) -> bool {
+    false
+}
+
+/// The process listening on a local TCP port, as its pid and name,
+/// when lsof is there to tell
+#[cfg(unix)]
+pub fn port_holder(port: u16) -> Option<(u32, String)> {
+    let output = std::process::Command::new("lsof")
+        .args(["-nP", &format!("-iTCP:{}", port), "-sTCP:LISTEN", "-Fpc"])
+        .output()
+        .ok()?;
+    parse_lsof(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The process listening on a local TCP port, as its pid and name
+#[cfg(windows)]
+pub fn port_holder(port: u16) -> Option<(u32, String)> {
+    let output = std::process::Command::new("netstat")
+        .args(["-ano", "-p", "TCP"])
+        .output()
+        .ok()?;
+    let suffix = format!(":{}", port);
+    let pid: u32 = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|x| x.split_whitespace().collect::<Vec<_>>())
+        .find(|x| x.len() == 5 && x[1].ends_with(&suffix) && x[3] == "LISTENING")?[4]
+        .parse()
+        .ok()?;
+    let output = std::process::Command::new("tasklist")
+        .args(["/NH", "/FO", "CSV", "/FI", &format!("PID eq {}", pid)])
+        .output()
+        .ok()?;
+    let name = String::from_utf8_lossy(&output.stdout)
+        .split(',')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_matches('"')
+        .to_string();
+    Some((pid, name))
+}
+
+/// Remember a spawned process group so `aptest clean` can find it
+/// if this run never gets to clean up after itself.
+fn record_group(pgid: u32, started: Option<&str>) {
+    let _ = std::fs::create_dir_all(".aptest");
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(PID_FILE) {
+        let _ = writeln!(
+            file,
+            "{} {} {}",
+            pgid,
+            std::process::id(),
+            started.unwrap_or_default()
+        );
+    }
+}
+
+/// Drop a process group from the record once it has been terminated.
+fn forget_group(pgid: u32) {
+    let recorded = match std::fs::read_to_string(PID_FILE) {
+        Ok(recorded) => recorded,
+        Err(_) => return,
+    };
+    let remaining: String = recorded
+        .lines()
+        .filter(|x| parse_group(x).map_or(true, |(recorded, _, _)| recorded != pgid))
+        .map(|x| format!("{}\n", x))
+        .collect();
+    if remaining.is_empty() {
+        let _ = std::fs::remove_file(PID_FILE);
+    } else {
+        let _ = std::fs::write(PID_FILE, remaining);
+    }
+}
+
+//A recorded group with the aptest process owning it and when its
+//leader started, which older records don't have
+fn parse_group(line: &str) -> Option<(u32, Option<u32>, Option<String>)> {
+    let mut fields = line.split_whitespace();
+    let pgid = fields.next()?.parse().ok()?;
+    let owner = fields.next().and_then(|x| x.parse().ok());
+    let started = fields.collect::<Vec<_>>().join(" ");
+    Some((pgid, owner, (!started.is_empty()).then_some(started)))
+}
+
+//The first process in the `-Fpc` output of lsof, a `p` line with its
+//pid followed by a `c` line with its name
+#[cfg(unix)]
+fn parse_lsof(output: &str) -> Option<(u32, String)> {
+    let mut lines = output.lines();
+    let pid = lines.find_map(|x| x.strip_prefix('p'))?.parse().ok()?;
+    let name = lines
+        .next()
+        .and_then(|x| x.strip_prefix('c'))
+        .unwrap_or_default();
+    Some((pid, name.to_string()))
+}
+
+#[test]
+fn test_parse_group() {
+    assert_eq!(
+        parse_group("1234 99 Fri Oct 16 00:20:51 2026"),
+        Some((1234, Some(99), Some("Fri Oct 16 00:20:51 2026".to_string())))
+    );
+    assert_eq!(parse_group("1234 99"), Some((1234, Some(99), None)));
+    assert_eq!(parse_group("1234"), Some((1234, None, None)));
+    assert_eq!(parse_group(""), None);
+    assert_eq!(parse_group("x"), None);
+}
+
+#[test]
+fn test_identify() {
+    let leader = |alive, started: Option<&str>| Leader {
+        alive,
+        started: started.map(str::to_string),
+    };
+    let start = Some("Fri Oct 16 00:20:51 2026");
+    let later = Some("Fri Oct 16 09:12:03 2026");
+    assert_eq!(
+        identify(start, &leader(true, start), || false),
+        Leftover::Ours
+    );
+    assert_eq!(
+        identify(start, &leader(true, later), || true),
+        Leftover::Gone
+    );
+    assert_eq!(
+        identify(start, &leader(false, None), || true),
+        Leftover::Ours
+    );
+    assert_eq!(
+        identify(start, &leader(false, None), || false),
+        Leftover::Gone
+    );
+    assert_eq!(
+        identify(None, &leader(true, later), || true),
+        Leftover::Unknown
+    );
+    assert_eq!(
+        identify(start, &leader(true, None), || true),
+        Leftover::Unknown
+    );
+}
+
+//npm is a node script, so the leader of a test runner group turns into
+//node, as sh does into sleep here, while keeping its start time
+#[cfg(unix)]
+#[test]
+fn test_started_across_exec() {
+    let mut child = std::process::Command::new("sh")
+        .args(["-c", "sleep 1; exec sleep 5"])
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+    let before = started(pid);
+    std::thread::sleep(Duration::from_millis(1500));
+    let leader = Leader {
+        alive: alive(pid),
+        started: started(pid),
+    };
+    let _ = child.kill();
+    let _ = child.wait();
+    assert!(before.is_some());
+    assert_eq!(
+        identify(before.as_deref(), &leader, || false),
+        Leftover::Ours
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_parse_lsof() {
+    assert_eq!(
+        parse_lsof("p4242\ncaptos-node\nf12\n"),
+        Some((4242, "aptos-node".to_string()))
+    );
+    assert_eq!(parse_lsof(""), None);
+}
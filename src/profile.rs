@@ -0,0 +1,307 @@
+use crate::accounts::TestAccount;
+use crate::error::{AptestError, OrFail};
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+//The aptos CLI config file, relative to the project
+const CONFIG_FILE: &str = ".aptos/config.yaml";
+
+//Held while the config file is rewritten, so that concurrent runs
+//don't lose each other's profiles
+const LOCK_FILE: &str = ".aptos/config.lock";
+
+/// The aptos CLI config file, `.aptos/config.yaml`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AptosConfig {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    //Kept as they are when the file is written back
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_yaml::Value>,
+}
+
+/// A profile of the aptos config file. Every field is optional in the
+/// file, the accessors report the missing ones.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rest_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub faucet_url: Option<String>,
+    #[serde(flatten)]
+    other: BTreeMap<String, serde_yaml::Value>,
+}
+
+impl AptosConfig {
+    /// Read the config file of the current directory
+    pub fn load() -> Result<Self, AptestError> {
+        let contents = std::fs::read_to_string(CONFIG_FILE).or_fail(
+            AptestError::Environment,
+            "Couldn't find .aptos/config.yaml. Did you run aptos init?",
+        )?;
+        Self::parse(&contents)
+    }
+
+    /// Read the config file, or start an empty one if there is none.
+    /// A file that can't be parsed is still an error, so that it is
+    /// never overwritten.
+    pub fn load_or_default() -> Result<Self, AptestError> {
+        if Path::new(CONFIG_FILE).exists() {
+            Self::load()
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    fn parse(contents: &str) -> Result<Self, AptestError> {
+        serde_yaml::from_str(contents).map_err(|err| {
+            AptestError::Environment(format!("Could not parse {}: {}", CONFIG_FILE, err))
+        })
+    }
+
+    /// Write the config file back, creating .aptos if needed
+    pub fn save(&self) -> Result<(), AptestError> {
+        let contents = serde_yaml::to_string(self).or_fail(
+            AptestError::Environment,
+            "Could not serialize the aptos config file",
+        )?;
+        std::fs::create_dir_all(".aptos").or_fail(
+            AptestError::Environment,
+            "Could not create directory .aptos",
+        )?;
+        std::fs::write(CONFIG_FILE, contents).or_fail(
+            AptestError::Environment,
+            &format!("Could not write {}", CONFIG_FILE),
+        )
+    }
+
+    /// Load the config file, change it and write it back, without
+    /// another aptest process changing it in the meantime
+    pub fn update(change: impl FnOnce(&mut Self)) -> Result<(), AptestError> {
+        std::fs::create_dir_all(".aptos").or_fail(
+            AptestError::Environment,
+            "Could not create directory .aptos",
+        )?;
+        let _lock = lock_config()?;
+        let mut config = Self::load_or_default()?;
+        change(&mut config);
+        config.save()
+    }
+
+    /// The profile with this name
+    pub fn profile(&self, name: &str) -> Result<&Profile, AptestError> {
+        self.profiles.get(name).ok_or_else(|| {
+            AptestError::Environment(format!("profiles.{} missing from {}", name, CONFIG_FILE))
+        })
+    }
+
+    /// The account of a profile, with its 0x prefix
+    pub fn account(&self, name: &str) -> Result<String, AptestError> {
+        let account = required(name, "account", &self.profile(name)?.account)?;
+        Ok(if account.starts_with("0x") {
+            account.to_string()
+        } else {
+            format!("0x{}", account)
+        })
+    }
+
+    /// The private key of a profile
+    pub fn private_key(&self, name: &str) -> Result<String, AptestError> {
+        required(name, "private_key", &self.profile(name)?.private_key).map(str::to_string)
+    }
+
+    /// Check that a profile talks to the node at this URL, since
+    /// funding and transactions would otherwise go to another network
+    pub fn check_rest_url(&self, name: &str, node_url: &str) -> Result<(), AptestError> {
+        let rest_url = required(name, "rest_url", &self.profile(name)?.rest_url)?;
+        if same_node(rest_url, node_url) {
+            Ok(())
+        } else {
+            Err(AptestError::Environment(format!(
+                "profiles.{}.rest_url is {} but the local node is at {}",
+                name, rest_url, node_url
+            )))
+        }
+    }
+}
+
+//A field of a profile, or an error naming where it is missing
+fn required<'a>(
+    profile: &str,
+    field: &str,
+    value: &'a Option<String>,
+) -> Result<&'a str, AptestError> {
+    value.as_deref().ok_or_else(|| {
+        AptestError::Environment(format!(
+            "profiles.{}.{} missing from {}",
+            profile, field, CONFIG_FILE
+        ))
+    })
+}
+
+//Whether two URLs point at the same local node, ignoring the
+//loopback name used, trailing slashes and the /v1 API prefix
+fn same_node(a: &str, b: &str) -> bool {
+    let normalize = |url: &str| {
+        let url = url.trim_end_matches('/');
+        let url = url.strip_suffix("/v1").unwrap_or(url);
+        ["localhost", "127.0.0.1"]
+            .iter()
+            .fold(url.to_string(), |url, host| {
+                url.replacen(host, "0.0.0.0", 1)
+            })
+    };
+    normalize(a) == normalize(b)
+}
+
+/// Fetch the account of a profile from the aptos config file
+/// for funding it on the local node.
+pub fn fetch_account(profile: &str) -> Result<String, AptestError> {
+    AptosConfig::load()?.account(profile)
+}
+
+/// Fetch the private key of a profile from the aptos config file.
+pub fn fetch_private_key(profile: &str) -> Result<String, AptestError> {
+    AptosConfig::load()?.private_key(profile)
+}
+
+/// Every profile in the aptos config file with its account,
+/// empty if there is no config file.
+pub fn list_profiles() -> Result<Vec<(String, String)>, AptestError> {
+    let config = match AptosConfig::load() {
+        Ok(config) => config,
+        Err(_) => return Ok(Vec::new()),
+    };
+    Ok(config
+        .profiles
+        .iter()
+        .filter_map(|(name, profile)| Some((name.clone(), profile.account.clone()?)))
+        .collect())
+}
+
+/// Whether the aptos config file has this profile
+pub fn has_profile(profile: &str) -> bool {
+    AptosConfig::load().map_or(false, |config| config.profiles.contains_key(profile))
+}
+
+/// Add a profile with a new key pointed at a local network to the
+/// aptos config file, creating the file if there is none, so that
+/// "aptos init" doesn't have to be run first.
+pub fn create_local_profile(
+    profile: &str,
+    node_url: &str,
+    faucet_url: Option<&str>,
+) -> Result<TestAccount, AptestError> {
+    let account = TestAccount::generate();
+    let fields = Profile {
+        network: Some("Local".to_string()),
+        private_key: Some(account.private_key.clone()),
+        public_key: Some(account.public_key.clone()),
+        account: Some(account.address.trim_start_matches("0x").to_string()),
+        rest_url: Some(node_url.to_string()),
+        faucet_url: faucet_url.map(str::to_string),
+        other: BTreeMap::new(),
+    };
+    AptosConfig::update(|config| {
+        config.profiles.insert(profile.to_string(), fields);
+    })?;
+    Ok(account)
+}
+
+/// Point an existing profile at another local network
+pub fn set_urls(
+    profile: &str,
+    node_url: &str,
+    faucet_url: Option<&str>,
+) -> Result<(), AptestError> {
+    AptosConfig::update(|config| {
+        if let Some(fields) = config.profiles.get_mut(profile) {
+            fields.rest_url = Some(node_url.to_string());
+            fields.faucet_url = faucet_url.map(str::to_string);
+        }
+    })
+}
+
+/// Remove profiles from the aptos config file
+pub fn remove_profiles(profiles: &[String]) -> Result<(), AptestError> {
+    AptosConfig::update(|config| {
+        for profile in profiles {
+            config.profiles.remove(profile);
+        }
+    })
+}
+
+//Takes the lock on the config file, held until the file returned is
+//closed
+#[cfg(unix)]
+fn lock_config() -> Result<File, AptestError> {
+    use nix::fcntl::{flock, FlockArg};
+    use std::os::unix::io::AsRawFd;
+
+    let lock = File::create(LOCK_FILE).or_fail(
+        AptestError::Environment,
+        &format!("Could not create {}", LOCK_FILE),
+    )?;
+    flock(lock.as_raw_fd(), FlockArg::LockExclusive).or_fail(
+        AptestError::Environment,
+        &format!("Could not lock {}", LOCK_FILE),
+    )?;
+    Ok(lock)
+}
+
+//Takes the lock on the config file, held until the file returned is
+//closed. Windows doesn't let another process open a file opened without
+//sharing, which is retried until the one holding it is done
+#[cfg(windows)]
+fn lock_config() -> Result<File, AptestError> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    loop {
+        let opened = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .share_mode(0)
+            .open(LOCK_FILE);
+        match opened {
+            Err(e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => {
+                std::thread::sleep(std::time::Duration::from_millis(100))
+            }
+            opened => {
+                return opened.or_fail(
+                    AptestError::Environment,
+                    &format!("Could not lock {}", LOCK_FILE),
+                )
+            }
+        }
+    }
+}
+
+#[test]
+fn test_missing_field() {
+    let config = AptosConfig::parse(
+        "---\nprofiles:\n  ci:\n    private_key: \"0x01\"\n    rest_url: \"http://localhost:8080/v1\"\n",
+    )
+    .unwrap();
+    let err = config.account("ci").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "profiles.ci.account missing from .aptos/config.yaml"
+    );
+    assert!(config.account("default").is_err());
+    assert!(config.check_rest_url("ci", "http://0.0.0.0:8080").is_ok());
+    assert!(config
+        .check_rest_url("ci", "https://fullnode.devnet.aptoslabs.com")
+        .is_err());
+}
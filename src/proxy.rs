@@ -0,0 +1,624 @@
+//! A local HTTP proxy in front of the node or the faucet, whose URL the
+//! tests get instead of theirs. With a chaos profile it delays requests,
+//! drops connections and answers with server errors, to check that the
+//! retry logic of a dapp copes with an unreliable network. It can also log
+//! every request and response, to see what an SDK sent when a test fails,
+//! and record them to be replayed in place of the node later on.
+
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::{AptestError, OrFail};
+use crate::recording::{self, Entry, Exchange};
+use crate::ui;
+
+/// The chaos profiles available without defining them in aptest.toml
+pub const CHAOS_PROFILES: [&str; 3] = ["slow", "flaky", "hostile"];
+
+/// Where the requests are logged unless told otherwise
+pub const PROXY_LOG_FILE: &str = "proxy-log.jsonl";
+
+//Headers describing a single connection rather than the request,
+//not passed on
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "content-length",
+    "host",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// How unreliable the proxy makes the network look. Each request is
+/// delayed by a random latency within the bounds, then either dropped,
+/// answered with a 503 or forwarded.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Chaos {
+    /// Least delay added to a request, in milliseconds
+    #[serde(default)]
+    pub min_latency_ms: u64,
+    /// Most delay added to a request, in milliseconds
+    #[serde(default)]
+    pub max_latency_ms: u64,
+    /// Share of the requests whose connection is closed without an answer
+    #[serde(default)]
+    pub drop_rate: f64,
+    /// Share of the requests answered with a 503 instead of being forwarded
+    #[serde(default)]
+    pub error_rate: f64,
+}
+
+impl Chaos {
+    /// One of the built-in profiles: "slow" only adds latency, "flaky"
+    /// fails a request now and then, "hostile" fails a lot of them
+    pub fn profile(name: &str) -> Option<Self> {
+        let (min_latency_ms, max_latency_ms, drop_rate, error_rate) = match name {
+            "slow" => (200, 1000, 0.0, 0.0),
+            "flaky" => (0, 200, 0.05, 0.1),
+            "hostile" => (100, 2000, 0.15, 0.25),
+            _ => return None,
+        };
+        Some(Chaos {
+            min_latency_ms,
+            max_latency_ms,
+            drop_rate,
+            error_rate,
+        })
+    }
+
+    //A random delay within the bounds
+    fn latency(&self) -> Duration {
+        let millis = if self.max_latency_ms > self.min_latency_ms {
+            rand::thread_rng().gen_range(self.min_latency_ms..=self.max_latency_ms)
+        } else {
+            self.min_latency_ms
+        };
+        Duration::from_millis(millis)
+    }
+}
+
+/// Forwards the requests made to a local port to another server.
+pub struct Proxy {
+    upstream: String,
+    chaos: Option<Chaos>,
+    log: Option<PathBuf>,
+    record: Option<(PathBuf, String)>,
+    replay: Option<Vec<Exchange>>,
+}
+
+/// A proxy that was started, serving until dropped
+pub struct RunningProxy {
+    url: String,
+    task: JoinHandle<()>,
+}
+
+//An HTTP request as read from a client
+struct Request {
+    method: String,
+    target: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+//An HTTP response to write back to a client
+#[derive(Clone)]
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+//What the connections share
+struct Shared {
+    upstream: String,
+    chaos: Option<Chaos>,
+    log: Option<Mutex<File>>,
+    //Recording and the name of the upstream in it
+    record: Option<(Mutex<File>, String)>,
+    //Recorded answers by method, path and body of the request
+    replay: Option<Mutex<BTreeMap<(String, String, Vec<u8>), VecDeque<Response>>>>,
+    http: reqwest::Client,
+}
+
+impl Proxy {
+    /// A proxy to the server at `upstream`, like `http://127.0.0.1:8080`
+    pub fn new(upstream: &str) -> Self {
+        Proxy {
+            upstream: upstream.trim_end_matches('/').to_string(),
+            chaos: None,
+            log: None,
+            record: None,
+            replay: None,
+        }
+    }
+
+    /// A proxy answering from recorded exchanges instead of forwarding.
+    /// The answers to the same request are given in the order they were
+    /// recorded in, the last one again once they run out.
+    pub fn replay(exchanges: Vec<Exchange>) -> Self {
+        Proxy {
+            replay: Some(exchanges),
+            ..Self::new("")
+        }
+    }
+
+    /// Make the network look unreliable
+    pub fn chaos(mut self, chaos: Chaos) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Append every request and its response to this file, as one JSON
+    /// object per line
+    pub fn log(mut self, path: &Path) -> Self {
+        self.log = Some(path.to_path_buf());
+        self
+    }
+
+    /// Append every request and the answer it got from `upstream`, as
+    /// the recording names it, to the recording at `path`
+    pub fn record(mut self, path: &Path, upstream: &str) -> Self {
+        self.record = Some((path.to_path_buf(), upstream.to_string()));
+        self
+    }
+
+    /// Listen on a free local port and serve in the background. Needs to
+    /// be called within the tokio runtime.
+    pub fn start(self) -> Result<RunningProxy, AptestError> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")
+            .or_fail(AptestError::Environment, "Could not start a local proxy")?;
+        let url = listener
+            .local_addr()
+            .map(|address| format!("http://{}", address))
+            .or_fail(AptestError::Environment, "Could not start a local proxy")?;
+        listener
+            .set_nonblocking(true)
+            .or_fail(AptestError::Environment, "Could not start a local proxy")?;
+        let listener = TcpListener::from_std(listener)
+            .or_fail(AptestError::Environment, "Could not start a local proxy")?;
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .or_fail(AptestError::Environment, "Could not start a local proxy")?;
+        let log = match &self.log {
+            Some(path) => Some(open_append(path)?),
+            None => None,
+        };
+        let record = match &self.record {
+            Some((path, upstream)) => Some((open_append(path)?, upstream.clone())),
+            None => None,
+        };
+        let replay = match self.replay {
+            Some(exchanges) => Some(Mutex::new(replayed(exchanges)?)),
+            None => None,
+        };
+        let shared = Arc::new(Shared {
+            upstream: self.upstream,
+            chaos: self.chaos,
+            log,
+            record,
+            replay,
+            http,
+        });
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(shared.clone(), stream));
+            }
+        });
+        Ok(RunningProxy { url, task })
+    }
+}
+
+impl RunningProxy {
+    /// Where the proxy listens
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Drop for RunningProxy {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Request {
+    //The value of a header, empty if it isn't there
+    fn header(&self, name: &str) -> &str {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map_or("", |(_, value)| value)
+    }
+}
+
+impl Response {
+    //An answer made up by the proxy, with a body like the node's errors
+    fn error(status: u16, message: &str) -> Self {
+        let body = serde_json::json!({
+            "message": message,
+            "error_code": "internal_error",
+            "vm_error_code": null,
+        });
+        Response {
+            status,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: body.to_string().into_bytes(),
+        }
+    }
+}
+
+impl Shared {
+    //The answer to a request, none to drop the connection, logged if asked
+    async fn handle(&self, request: &Request) -> Option<Response> {
+        let started = Instant::now();
+        let response = self.answer(request).await;
+        if let Some(log) = &self.log {
+            let entry = serde_json::json!({
+                "method": request.method,
+                "path": request.target,
+                "headers": request
+                    .headers
+                    .iter()
+                    .map(|(key, value)| (key.to_ascii_lowercase(), Value::from(value.as_str())))
+                    .collect::<serde_json::Map<_, _>>(),
+                "request": body_value(&request.body),
+                "status": response.as_ref().map(|x| x.status),
+                "response": response.as_ref().map_or(Value::Null, |x| body_value(&x.body)),
+                "millis": started.elapsed().as_millis() as u64,
+            });
+            if let Ok(mut file) = log.lock() {
+                let _ = writeln!(file, "{}", entry);
+            }
+        }
+        if let (Some((file, upstream)), Some(response)) = (&self.record, &response) {
+            let entry = Entry::Exchange(Exchange {
+                upstream: upstream.clone(),
+                method: request.method.clone(),
+                path: request.target.clone(),
+                request: hex::encode(&request.body),
+                status: response.status,
+                headers: response.headers.clone(),
+                response: hex::encode(&response.body),
+            });
+            if let Ok(mut file) = file.lock() {
+                let _ = recording::write_entry(&mut file, &entry);
+            }
+        }
+        response
+    }
+
+    //What the client gets, through the chaos if any
+    async fn answer(&self, request: &Request) -> Option<Response> {
+        if let Some(chaos) = &self.chaos {
+            tokio::time::sleep(chaos.latency()).await;
+            let roll: f64 = rand::thread_rng().gen();
+            if roll < chaos.drop_rate {
+                return None;
+            }
+            if roll < chaos.drop_rate + chaos.error_rate {
+                return Some(Response::error(503, "Failure injected by aptest --chaos"));
+            }
+        }
+        let replay = match &self.replay {
+            Some(replay) => replay,
+            None => return Some(self.forward(request).await),
+        };
+        let answer = replay
+            .lock()
+            .ok()
+            .and_then(|mut answers| next_answer(&mut answers, request));
+        Some(answer.unwrap_or_else(|| {
+            let message = format!(
+                "No answer to {} {} was recorded",
+                request.method, request.target
+            );
+            ui::failure(format!("Replay: {}", message));
+            Response::error(500, &message)
+        }))
+    }
+
+    //Passes the request on and returns the upstream answer
+    async fn forward(&self, request: &Request) -> Response {
+        let method = match reqwest::Method::from_bytes(request.method.as_bytes()) {
+            Ok(method) => method,
+            Err(_) => return Response::error(400, "Invalid method"),
+        };
+        let mut builder = self
+            .http
+            .request(method, format!("{}{}", self.upstream, request.target))
+            .body(request.body.clone());
+        for (key, value) in &request.headers {
+            if !is_hop_by_hop(key) {
+                builder = builder.header(key, value);
+            }
+        }
+        let answer = match builder.send().await {
+            Ok(answer) => answer,
+            Err(err) => {
+                let message = format!("Could not reach {}: {}", self.upstream, err);
+                return Response::error(502, &message);
+            }
+        };
+        let status = answer.status().as_u16();
+        let headers = answer
+            .headers()
+            .iter()
+            .filter(|(key, _)| !is_hop_by_hop(key.as_str()))
+            .map(|(key, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                (key.to_string(), value)
+            })
+            .collect();
+        match answer.bytes().await {
+            Ok(body) => Response {
+                status,
+                headers,
+                body: body.to_vec(),
+            },
+            Err(err) => {
+                let message = format!("Could not read the answer of {}: {}", self.upstream, err);
+                Response::error(502, &message)
+            }
+        }
+    }
+}
+
+//Answers the requests of a client connection until it closes
+async fn serve(shared: Arc<Shared>, stream: TcpStream) {
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    while let Ok(Some(request)) = read_request(&mut reader).await {
+        let keep_alive = !request.header("connection").eq_ignore_ascii_case("close");
+        let response = match shared.handle(&request).await {
+            Some(response) => response,
+            None => return,
+        };
+        if write_response(&mut write, &response, keep_alive)
+            .await
+            .is_err()
+            || !keep_alive
+        {
+            return;
+        }
+    }
+}
+
+//Reads the next request of a connection, none once the client closed it
+async fn read_request<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+) -> std::io::Result<Option<Request>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(invalid("Malformed request line")),
+    };
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(invalid("Truncated request headers"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = header.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    let mut request = Request {
+        method,
+        target,
+        headers,
+        body: Vec::new(),
+    };
+    if request
+        .header("transfer-encoding")
+        .eq_ignore_ascii_case("chunked")
+    {
+        request.body = read_chunked(reader).await?;
+    } else if let Ok(length) = request.header("content-length").parse::<usize>() {
+        request.body = vec![0; length];
+        reader.read_exact(&mut request.body).await?;
+    }
+    Ok(Some(request))
+}
+
+//Reads a body sent in chunks
+async fn read_chunked<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> std::io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).await?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| invalid("Malformed chunk size"))?;
+        if size == 0 {
+            //Trailers, up to the empty line ending the body
+            loop {
+                line.clear();
+                if reader.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+                    return Ok(body);
+                }
+            }
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).await?;
+        let mut end = [0u8; 2];
+        reader.read_exact(&mut end).await?;
+    }
+}
+
+//Writes a response with its length, so the connection can be reused
+async fn write_response<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    response: &Response,
+    keep_alive: bool,
+) -> std::io::Result<()> {
+    let reason = reqwest::StatusCode::from_u16(response.status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or_default();
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason);
+    for (key, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    head.push_str(&format!("content-length: {}\r\n", response.body.len()));
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    head.push_str(&format!("connection: {}\r\n\r\n", connection));
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(&response.body).await?;
+    writer.flush().await
+}
+
+//Opens a file to add lines to, shared by the connections
+fn open_append(path: &Path) -> Result<Mutex<File>, AptestError> {
+    let file = File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+        .or_fail(
+            AptestError::Environment,
+            &format!("Could not open {}", path.display()),
+        )?;
+    Ok(Mutex::new(file))
+}
+
+//The answers of the exchanges, in order, by request
+fn replayed(
+    exchanges: Vec<Exchange>,
+) -> Result<BTreeMap<(String, String, Vec<u8>), VecDeque<Response>>, AptestError> {
+    let mut answers: BTreeMap<_, VecDeque<_>> = BTreeMap::new();
+    for exchange in exchanges {
+        let request = hex::decode(&exchange.request).or_fail(
+            AptestError::Environment,
+            "Corrupted request in the recording",
+        )?;
+        let body = hex::decode(&exchange.response).or_fail(
+            AptestError::Environment,
+            "Corrupted response in the recording",
+        )?;
+        answers
+            .entry((exchange.method, exchange.path, request))
+            .or_default()
+            .push_back(Response {
+                status: exchange.status,
+                headers: exchange.headers,
+                body,
+            });
+    }
+    Ok(answers)
+}
+
+//The next recorded answer to the request, the last one staying there
+fn next_answer(
+    answers: &mut BTreeMap<(String, String, Vec<u8>), VecDeque<Response>>,
+    request: &Request,
+) -> Option<Response> {
+    let key = (
+        request.method.clone(),
+        request.target.clone(),
+        request.body.clone(),
+    );
+    let answers = answers.get_mut(&key)?;
+    if answers.len() > 1 {
+        answers.pop_front()
+    } else {
+        answers.front().cloned()
+    }
+}
+
+//A body as logged: JSON as such, text as a string and anything else,
+//like a BCS encoded transaction, as 0x-hex
+fn body_value(body: &[u8]) -> Value {
+    if body.is_empty() {
+        return Value::Null;
+    }
+    if let Ok(json) = serde_json::from_slice(body) {
+        return json;
+    }
+    match std::str::from_utf8(body) {
+        Ok(text) => Value::from(text),
+        Err(_) => Value::from(format!("0x{}", hex::encode(body))),
+    }
+}
+
+fn is_hop_by_hop(header: &str) -> bool {
+    HOP_BY_HOP
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(header))
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+#[tokio::test]
+async fn test_read_request() {
+    let bytes = b"POST /v1/view HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  4\r\n{\"a\"\r\n3\r\n:1}\r\n0\r\n\r\nGET /v1 HTTP/1.1\r\nContent-Length: 0\r\n\r\n";
+    let mut reader = BufReader::new(&bytes[..]);
+    let request = read_request(&mut reader).await.unwrap().unwrap();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.target, "/v1/view");
+    assert_eq!(request.header("host"), "localhost");
+    assert_eq!(request.body, b"{\"a\":1}");
+    let request = read_request(&mut reader).await.unwrap().unwrap();
+    assert_eq!(request.target, "/v1");
+    assert!(request.body.is_empty());
+    assert!(read_request(&mut reader).await.unwrap().is_none());
+    assert_eq!(Chaos::profile("slow").unwrap().drop_rate, 0.0);
+    assert!(Chaos::profile("calm").is_none());
+    assert_eq!(body_value(b"{\"a\":1}"), serde_json::json!({"a": 1}));
+    assert_eq!(body_value(&[0xff, 0x01]), Value::from("0xff01"));
+}
+
+#[test]
+fn test_next_answer() {
+    let exchange = |response: &str| Exchange {
+        upstream: "node".to_string(),
+        method: "GET".to_string(),
+        path: "/v1/transactions/by_hash/0xab".to_string(),
+        request: String::new(),
+        status: 200,
+        headers: Vec::new(),
+        response: hex::encode(response),
+    };
+    let mut answers = replayed(vec![exchange("pending"), exchange("committed")]).unwrap();
+    let request = Request {
+        method: "GET".to_string(),
+        target: "/v1/transactions/by_hash/0xab".to_string(),
+        headers: Vec::new(),
+        body: Vec::new(),
+    };
+    let mut next = || next_answer(&mut answers, &request).map(|x| x.body);
+    assert_eq!(next().unwrap(), b"pending");
+    assert_eq!(next().unwrap(), b"committed");
+    assert_eq!(next().unwrap(), b"committed");
+    let other = Request {
+        target: "/v1".to_string(),
+        ..request
+    };
+    assert!(next_answer(&mut answers, &other).is_none());
+}
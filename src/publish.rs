@@ -0,0 +1,798 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::abort::ErrorMap;
+use crate::accounts::DEFAULT_FUND_AMOUNT;
+use crate::cli;
+use crate::coverage::Coverage;
+use crate::error::{AptestError, OrFail};
+use crate::faucet::{Funder, FAUCET_URL};
+use crate::node::NODE_URL;
+use crate::process::{drain, recent_output, status_with_spinner, Log};
+use crate::profile::fetch_account;
+use crate::rest::NodeClient;
+use crate::retry::Retry;
+use crate::state::{self, Published, State};
+use crate::ui;
+
+//Ways aptos reports a package being published again without going
+//through the upgrade path
+const ALREADY_PUBLISHED: [&str; 3] = [
+    "EMODULE_ALREADY_EXISTS",
+    "DUPLICATE_MODULE_NAME",
+    "already published",
+];
+
+//Ways aptos reports a publish failing because the node or its mempool
+//wasn't ready yet, which are worth retrying
+const TRANSIENT: [&str; 5] = [
+    "error sending request",
+    "Connection refused",
+    "mempool",
+    "SEQUENCE_NUMBER_TOO_OLD",
+    "503 Service Unavailable",
+];
+
+//Ways aptos reports a package too large for the gas or size limits of
+//a transaction
+const GAS_LIMITS: [&str; 4] = [
+    "OUT_OF_GAS",
+    "MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS",
+    "MAX_GAS_UNITS_EXCEEDS_MAX_GAS_UNITS_BOUND",
+    "EXCEEDED_MAX_TRANSACTION_SIZE",
+];
+
+/// A package published from a profile, as exposed to the tests
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deployment {
+    pub package: String,
+    pub profile: String,
+    /// Account the modules were published under
+    pub sender: String,
+    pub transaction_hash: String,
+    pub gas_used: u64,
+    /// Every module under the sender, as `address::name`
+    pub modules: Vec<String>,
+    /// Left as it was on chain since the package is unchanged
+    #[serde(skip)]
+    pub skipped: bool,
+}
+
+//Outcome of one aptos CLI command
+struct AptosRun {
+    status: ExitStatus,
+    stdout: String,
+    //stdout and stderr together
+    output: String,
+}
+
+/// Upgrade policy a package is published with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum UpgradePolicy {
+    Compatible,
+    Immutable,
+}
+
+impl UpgradePolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            UpgradePolicy::Compatible => "compatible",
+            UpgradePolicy::Immutable => "immutable",
+        }
+    }
+}
+
+/// Artifacts published along with the bytecode, less of them making for
+/// a smaller and cheaper transaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum IncludedArtifacts {
+    None,
+    Sparse,
+    All,
+}
+
+impl IncludedArtifacts {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            IncludedArtifacts::None => "none",
+            IncludedArtifacts::Sparse => "sparse",
+            IncludedArtifacts::All => "all",
+        }
+    }
+}
+
+/// Compiles the Move package and publishes it to the local network
+/// through the aptos CLI.
+pub struct Publisher {
+    node_url: String,
+    packages: Vec<PathBuf>,
+    profiles: Vec<String>,
+    funder: Option<Funder>,
+    fund_amount: Option<u64>,
+    fund_profiles: Vec<String>,
+    force: bool,
+    upgrade_policy: Option<UpgradePolicy>,
+    max_gas: Option<u64>,
+    gas_unit_price: Option<u64>,
+    included_artifacts: Option<IncludedArtifacts>,
+    named_addresses: BTreeMap<String, String>,
+    build_args: Vec<String>,
+    save_metadata: bool,
+    retry: Retry,
+    fund_timeout: Option<Duration>,
+    publish_timeout: Option<Duration>,
+    extra_args: Vec<String>,
+}
+
+impl Default for Publisher {
+    fn default() -> Self {
+        Publisher {
+            node_url: NODE_URL.to_string(),
+            packages: Vec::new(),
+            profiles: vec!["default".to_string()],
+            funder: Some(Funder::Faucet(FAUCET_URL.to_string())),
+            fund_amount: None,
+            fund_profiles: Vec::new(),
+            force: false,
+            upgrade_policy: None,
+            max_gas: None,
+            gas_unit_price: None,
+            included_artifacts: None,
+            named_addresses: BTreeMap::new(),
+            build_args: Vec::new(),
+            save_metadata: false,
+            retry: Retry::default(),
+            fund_timeout: None,
+            publish_timeout: None,
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+impl Publisher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// REST API the package gets published to
+    pub fn node_url(mut self, url: impl Into<String>) -> Self {
+        self.node_url = url.into();
+        self
+    }
+
+    /// Package directories compiled and published in this order,
+    /// only the current directory unless set
+    pub fn packages(mut self, packages: Vec<PathBuf>) -> Self {
+        self.packages = packages;
+        self
+    }
+
+    /// Aptos profiles the package is published from, one publish
+    /// per profile, only "default" unless set
+    pub fn profiles(mut self, profiles: Vec<String>) -> Self {
+        self.profiles = profiles;
+        self
+    }
+
+    /// The profile the package is published from first
+    pub fn first_profile(&self) -> Option<&str> {
+        self.profiles.first().map(String::as_str)
+    }
+
+    /// How the publishing account is funded beforehand,
+    /// or `None` to skip funding
+    pub fn funder(mut self, funder: Option<Funder>) -> Self {
+        self.funder = funder;
+        self
+    }
+
+    /// Octas given to each funded profile,
+    /// or `None` for the aptos CLI default
+    pub fn fund_amount(mut self, amount: Option<u64>) -> Self {
+        self.fund_amount = amount;
+        self
+    }
+
+    /// Aptos profiles funded before publishing besides the publishing ones
+    pub fn fund_profiles(mut self, profiles: Vec<String>) -> Self {
+        self.fund_profiles = profiles;
+        self
+    }
+
+    /// Publish even packages that are unchanged since they were last
+    /// published to the same chain
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Upgrade policy to publish with, or `None` for the one in Move.toml.
+    /// Without one, packages already on chain are republished as a
+    /// compatible upgrade.
+    pub fn upgrade_policy(mut self, policy: Option<UpgradePolicy>) -> Self {
+        self.upgrade_policy = policy;
+        self
+    }
+
+    /// Maximum gas units each publish may use,
+    /// or `None` for the aptos CLI's estimate
+    pub fn max_gas(mut self, max_gas: Option<u64>) -> Self {
+        self.max_gas = max_gas;
+        self
+    }
+
+    /// Octas paid per gas unit, or `None` for the aptos CLI's estimate
+    pub fn gas_unit_price(mut self, price: Option<u64>) -> Self {
+        self.gas_unit_price = price;
+        self
+    }
+
+    /// Artifacts published along with the bytecode,
+    /// or `None` for the aptos CLI default
+    pub fn included_artifacts(mut self, artifacts: Option<IncludedArtifacts>) -> Self {
+        self.included_artifacts = artifacts;
+        self
+    }
+
+    /// Addresses of the named addresses left unset in Move.toml,
+    /// given to every compile and publish
+    pub fn named_addresses(mut self, addresses: BTreeMap<String, String>) -> Self {
+        self.named_addresses = addresses;
+        self
+    }
+
+    /// Options of the package build, like `--dev` or `--compiler-version`,
+    /// given to every compile, prove, test and publish
+    pub fn build_args(mut self, args: Vec<String>) -> Self {
+        self.build_args = args;
+        self
+    }
+
+    /// Save the package metadata next to the bytecode when compiling
+    pub fn save_metadata(mut self, save: bool) -> Self {
+        self.save_metadata = save;
+        self
+    }
+
+    /// How failed funding and transient publish failures are retried
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Time allowed to funding each profile, or `None` for no limit
+    pub fn fund_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.fund_timeout = timeout;
+        self
+    }
+
+    /// Time allowed to each "aptos move publish", or `None` for no limit
+    pub fn publish_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.publish_timeout = timeout;
+        self
+    }
+
+    /// Arguments appended to "aptos move compile" and "aptos move publish"
+    pub fn extra_args(mut self, args: Vec<String>) -> Self {
+        self.extra_args = args;
+        self
+    }
+
+    /// Compile the packages with "aptos move compile"
+    pub async fn compile(&self) -> Result<(), AptestError> {
+        for package in self.package_dirs() {
+            match package {
+                Some(dir) => ui::info(format!("Compiling Move code in {}...", dir.display())),
+                None => ui::info("Compiling Move code..."),
+            }
+            let mut command = Command::new(cli::program());
+            command.args(["move", "compile"]);
+            if let Some(dir) = package {
+                command.arg("--package-dir").arg(dir);
+            }
+            self.add_package_args(&mut command);
+            if self.save_metadata {
+                command.arg("--save-metadata");
+            }
+            command.args(&self.extra_args);
+            ui::command(&command);
+            let exit_code = status_with_spinner(&mut command, "Compiling")
+                .await
+                .or_fail(
+                    AptestError::Environment,
+                    "Couldn't find aptos command. Is it installed ?",
+                )?;
+            if !exit_code.success() {
+                return Err(AptestError::Compile(
+                    "Compilation failed, exiting early...".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the Move prover on the specifications of each package, with
+    /// these arguments appended. Failing proofs halt the run like
+    /// compilation errors do.
+    pub async fn prove(&self, extra_args: &[String]) -> Result<(), AptestError> {
+        for package in self.package_dirs() {
+            match package {
+                Some(dir) => ui::info(format!("Proving Move code in {}...", dir.display())),
+                None => ui::info("Proving Move code..."),
+            }
+            let mut command = Command::new(cli::program());
+            command.args(["move", "prove"]);
+            if let Some(dir) = package {
+                command.arg("--package-dir").arg(dir);
+            }
+            self.add_package_args(&mut command);
+            command.args(extra_args);
+            ui::command(&command);
+            let exit_code = status_with_spinner(&mut command, "Proving").await.or_fail(
+                AptestError::Environment,
+                "Couldn't find aptos command. Is it installed ?",
+            )?;
+            if !exit_code.success() {
+                return Err(AptestError::Compile(
+                    "Proving failed, exiting early...".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the Move unit tests of each package with coverage, then read
+    /// how much of each module they covered. Failing unit tests halt
+    /// the run.
+    pub async fn coverage(&self) -> Result<Coverage, AptestError> {
+        let mut coverage = Coverage::default();
+        for package in self.package_dirs() {
+            match package {
+                Some(dir) => ui::info(format!("Running Move unit tests in {}...", dir.display())),
+                None => ui::info("Running Move unit tests..."),
+            }
+            let command = |args: &[&str]| {
+                let mut command = Command::new(cli::program());
+                command.args(["move"]).args(args);
+                if let Some(dir) = package {
+                    command.arg("--package-dir").arg(dir);
+                }
+                self.add_package_args(&mut command);
+                command
+            };
+            let mut test = command(&["test", "--coverage"]);
+            test.args(&self.extra_args);
+            ui::command(&test);
+            let exit_code = status_with_spinner(&mut test, "Running unit tests")
+                .await
+                .or_fail(
+                    AptestError::Environment,
+                    "Couldn't find aptos command. Is it installed ?",
+                )?;
+            if !exit_code.success() {
+                return Err(AptestError::Tests(
+                    "Move unit tests failed, exiting early...".to_string(),
+                ));
+            }
+
+            let mut summary = command(&["coverage", "summary", "--summarize-functions"]);
+            ui::command(&summary);
+            let output = summary.kill_on_drop(true).output().await.or_fail(
+                AptestError::Environment,
+                "Couldn't find aptos command. Is it installed ?",
+            )?;
+            if !output.status.success() {
+                return Err(AptestError::Tests(format!(
+                    "Could not read the coverage of the unit tests:\n{}",
+                    String::from_utf8_lossy(&output.stderr)
+                )));
+            }
+            coverage.extend(Coverage::parse(
+                &String::from_utf8_lossy(&output.stdout),
+                &[package],
+            ));
+        }
+        Ok(coverage)
+    }
+
+    /// Fund the publishing accounts and publish the contract to the
+    /// validator node, will halt and error if the publishing fails.
+    /// Returns what was deployed, unchanged packages included.
+    pub async fn publish(&self) -> Result<Vec<Deployment>, AptestError> {
+        self.fund().await?;
+        self.deploy().await
+    }
+
+    /// Fund the publishing profiles, and the other profiles to fund,
+    /// unless there is no funder
+    pub async fn fund(&self) -> Result<(), AptestError> {
+        //-----------------------------Funding--------------------------------------
+        if let Some(funder) = &self.funder {
+            ui::info("Funding new account on local node...");
+
+            let mut profiles = self.profiles.clone();
+            for profile in &self.fund_profiles {
+                if !profiles.contains(profile) {
+                    profiles.push(profile.clone());
+                }
+            }
+            for profile in &profiles {
+                let account = fetch_account(profile)?;
+                let what = format!("Funding profile {}", profile);
+                self.retry
+                    .run(&what, || self.fund_account(funder, &account, &what))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    //Funds one account through the faucet with the aptos CLI,
+    //or by minting when there is no faucet
+    async fn fund_account(
+        &self,
+        funder: &Funder,
+        account: &str,
+        what: &str,
+    ) -> Result<(), AptestError> {
+        let faucet_url = match funder {
+            Funder::Faucet(faucet_url) => faucet_url,
+            //Without a faucet the aptos CLI can't fund, so mint directly
+            Funder::Mint { .. } => {
+                let amount = self.fund_amount.unwrap_or(DEFAULT_FUND_AMOUNT);
+                let mint = funder.fund(account, amount);
+                return match self.fund_timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, mint).await.map_err(|_| {
+                        AptestError::Timeout(format!(
+                            "{} timed out after {}s",
+                            what,
+                            timeout.as_secs()
+                        ))
+                    })?,
+                    None => mint.await,
+                };
+            }
+        };
+        let amount = self.fund_amount.map(|x| x.to_string());
+        let mut command = Command::new(cli::program());
+        command.args([
+            "account",
+            cli::version().fund_subcommand(),
+            "--faucet-url",
+            faucet_url.as_str(),
+            "--account",
+            account,
+        ]);
+        if let Some(amount) = &amount {
+            command.args(["--amount", amount.as_str()]);
+        }
+        let run = run_aptos(&mut command, self.fund_timeout, "aptos account fund").await?;
+        if run.status.success() {
+            Ok(())
+        } else {
+            Err(AptestError::Publish(format!("{} failed", what)))
+        }
+    }
+
+    /// Publish the contract to the validator node without funding first,
+    /// will halt and error if the publishing fails.
+    /// Returns what was deployed, unchanged packages included.
+    pub async fn deploy(&self) -> Result<Vec<Deployment>, AptestError> {
+        //-----------------------------Deploying-------------------------------------
+        let mut state = State::load();
+        let node = state::node_identity(&self.node_url).await?;
+        let mut deployments = Vec::new();
+        for package in self.package_dirs() {
+            let dir = package.map_or(Path::new("."), |x| x.as_path());
+            let digest = state::package_digest(dir)?;
+            for profile in &self.profiles {
+                let from = match package {
+                    Some(dir) => format!("{} from profile {}", dir.display(), profile),
+                    None => format!("from profile {}", profile),
+                };
+                let unchanged = state
+                    .published(dir, profile)
+                    .filter(|x| x.node == node && x.digest == digest);
+                if let (false, Some(unchanged)) = (self.force, unchanged) {
+                    ui::success(format!(
+                        "Move code {} is unchanged, skipping publish.",
+                        from
+                    ));
+                    if let Some(mut deployment) = unchanged.deployment.clone() {
+                        deployment.skipped = true;
+                        deployments.push(deployment);
+                    }
+                    continue;
+                }
+                ui::info(format!("Deploying move code {}...", from));
+                let what = format!("Publishing {}", from);
+                let mut run = self
+                    .retry
+                    .run(&what, || async {
+                        let run = self
+                            .publish_package(package, profile, self.upgrade_policy)
+                            .await?;
+                        if !run.status.success() && TRANSIENT.iter().any(|x| run.output.contains(x))
+                        {
+                            return Err(AptestError::Publish(format!("{} failed", what)));
+                        }
+                        Ok(run)
+                    })
+                    .await?;
+
+                //A package left on chain by a previous run (persisted state,
+                //attached node) has to be republished as an upgrade
+                if !run.status.success()
+                    && self.upgrade_policy.is_none()
+                    && ALREADY_PUBLISHED.iter().any(|x| run.output.contains(x))
+                {
+                    ui::info("Package already published, retrying as a compatible upgrade...");
+                    run = self
+                        .publish_package(package, profile, Some(UpgradePolicy::Compatible))
+                        .await?;
+                }
+
+                //------------------------Error Handling of Publish--------------------------
+                if !run.status.success() {
+                    ErrorMap::load(&self.packages).report(&run.output);
+                    if GAS_LIMITS.iter().any(|x| run.output.contains(x)) {
+                        ui::info(
+                            "The package is too large for the gas settings, raise them with \
+                             --max-gas and --gas-unit-price or publish less of it with \
+                             --included-artifacts",
+                        );
+                    }
+                    return Err(AptestError::Publish(format!(
+                        "Aptos reports publish {} failed",
+                        from
+                    )));
+                }
+
+                let deployment = self.deployment(dir, profile, &run.stdout).await?;
+                state.set_published(
+                    dir,
+                    profile,
+                    Published {
+                        node: node.clone(),
+                        digest: digest.clone(),
+                        deployment: Some(deployment.clone()),
+                    },
+                );
+                state.save()?;
+                deployments.push(deployment);
+            }
+        }
+
+        //-----------------------------Summary---------------------------------------
+        if !deployments.is_empty() {
+            ui::info("Deployment summary");
+        }
+        for deployment in &deployments {
+            ui::print(format!(
+                "{} ({}): {}\n  gas used: {}\n  modules: {}",
+                deployment.package,
+                deployment.profile,
+                deployment.transaction_hash,
+                deployment.gas_used,
+                deployment.modules.join(", ")
+            ));
+        }
+        Ok(deployments)
+    }
+
+    //Passes the named addresses and the build options to an
+    //"aptos move" command
+    fn add_package_args(&self, command: &mut Command) {
+        command.args(&self.build_args);
+        if self.named_addresses.is_empty() {
+            return;
+        }
+        let addresses: Vec<_> = self
+            .named_addresses
+            .iter()
+            .map(|(name, address)| format!("{}={}", name, address))
+            .collect();
+        command.args(["--named-addresses", &addresses.join(",")]);
+    }
+
+    //Runs "aptos move publish" for one package and profile
+    async fn publish_package(
+        &self,
+        package: Option<&PathBuf>,
+        profile: &str,
+        upgrade_policy: Option<UpgradePolicy>,
+    ) -> Result<AptosRun, AptestError> {
+        let mut command = Command::new(cli::program());
+        command.args([
+            "move",
+            "publish",
+            cli::version().url_flag(),
+            self.node_url.as_str(),
+            "--profile",
+            profile,
+            //Output is piped, so the confirmation prompt couldn't be seen
+            "--assume-yes",
+        ]);
+        if let Some(dir) = package {
+            command.arg("--package-dir").arg(dir);
+        }
+        if let Some(policy) = upgrade_policy {
+            command.args(["--upgrade-policy", policy.as_str()]);
+        }
+        if let Some(max_gas) = self.max_gas {
+            command.args(["--max-gas", &max_gas.to_string()]);
+        }
+        if let Some(price) = self.gas_unit_price {
+            command.args(["--gas-unit-price", &price.to_string()]);
+        }
+        if let Some(artifacts) = self.included_artifacts {
+            command.args(["--included-artifacts", artifacts.as_str()]);
+        }
+        self.add_package_args(&mut command);
+        command.args(&self.extra_args);
+        run_aptos(&mut command, self.publish_timeout, "aptos move publish").await
+    }
+
+    //Details of a successful publish, from the JSON result printed by
+    //the aptos CLI and the modules now under the publishing account
+    async fn deployment(
+        &self,
+        dir: &Path,
+        profile: &str,
+        stdout: &str,
+    ) -> Result<Deployment, AptestError> {
+        let json = stdout.find('{').map_or("", |start| &stdout[start..]);
+        let result: serde_json::Value = serde_json::from_str(json).or_fail(
+            AptestError::Publish,
+            "Could not parse the result of aptos move publish",
+        )?;
+        let result = &result["Result"];
+
+        let sender = match result["sender"].as_str() {
+            Some(sender) => sender.to_string(),
+            None => fetch_account(profile)?,
+        };
+        let sender = if sender.starts_with("0x") {
+            sender
+        } else {
+            format!("0x{}", sender)
+        };
+        let modules = NodeClient::new(&self.node_url)
+            .get(&format!("/accounts/{}/modules", sender))
+            .await?;
+        let modules = modules
+            .as_array()
+            .map(|modules| {
+                modules
+                    .iter()
+                    .filter_map(|x| x["abi"]["name"].as_str())
+                    .map(|name| format!("{}::{}", sender, name))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Deployment {
+            package: dir.display().to_string(),
+            profile: profile.to_string(),
+            transaction_hash: result["transaction_hash"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            gas_used: result["gas_used"].as_u64().unwrap_or_default(),
+            sender,
+            modules,
+            skipped: false,
+        })
+    }
+
+    //The packages to build, None standing for the current directory
+    fn package_dirs(&self) -> Vec<Option<&PathBuf>> {
+        if self.packages.is_empty() {
+            vec![None]
+        } else {
+            self.packages.iter().map(Some).collect()
+        }
+    }
+}
+
+/// Write the deployments to `path` as JSON for the tests to pick up.
+pub fn write_deployments(deployments: &[Deployment], path: &Path) -> Result<(), AptestError> {
+    let json = serde_json::to_string_pretty(deployments)
+        .or_fail(AptestError::Environment, "Could not serialize deployments")?;
+    std::fs::write(path, json).or_fail(
+        AptestError::Environment,
+        &format!("Could not write {}", path.display()),
+    )
+}
+
+/// Read the deployments written by a previous run
+pub fn read_deployments(path: &Path) -> Result<Vec<Deployment>, AptestError> {
+    let json = std::fs::read_to_string(path).or_fail(
+        AptestError::Environment,
+        &format!(
+            "Could not read {}. Has the package been published?",
+            path.display()
+        ),
+    )?;
+    serde_json::from_str(&json).or_fail(
+        AptestError::Environment,
+        &format!("Could not parse {}", path.display()),
+    )
+}
+
+//Runs an aptos CLI command, echoing its output while capturing it to
+//recognize failures. Past the timeout the command is killed and its
+//last output shown
+async fn run_aptos(
+    command: &mut Command,
+    timeout: Option<Duration>,
+    what: &str,
+) -> Result<AptosRun, AptestError> {
+    ui::command(command);
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .or_fail(
+            AptestError::Environment,
+            "Couldn't find aptos command. Is it installed ?",
+        )?;
+
+    //stdout carries the JSON result, both carry errors
+    let (stdout_log, output_log) = (Log::default(), Log::default());
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let drain_stdout = async {
+        if let Some(stdout) = stdout {
+            drain(stdout, stdout_log.clone(), |line| {
+                if !ui::quiet() {
+                    print!("{}", line);
+                }
+                if let Ok(mut log) = output_log.lock() {
+                    log.extend_from_slice(line.as_bytes());
+                }
+            })
+            .await;
+        }
+    };
+    let drain_stderr = async {
+        if let Some(stderr) = stderr {
+            drain(stderr, output_log.clone(), |line| eprint!("{}", line)).await;
+        }
+    };
+    let finished = async { tokio::join!(child.wait(), drain_stdout, drain_stderr).0 };
+    let status = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, finished).await.ok(),
+        None => Some(finished.await),
+    };
+    let status = match status {
+        Some(status) => {
+            status.or_fail(AptestError::Publish, &format!("Could not wait on {}", what))?
+        }
+        None => {
+            let _ = child.kill().await;
+            return Err(AptestError::Timeout(format!(
+                "{} timed out after {}s\n\nLast output:\n{}",
+                what,
+                timeout.unwrap_or_default().as_secs(),
+                recent_output(&output_log)
+            )));
+        }
+    };
+
+    let read = |log: &Log| {
+        let bytes = log.lock().map(|x| x.clone()).unwrap_or_default();
+        String::from_utf8_lossy(&bytes).into_owned()
+    };
+    Ok(AptosRun {
+        status,
+        stdout: read(&stdout_log),
+        output: read(&output_log),
+    })
+}
@@ -0,0 +1,131 @@
+//! Recordings of the requests the tests made to the node and the faucet,
+//! and of the answers they got, so that the tests can be run again
+//! against the answers alone, without a node.
+//!
+//! A recording is a JSON lines file: each line is either an exchange, the
+//! environment the tests were run with or a file it points to.
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use crate::error::{AptestError, OrFail};
+
+/// A request and the answer it got, bodies hex encoded
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Exchange {
+    /// What was asked, "node" or "faucet"
+    pub upstream: String,
+    pub method: String,
+    pub path: String,
+    pub request: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub response: String,
+}
+
+/// A line of a recording
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Entry {
+    /// Environment variables the tests were run with, URLs aside
+    Env(BTreeMap<String, String>),
+    /// A file an environment variable points to, with its contents
+    File {
+        path: String,
+        contents: String,
+    },
+    Exchange(Exchange),
+}
+
+/// What a recording holds, later lines overriding the environment and
+/// files of earlier ones
+#[derive(Debug, Default)]
+pub struct Recording {
+    pub env: BTreeMap<String, String>,
+    pub files: BTreeMap<String, String>,
+    pub exchanges: Vec<Exchange>,
+}
+
+impl Recording {
+    /// Read the recording at `path`
+    pub fn load(path: &Path) -> Result<Self, AptestError> {
+        let text = std::fs::read_to_string(path).or_fail(
+            AptestError::Environment,
+            &format!("Could not read {}", path.display()),
+        )?;
+        let mut recording = Recording::default();
+        for line in text.lines().filter(|x| !x.trim().is_empty()) {
+            let entry = serde_json::from_str(line).or_fail(
+                AptestError::Environment,
+                &format!("Could not parse {}", path.display()),
+            )?;
+            match entry {
+                Entry::Env(env) => recording.env.extend(env),
+                Entry::File { path, contents } => {
+                    recording.files.insert(path, contents);
+                }
+                Entry::Exchange(exchange) => recording.exchanges.push(exchange),
+            }
+        }
+        Ok(recording)
+    }
+
+    /// The exchanges with the node or the faucet, in order
+    pub fn exchanges(&self, upstream: &str) -> Vec<Exchange> {
+        self.exchanges
+            .iter()
+            .filter(|x| x.upstream == upstream)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Add a line to the recording at `path`
+pub fn append(path: &Path, entry: &Entry) -> Result<(), AptestError> {
+    let mut file = File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+        .or_fail(
+            AptestError::Environment,
+            &format!("Could not open {}", path.display()),
+        )?;
+    write_entry(&mut file, entry).or_fail(
+        AptestError::Environment,
+        &format!("Could not write {}", path.display()),
+    )
+}
+
+/// Write an entry as a line of its own
+pub fn write_entry(file: &mut File, entry: &Entry) -> std::io::Result<()> {
+    let line = serde_json::to_string(entry)?;
+    writeln!(file, "{}", line)
+}
+
+#[test]
+fn test_entries() {
+    let exchange = Exchange {
+        upstream: "node".to_string(),
+        method: "GET".to_string(),
+        path: "/v1".to_string(),
+        request: String::new(),
+        status: 200,
+        headers: vec![("content-type".to_string(), "application/json".to_string())],
+        response: hex::encode("{}"),
+    };
+    let entry = serde_json::to_string(&Entry::Exchange(exchange.clone())).unwrap();
+    assert!(entry.starts_with("{\"exchange\":{\"upstream\":\"node\""));
+    let env = BTreeMap::from([("APTEST_ACCOUNT_0_ADDRESS".to_string(), "0x1".to_string())]);
+    assert_eq!(
+        serde_json::to_string(&Entry::Env(env)).unwrap(),
+        "{\"env\":{\"APTEST_ACCOUNT_0_ADDRESS\":\"0x1\"}}"
+    );
+    assert_eq!(
+        serde_json::from_str::<Entry>(&entry).unwrap(),
+        Entry::Exchange(exchange)
+    );
+}
@@ -0,0 +1,461 @@
+//! Machine readable results of a run for CI systems: which phases ran,
+//! how long they took and how they ended, as JUnit XML or JSON.
+
+use colored::*;
+use serde::Serialize;
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::coverage::Coverage;
+use crate::diff::Change;
+use crate::error::{AptestError, OrFail};
+use crate::gas::GasReport;
+use crate::history::TransactionRecord;
+use crate::process::Log;
+use crate::ui;
+
+/// Collects the outcome of each phase of a run as it goes.
+pub struct Report {
+    started: Instant,
+    finished: Option<Instant>,
+    phases: Vec<Phase>,
+    test_output: Log,
+    coverage: Option<Coverage>,
+    gas: Option<GasReport>,
+    transactions: Vec<TransactionRecord>,
+    state_changes: Vec<Change>,
+}
+
+/// How one phase of the run went
+#[derive(Clone, Debug, Serialize)]
+pub struct Phase {
+    pub name: String,
+    pub duration_secs: f64,
+    pub success: bool,
+    pub exit_code: i32,
+    pub error: Option<String>,
+    /// Times it was run again after failing, making it flaky if it then passed
+    pub retries: u32,
+}
+
+/// A run of "aptest matrix", with the combination of settings it had
+pub struct MatrixRun {
+    pub combination: String,
+    pub report: Report,
+    pub result: Result<(), AptestError>,
+}
+
+//The JSON report
+#[derive(Serialize)]
+struct Summary<'a> {
+    success: bool,
+    flaky: bool,
+    exit_code: i32,
+    error: Option<String>,
+    duration_secs: f64,
+    phases: &'a [Phase],
+    test_output: String,
+    coverage: Option<&'a Coverage>,
+    gas: Option<&'a GasReport>,
+}
+
+//The JSON report of a run of the matrix
+#[derive(Serialize)]
+struct MatrixSummary<'a> {
+    combination: &'a str,
+    #[serde(flatten)]
+    summary: Summary<'a>,
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Report {
+            started: Instant::now(),
+            finished: None,
+            phases: Vec::new(),
+            test_output: Log::default(),
+            coverage: None,
+            gas: None,
+            transactions: Vec::new(),
+            state_changes: Vec::new(),
+        }
+    }
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run a phase of the pipeline, recording how long it took and how it ended
+    pub async fn phase<T>(
+        &mut self,
+        name: &str,
+        phase: impl Future<Output = Result<T, AptestError>>,
+    ) -> Result<T, AptestError> {
+        let phases = RefCell::new(&mut self.phases);
+        recorded(&phases, name, phase).await
+    }
+
+    /// Run two phases side by side, recording each as it ends. The first
+    /// to fail cancels the other, which isn't recorded then
+    pub async fn concurrently<A, B>(
+        &mut self,
+        (first, a): (&str, impl Future<Output = Result<A, AptestError>>),
+        (second, b): (&str, impl Future<Output = Result<B, AptestError>>),
+    ) -> Result<(A, B), AptestError> {
+        let phases = RefCell::new(&mut self.phases);
+        tokio::try_join!(recorded(&phases, first, a), recorded(&phases, second, b))
+    }
+
+    /// The phases recorded so far, in order
+    pub fn phases(&self) -> &[Phase] {
+        &self.phases
+    }
+
+    /// Record that the last phase with this name was run again this many
+    /// times after failing
+    pub fn set_retries(&mut self, name: &str, retries: u32) {
+        if let Some(phase) = self.phases.iter_mut().rev().find(|x| x.name == name) {
+            phase.retries = retries;
+        }
+    }
+
+    /// Whether a phase only passed once run again
+    pub fn flaky(&self) -> bool {
+        self.phases.iter().any(|x| x.success && x.retries > 0)
+    }
+
+    /// Record the coverage of the Move unit tests
+    pub fn set_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    /// Coverage of the Move unit tests, if they ran with it
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// The gas report, started when first added to
+    pub fn gas_mut(&mut self) -> &mut GasReport {
+        self.gas.get_or_insert_with(GasReport::default)
+    }
+
+    /// Gas used by the run, if it was tracked
+    pub fn gas(&self) -> Option<&GasReport> {
+        self.gas.as_ref()
+    }
+
+    /// Keep the user transactions committed while the tests ran, as the
+    /// node's REST API returns them
+    pub fn add_transactions(&mut self, transactions: &[serde_json::Value]) {
+        self.transactions
+            .extend(transactions.iter().map(TransactionRecord::from_json));
+    }
+
+    /// The transactions kept so far, oldest first
+    pub fn transactions(&self) -> &[TransactionRecord] {
+        &self.transactions
+    }
+
+    /// Keep how the resources of the tracked accounts changed while the
+    /// tests ran
+    pub fn add_state_changes(&mut self, changes: Vec<Change>) {
+        self.state_changes.extend(changes);
+    }
+
+    /// The state changes kept so far, those of every test suite in turn
+    pub fn state_changes(&self) -> &[Change] {
+        &self.state_changes
+    }
+
+    /// Stop the clock of the run, which keeps going until then
+    pub fn finish(&mut self) {
+        self.finished = Some(Instant::now());
+    }
+
+    /// Print a table of how long each phase took and how the run ended,
+    /// after the coverage of the Move unit tests and the gas report if any
+    pub fn print_summary(&self, result: &Result<(), AptestError>) {
+        if self.phases.is_empty() {
+            return;
+        }
+        if let Some(coverage) = &self.coverage {
+            coverage.print_summary();
+        }
+        if let Some(gas) = &self.gas {
+            gas.print_summary();
+        }
+        ui::info("Run summary");
+        for phase in &self.phases {
+            let outcome = if phase.success && phase.retries > 0 {
+                format!("ok on attempt {}", phase.retries + 1).bright_yellow()
+            } else if phase.success {
+                "ok".bright_green()
+            } else {
+                format!("failed ({})", phase.exit_code).bright_red()
+            };
+            ui::print(format!(
+                "  {:<10} {:>8.1}s  {}",
+                phase.name, phase.duration_secs, outcome
+            ));
+        }
+        let outcome = match result {
+            Ok(()) if self.flaky() => "passed (flaky)".bright_yellow().bold(),
+            Ok(()) => "passed".bright_green().bold(),
+            Err(e) => format!("failed ({})", e.exit_code()).bright_red().bold(),
+        };
+        ui::print(format!(
+            "  {:<10} {:>8.1}s  {}\n",
+            "total",
+            self.elapsed(),
+            outcome
+        ));
+    }
+
+    /// Log the test runner's output is captured into, see [`crate::TestRunner::capture`]
+    pub fn test_output(&self) -> Log {
+        self.test_output.clone()
+    }
+
+    /// Write the report for a run that ended with `result` to `path`,
+    /// as JUnit XML if it ends in .xml and as JSON otherwise.
+    pub fn write(&self, path: &Path, result: &Result<(), AptestError>) -> Result<(), AptestError> {
+        let contents = match path.extension().and_then(|x| x.to_str()) {
+            Some("xml") => self.junit(),
+            _ => self.json(result)?,
+        };
+        std::fs::write(path, contents).or_fail(
+            AptestError::Environment,
+            &format!("Could not write the report to {}", path.display()),
+        )
+    }
+
+    fn json(&self, result: &Result<(), AptestError>) -> Result<String, AptestError> {
+        serde_json::to_string_pretty(&self.summary(result))
+            .or_fail(AptestError::Environment, "Could not serialize the report")
+    }
+
+    fn summary(&self, result: &Result<(), AptestError>) -> Summary {
+        Summary {
+            success: result.is_ok(),
+            flaky: result.is_ok() && self.flaky(),
+            exit_code: result.as_ref().err().map_or(0, |e| e.exit_code()),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            duration_secs: self.elapsed(),
+            phases: &self.phases,
+            test_output: self.captured(),
+            coverage: self.coverage.as_ref(),
+            gas: self.gas.as_ref(),
+        }
+    }
+
+    fn junit(&self) -> String {
+        let xml = junit_header(self.phases.len(), self.failures(), self.elapsed());
+        xml + &self.testsuite("aptest") + "</testsuites>\n"
+    }
+
+    //One test case per phase, the tests' output going with the tests phase
+    fn testsuite(&self, name: &str) -> String {
+        let mut xml = format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+            escape(name),
+            self.phases.len(),
+            self.failures(),
+            self.elapsed()
+        );
+        for phase in &self.phases {
+            xml += &format!(
+                "    <testcase name=\"{}\" classname=\"aptest\" time=\"{:.3}\">\n",
+                escape(&phase.name),
+                phase.duration_secs
+            );
+            if let Some(error) = &phase.error {
+                xml += &format!(
+                    "      <failure message=\"{}\" type=\"exit code {}\"/>\n",
+                    escape(error),
+                    phase.exit_code
+                );
+            }
+            if phase.name == "tests" {
+                xml += &format!(
+                    "      <system-out>{}</system-out>\n",
+                    escape(&self.captured())
+                );
+            }
+            xml += "    </testcase>\n";
+        }
+        xml += "  </testsuite>\n";
+        xml
+    }
+
+    fn failures(&self) -> usize {
+        self.phases.iter().filter(|x| !x.success).count()
+    }
+
+    fn elapsed(&self) -> f64 {
+        let end = self.finished.unwrap_or_else(Instant::now);
+        (end - self.started).as_secs_f64()
+    }
+
+    fn captured(&self) -> String {
+        let output = self
+            .test_output
+            .lock()
+            .map(|x| x.clone())
+            .unwrap_or_default();
+        String::from_utf8_lossy(&output).into_owned()
+    }
+}
+
+/// Print a table of how each run of the matrix went and how long it took
+pub fn print_matrix_summary(runs: &[MatrixRun]) {
+    ui::info("Matrix summary");
+    let width = runs.iter().map(|x| x.combination.len()).max().unwrap_or(0);
+    for run in runs {
+        let outcome = match &run.result {
+            Ok(()) if run.report.flaky() => "passed (flaky)".bright_yellow(),
+            Ok(()) => "passed".bright_green(),
+            Err(e) => format!("failed ({})", e.exit_code()).bright_red(),
+        };
+        ui::print(format!(
+            "  {:<width$} {:>8.1}s  {}",
+            run.combination,
+            run.report.elapsed(),
+            outcome
+        ));
+    }
+    let failed = runs.iter().filter(|x| x.result.is_err()).count();
+    let outcome = if failed == 0 {
+        "passed".bright_green().bold()
+    } else {
+        format!("{} of {} failed", failed, runs.len())
+            .bright_red()
+            .bold()
+    };
+    ui::print(format!("  {:<width$} {:>8}   {}\n", "total", "", outcome));
+}
+
+/// Write the reports of the runs of the matrix to `path`, as JUnit XML with
+/// a test suite per run if it ends in .xml and as a JSON array otherwise.
+pub fn write_matrix(runs: &[MatrixRun], path: &Path) -> Result<(), AptestError> {
+    let contents = match path.extension().and_then(|x| x.to_str()) {
+        Some("xml") => {
+            let tests = runs.iter().map(|x| x.report.phases.len()).sum();
+            let failures = runs.iter().map(|x| x.report.failures()).sum();
+            let time = runs.iter().map(|x| x.report.elapsed()).sum();
+            let mut xml = junit_header(tests, failures, time);
+            for run in runs {
+                xml += &run.report.testsuite(&run.combination);
+            }
+            xml + "</testsuites>\n"
+        }
+        _ => {
+            let summaries: Vec<_> = runs
+                .iter()
+                .map(|run| MatrixSummary {
+                    combination: &run.combination,
+                    summary: run.report.summary(&run.result),
+                })
+                .collect();
+            serde_json::to_string_pretty(&summaries)
+                .or_fail(AptestError::Environment, "Could not serialize the report")?
+        }
+    };
+    std::fs::write(path, contents).or_fail(
+        AptestError::Environment,
+        &format!("Could not write the report to {}", path.display()),
+    )
+}
+
+//The start of a JUnit report, up to its test suites
+fn junit_header(tests: usize, failures: usize, time: f64) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"aptest\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        tests, failures, time
+    )
+}
+
+//Escapes text for XML, dropping color codes and the other
+//control characters XML 1.0 doesn't allow at all
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            //ESC [ parameters, up to a final byte in @..~
+            '\u{1b}' if chars.peek() == Some(&'[') => {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+//Runs a phase, adding how it went to the phases once it ends. Phases
+//running side by side share the list, each only holding it to add to it
+async fn recorded<T>(
+    phases: &RefCell<&mut Vec<Phase>>,
+    name: &str,
+    phase: impl Future<Output = Result<T, AptestError>>,
+) -> Result<T, AptestError> {
+    let started = Instant::now();
+    let result = phase.await;
+    phases.borrow_mut().push(Phase {
+        name: name.to_string(),
+        duration_secs: started.elapsed().as_secs_f64(),
+        success: result.is_ok(),
+        exit_code: result.as_ref().err().map_or(0, |e| e.exit_code()),
+        error: result.as_ref().err().map(|e| e.to_string()),
+        retries: 0,
+    });
+    result
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(
+        escape("\u{1b}[31m<fail> & \"done\"\u{1b}[0m\n"),
+        "&lt;fail&gt; &amp; &quot;done&quot;\n"
+    );
+}
+
+#[tokio::test]
+async fn test_concurrently() {
+    let mut report = Report::new();
+    let (a, b) = report
+        .concurrently(("first", async { Ok(1) }), ("second", async { Ok("two") }))
+        .await
+        .unwrap();
+    assert_eq!((a, b), (1, "two"));
+    let failed = report
+        .concurrently(
+            ("failing", async {
+                Err::<(), _>(AptestError::Compile("broken".to_string()))
+            }),
+            ("pending", std::future::pending::<Result<(), AptestError>>()),
+        )
+        .await;
+    assert!(failed.is_err());
+    let names: Vec<_> = report.phases().iter().map(|x| x.name.as_str()).collect();
+    assert_eq!(names, ["first", "second", "failing"]);
+    assert!(!report.flaky());
+    report.set_retries("first", 2);
+    assert!(report.flaky());
+}
@@ -0,0 +1,259 @@
+use serde_json::{json, Value};
+
+use std::time::Duration;
+
+use crate::error::{AptestError, OrFail};
+
+/// Minimal client for the REST API of a node, covering what aptest
+/// needs to inspect accounts without going through the aptos CLI.
+#[derive(Clone)]
+pub struct NodeClient {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl NodeClient {
+    pub fn new(url: &str) -> Self {
+        NodeClient {
+            url: url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// A client giving up on requests not answered within `timeout`,
+    /// for nodes that may be hung
+    pub fn with_timeout(url: &str, timeout: Duration) -> Self {
+        NodeClient {
+            url: url.trim_end_matches('/').to_string(),
+            http: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// GET a path under /v1 and decode the JSON answer
+    pub async fn get(&self, path: &str) -> Result<Value, AptestError> {
+        let request = self.http.get(format!("{}/v1{}", self.url, path));
+        Self::send(request, path).await
+    }
+
+    /// POST a JSON body to a path under /v1 and decode the JSON answer
+    pub async fn post(&self, path: &str, body: &Value) -> Result<Value, AptestError> {
+        let request = self
+            .http
+            .post(format!("{}/v1{}", self.url, path))
+            .json(body);
+        Self::send(request, path).await
+    }
+
+    /// Whether an account exists on chain
+    pub async fn account_exists(&self, address: &str) -> bool {
+        self.get(&format!("/accounts/{}", address)).await.is_ok()
+    }
+
+    /// Sequence number of the next transaction sent by an account
+    pub async fn sequence_number(&self, address: &str) -> Result<u64, AptestError> {
+        let account = self.get(&format!("/accounts/{}", address)).await?;
+        parse_u64(&account["sequence_number"])
+    }
+
+    /// Chain id transactions must be signed for
+    pub async fn chain_id(&self) -> Result<u8, AptestError> {
+        let info = self.get("").await?;
+        info["chain_id"]
+            .as_u64()
+            .and_then(|x| u8::try_from(x).ok())
+            .ok_or_else(|| {
+                AptestError::Request(format!("Unexpected chain id {}", info["chain_id"]))
+            })
+    }
+
+    /// Submit a BCS encoded signed transaction, returning its hash
+    pub async fn submit(&self, signed: Vec<u8>) -> Result<String, AptestError> {
+        let request = self
+            .http
+            .post(format!("{}/v1/transactions", self.url))
+            .header("Content-Type", "application/x.aptos.signed_transaction+bcs")
+            .body(signed);
+        let pending = Self::send(request, "/transactions").await?;
+        pending["hash"].as_str().map(String::from).ok_or_else(|| {
+            AptestError::Request("The node returned no transaction hash".to_string())
+        })
+    }
+
+    /// Every resource under an account
+    pub async fn resources(&self, address: &str) -> Result<Value, AptestError> {
+        self.get(&format!("/accounts/{}/resources", address)).await
+    }
+
+    /// A resource of an account, by type
+    pub async fn resource(&self, address: &str, resource_type: &str) -> Result<Value, AptestError> {
+        let encoded: String = resource_type
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| match c {
+                '<' => "%3C".to_string(),
+                '>' => "%3E".to_string(),
+                ',' => "%2C".to_string(),
+                _ => c.to_string(),
+            })
+            .collect();
+        self.get(&format!("/accounts/{}/resource/{}", address, encoded))
+            .await
+    }
+
+    /// Every module published under an account, with its ABI
+    pub async fn modules(&self, address: &str) -> Result<Value, AptestError> {
+        self.get(&format!("/accounts/{}/modules", address)).await
+    }
+
+    /// An item of a table, by the handle of the table and a key
+    pub async fn table_item(
+        &self,
+        handle: &str,
+        key_type: &str,
+        value_type: &str,
+        key: Value,
+    ) -> Result<Value, AptestError> {
+        let request = json!({
+            "key_type": key_type,
+            "value_type": value_type,
+            "key": key,
+        });
+        self.post(&format!("/tables/{}/item", handle), &request)
+            .await
+    }
+
+    /// Version of the latest transaction on the ledger
+    pub async fn ledger_version(&self) -> Result<u64, AptestError> {
+        let info = self.get("").await?;
+        parse_u64(&info["ledger_version"])
+    }
+
+    /// Epoch the chain is in
+    pub async fn epoch(&self) -> Result<u64, AptestError> {
+        let info = self.get("").await?;
+        parse_u64(&info["epoch"])
+    }
+
+    /// Timestamp of the latest block, in microseconds since the Unix epoch
+    pub async fn ledger_timestamp(&self) -> Result<u64, AptestError> {
+        let info = self.get("").await?;
+        parse_u64(&info["ledger_timestamp"])
+    }
+
+    /// The user transactions committed after a ledger version, oldest first
+    pub async fn user_transactions_since(&self, version: u64) -> Result<Vec<Value>, AptestError> {
+        const PAGE: u64 = 100;
+        let latest = self.ledger_version().await?;
+        let mut transactions = Vec::new();
+        let mut start = version + 1;
+        while start <= latest {
+            let page = self
+                .get(&format!("/transactions?start={}&limit={}", start, PAGE))
+                .await?;
+            let page = page.as_array().cloned().unwrap_or_default();
+            if page.is_empty() {
+                break;
+            }
+            start += page.len() as u64;
+            transactions.extend(page.into_iter().filter(|x| x["type"] == "user_transaction"));
+        }
+        Ok(transactions)
+    }
+
+    /// A transaction by hash, pending or executed
+    pub async fn transaction(&self, hash: &str) -> Result<Value, AptestError> {
+        self.get(&format!("/transactions/by_hash/{}", hash)).await
+    }
+
+    /// Wait for a transaction to be executed, failing if it was aborted
+    pub async fn wait_for_transaction(&self, hash: &str) -> Result<(), AptestError> {
+        //Pending transactions may not be known by hash yet, so errors
+        //are retried until the deadline like a pending answer
+        for _ in 0..60 {
+            if let Ok(transaction) = self.transaction(hash).await {
+                if transaction["type"] != "pending_transaction" {
+                    if transaction["success"].as_bool() == Some(true) {
+                        return Ok(());
+                    }
+                    return Err(AptestError::Request(format!(
+                        "Transaction {} failed: {}",
+                        hash,
+                        transaction["vm_status"].as_str().unwrap_or_default()
+                    )));
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        Err(AptestError::Request(format!(
+            "Transaction {} was not executed in time",
+            hash
+        )))
+    }
+
+    /// Call a view function, returning the values it returned
+    pub async fn view(
+        &self,
+        function: &str,
+        type_args: &[String],
+        args: Vec<Value>,
+    ) -> Result<Value, AptestError> {
+        let view = json!({
+            "function": function,
+            "type_arguments": type_args,
+            "arguments": args,
+        });
+        self.post("/view", &view).await
+    }
+
+    /// APT balance of an account, in octas
+    pub async fn balance(&self, address: &str) -> Result<u64, AptestError> {
+        let view = json!({
+            "function": "0x1::coin::balance",
+            "type_arguments": ["0x1::aptos_coin::AptosCoin"],
+            "arguments": [address],
+        });
+        let balance = match self.post("/view", &view).await {
+            Ok(result) => result[0].clone(),
+            //Nodes predating view functions only expose the coin store
+            Err(_) => {
+                let store = self
+                    .resource(address, "0x1::coin::CoinStore<0x1::aptos_coin::AptosCoin>")
+                    .await?;
+                store["data"]["coin"]["value"].clone()
+            }
+        };
+        parse_u64(&balance)
+    }
+
+    //Sends a request, turning error statuses into errors carrying the node's message
+    async fn send(request: reqwest::RequestBuilder, path: &str) -> Result<Value, AptestError> {
+        let response = request.send().await.or_fail(
+            AptestError::Request,
+            &format!("Could not reach the node for {}", path),
+        )?;
+        let status = response.status();
+        let body: Value = response.json().await.or_fail(
+            AptestError::Request,
+            &format!("The node answered {} with invalid JSON", path),
+        )?;
+        if !status.is_success() {
+            let message = body["message"].as_str().unwrap_or_default();
+            return Err(AptestError::Request(format!(
+                "The node answered {} with {}: {}",
+                path, status, message
+            )));
+        }
+        Ok(body)
+    }
+}
+
+//The REST API returns u64 values as strings
+fn parse_u64(value: &Value) -> Result<u64, AptestError> {
+    value
+        .as_str()
+        .and_then(|x| x.parse().ok())
+        .ok_or_else(|| AptestError::Request(format!("Unexpected value {}", value)))
+}
@@ -0,0 +1,112 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::error::AptestError;
+use crate::ui;
+
+/// How often and how patiently an operation failing transiently,
+/// like funding or publishing right after the node started, is retried.
+/// The delay doubles after each failed attempt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Retry {
+    attempts: u32,
+    delay: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            attempts: 3,
+            delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl Retry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A policy trying only once
+    pub fn never() -> Self {
+        Self::new().attempts(1)
+    }
+
+    /// Attempts in total, the first one included
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Run the operation until it succeeds or the attempts run out,
+    /// returning its last error. Missing tools, timeouts and interruptions
+    /// are never retried.
+    pub async fn run<T, F, Fut>(&self, what: &str, mut operation: F) -> Result<T, AptestError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, AptestError>>,
+    {
+        let mut delay = self.delay;
+        let mut attempt = 1;
+        loop {
+            let err = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            let fatal = matches!(
+                err,
+                AptestError::Environment(_)
+                    | AptestError::Timeout(_)
+                    | AptestError::NodeExited(_)
+                    | AptestError::Interrupted
+            );
+            if fatal || attempt >= self.attempts {
+                return Err(err);
+            }
+            ui::info(format!(
+                "{} failed, retrying in {}ms ({}/{})...",
+                what,
+                delay.as_millis(),
+                attempt,
+                self.attempts - 1
+            ));
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_retry() {
+    let retry = Retry::new().attempts(3).delay(Duration::from_millis(1));
+    let mut calls = 0;
+    let result = retry
+        .run("Flaky", || {
+            calls += 1;
+            let result = if calls < 3 {
+                Err(AptestError::Publish("mempool is full".to_string()))
+            } else {
+                Ok(calls)
+            };
+            async move { result }
+        })
+        .await;
+    assert_eq!(result.unwrap(), 3);
+
+    let mut calls = 0;
+    let result: Result<(), _> = retry
+        .run("Missing", || {
+            calls += 1;
+            async { Err(AptestError::Environment("no aptos".to_string())) }
+        })
+        .await;
+    assert!(result.is_err());
+    assert_eq!(calls, 1);
+}
@@ -0,0 +1,299 @@
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::abort::ErrorMap;
+use crate::accounts::TestAccount;
+use crate::error::{AptestError, OrFail};
+use crate::multisig::Multisig;
+use crate::node::LocalNet;
+use crate::process::{self, drain, recent_output, spawn_grouped, GroupChild, Log};
+use crate::proxy::RunningProxy;
+use crate::publish::Deployment;
+use crate::signal::Interrupt;
+use crate::ui;
+
+/// Runs the end to end test suite, by default with "npm run test".
+#[derive(Clone)]
+pub struct TestRunner {
+    program: String,
+    args: Vec<String>,
+    envs: Vec<(String, String)>,
+    capture: Option<Log>,
+    timeout: Option<Duration>,
+    errors: ErrorMap,
+    //Kept up as long as the runner, or a clone of it
+    proxies: Vec<Arc<RunningProxy>>,
+}
+
+impl Default for TestRunner {
+    fn default() -> Self {
+        TestRunner {
+            program: process::script("npm"),
+            args: vec!["run".to_string(), "test".to_string()],
+            envs: Vec::new(),
+            capture: None,
+            timeout: None,
+            errors: ErrorMap::default(),
+            proxies: Vec::new(),
+        }
+    }
+}
+
+impl TestRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the tests with another command than "npm run test"
+    pub fn command(mut self, program: impl Into<String>, args: &[&str]) -> Self {
+        self.program = program.into();
+        self.args = args.iter().map(|x| x.to_string()).collect();
+        self
+    }
+
+    /// Append arguments to the test command
+    pub fn args(mut self, args: &[&str]) -> Self {
+        self.args.extend(args.iter().map(|x| x.to_string()));
+        self
+    }
+
+    /// Set an environment variable for the test process, replacing
+    /// the value it was given before if any
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let key = key.into();
+        self.envs.retain(|(k, _)| *k != key);
+        self.envs.push((key, value.into()));
+        self
+    }
+
+    /// The environment variables set for the test process,
+    /// also handed to the hooks
+    pub fn envs(&self) -> &[(String, String)] {
+        &self.envs
+    }
+
+    /// Point the tests at the network through the APTEST_NODE_URL,
+    /// APTEST_FAUCET_URL and APTEST_INDEXER_URL environment variables
+    pub fn network(mut self, net: &LocalNet) -> Self {
+        self = self.env("APTEST_NODE_URL", net.node_url());
+        if let Some(faucet_url) = net.faucet_url() {
+            self = self.env("APTEST_FAUCET_URL", faucet_url);
+        }
+        if let Some(indexer_url) = net.indexer_url() {
+            self = self.env("APTEST_INDEXER_URL", indexer_url);
+        }
+        self
+    }
+
+    /// Point the URL in the environment variable `key` at a proxy
+    /// instead, like APTEST_NODE_URL once set by [`TestRunner::network`].
+    /// The proxy serves until the runner and its clones are dropped.
+    pub fn proxy(mut self, key: &str, proxy: RunningProxy) -> Self {
+        self = self.env(key, proxy.url());
+        self.proxies.push(Arc::new(proxy));
+        self
+    }
+
+    /// Expose generated accounts to the tests as APTEST_ACCOUNT_<i>_ADDRESS
+    /// and APTEST_ACCOUNT_<i>_PRIVATE_KEY, plus APTEST_ACCOUNTS_FILE
+    /// pointing at the JSON file they were written to
+    pub fn accounts(mut self, accounts: &[TestAccount], file: &str) -> Self {
+        if accounts.is_empty() {
+            return self;
+        }
+        self = self.env("APTEST_ACCOUNTS_FILE", file);
+        for (i, account) in accounts.iter().enumerate() {
+            self = self
+                .env(format!("APTEST_ACCOUNT_{}_ADDRESS", i), &account.address)
+                .env(
+                    format!("APTEST_ACCOUNT_{}_PRIVATE_KEY", i),
+                    &account.private_key,
+                );
+        }
+        self
+    }
+
+    /// Expose the account paying the gas of sponsored transactions to the
+    /// tests as APTEST_FEE_PAYER_ADDRESS and APTEST_FEE_PAYER_PRIVATE_KEY
+    pub fn fee_payer(self, account: &TestAccount) -> Self {
+        self.env("APTEST_FEE_PAYER_ADDRESS", &account.address)
+            .env("APTEST_FEE_PAYER_PRIVATE_KEY", &account.private_key)
+    }
+
+    /// Expose a multisig account to the tests as APTEST_MULTISIG_ADDRESS,
+    /// APTEST_MULTISIG_REQUIRED for the approvals it needs, and its owners
+    /// as APTEST_MULTISIG_OWNER_<i>_ADDRESS and _PRIVATE_KEY
+    pub fn multisig(mut self, multisig: &Multisig) -> Self {
+        self = self
+            .env("APTEST_MULTISIG_ADDRESS", &multisig.address)
+            .env("APTEST_MULTISIG_REQUIRED", multisig.required.to_string());
+        for (i, owner) in multisig.owners.iter().enumerate() {
+            self = self
+                .env(
+                    format!("APTEST_MULTISIG_OWNER_{}_ADDRESS", i),
+                    &owner.address,
+                )
+                .env(
+                    format!("APTEST_MULTISIG_OWNER_{}_PRIVATE_KEY", i),
+                    &owner.private_key,
+                );
+        }
+        self
+    }
+
+    /// Expose published packages to the tests as APTEST_DEPLOYMENT_<i>_SENDER,
+    /// APTEST_DEPLOYMENT_<i>_TRANSACTION_HASH, APTEST_DEPLOYMENT_<i>_GAS_USED
+    /// and APTEST_DEPLOYMENT_<i>_MODULES (comma separated), plus
+    /// APTEST_DEPLOYMENT_FILE pointing at the JSON file they were written to
+    pub fn deployments(mut self, deployments: &[Deployment], file: &str) -> Self {
+        if deployments.is_empty() {
+            return self;
+        }
+        self = self.env("APTEST_DEPLOYMENT_FILE", file);
+        for (i, deployment) in deployments.iter().enumerate() {
+            self = self
+                .env(
+                    format!("APTEST_DEPLOYMENT_{}_SENDER", i),
+                    &deployment.sender,
+                )
+                .env(
+                    format!("APTEST_DEPLOYMENT_{}_TRANSACTION_HASH", i),
+                    &deployment.transaction_hash,
+                )
+                .env(
+                    format!("APTEST_DEPLOYMENT_{}_GAS_USED", i),
+                    deployment.gas_used.to_string(),
+                )
+                .env(
+                    format!("APTEST_DEPLOYMENT_{}_MODULES", i),
+                    deployment.modules.join(","),
+                );
+        }
+        self
+    }
+
+    /// Also copy the output of the tests into this log when running them,
+    /// while still showing it
+    pub fn capture(mut self, log: Log) -> Self {
+        self.capture = Some(log);
+        self
+    }
+
+    /// Kill the tests and fail if they run for longer than this,
+    /// or `None` to wait on them however long they take
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Explain the Move aborts in the output of failed tests with these
+    /// error constants, which has the output captured
+    pub fn errors(mut self, errors: ErrorMap) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    /// Start the tests in their own process group without waiting on them
+    pub fn spawn(&self) -> Result<GroupChild, AptestError> {
+        ui::info("Running e2e tests...");
+        spawn_grouped(&mut self.test_command())
+            .or_fail(AptestError::Environment, "Error running e2e tests")
+    }
+
+    /// Run the tests to completion, failing if they do
+    pub async fn run(&self, interrupt: &Interrupt) -> Result<(), AptestError> {
+        //The output is also needed to show where tests that timed out hung,
+        //and to find the aborts that made them fail
+        let log = match (&self.capture, self.timeout) {
+            (Some(log), _) => log.clone(),
+            (None, Some(_)) => Log::default(),
+            (None, None) if !self.errors.is_empty() => Log::default(),
+            (None, None) => return self.wait(self.spawn()?, interrupt, None).await,
+        };
+
+        ui::info("Running e2e tests...");
+        let mut child = spawn_grouped(
+            self.test_command()
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+        )
+        .or_fail(AptestError::Environment, "Error running e2e tests")?;
+
+        //Drained alongside the wait so all the output is in
+        //the log by the time the tests are over
+        let (stdout, stderr) = (child.child.stdout.take(), child.child.stderr.take());
+        let drain_stdout = async {
+            if let Some(stdout) = stdout {
+                drain(stdout, log.clone(), |line| {
+                    if !ui::quiet() {
+                        print!("{}", line);
+                    }
+                })
+                .await;
+            }
+        };
+        let drain_stderr = async {
+            if let Some(stderr) = stderr {
+                drain(stderr, log.clone(), |line| eprint!("{}", line)).await;
+            }
+        };
+        let (result, _, _) = tokio::join!(
+            self.wait(child, interrupt, Some(&log)),
+            drain_stdout,
+            drain_stderr
+        );
+        if let Err(AptestError::Tests(_)) = result {
+            let output = log.lock().map(|x| x.clone()).unwrap_or_default();
+            self.errors.report(&String::from_utf8_lossy(&output));
+        }
+        result
+    }
+
+    //The command running the tests
+    fn test_command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .envs(self.envs.iter().map(|(k, v)| (k, v)))
+            .stdout(ui::child_stdout());
+        command
+    }
+
+    //Waits on the tests, failing if they do or if they run past the timeout
+    async fn wait(
+        &self,
+        mut child: GroupChild,
+        interrupt: &Interrupt,
+        log: Option<&Log>,
+    ) -> Result<(), AptestError> {
+        let waited = match self.timeout {
+            Some(timeout) => {
+                tokio::time::timeout(timeout, child.wait_or_interrupt(interrupt)).await
+            }
+            None => Ok(child.wait_or_interrupt(interrupt).await),
+        };
+        let status = match waited {
+            Ok(status) => status
+                .or_fail(AptestError::Environment, "Could not wait on e2e tests")?
+                .ok_or_else(|| interrupt.reason())?,
+            Err(_) => {
+                child.kill();
+                let _ = child.child.wait().await;
+                let timeout = self.timeout.unwrap_or_default();
+                return Err(AptestError::Timeout(format!(
+                    "End to end tests timed out after {}s\n\nLast output of the tests:\n{}",
+                    timeout.as_secs(),
+                    log.map_or_else(|| "(not captured)".to_string(), recent_output)
+                )));
+            }
+        };
+
+        //Failing tests must fail the run so CI pipelines notice
+        if !status.success() {
+            return Err(AptestError::Tests("End to end tests failed.".to_string()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! Fixtures of `aptest.toml`, seeding the chain with the state the tests
+//! share (pools, minted tokens, registered accounts) once the package is
+//! published, instead of every test file setting it up on its own.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use tokio::process::Command;
+
+use crate::cli;
+use crate::config::Seed;
+use crate::error::{AptestError, OrFail};
+use crate::rest::NodeClient;
+use crate::ui;
+
+/// Outcome of a fixture, as logged for the tests
+#[derive(Debug, Serialize)]
+pub struct Seeded {
+    pub name: String,
+    pub profile: String,
+    pub transaction_hash: String,
+    pub gas_used: u64,
+    /// Events the transaction emitted, as the node returned them
+    pub events: Vec<Value>,
+}
+
+/// Send the fixtures to the node at `node_url` one after the other with
+/// the aptos CLI, failing on the first that doesn't go through, and
+/// print what each one did.
+pub async fn seed(fixtures: &[Seed], node_url: &str) -> Result<Vec<Seeded>, AptestError> {
+    if fixtures.is_empty() {
+        return Ok(Vec::new());
+    }
+    ui::info("Seeding fixtures...");
+    let client = NodeClient::new(node_url);
+    let mut seeded = Vec::new();
+    for fixture in fixtures {
+        let result = send(fixture, node_url).await?;
+        let transaction = client.transaction(&result.transaction_hash).await?;
+        let result = Seeded {
+            events: transaction["events"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default(),
+            ..result
+        };
+        ui::print(format!(
+            "{}  {}  {} gas, {} events",
+            result.name,
+            result.transaction_hash,
+            result.gas_used,
+            result.events.len()
+        ));
+        seeded.push(result);
+    }
+    Ok(seeded)
+}
+
+/// Write the outcome of the fixtures to `path` as JSON for the tests to
+/// pick up.
+pub fn write(seeded: &[Seeded], path: &Path) -> Result<(), AptestError> {
+    let json = serde_json::to_string_pretty(seeded)
+        .or_fail(AptestError::Environment, "Could not serialize fixtures")?;
+    std::fs::write(path, json).or_fail(
+        AptestError::Environment,
+        &format!("Could not write {}", path.display()),
+    )
+}
+
+//Runs "aptos move run" or "aptos move run-script" for one fixture and
+//reads the transaction it sent from the JSON result
+async fn send(fixture: &Seed, node_url: &str) -> Result<Seeded, AptestError> {
+    let mut command = Command::new(cli::program());
+    let name = match (&fixture.function, &fixture.script) {
+        (Some(function), None) => {
+            command.args(["move", "run", "--function-id", function.as_str()]);
+            function.clone()
+        }
+        (None, Some(script)) => {
+            command
+                .args(["move", "run-script", "--script-path"])
+                .arg(script);
+            script.display().to_string()
+        }
+        _ => {
+            return Err(AptestError::Publish(
+                "Every fixture needs exactly one of function or script".to_string(),
+            ))
+        }
+    };
+    let name = fixture.name.clone().unwrap_or(name);
+    if !fixture.args.is_empty() {
+        command.arg("--args").args(&fixture.args);
+    }
+    if !fixture.type_args.is_empty() {
+        command.arg("--type-args").args(&fixture.type_args);
+    }
+    let profile = fixture.profile.as_deref().unwrap_or("default");
+    let url_flag = cli::version().url_flag();
+    command.args(["--profile", profile, url_flag, node_url, "--assume-yes"]);
+
+    ui::command(&command);
+    let output = command.kill_on_drop(true).output().await.or_fail(
+        AptestError::Environment,
+        "Couldn't find aptos command. Is it installed ?",
+    )?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json = stdout.find('{').map_or("", |start| &stdout[start..]);
+    let result: Value = serde_json::from_str(json).unwrap_or_default();
+    if !output.status.success() || result["Result"]["success"] == false {
+        let reason = result["Error"]
+            .as_str()
+            .or_else(|| result["Result"]["vm_status"].as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| String::from_utf8_lossy(&output.stderr).trim().to_string());
+        return Err(AptestError::Publish(format!(
+            "The fixture {} failed: {}",
+            name, reason
+        )));
+    }
+    let result = &result["Result"];
+    Ok(Seeded {
+        name,
+        profile: profile.to_string(),
+        transaction_hash: result["transaction_hash"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        gas_used: result["gas_used"].as_u64().unwrap_or_default(),
+        events: Vec::new(),
+    })
+}
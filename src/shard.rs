@@ -0,0 +1,107 @@
+//! Splitting the end to end tests between CI workers with `--shard`, each
+//! running its share against a network of its own.
+
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::{AptestError, OrFail};
+
+/// Directories under the tests that hold no test files
+const SKIPPED_DIRS: [&str; 2] = ["node_modules", "generated"];
+
+/// One of `total` shards, numbered from 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize,
+    pub total: usize,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{} isn't a shard like 2/5", s);
+        let (index, total) = s.split_once('/').ok_or_else(invalid)?;
+        let index = index.trim().parse().map_err(|_| invalid())?;
+        let total = total.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > total {
+            return Err(format!("Shard {} isn't between 1 and {}", index, total));
+        }
+        Ok(Shard { index, total })
+    }
+}
+
+impl Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.total)
+    }
+}
+
+impl Shard {
+    /// The items of this shard, every `total`th one starting from its own,
+    /// so that the shards of the same items never overlap
+    pub fn pick<T>(&self, items: Vec<T>) -> Vec<T> {
+        items
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| i % self.total == self.index - 1)
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
+
+/// The test files under `dir`, named like `*.test.ts`, `*.spec.js`,
+/// `test_*.py` or `*_test.py` as test runners expect them, sorted so that
+/// every worker finds them in the same order
+pub fn discover(dir: &Path) -> Result<Vec<PathBuf>, AptestError> {
+    let mut files = Vec::new();
+    let entries = std::fs::read_dir(dir).or_fail(
+        AptestError::Environment,
+        &format!("Could not read the tests in {}", dir.display()),
+    )?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if path.is_dir() {
+            if !SKIPPED_DIRS.contains(&name.as_str()) {
+                files.extend(discover(&path)?);
+            }
+        } else if is_test_file(&name) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+//Whether a file is named like a test file
+fn is_test_file(name: &str) -> bool {
+    let mut parts = name.split('.');
+    let stem = parts.next().unwrap_or_default();
+    let parts: Vec<_> = parts.collect();
+    match parts.split_last() {
+        Some((_, middle)) => {
+            middle.iter().any(|x| *x == "test" || *x == "spec")
+                || stem.starts_with("test_")
+                || stem.ends_with("_test")
+        }
+        None => false,
+    }
+}
+
+#[test]
+fn test_shard() {
+    let shard: Shard = "2/3".parse().unwrap();
+    assert_eq!(shard.to_string(), "2/3");
+    assert_eq!(shard.pick((1..=7).collect()), [2, 5]);
+    assert!("0/3".parse::<Shard>().is_err());
+    assert!("4/3".parse::<Shard>().is_err());
+    assert!("2".parse::<Shard>().is_err());
+
+    assert!(is_test_file("counter.test.ts"));
+    assert!(is_test_file("market.spec.js"));
+    assert!(is_test_file("test_token.py"));
+    assert!(!is_test_file("helpers.ts"));
+    assert!(!is_test_file("test_data"));
+}
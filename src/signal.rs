@@ -0,0 +1,193 @@
+use crate::error::{AptestError, OrFail};
+
+use std::future::Future;
+use std::time::Duration;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, Signal, SignalKind};
+#[cfg(windows)]
+use tokio::signal::windows::{ctrl_break, ctrl_c, ctrl_close, CtrlBreak, CtrlC, CtrlClose};
+use tokio::sync::watch;
+
+/// Receives termination signals so that long waits can be cut short
+/// and the local network torn down.
+#[derive(Clone)]
+pub struct Interrupt {
+    rx: watch::Receiver<bool>,
+    //Why a process the run relies on went away, once it did
+    exit: Option<watch::Receiver<Option<String>>>,
+}
+
+impl Interrupt {
+    /// Listen for Ctrl+C, SIGTERM (CI runners) and SIGHUP (closed terminals),
+    /// or Ctrl+C, Ctrl+Break and closed consoles on Windows, all of which
+    /// go through the same cleanup path.
+    /// Must be called from within a tokio runtime.
+    pub fn listen() -> Result<Self, AptestError> {
+        let mut signals =
+            Signals::new().or_fail(AptestError::Environment, "Could not set signal handlers")?;
+
+        let (tx, rx) = watch::channel(false);
+        tokio::spawn(async move {
+            loop {
+                signals.recv().await;
+                if tx.send(true).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Interrupt { rx, exit: None })
+    }
+
+    /// An interrupt that never fires, for callers handling signals themselves.
+    pub fn never() -> Self {
+        let (_, rx) = watch::channel(false);
+        Interrupt { rx, exit: None }
+    }
+
+    /// The same interrupt, also firing once `exit` gets why a process the
+    /// run relies on went away, which fails the run with that reason.
+    pub fn or_exit(&self, exit: watch::Receiver<Option<String>>) -> Self {
+        Interrupt {
+            rx: self.rx.clone(),
+            exit: Some(exit),
+        }
+    }
+
+    /// Fail if a signal has been received, or a process went away.
+    pub fn check(&self) -> Result<(), AptestError> {
+        if *self.rx.borrow() {
+            return Err(AptestError::Interrupted);
+        }
+        self.exited()
+    }
+
+    /// Fail if a process the run relies on went away.
+    pub fn exited(&self) -> Result<(), AptestError> {
+        match self.exit.as_ref().and_then(|x| x.borrow().clone()) {
+            Some(reason) => Err(AptestError::NodeExited(reason)),
+            None => Ok(()),
+        }
+    }
+
+    /// Wait until a signal is received, or a process went away.
+    pub async fn wait(&self) {
+        let signal = async {
+            let mut rx = self.rx.clone();
+            while !*rx.borrow() {
+                //Nobody can signal us anymore
+                if rx.changed().await.is_err() {
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+        let exit = async {
+            let mut exit = match self.exit.clone() {
+                Some(exit) => exit,
+                None => return std::future::pending().await,
+            };
+            while exit.borrow().is_none() {
+                //Nothing is watched anymore
+                if exit.changed().await.is_err() {
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+        tokio::select! {
+            _ = signal => {}
+            _ = exit => {}
+        }
+    }
+
+    /// Why a wait was cut short: a signal, or a process gone.
+    pub fn reason(&self) -> AptestError {
+        self.check().err().unwrap_or(AptestError::Interrupted)
+    }
+
+    /// Sleep for the given duration, failing early if a signal arrives.
+    pub async fn sleep(&self, duration: Duration) -> Result<(), AptestError> {
+        self.race(async {
+            tokio::time::sleep(duration).await;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Run a future to completion, failing early if a signal arrives.
+    pub async fn race<T>(
+        &self,
+        future: impl Future<Output = Result<T, AptestError>>,
+    ) -> Result<T, AptestError> {
+        tokio::select! {
+            result = future => result,
+            _ = self.wait() => Err(self.reason()),
+        }
+    }
+}
+
+//The termination signals of the platform
+#[cfg(unix)]
+struct Signals {
+    sigint: Signal,
+    sigterm: Signal,
+    sighup: Signal,
+}
+
+#[cfg(unix)]
+impl Signals {
+    fn new() -> std::io::Result<Self> {
+        Ok(Signals {
+            sigint: signal(SignalKind::interrupt())?,
+            sigterm: signal(SignalKind::terminate())?,
+            sighup: signal(SignalKind::hangup())?,
+        })
+    }
+
+    //Waits for the next signal of any kind
+    async fn recv(&mut self) {
+        tokio::select! {
+            _ = self.sigint.recv() => {}
+            _ = self.sigterm.recv() => {}
+            _ = self.sighup.recv() => {}
+        }
+    }
+}
+
+//The termination signals of the platform
+#[cfg(windows)]
+struct Signals {
+    ctrl_c: CtrlC,
+    ctrl_break: CtrlBreak,
+    ctrl_close: CtrlClose,
+}
+
+#[cfg(windows)]
+impl Signals {
+    fn new() -> std::io::Result<Self> {
+        Ok(Signals {
+            ctrl_c: ctrl_c()?,
+            ctrl_break: ctrl_break()?,
+            ctrl_close: ctrl_close()?,
+        })
+    }
+
+    //Waits for the next signal of any kind
+    async fn recv(&mut self) {
+        tokio::select! {
+            _ = self.ctrl_c.recv() => {}
+            _ = self.ctrl_break.recv() => {}
+            _ = self.ctrl_close.recv() => {}
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_or_exit() {
+    let (tx, rx) = watch::channel(None);
+    let interrupt = Interrupt::never().or_exit(rx);
+    assert!(interrupt.check().is_ok());
+    tx.send(Some("The validator exited".to_string())).unwrap();
+    let result = interrupt
+        .race(std::future::pending::<Result<(), _>>())
+        .await;
+    assert!(matches!(result, Err(AptestError::NodeExited(_))));
+}
@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::{AptestError, OrFail};
+
+//Where snapshots of the chain state are kept
+const SNAPSHOT_DIR: &str = ".aptest/snapshots";
+
+/// Directory a snapshot is kept in
+pub fn path(name: &str) -> PathBuf {
+    Path::new(SNAPSHOT_DIR).join(name)
+}
+
+/// Names of the saved snapshots, sorted
+pub fn list() -> Vec<String> {
+    let mut names: Vec<_> = std::fs::read_dir(SNAPSHOT_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// Copy the data directory of a stopped node into a snapshot,
+/// replacing any previous snapshot with that name.
+pub fn save(state_dir: &Path, name: &str) -> Result<(), AptestError> {
+    if !state_dir.is_dir() {
+        return Err(AptestError::Environment(format!(
+            "There is no chain state in {} to snapshot",
+            state_dir.display()
+        )));
+    }
+    let snapshot = path(name);
+    remove(&snapshot)?;
+    copy_dir(state_dir, &snapshot)
+}
+
+/// Replace the data directory of a stopped node with a snapshot,
+/// rolling the chain back to when it was taken.
+pub fn restore(name: &str, state_dir: &Path) -> Result<(), AptestError> {
+    let snapshot = path(name);
+    if !snapshot.is_dir() {
+        return Err(AptestError::Environment(format!(
+            "There is no snapshot named {}",
+            name
+        )));
+    }
+    remove(state_dir)?;
+    copy_dir(&snapshot, state_dir)
+}
+
+/// Delete a snapshot, if there is one with that name
+pub fn delete(name: &str) -> Result<(), AptestError> {
+    remove(&path(name))
+}
+
+//Removes a directory if it exists
+pub(crate) fn remove(dir: &Path) -> Result<(), AptestError> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    std::fs::remove_dir_all(dir).or_fail(
+        AptestError::Environment,
+        &format!("Could not remove {}", dir.display()),
+    )
+}
+
+//Copies a directory and everything under it
+pub(crate) fn copy_dir(from: &Path, to: &Path) -> Result<(), AptestError> {
+    std::fs::create_dir_all(to).or_fail(
+        AptestError::Environment,
+        &format!("Could not create directory {}", to.display()),
+    )?;
+    let entries = std::fs::read_dir(from).or_fail(
+        AptestError::Environment,
+        &format!("Could not read {}", from.display()),
+    )?;
+    for entry in entries {
+        let entry = entry.or_fail(
+            AptestError::Environment,
+            &format!("Could not read {}", from.display()),
+        )?;
+        let (source, target) = (entry.path(), to.join(entry.file_name()));
+        if source.is_dir() {
+            copy_dir(&source, &target)?;
+        } else {
+            std::fs::copy(&source, &target).or_fail(
+                AptestError::Environment,
+                &format!("Could not copy {}", source.display()),
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::{AptestError, OrFail};
+use crate::publish::Deployment;
+use crate::rest::NodeClient;
+
+//Where aptest remembers what it did across runs
+const STATE_FILE: &str = ".aptest/state.json";
+
+/// What aptest remembers across runs, in `.aptest/state.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    //By package directory and profile
+    #[serde(default)]
+    published: BTreeMap<String, Published>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    localnet: Option<Localnet>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_run: Option<LastRun>,
+}
+
+/// How far the last run got, for `aptest run --resume`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastRun {
+    /// Digests of the packages it ran on, in order
+    pub digests: Vec<String>,
+    /// The phases it got through that a resumed run can skip, in order
+    pub done: Vec<String>,
+    /// Whether it passed as a whole
+    pub passed: bool,
+}
+
+/// The local network an aptest process is running
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Localnet {
+    /// The aptest process running it
+    pub pid: u32,
+    /// When it was started, in seconds since the Unix epoch
+    pub started: u64,
+    pub node_url: String,
+    pub faucet_url: Option<String>,
+    /// Output of the validator, written as it runs
+    pub node_log: PathBuf,
+    /// Output of the faucet, if there is one
+    pub faucet_log: Option<PathBuf>,
+    /// Root key of the chain, for "aptest clock"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mint_key: Option<String>,
+}
+
+/// A package as it was last published
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Published {
+    /// Identity of the chain it was published to
+    pub node: String,
+    /// Digest of the package sources
+    pub digest: String,
+    /// What the publish deployed, for runs skipping it
+    #[serde(default)]
+    pub deployment: Option<Deployment>,
+}
+
+impl State {
+    /// Read the state, empty if there is none yet or it is unreadable
+    pub fn load() -> Self {
+        std::fs::read_to_string(STATE_FILE)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), AptestError> {
+        let json = serde_json::to_string_pretty(self)
+            .or_fail(AptestError::Environment, "Could not serialize the state")?;
+        std::fs::create_dir_all(".aptest")
+            .or_fail(AptestError::Environment, "Could not create .aptest")?;
+        std::fs::write(STATE_FILE, json).or_fail(
+            AptestError::Environment,
+            &format!("Could not write {}", STATE_FILE),
+        )
+    }
+
+    /// How the package in `dir` was last published from `profile`
+    pub fn published(&self, dir: &Path, profile: &str) -> Option<&Published> {
+        self.published.get(&key(dir, profile))
+    }
+
+    pub fn set_published(&mut self, dir: &Path, profile: &str, published: Published) {
+        self.published.insert(key(dir, profile), published);
+    }
+
+    /// Every package published, as `directory:profile`
+    pub fn published_packages(&self) -> impl Iterator<Item = (&str, &Published)> {
+        self.published
+            .iter()
+            .map(|(key, published)| (key.as_str(), published))
+    }
+
+    /// Forget what was published to a chain, which is no longer there
+    pub fn forget_chain(&mut self, node: &str) {
+        self.published.retain(|_, published| published.node != node);
+    }
+
+    /// The local network last started by aptest, if it wasn't stopped
+    pub fn localnet(&self) -> Option<&Localnet> {
+        self.localnet.as_ref()
+    }
+
+    pub fn set_localnet(&mut self, localnet: Option<Localnet>) {
+        self.localnet = localnet;
+    }
+
+    /// How far the last run got, if it was recorded
+    pub fn last_run(&self) -> Option<&LastRun> {
+        self.last_run.as_ref()
+    }
+
+    pub fn set_last_run(&mut self, last_run: Option<LastRun>) {
+        self.last_run = last_run;
+    }
+}
+
+fn key(dir: &Path, profile: &str) -> String {
+    format!("{}:{}", dir.display(), profile)
+}
+
+/// Identity of the chain behind a node: its URL and genesis transaction,
+/// which differs every time a fresh genesis is generated.
+pub async fn node_identity(node_url: &str) -> Result<String, AptestError> {
+    let genesis = NodeClient::new(node_url)
+        .get("/transactions/by_version/0")
+        .await?;
+    let hash = genesis["hash"].as_str().unwrap_or_default();
+    Ok(format!("{}#{}", node_url, hash))
+}
+
+/// Digest of a package's Move.toml and everything under its sources/,
+/// changing whenever a publish could produce different modules.
+pub fn package_digest(dir: &Path) -> Result<String, AptestError> {
+    let mut files = vec![dir.join("Move.toml")];
+    collect_files(&dir.join("sources"), &mut files);
+    files[1..].sort();
+
+    let mut hasher = Sha3_256::new();
+    for file in &files {
+        let contents = std::fs::read(file).or_fail(
+            AptestError::Environment,
+            &format!("Could not read {}", file.display()),
+        )?;
+        let relative = file.strip_prefix(dir).unwrap_or(file);
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(contents);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+//Recursively collects the files under dir
+pub(crate) fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Builds, signs and submits entry function transactions natively,
+//! so aptest can act on chain without the aptos CLI or a faucet.
+
+use ed25519_dalek::{Signer, SigningKey};
+use sha3::{Digest, Sha3_256};
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::args::MoveValue;
+use crate::error::AptestError;
+use crate::rest::NodeClient;
+
+//Gas settings generous enough for any single call on a local network
+const MAX_GAS_AMOUNT: u64 = 200_000;
+const GAS_UNIT_PRICE: u64 = 100;
+const EXPIRATION: Duration = Duration::from_secs(60);
+
+/// A call to a public entry function, with its arguments BCS encoded.
+pub struct EntryFunction {
+    address: [u8; 32],
+    module: String,
+    function: String,
+    type_args: Vec<u8>,
+    type_arg_count: u64,
+    args: Vec<Vec<u8>>,
+}
+
+impl EntryFunction {
+    /// A call to `function`, given as `0x1::module::function`
+    pub fn new(function: &str) -> Result<Self, AptestError> {
+        let mut parts = function.split("::");
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(address), Some(module), Some(function), None) => Ok(EntryFunction {
+                address: parse_address(address)?,
+                module: module.to_string(),
+                function: function.to_string(),
+                type_args: Vec::new(),
+                type_arg_count: 0,
+                args: Vec::new(),
+            }),
+            _ => Err(AptestError::Environment(format!(
+                "{} is not of the form <address>::<module>::<function>",
+                function
+            ))),
+        }
+    }
+
+    /// Append an address argument
+    pub fn address_arg(mut self, address: &str) -> Result<Self, AptestError> {
+        self.args.push(parse_address(address)?.to_vec());
+        Ok(self)
+    }
+
+    /// Append a u64 argument
+    pub fn u64_arg(mut self, value: u64) -> Self {
+        self.args.push(value.to_le_bytes().to_vec());
+        self
+    }
+
+    /// Append an argument of any type
+    pub fn arg(mut self, value: &MoveValue) -> Self {
+        let mut out = Vec::new();
+        encode_value(&mut out, value);
+        self.args.push(out);
+        self
+    }
+
+    /// Append a type argument, like `0x1::aptos_coin::AptosCoin`
+    /// or `vector<u8>`
+    pub fn type_arg(mut self, tag: &str) -> Result<Self, AptestError> {
+        encode_type_tag(&mut self.type_args, tag)?;
+        self.type_arg_count += 1;
+        Ok(self)
+    }
+
+    //BCS encoding of TransactionPayload::EntryFunction
+    fn encode_payload(&self, out: &mut Vec<u8>) {
+        uleb128(out, 2);
+        out.extend_from_slice(&self.address);
+        bytes(out, self.module.as_bytes());
+        bytes(out, self.function.as_bytes());
+        uleb128(out, self.type_arg_count);
+        out.extend_from_slice(&self.type_args);
+        uleb128(out, self.args.len() as u64);
+        for arg in &self.args {
+            bytes(out, arg);
+        }
+    }
+}
+
+/// Sign `call` as the account at `sender` controlled by `key`, submit it
+/// and wait for it to be executed. Returns the transaction hash.
+pub async fn submit(
+    client: &NodeClient,
+    sender: &str,
+    key: &SigningKey,
+    call: &EntryFunction,
+) -> Result<String, AptestError> {
+    let sequence_number = client.sequence_number(sender).await?;
+    let chain_id = client.chain_id().await?;
+    let signed = sign(sender, key, sequence_number, chain_id, call)?;
+    let hash = client.submit(signed).await?;
+    client.wait_for_transaction(&hash).await?;
+    Ok(hash)
+}
+
+/// Sign `call` as the account at `sender` controlled by `key`, with the
+/// given sequence number, as a BCS encoded SignedTransaction. Senders
+/// keeping track of their sequence numbers can submit several at once.
+pub fn sign(
+    sender: &str,
+    key: &SigningKey,
+    sequence_number: u64,
+    chain_id: u8,
+    call: &EntryFunction,
+) -> Result<Vec<u8>, AptestError> {
+    let sender_address = parse_address(sender)?;
+    let expiration = (SystemTime::now() + EXPIRATION)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    //-----------------------------RawTransaction--------------------------------
+    let mut raw = Vec::new();
+    raw.extend_from_slice(&sender_address);
+    raw.extend_from_slice(&sequence_number.to_le_bytes());
+    call.encode_payload(&mut raw);
+    raw.extend_from_slice(&MAX_GAS_AMOUNT.to_le_bytes());
+    raw.extend_from_slice(&GAS_UNIT_PRICE.to_le_bytes());
+    raw.extend_from_slice(&expiration.to_le_bytes());
+    raw.push(chain_id);
+
+    //-----------------------------Signing---------------------------------------
+    //Transactions are signed behind a domain separating prefix
+    let mut message = Sha3_256::digest(b"APTOS::RawTransaction").to_vec();
+    message.extend_from_slice(&raw);
+    let signature = key.sign(&message);
+
+    //SignedTransaction with a TransactionAuthenticator::Ed25519
+    let mut signed = raw;
+    uleb128(&mut signed, 0);
+    bytes(&mut signed, key.verifying_key().as_bytes());
+    bytes(&mut signed, &signature.to_bytes());
+    Ok(signed)
+}
+
+/// Parse a 0x-hex address, padding short forms like 0x1 to 32 bytes.
+pub fn parse_address(address: &str) -> Result<[u8; 32], AptestError> {
+    let invalid = || AptestError::Environment(format!("{} is not a valid address", address));
+    let digits = address.strip_prefix("0x").unwrap_or(address);
+    if digits.is_empty() || digits.len() > 64 {
+        return Err(invalid());
+    }
+    let padded = format!("{:0>64}", digits);
+    let mut out = [0u8; 32];
+    hex::decode_to_slice(padded, &mut out).map_err(|_| invalid())?;
+    Ok(out)
+}
+
+//BCS encoding of a Move value
+fn encode_value(out: &mut Vec<u8>, value: &MoveValue) {
+    match value {
+        MoveValue::Bool(x) => out.push(*x as u8),
+        MoveValue::U8(x) => out.push(*x),
+        MoveValue::U16(x) => out.extend_from_slice(&x.to_le_bytes()),
+        MoveValue::U32(x) => out.extend_from_slice(&x.to_le_bytes()),
+        MoveValue::U64(x) => out.extend_from_slice(&x.to_le_bytes()),
+        MoveValue::U128(x) => out.extend_from_slice(&x.to_le_bytes()),
+        MoveValue::U256(x) | MoveValue::Address(x) => out.extend_from_slice(x),
+        MoveValue::String(x) => bytes(out, x.as_bytes()),
+        MoveValue::Bytes(x) => bytes(out, x),
+        MoveValue::Vector(items) => {
+            uleb128(out, items.len() as u64);
+            for item in items {
+                encode_value(out, item);
+            }
+        }
+    }
+}
+
+//BCS encoding of a TypeTag written like 0x1::coin::Coin<0x1::aptos_coin::AptosCoin>
+fn encode_type_tag(out: &mut Vec<u8>, tag: &str) -> Result<(), AptestError> {
+    let tag = tag.trim();
+    let invalid = || AptestError::Environment(format!("{} is not a valid type", tag));
+    let variant = match tag {
+        "bool" => 0,
+        "u8" => 1,
+        "u64" => 2,
+        "u128" => 3,
+        "address" => 4,
+        "signer" => 5,
+        "u16" => 8,
+        "u32" => 9,
+        "u256" => 10,
+        _ => {
+            if let Some(inner) = tag
+                .strip_prefix("vector<")
+                .and_then(|x| x.strip_suffix('>'))
+            {
+                uleb128(out, 6);
+                return encode_type_tag(out, inner);
+            }
+            let (path, params) = match tag.split_once('<') {
+                Some((path, params)) => (path, params.strip_suffix('>').ok_or_else(invalid)?),
+                None => (tag, ""),
+            };
+            let mut parts = path.split("::");
+            let (address, module, name) =
+                match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                    (Some(address), Some(module), Some(name), None) => (address, module, name),
+                    _ => return Err(invalid()),
+                };
+            uleb128(out, 7);
+            out.extend_from_slice(&parse_address(address)?);
+            bytes(out, module.as_bytes());
+            bytes(out, name.as_bytes());
+            let params = split_params(params);
+            uleb128(out, params.len() as u64);
+            for param in params {
+                encode_type_tag(out, param)?;
+            }
+            return Ok(());
+        }
+    };
+    uleb128(out, variant);
+    Ok(())
+}
+
+//Splits type parameters on the commas not nested in another type
+fn split_params(params: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in params.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                split.push(&params[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&params[start..]);
+    split.retain(|x| !x.trim().is_empty());
+    split
+}
+
+//ULEB128, used by BCS for lengths and enum variants
+fn uleb128(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+//Length prefixed byte sequence
+fn bytes(out: &mut Vec<u8>, value: &[u8]) {
+    uleb128(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+#[test]
+fn test_parse_address() {
+    let address = parse_address("0xa550c18").unwrap();
+    assert_eq!(
+        hex::encode(address),
+        "000000000000000000000000000000000000000000000000000000000a550c18"
+    );
+    assert!(parse_address("0xzz").is_err());
+}
+
+#[test]
+fn test_type_tag() {
+    let mut out = Vec::new();
+    encode_type_tag(
+        &mut out,
+        "vector<0x1::coin::Coin<0x1::aptos_coin::AptosCoin>>",
+    )
+    .unwrap();
+    assert_eq!(out[..2], [6, 7]);
+    assert_eq!(
+        split_params("u8, 0x1::a::B<u8, u64>"),
+        ["u8", " 0x1::a::B<u8, u64>"]
+    );
+    assert!(encode_type_tag(&mut Vec::new(), "0x1::coin").is_err());
+}
@@ -0,0 +1,338 @@
+//! How aptest shows its own messages, and how much it shows besides them.
+
+use colored::*;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde_json::{json, Value};
+
+use std::fmt::Display;
+use std::io::Write;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+
+use crate::error::{AptestError, OrFail};
+use crate::process::Log;
+
+/// How much output a run shows
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only aptest's banners and failures, no output from the tools it runs
+    Quiet,
+    Normal,
+    /// Also the commands run, their environment and readiness polling
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+//Plain "[aptest] ..." lines instead of spaced out colored banners
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// What goes to stdout
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Messages for people, and the output of the tools aptest runs
+    Text,
+    /// One JSON event per line for each milestone of the run. Everything
+    /// else goes to stderr, and the tools' stdout is hidden
+    Json,
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+//The spinners on screen, drawn together so that those of steps running
+//side by side don't overwrite each other or the lines printed meanwhile
+static SPINNERS: Mutex<Option<MultiProgress>> = Mutex::new(None);
+
+//How often a spinner shows the last line of what it waits on
+const SPINNER_REFRESH: Duration = Duration::from_millis(200);
+
+/// Set what goes to stdout for the rest of the process
+pub fn set_output(format: OutputFormat) {
+    JSON.store(format == OutputFormat::Json, Ordering::Relaxed);
+}
+
+/// Emit a milestone of the run as a line of JSON on stdout with
+/// `--output json`, with its name, the milliseconds since the Unix
+/// epoch and the fields of `data`. Does nothing otherwise.
+pub fn event(name: &str, data: Value) {
+    if !JSON.load(Ordering::Relaxed) {
+        return;
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_millis());
+    let mut event = json!({ "event": name, "timestamp": timestamp });
+    if let (Some(event), Value::Object(data)) = (event.as_object_mut(), data) {
+        event.extend(data);
+    }
+    println!("{}", event);
+}
+
+/// Print a line of aptest's own output, on stderr when
+/// stdout carries the event stream
+pub fn print(message: impl Display) {
+    if JSON.load(Ordering::Relaxed) {
+        eprintln!("{}", message);
+    } else {
+        with_spinners(|spinners| spinners.suspend(|| println!("{}", message)));
+    }
+}
+
+/// Pick the output style from --no-color and the environment.
+/// NO_COLOR disables colors, and CI=true also switches to plain
+/// `[aptest] ...` lines that log collectors handle well.
+pub fn init_style(no_color: bool) {
+    let ci = matches!(std::env::var("CI").as_deref(), Ok("true") | Ok("1"));
+    let no_color_env = matches!(std::env::var_os("NO_COLOR"), Some(x) if !x.is_empty());
+    if no_color || no_color_env || ci {
+        colored::control::set_override(false);
+    }
+    PLAIN.store(ci, Ordering::Relaxed);
+}
+
+/// Announce a step of the run
+pub fn info(message: impl Display) {
+    banner(message.to_string().bright_blue().bold());
+}
+
+/// Announce something went well
+pub fn success(message: impl Display) {
+    banner(message.to_string().bright_green().bold());
+}
+
+/// Tick off a step on the way to a longer one, on a line of its own
+pub fn check(message: impl Display) {
+    if PLAIN.load(Ordering::Relaxed) {
+        print(format!("[aptest] ✔ {}", message));
+    } else {
+        print(format!("{} {}", "✔".bright_green().bold(), message));
+    }
+}
+
+/// Announce a failure
+pub fn failure(message: impl Display) {
+    banner(message.to_string().bright_red().bold());
+}
+
+fn banner(message: ColoredString) {
+    if PLAIN.load(Ordering::Relaxed) {
+        for line in message.lines() {
+            print(format!("[aptest] {}", line));
+        }
+    } else {
+        print(format!("\n{}\n", message));
+    }
+}
+
+/// Set the output level for the rest of the process
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Whether the output of the tools aptest runs is hidden, as it
+/// also is when stdout carries the event stream
+pub fn quiet() -> bool {
+    verbosity() == Verbosity::Quiet || JSON.load(Ordering::Relaxed)
+}
+
+/// Where the stdout of a tool run by aptest goes
+pub fn child_stdout() -> Stdio {
+    if quiet() {
+        Stdio::null()
+    } else {
+        Stdio::inherit()
+    }
+}
+
+/// Print a detail only shown with --verbose
+pub fn debug(message: impl Display) {
+    if verbosity() == Verbosity::Verbose {
+        print(message.to_string().dimmed());
+    }
+}
+
+/// Show the command line about to run, and the environment
+/// variables set for it, with --verbose
+pub fn command(command: &Command) {
+    if verbosity() != Verbosity::Verbose {
+        return;
+    }
+    let command = command.as_std();
+    let args: Vec<_> = command.get_args().map(|x| x.to_string_lossy()).collect();
+    debug(format!(
+        "$ {} {}",
+        command.get_program().to_string_lossy(),
+        args.join(" ")
+    ));
+    for (key, value) in command.get_envs() {
+        if let Some(value) = value {
+            debug(format!(
+                "  {}={}",
+                key.to_string_lossy(),
+                value.to_string_lossy()
+            ));
+        }
+    }
+}
+
+/// Whether long steps show a spinner: on a terminal, at the normal output
+/// level, outside CI and without the event stream. Otherwise they only
+/// get their banner, and the output of the tools they run as usual
+pub fn spinners() -> bool {
+    verbosity() == Verbosity::Normal
+        && !PLAIN.load(Ordering::Relaxed)
+        && !JSON.load(Ordering::Relaxed)
+        && !ProgressDrawTarget::stderr().is_hidden()
+}
+
+/// A spinner showing a step is still going, with the time it has taken
+/// and the last line written by what it waits on. Dropping it clears it
+pub struct Spinner {
+    bar: Option<ProgressBar>,
+    follower: Option<JoinHandle<()>>,
+}
+
+/// Show a spinner for a step, when spinners are shown at all
+pub fn spinner(step: impl Display) -> Spinner {
+    if !spinners() {
+        return Spinner {
+            bar: None,
+            follower: None,
+        };
+    }
+    let style =
+        ProgressStyle::with_template("{spinner:.blue} {prefix:.bold} {elapsed} {wide_msg:.dim}")
+            .unwrap_or_else(|_| ProgressStyle::default_spinner());
+    let bar = ProgressBar::new_spinner()
+        .with_style(style)
+        .with_prefix(step.to_string());
+    let bar = with_spinners(|spinners| spinners.add(bar));
+    bar.enable_steady_tick(Duration::from_millis(100));
+    Spinner {
+        bar: Some(bar),
+        follower: None,
+    }
+}
+
+impl Spinner {
+    /// Keep showing the last line written to a log
+    pub fn follow(mut self, log: &Log) -> Self {
+        if let Some(bar) = &self.bar {
+            let (bar, log) = (bar.clone(), log.clone());
+            self.follower = Some(tokio::spawn(async move {
+                loop {
+                    bar.set_message(last_line(&log));
+                    tokio::time::sleep(SPINNER_REFRESH).await;
+                }
+            }));
+        }
+        self
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        if let Some(follower) = &self.follower {
+            follower.abort();
+        }
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+            with_spinners(|spinners| spinners.remove(bar));
+        }
+    }
+}
+
+//Runs with the spinners drawn on stderr, a poisoned lock being as good
+fn with_spinners<T>(f: impl FnOnce(&MultiProgress) -> T) -> T {
+    let mut spinners = SPINNERS.lock().unwrap_or_else(|e| e.into_inner());
+    f(
+        spinners
+            .get_or_insert_with(|| MultiProgress::with_draw_target(ProgressDrawTarget::stderr())),
+    )
+}
+
+//The last line with something on it written to a log, looking only at
+//its end since the log of a node grows large
+fn last_line(log: &Log) -> String {
+    let log = match log.lock() {
+        Ok(log) => log,
+        Err(_) => return String::new(),
+    };
+    String::from_utf8_lossy(&log[log.len().saturating_sub(4096)..])
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|x| !x.is_empty())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Ask a question on the terminal, the answer being `default` if left empty
+pub fn ask(question: &str, default: &str) -> Result<String, AptestError> {
+    print!("{} [{}]: ", question.bold(), default);
+    std::io::stdout()
+        .flush()
+        .or_fail(AptestError::Environment, "Could not write to the terminal")?;
+    let mut answer = String::new();
+    std::io::stdin()
+        .read_line(&mut answer)
+        .or_fail(AptestError::Environment, "Could not read the answer")?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+/// Ask for one of the values of a command line option,
+/// again until one of them is given
+pub fn choose<T: clap::ValueEnum>(question: &str, default: T) -> Result<T, AptestError> {
+    let names: Vec<_> = T::value_variants()
+        .iter()
+        .filter_map(|x| Some(x.to_possible_value()?.get_name()))
+        .collect();
+    let default = default
+        .to_possible_value()
+        .map(|x| x.get_name())
+        .unwrap_or_default();
+    loop {
+        let answer = ask(&format!("{} ({})", question, names.join("/")), default)?;
+        if let Ok(value) = T::from_str(&answer, true) {
+            return Ok(value);
+        }
+    }
+}
+
+/// Ask a yes or no question, again until answered with one of them
+pub fn confirm(question: &str, default: bool) -> Result<bool, AptestError> {
+    let default = if default { "y" } else { "n" };
+    loop {
+        match ask(&format!("{} (y/n)", question), default)?
+            .to_lowercase()
+            .as_str()
+        {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_last_line() {
+    let log = Log::default();
+    assert_eq!(last_line(&log), "");
+    log.lock()
+        .unwrap()
+        .extend_from_slice(b"Compiling...\n  BUILDING Example  \n\n");
+    assert_eq!(last_line(&log), "BUILDING Example");
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::error::AptestError;
+use crate::init::Template;
+
+//How often the watched files are rescanned
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+//Tests watched when the harness isn't known, those of every template
+const TEST_FILES: [(&str, &str); 4] = [
+    ("tests", "ts"),
+    ("tests", "js"),
+    ("tests", "py"),
+    ("e2e", "rs"),
+];
+
+//Dependencies and build output, which the tests themselves write to
+const SKIPPED_DIRS: [&str; 3] = ["node_modules", "target", "__pycache__"];
+
+/// Watches the Move sources and the e2e tests for changes,
+/// by polling the modification times of the files.
+pub struct Watcher {
+    roots: Vec<(PathBuf, String)>,
+    snapshot: HashMap<PathBuf, SystemTime>,
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Watcher::new().watch("sources", "move").watch_tests(None)
+    }
+}
+
+impl Watcher {
+    /// A watcher with nothing to watch yet
+    pub fn new() -> Self {
+        Watcher {
+            roots: Vec::new(),
+            snapshot: HashMap::new(),
+        }
+    }
+
+    /// Also watch every file with the given extension under a directory
+    pub fn watch(mut self, dir: impl Into<PathBuf>, extension: &str) -> Self {
+        self.roots.push((dir.into(), extension.to_string()));
+        self.snapshot = self.scan();
+        self
+    }
+
+    /// Also watch the tests of the harness set up by "aptest init", or
+    /// those of any of them when it isn't known
+    pub fn watch_tests(self, template: Option<Template>) -> Self {
+        match template {
+            Some(template) => {
+                let (dir, extension) = template.tests();
+                self.watch(dir, extension)
+            }
+            None => TEST_FILES.iter().fold(self, |watcher, (dir, extension)| {
+                watcher.watch(dir, extension)
+            }),
+        }
+    }
+
+    /// Wait until a watched file is created, modified or removed,
+    /// returning the paths that changed.
+    pub async fn changed(&mut self) -> Result<Vec<PathBuf>, AptestError> {
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let mut current = self.scan();
+            if current == self.snapshot {
+                continue;
+            }
+
+            //Editors often write a file in several steps,
+            //give them a moment to settle
+            tokio::time::sleep(POLL_INTERVAL).await;
+            current = self.scan();
+
+            let mut changed: Vec<PathBuf> = current
+                .iter()
+                .filter(|(path, time)| self.snapshot.get(*path) != Some(*time))
+                .map(|(path, _)| path.clone())
+                .collect();
+            changed.extend(
+                self.snapshot
+                    .keys()
+                    .filter(|path| !current.contains_key(*path))
+                    .cloned(),
+            );
+            self.snapshot = current;
+            if !changed.is_empty() {
+                changed.sort();
+                return Ok(changed);
+            }
+        }
+    }
+
+    //Modification times of every watched file
+    fn scan(&self) -> HashMap<PathBuf, SystemTime> {
+        let mut files = HashMap::new();
+        for (dir, extension) in &self.roots {
+            scan_dir(dir, extension, &mut files);
+        }
+        files
+    }
+}
+
+//Recursively collects the files with the extension under dir
+fn scan_dir(dir: &Path, extension: &str, files: &mut HashMap<PathBuf, SystemTime>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if !SKIPPED_DIRS.iter().any(|x| entry.file_name() == *x) {
+                scan_dir(&path, extension, files);
+            }
+        } else if path.extension().and_then(|x| x.to_str()) == Some(extension) {
+            if let Ok(modified) = entry.metadata().and_then(|x| x.modified()) {
+                files.insert(path, modified);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_watch_tests() {
+    let roots = |watcher: Watcher| -> Vec<(String, String)> {
+        watcher
+            .roots
+            .into_iter()
+            .map(|(dir, extension)| (dir.display().to_string(), extension))
+            .collect()
+    };
+    assert_eq!(
+        roots(Watcher::new().watch_tests(Some(Template::Rust))),
+        [("e2e".to_string(), "rs".to_string())]
+    );
+    assert_eq!(
+        roots(Watcher::new().watch_tests(Some(Template::Python))),
+        [("tests".to_string(), "py".to_string())]
+    );
+    assert_eq!(
+        roots(Watcher::new().watch_tests(None)).len(),
+        TEST_FILES.len()
+    );
+}
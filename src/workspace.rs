@@ -0,0 +1,152 @@
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::Workspace;
+use crate::error::{AptestError, OrFail};
+
+//The parts of Move.toml needed to order packages and to publish them
+//in dev mode
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    dependencies: BTreeMap<String, Dependency>,
+    #[serde(default, rename = "dev-addresses")]
+    dev_addresses: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct Dependency {
+    local: Option<PathBuf>,
+}
+
+impl Workspace {
+    /// The workspace's package directories, ordered so that every package
+    /// comes after the local packages it depends on.
+    pub fn ordered_packages(&self) -> Result<Vec<PathBuf>, AptestError> {
+        let mut canonical = Vec::new();
+        for dir in &self.packages {
+            canonical.push(dir.canonicalize().or_fail(
+                AptestError::Environment,
+                &format!("Could not find the package {}", dir.display()),
+            )?);
+        }
+
+        //Dependencies on packages outside the workspace don't affect the order
+        let mut dependencies = Vec::new();
+        for dir in &canonical {
+            let deps = local_dependencies(dir)?
+                .iter()
+                .filter_map(|dep| canonical.iter().position(|x| x == dep))
+                .collect();
+            dependencies.push(deps);
+        }
+
+        let order = dependency_order(&dependencies).map_err(|i| {
+            AptestError::Environment(format!(
+                "The package {} is part of a dependency cycle",
+                self.packages[i].display()
+            ))
+        })?;
+        Ok(order
+            .into_iter()
+            .map(|i| self.packages[i].clone())
+            .collect())
+    }
+}
+
+/// The named addresses given in the `[dev-addresses]` section of the
+/// Move.toml of the package in `dir`, used instead of `_` by dev builds
+pub fn dev_addresses(dir: &Path) -> Result<Vec<String>, AptestError> {
+    Ok(read_manifest(dir)?.dev_addresses.into_keys().collect())
+}
+
+//Canonical paths of the local dependencies listed in a package's Move.toml
+fn local_dependencies(dir: &Path) -> Result<Vec<PathBuf>, AptestError> {
+    Ok(read_manifest(dir)?
+        .dependencies
+        .values()
+        .filter_map(|dep| dep.local.as_ref())
+        .filter_map(|local| dir.join(local).canonicalize().ok())
+        .collect())
+}
+
+//Reads the Move.toml of the package in dir
+fn read_manifest(dir: &Path) -> Result<Manifest, AptestError> {
+    let manifest_path = dir.join("Move.toml");
+    let manifest = std::fs::read_to_string(&manifest_path).or_fail(
+        AptestError::Environment,
+        &format!("Could not read {}", manifest_path.display()),
+    )?;
+    toml::from_str(&manifest).or_fail(
+        AptestError::Environment,
+        &format!("Could not parse {}", manifest_path.display()),
+    )
+}
+
+/// Depth first topological sort, keeping the listed order where the
+/// dependencies allow it. `dependencies[i]` are the indices package `i`
+/// depends on. Fails with the index of a package found in a cycle.
+fn dependency_order(dependencies: &[Vec<usize>]) -> Result<Vec<usize>, usize> {
+    //0 = unvisited, 1 = being visited, 2 = done
+    fn visit(
+        i: usize,
+        dependencies: &[Vec<usize>],
+        state: &mut [u8],
+        order: &mut Vec<usize>,
+    ) -> Result<(), usize> {
+        match state[i] {
+            1 => return Err(i),
+            2 => return Ok(()),
+            _ => {}
+        }
+        state[i] = 1;
+        for &dep in &dependencies[i] {
+            visit(dep, dependencies, state, order)?;
+        }
+        state[i] = 2;
+        order.push(i);
+        Ok(())
+    }
+
+    let mut state = vec![0; dependencies.len()];
+    let mut order = Vec::new();
+    for i in 0..dependencies.len() {
+        visit(i, dependencies, &mut state, &mut order)?;
+    }
+    Ok(order)
+}
+
+#[test]
+fn test_dependency_order() {
+    //periphery depends on core, listed first
+    assert_eq!(dependency_order(&[vec![1], vec![]]), Ok(vec![1, 0]));
+    assert_eq!(
+        dependency_order(&[vec![], vec![2], vec![0]]),
+        Ok(vec![0, 2, 1])
+    );
+    assert_eq!(dependency_order(&[vec![1], vec![0]]), Err(0));
+}
+
+#[test]
+fn test_dev_addresses() {
+    let manifest: Manifest = toml::from_str(
+        r#"
+        [package]
+        name = "counter"
+
+        [addresses]
+        counter = "_"
+
+        [dev-addresses]
+        counter = "0xCAFE"
+        "#,
+    )
+    .unwrap();
+    assert!(manifest.dependencies.is_empty());
+    assert_eq!(
+        manifest.dev_addresses.into_keys().collect::<Vec<_>>(),
+        ["counter"]
+    );
+}
@@ -0,0 +1,65 @@
+module example::example_coin {
+    use std::signer;
+    use std::string;
+    use aptos_framework::coin::{Self, BurnCapability, FreezeCapability, MintCapability};
+    #[test_only]
+    use aptos_framework::account;
+
+    /// More coins were claimed at once than allowed
+    const ECLAIM_TOO_LARGE: u64 = 1;
+
+    const MAX_CLAIM: u64 = 1000000000;
+
+    struct ExampleCoin {}
+
+    struct Capabilities has key {
+        burn: BurnCapability<ExampleCoin>,
+        freeze: FreezeCapability<ExampleCoin>,
+        mint: MintCapability<ExampleCoin>,
+    }
+
+    fun init_module(publisher: &signer) {
+        let (burn, freeze, mint) = coin::initialize<ExampleCoin>(
+            publisher,
+            string::utf8(b"Example Coin"),
+            string::utf8(b"EXC"),
+            8,
+            true,
+        );
+        move_to(publisher, Capabilities { burn, freeze, mint });
+    }
+
+    /// Mint coins to the account, which anyone can do on this example coin
+    public entry fun claim(account: &signer, amount: u64) acquires Capabilities {
+        assert!(amount <= MAX_CLAIM, ECLAIM_TOO_LARGE);
+        if (!coin::is_account_registered<ExampleCoin>(signer::address_of(account))) {
+            coin::register<ExampleCoin>(account);
+        };
+        let capabilities = borrow_global<Capabilities>(@example);
+        let coins = coin::mint(amount, &capabilities.mint);
+        coin::deposit(signer::address_of(account), coins);
+    }
+
+    #[view]
+    public fun balance(owner: address): u64 {
+        coin::balance<ExampleCoin>(owner)
+    }
+
+    #[test(publisher = @example, user = @0x123)]
+    fun test_claim(publisher: signer, user: signer) acquires Capabilities {
+        account::create_account_for_test(@example);
+        account::create_account_for_test(@0x123);
+        init_module(&publisher);
+        claim(&user, 100);
+        assert!(balance(@0x123) == 100, 0);
+    }
+
+    #[test(publisher = @example, user = @0x123)]
+    #[expected_failure(abort_code = ECLAIM_TOO_LARGE)]
+    fun test_claim_too_large(publisher: signer, user: signer) acquires Capabilities {
+        account::create_account_for_test(@example);
+        account::create_account_for_test(@0x123);
+        init_module(&publisher);
+        claim(&user, MAX_CLAIM + 1);
+    }
+}
@@ -0,0 +1,39 @@
+module example::counter {
+    use std::signer;
+
+    /// The account has never incremented its counter
+    const ENO_COUNTER: u64 = 1;
+
+    struct Counter has key {
+        value: u64,
+    }
+
+    /// Add one to the counter of the account, starting it at zero
+    public entry fun increment(account: &signer) acquires Counter {
+        let addr = signer::address_of(account);
+        if (!exists<Counter>(addr)) {
+            move_to(account, Counter { value: 0 });
+        };
+        let counter = borrow_global_mut<Counter>(addr);
+        counter.value = counter.value + 1;
+    }
+
+    #[view]
+    public fun value(addr: address): u64 acquires Counter {
+        assert!(exists<Counter>(addr), ENO_COUNTER);
+        borrow_global<Counter>(addr).value
+    }
+
+    #[test(account = @0x123)]
+    fun test_increment(account: signer) acquires Counter {
+        increment(&account);
+        increment(&account);
+        assert!(value(@0x123) == 2, 0);
+    }
+
+    #[test]
+    #[expected_failure(abort_code = ENO_COUNTER)]
+    fun test_value_without_counter() acquires Counter {
+        value(@0x123);
+    }
+}
@@ -0,0 +1,46 @@
+import assert from "assert";
+import { Account, Aptos, AptosConfig, Network } from "@aptos-labs/ts-sdk";
+
+//Set by aptest for the node it started and the package it published
+const aptos = new Aptos(
+  new AptosConfig({
+    network: Network.CUSTOM,
+    fullnode: `${process.env.APTEST_NODE_URL}/v1`,
+    faucet: process.env.APTEST_FAUCET_URL,
+  })
+);
+const MODULE = `${process.env.APTEST_DEPLOYMENT_0_SENDER}::counter`;
+
+function fn(name: string): `${string}::${string}::${string}` {
+  return `${MODULE}::${name}`;
+}
+
+describe("counter", () => {
+  it("counts increments", async () => {
+    const account = Account.generate();
+    await aptos.fundAccount({
+      accountAddress: account.accountAddress,
+      amount: 100_000_000,
+    });
+
+    for (let i = 0; i < 2; i++) {
+      const transaction = await aptos.transaction.build.simple({
+        sender: account.accountAddress,
+        data: { function: fn("increment"), functionArguments: [] },
+      });
+      const pending = await aptos.signAndSubmitTransaction({
+        signer: account,
+        transaction,
+      });
+      await aptos.waitForTransaction({ transactionHash: pending.hash });
+    }
+
+    const [value] = await aptos.view({
+      payload: {
+        function: fn("value"),
+        functionArguments: [account.accountAddress],
+      },
+    });
+    assert.strictEqual(value, "2");
+  });
+});
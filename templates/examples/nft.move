@@ -0,0 +1,60 @@
+module example::example_nft {
+    use std::option;
+    use std::signer;
+    use std::string::{Self, String};
+    use aptos_framework::object::{Self, ExtendRef};
+    use aptos_token_objects::collection;
+    use aptos_token_objects::token::{Self, Token};
+
+    const COLLECTION: vector<u8> = b"Example Collection";
+    const MINTER: vector<u8> = b"minter";
+
+    /// Lets the package sign as the object owning the collection
+    struct Minter has key {
+        extend_ref: ExtendRef,
+    }
+
+    fun init_module(publisher: &signer) {
+        let constructor_ref = object::create_named_object(publisher, MINTER);
+        let minter = object::generate_signer(&constructor_ref);
+        collection::create_unlimited_collection(
+            &minter,
+            string::utf8(b"Collection of the aptest example"),
+            string::utf8(COLLECTION),
+            option::none(),
+            string::utf8(b"https://example.com"),
+        );
+        let extend_ref = object::generate_extend_ref(&constructor_ref);
+        move_to(publisher, Minter { extend_ref });
+    }
+
+    /// Mint a token of the collection to the account, which anyone can do
+    public entry fun mint(account: &signer, name: String) acquires Minter {
+        let minter = borrow_global<Minter>(@example);
+        let minter = object::generate_signer_for_extending(&minter.extend_ref);
+        let constructor_ref = token::create_named_token(
+            &minter,
+            string::utf8(COLLECTION),
+            string::utf8(b"A token of the aptest example"),
+            name,
+            option::none(),
+            string::utf8(b"https://example.com/token"),
+        );
+        let token = object::object_from_constructor_ref<Token>(&constructor_ref);
+        object::transfer(&minter, token, signer::address_of(account));
+    }
+
+    #[view]
+    public fun owner(name: String): address {
+        let minter = object::create_object_address(&@example, MINTER);
+        let token = token::create_token_address(&minter, &string::utf8(COLLECTION), &name);
+        object::owner(object::address_to_object<Token>(token))
+    }
+
+    #[test(publisher = @example, user = @0x123)]
+    fun test_mint(publisher: signer, user: signer) acquires Minter {
+        init_module(&publisher);
+        mint(&user, string::utf8(b"first"));
+        assert!(owner(string::utf8(b"first")) == @0x123, 0);
+    }
+}
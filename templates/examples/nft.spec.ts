@@ -0,0 +1,47 @@
+import assert from "assert";
+import {
+  Account,
+  AccountAddress,
+  Aptos,
+  AptosConfig,
+  Network,
+} from "@aptos-labs/ts-sdk";
+
+//Set by aptest for the node it started and the package it published
+const aptos = new Aptos(
+  new AptosConfig({
+    network: Network.CUSTOM,
+    fullnode: `${process.env.APTEST_NODE_URL}/v1`,
+    faucet: process.env.APTEST_FAUCET_URL,
+  })
+);
+const MODULE = `${process.env.APTEST_DEPLOYMENT_0_SENDER}::example_nft`;
+
+function fn(name: string): `${string}::${string}::${string}` {
+  return `${MODULE}::${name}`;
+}
+
+describe("example nft", () => {
+  it("is minted to the caller", async () => {
+    const account = Account.generate();
+    await aptos.fundAccount({
+      accountAddress: account.accountAddress,
+      amount: 100_000_000,
+    });
+
+    const transaction = await aptos.transaction.build.simple({
+      sender: account.accountAddress,
+      data: { function: fn("mint"), functionArguments: ["first"] },
+    });
+    const pending = await aptos.signAndSubmitTransaction({
+      signer: account,
+      transaction,
+    });
+    await aptos.waitForTransaction({ transactionHash: pending.hash });
+
+    const [owner] = await aptos.view({
+      payload: { function: fn("owner"), functionArguments: ["first"] },
+    });
+    assert.ok(AccountAddress.from(owner as string).equals(account.accountAddress));
+  });
+});